@@ -77,7 +77,7 @@ async fn handle_request(
     if let Some(asset) = find_asset(url, &assets.keys().cloned().collect()) {
         println!("              {}", input("Asset found"));
 
-        let run_result = match handle_asset(public_dir.unwrap(), asset) {
+        let run_result = match handle_asset(public_dir.unwrap(), asset, None) {
             Ok(response) => RunResult::Response(response, None),
             Err(error) => RunResult::Error(format!("Could not retrieve asset ({asset}): {error}")),
         };
@@ -103,6 +103,9 @@ async fn handle_request(
                     .send_async(IsolateEvent::Request(IsolateRequest {
                         request,
                         sender: tx,
+                        cancellation_token: None,
+                        memory: None,
+                        max_body_size: None,
                     }))
                     .await
                     .unwrap_or(());
@@ -170,7 +173,7 @@ pub async fn dev(
     let assets = Arc::new(Mutex::new(assets));
 
     let runtime =
-        Runtime::new(RuntimeOptions::default().allow_code_generation(allow_code_generation));
+        Runtime::new(RuntimeOptions::default().allow_code_generation(allow_code_generation))?;
     let addr = format!(
         "{}:{}",
         hostname.unwrap_or_else(|| "127.0.0.1".into()),
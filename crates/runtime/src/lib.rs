@@ -1,3 +1,5 @@
+use anyhow::Result;
+use lagon_runtime_v8_utils::{parse_v8_flags, V8FlagScope};
 use v8::V8;
 
 pub mod options;
@@ -10,11 +12,14 @@ struct IcuData([u8; 10541264]);
 static ICU_DATA: IcuData = IcuData(*include_bytes!("../icudtl.dat"));
 
 const FLAGS: [&str; 0] = [];
+// In KB. Isolates run on threads with the default 2 MB stack, where the
+// rest is kept for the frames below the isolate and the native calls
+const MAX_STACK_SIZE: usize = 1536;
 
 pub struct Runtime;
 
 impl Runtime {
-    pub fn new(options: RuntimeOptions) -> Self {
+    pub fn new(options: RuntimeOptions) -> Result<Self> {
         // Load ICU data to enable i18n, similar to Deno:
         // https://github.com/denoland/deno/blob/a55b194638bcaace38917703b7d9233fb1989d44/core/runtime.rs#L223
         v8::icu::set_common_data_72(&ICU_DATA.0).expect("Failed to load ICU data");
@@ -31,13 +36,24 @@ impl Runtime {
             flags += " --expose-gc";
         }
 
+        // Throw a RangeError past the given stack size, instead of overflowing
+        // the stack of the thread running the isolate
+        if let Some(max_stack_size) = options.max_stack_size {
+            flags += &format!(" --stack-size={}", max_stack_size.min(MAX_STACK_SIZE));
+        }
+
+        // Set once for all the isolates, before initializing V8
+        for flag in parse_v8_flags(&options.v8_flags, V8FlagScope::Global)? {
+            flags += &format!(" {flag}");
+        }
+
         V8::set_flags_from_string(&flags);
 
         let platform = v8::new_default_platform(0, false).make_shared();
         V8::initialize_platform(platform);
         V8::initialize();
 
-        Runtime
+        Ok(Runtime)
     }
 
     pub fn dispose(&self) {
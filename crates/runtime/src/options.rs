@@ -2,6 +2,14 @@
 pub struct RuntimeOptions {
     pub allow_code_generation: bool,
     pub expose_gc: bool,
+    // Size of the stack the JS code of every isolate can use in KB, after which
+    // a RangeError is thrown. Capped to 1.5 MB, since the threads running the
+    // isolates have a 2 MB stack
+    pub max_stack_size: Option<usize>,
+    // Process-global V8 flags, e.g `--max-semi-space-size=32` or `--jitless`.
+    // Only the allowed flags can be set, `Runtime::new` returning an error
+    // for the others
+    pub v8_flags: Vec<String>,
 }
 
 impl RuntimeOptions {
@@ -14,4 +22,14 @@ impl RuntimeOptions {
         self.expose_gc = expose_gc;
         self
     }
+
+    pub fn max_stack_size(mut self, max_stack_size: usize) -> Self {
+        self.max_stack_size = Some(max_stack_size);
+        self
+    }
+
+    pub fn v8_flags(mut self, v8_flags: Vec<String>) -> Self {
+        self.v8_flags = v8_flags;
+        self
+    }
 }
@@ -0,0 +1,181 @@
+use lagon_runtime_http::{match_locale, parse_accept_language, Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::collections::HashMap;
+
+mod utils;
+
+fn languages(header: &str) -> Vec<(String, f64)> {
+    parse_accept_language(header)
+}
+
+fn request_with_accept_language(accept_language: &str) -> Request {
+    Request {
+        headers: Some(HashMap::from([(
+            "accept-language".into(),
+            vec![accept_language.into()],
+        )])),
+        ..Request::default()
+    }
+}
+
+#[test]
+fn quality_ordering() {
+    assert_eq!(
+        languages("fr;q=0.5, en-US, de;q=0.8, en;q=0.8"),
+        vec![
+            ("en-US".into(), 1.0),
+            ("de".into(), 0.8),
+            ("en".into(), 0.8),
+            ("fr".into(), 0.5),
+        ]
+    );
+}
+
+#[test]
+fn wildcard() {
+    let supported = vec!["en".to_string(), "fr".to_string()];
+
+    assert_eq!(
+        languages("de, *;q=0.1"),
+        vec![("de".into(), 1.0), ("*".into(), 0.1)]
+    );
+    assert_eq!(
+        match_locale(&languages("de, *;q=0.1"), &supported),
+        Some("en")
+    );
+    assert_eq!(match_locale(&languages("de"), &supported), None);
+}
+
+#[test]
+fn locale_matching() {
+    let supported = vec!["en-US".to_string(), "fr".to_string()];
+
+    assert_eq!(match_locale(&languages("FR-ca"), &supported), Some("fr"));
+    assert_eq!(match_locale(&languages("en"), &supported), Some("en-US"));
+    assert_eq!(
+        match_locale(&languages("de, fr;q=0.2, en-us;q=0.5"), &supported),
+        Some("en-US")
+    );
+    assert_eq!(match_locale(&languages("en"), &[]), None);
+}
+
+#[test]
+fn malformed_input() {
+    assert_eq!(languages(""), vec![]);
+    assert_eq!(languages(",,, ;;"), vec![]);
+    assert_eq!(languages("en;q=abc, fr;q=2, de;q=-1, it;q="), vec![]);
+    assert_eq!(languages("en;q=0, fr"), vec![("fr".into(), 1.0)]);
+    assert_eq!(
+        languages("-en, en--US, en-, e n, fr"),
+        vec![("fr".into(), 1.0)]
+    );
+    assert_eq!(languages("en;foo, fr;level=1"), vec![("fr".into(), 1.0)]);
+    assert_eq!(languages("é, 日本, fr"), vec![("fr".into(), 1.0)]);
+}
+
+// Parse random headers made from the characters used by the format,
+// checking that the result always stays valid
+#[test]
+fn fuzz() {
+    const ALPHABET: &[u8] = b"enfrUS-*,;=q. 0123456789\t\"\\\0";
+
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    let supported = vec!["en-US".to_string(), "fr".to_string()];
+
+    for _ in 0..10_000 {
+        let length = (next() % 64) as usize;
+        let header = (0..length)
+            .map(|_| match next() % 8 {
+                // Sometimes use any byte, including invalid UTF-8
+                0 => (next() % 256) as u8,
+                _ => ALPHABET[(next() % ALPHABET.len() as u64) as usize],
+            })
+            .collect::<Vec<_>>();
+        let header = String::from_utf8_lossy(&header);
+
+        let languages = parse_accept_language(&header);
+
+        for (tag, quality) in &languages {
+            assert!(!tag.is_empty(), "{header:?}");
+            assert!(*quality > 0.0 && *quality <= 1.0, "{header:?}");
+        }
+
+        assert!(
+            languages.windows(2).all(|pair| pair[0].1 >= pair[1].1),
+            "{header:?}"
+        );
+
+        if let Some(locale) = match_locale(&languages, &supported) {
+            assert!(supported.iter().any(|supported| supported == locale));
+        }
+    }
+}
+
+#[tokio::test]
+async fn request_accept_languages() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler(request) {
+    const languages = request.acceptLanguages().map(({ locale, quality }) => `${locale}:${quality}`);
+    return new Response(languages.join(','));
+}"
+        .into(),
+    ));
+    send(request_with_accept_language("fr;q=0.5, en-US, *;q=0.1"));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("en-US:1,fr:0.5,*:0.1")
+    );
+}
+
+#[tokio::test]
+async fn request_locale() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler(request) {
+    return new Response(request.locale);
+}"
+            .into(),
+        )
+        .supported_locales(vec!["en".into(), "fr".into()]),
+    );
+    send(request_with_accept_language("de, fr-CA;q=0.8, en;q=0.5"));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("fr")
+    );
+
+    // Falls back to the first supported locale
+    send(request_with_accept_language("de"));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("en")
+    );
+}
+
+#[tokio::test]
+async fn request_locale_without_supported_locales() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler(request) {
+    return new Response(`${request.locale}`);
+}"
+        .into(),
+    ));
+    send(request_with_accept_language("fr"));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("undefined")
+    );
+}
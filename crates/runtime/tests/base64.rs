@@ -0,0 +1,176 @@
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn decode_base64() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const bytes = Uint8Array.fromBase64('SGVs bG8\\n');
+    const url = Uint8Array.fromBase64('-_8', { alphabet: 'base64url' });
+
+    return new Response(`${bytes instanceof Uint8Array} ${new TextDecoder().decode(bytes)} ${url.join(',')}`);
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("true Hello 251,255")
+    );
+}
+
+#[tokio::test]
+async fn decode_invalid_base64() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    try {
+        Uint8Array.fromBase64('SGVsbG8*');
+        return new Response('ok');
+    } catch (error) {
+        return new Response(error.name);
+    }
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("SyntaxError")
+    );
+}
+
+#[tokio::test]
+async fn binary_response_body() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler(request) {
+    const bytes = Uint8Array.fromBase64('AAECAwQF');
+
+    switch (new URL(request.url).pathname) {
+        case '/buffer':
+            return new Response(bytes.buffer);
+        case '/view':
+            return new Response(new DataView(bytes.buffer, 1, 3));
+        default:
+            return new Response(bytes);
+    }
+}"
+        .into(),
+    ));
+
+    for (path, body) in [
+        ("/", vec![0, 1, 2, 3, 4, 5]),
+        ("/buffer", vec![0, 1, 2, 3, 4, 5]),
+        ("/view", vec![1, 2, 3]),
+    ] {
+        send(Request {
+            url: format!("http://localhost{path}"),
+            ..Request::default()
+        });
+
+        assert_eq!(
+            receiver.recv_async().await.unwrap().as_response().body,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn array_buffer_of_view_body() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export async function handler() {
+    const view = new Uint8Array([0, 1, 2, 3, 4, 5]).subarray(1, 4);
+    const body = await new Response(view).arrayBuffer();
+
+    return new Response(`${body instanceof ArrayBuffer} ${new Uint8Array(body).join(',')}`);
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("true 1,2,3")
+    );
+}
+
+#[tokio::test]
+async fn decode_large_blob() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    // 768 KB of 'ABC'
+    const blob = 'QUJD'.repeat(256 * 1024);
+
+    const host = Uint8Array.fromBase64(blob);
+    const js = Uint8Array.from(atob(blob), char => char.charCodeAt(0));
+
+    const equal = host.length === js.length && host.every((byte, index) => byte === js[index]);
+
+    return new Response(`${host.length} ${equal}`);
+}"
+            .into(),
+        )
+        .tick_timeout(Duration::from_secs(5))
+        .total_timeout(Duration::from_secs(10)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("786432 true")
+    );
+}
+
+// Compare the host decoding with a decoding done in JS, run with
+// `cargo test --test base64 -- --ignored --nocapture`
+#[tokio::test]
+#[ignore]
+async fn bench_decode_large_blob() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    // 768 KB of 'ABC'
+    const blob = 'QUJD'.repeat(256 * 1024);
+    const iterations = 20;
+
+    let start = Date.now();
+    for (let i = 0; i < iterations; i++) {
+        Uint8Array.fromBase64(blob);
+    }
+    const host = (Date.now() - start) / iterations;
+
+    start = Date.now();
+    for (let i = 0; i < iterations; i++) {
+        Uint8Array.from(atob(blob), char => char.charCodeAt(0));
+    }
+    const js = (Date.now() - start) / iterations;
+
+    return new Response(`${host} ${js}`);
+}"
+            .into(),
+        )
+        .tick_timeout(Duration::from_secs(30))
+        .total_timeout(Duration::from_secs(60)),
+    );
+    send(Request::default());
+
+    let body = receiver.recv_async().await.unwrap().as_response().body;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let (host, js) = body.split_once(' ').unwrap();
+    let (host, js) = (host.parse::<f64>().unwrap(), js.parse::<f64>().unwrap());
+
+    println!("Decoding 768 KB: {host}ms on the host, {js}ms in JS");
+
+    assert!(host < js, "{host}ms on the host, {js}ms in JS");
+}
@@ -0,0 +1,110 @@
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+
+mod utils;
+
+// Enqueue 4 chunks of 8 bytes
+const STREAM: &str = "new ReadableStream({
+        start(controller) {
+            for (let i = 0; i < 4; i++) {
+                controller.enqueue(new TextEncoder().encode('a'.repeat(8)));
+            }
+
+            controller.close();
+        },
+    })";
+
+#[tokio::test]
+async fn array_buffer_over_limit() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    try {{
+        const body = await new Response({STREAM}).arrayBuffer();
+        return new Response(body.byteLength);
+    }} catch (error) {{
+        return new Response(`${{error.name}}: ${{error.message}}`);
+    }}
+}}"
+        ))
+        .max_body_size(16),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: Body exceeds the maximum size of 16 bytes")
+    );
+}
+
+#[tokio::test]
+async fn text_over_limit() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    return new Response({STREAM}).text().catch(error => new Response(error.message));
+}}"
+        ))
+        .max_body_size(16),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Body exceeds the maximum size of 16 bytes")
+    );
+}
+
+#[tokio::test]
+async fn within_limit() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const body = await new Response({STREAM}).text();
+    return new Response(body.length);
+}}"
+        ))
+        .max_body_size(32),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("32")
+    );
+}
+
+#[tokio::test]
+async fn fetch_response_over_limit() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("a".repeat(32))),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    try {{
+        await fetch('{url}').then(res => res.arrayBuffer());
+        return new Response('Read');
+    }} catch (error) {{
+        return new Response(`${{error.name}}: ${{error.message}}`);
+    }}
+}}"
+        ))
+        .max_body_size(16),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: Body exceeds the maximum size of 16 bytes")
+    );
+}
@@ -0,0 +1,136 @@
+use lagon_runtime_http::{Request, RunResult};
+use lagon_runtime_isolate::{
+    options::IsolateOptions, Isolate, IsolateEvent, IsolateLifecycleEvent, IsolateRequest,
+    SourceMap,
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+mod utils;
+
+const CODE: &str = "export function handler() {
+    return new Response('Hello world');
+}";
+
+#[tokio::test]
+async fn bundle_metadata() {
+    utils::setup();
+    let (_tx, rx) = flume::unbounded();
+    let mut isolate = Isolate::new(IsolateOptions::new(CODE.into()), rx);
+
+    assert!(isolate.get_bundle_metadata().is_none());
+
+    isolate.evaluate();
+
+    let bundle = isolate.get_bundle_metadata().unwrap();
+    assert_eq!(
+        bundle.hash,
+        "1692caa01d3de361941d58781304fe6cf71480f6d6eb2d3147d50ef094333ee9"
+    );
+    assert_eq!(bundle.size, 69);
+    assert_eq!(bundle.modules, 1);
+    assert!(!bundle.has_source_map);
+    assert!(!bundle.compilation_time.is_zero());
+}
+
+#[tokio::test]
+async fn bundle_metadata_source_map() {
+    utils::setup();
+    let (_tx, rx) = flume::unbounded();
+    let source_map = SourceMap::from_slice(
+        br#"{"version":3,"sources":["src/index.ts"],"names":[],"mappings":"AAAA"}"#,
+    )
+    .unwrap();
+    let mut isolate = Isolate::new(
+        IsolateOptions::new(CODE.into()).source_map(Some(Arc::new(source_map))),
+        rx,
+    );
+    isolate.evaluate();
+
+    assert!(isolate.get_bundle_metadata().unwrap().has_source_map);
+}
+
+#[tokio::test]
+async fn bundle_metadata_compilation_error() {
+    utils::setup();
+    let (_tx, rx) = flume::unbounded();
+    let mut isolate = Isolate::new(
+        IsolateOptions::new("export function handler() {".into()),
+        rx,
+    );
+    isolate.evaluate();
+
+    let bundle = isolate.get_bundle_metadata().unwrap();
+    assert_eq!(
+        bundle.hash,
+        "ce36eb838d6ad7384bcade3573081ed3bf3b222c7ccf61aaf33ea8bf10987bd5"
+    );
+    assert_eq!(bundle.size, 27);
+}
+
+#[tokio::test]
+async fn bundle_metadata_modules() {
+    utils::setup();
+    let (_tx, rx) = flume::unbounded();
+    let mut isolate = Isolate::new(
+        IsolateOptions::new("import './empty.js';\n".to_string() + CODE)
+            .modules(HashMap::from([("empty.js".into(), "export {}".into())])),
+        rx,
+    );
+    isolate.evaluate();
+
+    let bundle = isolate.get_bundle_metadata().unwrap();
+    assert_eq!(bundle.size, 21 + 69 + 9);
+    assert_eq!(bundle.modules, 2);
+}
+
+#[tokio::test]
+async fn bundle_metadata_timings() {
+    utils::setup();
+    let (tx, rx) = flume::unbounded();
+    let (lifecycle_sender, lifecycle_receiver) = flume::unbounded();
+    let (statistics_sender, statistics_receiver) = flume::unbounded();
+    let mut options = IsolateOptions::new(CODE.into())
+        .lifecycle_sender(lifecycle_sender)
+        .on_statistics_callback(Box::new(move |_, statistics| {
+            statistics_sender.send(statistics).unwrap();
+        }));
+    options.statistics_interval = Duration::ZERO;
+
+    let mut isolate = Isolate::new(options, rx);
+    isolate.evaluate();
+    let bundle = isolate.get_bundle_metadata().cloned().unwrap();
+
+    let (sender, receiver) = flume::unbounded();
+    tx.send_async(IsolateEvent::Request(IsolateRequest {
+        request: Request::default(),
+        sender,
+        cancellation_token: None,
+        memory: None,
+        max_body_size: None,
+    }))
+    .await
+    .unwrap();
+
+    tokio::select! {
+        _ = isolate.run_event_loop() => {}
+        result = receiver.recv_async() => {
+            assert!(matches!(result.unwrap(), RunResult::Response(_, Some(_))));
+        }
+    }
+
+    // The compilation and evaluation times of the bundle are sent with the
+    // handler time, in both the statistics and the completion event
+    let timings = statistics_receiver.try_recv().unwrap().timings.unwrap();
+    assert_eq!(timings.compilation_time, bundle.compilation_time);
+    assert_eq!(timings.evaluation_time, bundle.evaluation_time);
+
+    let finished = lifecycle_receiver
+        .try_iter()
+        .find_map(|(event, _)| match event {
+            IsolateLifecycleEvent::RequestFinished { timings } => Some(timings),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(finished.compilation_time, bundle.compilation_time);
+    assert_eq!(finished.evaluation_time, bundle.evaluation_time);
+}
@@ -0,0 +1,109 @@
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::{options::IsolateOptions, CodeCache, Isolate};
+use std::{env, fs, path::PathBuf, sync::Arc, time::Duration};
+
+mod utils;
+
+const CODE: &str = "export function handler() {
+    return new Response('Hello world');
+}";
+
+fn get_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("lagon-code-cache-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    dir
+}
+
+fn evaluate(code: &str, code_cache: &Arc<CodeCache>) -> bool {
+    let (_tx, rx) = flume::unbounded();
+    let mut isolate = Isolate::new(
+        IsolateOptions::new(code.into()).code_cache(Arc::clone(code_cache)),
+        rx,
+    );
+    isolate.evaluate();
+
+    assert!(isolate.get_compilation_error().is_none());
+    isolate.get_bundle_metadata().unwrap().code_cache
+}
+
+#[tokio::test]
+async fn consume_code_cache() {
+    utils::setup();
+    let dir = get_dir("consume");
+    let code_cache = Arc::new(CodeCache::new(&dir));
+
+    assert!(!evaluate(CODE, &code_cache));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    assert!(evaluate(CODE, &code_cache));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn invalidate_code_cache() {
+    utils::setup();
+    let dir = get_dir("invalidate");
+    let code_cache = Arc::new(CodeCache::new(&dir));
+
+    assert!(!evaluate(CODE, &code_cache));
+    assert!(!evaluate(
+        &CODE.replace("Hello world", "Hello"),
+        &code_cache
+    ));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    assert_ne!(
+        CodeCache::get_key(CODE, &[]),
+        CodeCache::get_key("export {}", &[])
+    );
+    assert_ne!(
+        CodeCache::get_key(CODE, &[]),
+        CodeCache::get_key(CODE, &["--stack-trace-limit=20".into()])
+    );
+}
+
+#[tokio::test]
+async fn rejected_code_cache() {
+    utils::setup();
+    let dir = get_dir("rejected");
+    let code_cache = Arc::new(CodeCache::new(&dir));
+
+    // V8 compiles the bundle from scratch
+    code_cache.insert(&CodeCache::get_key(CODE, &[]), b"invalid");
+    assert!(evaluate(CODE, &code_cache));
+}
+
+#[tokio::test]
+async fn evict_code_cache() {
+    utils::setup();
+    let dir = get_dir("evict");
+    let code_cache = Arc::new(CodeCache::new(&dir).max_size(10));
+
+    code_cache.insert("first", b"12345678");
+    std::thread::sleep(Duration::from_millis(10));
+    code_cache.insert("second", b"12345678");
+
+    assert!(code_cache.get("first").is_none());
+    assert!(code_cache.get("second").is_some());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn cached_bundle_response() {
+    utils::setup();
+    let dir = get_dir("response");
+    let code_cache = Arc::new(CodeCache::new(&dir));
+
+    assert!(!evaluate(CODE, &code_cache));
+
+    let (send, receiver) = utils::create_isolate_without_snapshot(
+        IsolateOptions::new(CODE.into()).code_cache(code_cache),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
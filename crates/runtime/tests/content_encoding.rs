@@ -0,0 +1,99 @@
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+
+mod utils;
+
+// "Hello, World! " repeated 64 times
+const GZIP_BODY: &[u8] = &[
+    31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 243, 72, 205, 201, 201, 215, 81, 8, 207, 47, 202, 73, 81, 84,
+    240, 24, 229, 141, 242, 70, 121, 116, 228, 1, 0, 40, 105, 185, 79, 128, 3, 0, 0,
+];
+
+fn gzip_server() -> Server {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/")).respond_with(
+            status_code(200)
+                .insert_header("content-encoding", "gzip")
+                .body(GZIP_BODY),
+        ),
+    );
+
+    server
+}
+
+#[tokio::test]
+async fn gzip_streamed() {
+    utils::setup();
+    let server = gzip_server();
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}');
+    const reader = response.body.getReader();
+    const decoder = new TextDecoder();
+    let body = '';
+
+    while (true) {{
+        const {{ done, value }} = await reader.read();
+
+        if (done) {{
+            break;
+        }}
+
+        body += decoder.decode(value, {{ stream: true }});
+    }}
+
+    return new Response(`${{response.headers.get('content-encoding')}} ${{body === 'Hello, World! '.repeat(64)}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("null true")
+    );
+}
+
+#[tokio::test]
+async fn gzip_buffered() {
+    utils::setup();
+    let server = gzip_server();
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const body = await fetch('{url}').then(res => res.text());
+    return new Response(body.length);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("896")
+    );
+}
+
+#[tokio::test]
+async fn decompress_opt_out() {
+    utils::setup();
+    let server = gzip_server();
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}', {{ decompress: false }});
+    const body = await response.arrayBuffer();
+    return new Response(`${{response.headers.get('content-encoding')}} ${{body.byteLength}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("gzip 43")
+    );
+}
@@ -1,6 +1,6 @@
-use lagon_runtime_http::{Request, RunResult};
-use lagon_runtime_isolate::options::IsolateOptions;
-use std::time::Duration;
+use lagon_runtime_http::{Request, Response, RunResult};
+use lagon_runtime_isolate::{options::IsolateOptions, SourceMap};
+use std::{sync::Arc, time::Duration};
 
 mod utils;
 
@@ -135,6 +135,50 @@ async fn total_timeout_reached() {
     assert_eq!(receiver.recv_async().await.unwrap(), RunResult::Timeout);
 }
 
+#[tokio::test]
+async fn async_timeout_reached() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    for (let i = 0; i < 10; i++) {
+        await new Promise((resolve) => setTimeout(resolve, 100));
+    }
+
+    return new Response('Should not be reached');
+}"
+            .into(),
+        )
+        .total_timeout(Duration::from_secs(5))
+        .async_timeout(Duration::from_millis(300)),
+    );
+    send(Request::default());
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::Timeout);
+}
+
+#[tokio::test]
+async fn async_timeout_not_reached() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    // Concurrent operations are only counted once
+    await Promise.all([1, 2, 3].map(() => new Promise((resolve) => setTimeout(resolve, 200))));
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .async_timeout(Duration::from_millis(500)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
+
 #[tokio::test]
 async fn memory_reached() {
     utils::setup();
@@ -163,6 +207,149 @@ async fn memory_reached() {
     assert_eq!(receiver.recv_async().await.unwrap(), RunResult::MemoryLimit);
 }
 
+#[tokio::test]
+async fn memory_reached_with_host_buffers() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    // Blobs bytes are stored outside of the V8 heap
+    const blobs = [];
+    for (let i = 0; i < 32; i++) {
+        blobs.push(new Blob([new Uint8Array(1024 * 1024).fill(1)]));
+    }
+    await new Promise((resolve) => setTimeout(resolve, 100));
+    return new Response(String(blobs.length));
+}"
+            .into(),
+        )
+        .total_timeout(Duration::from_secs(2))
+        .memory(16),
+    );
+    send(Request::default());
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::MemoryLimit);
+}
+
+#[tokio::test]
+async fn host_buffers_under_memory_limit() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    const blobs = [];
+    for (let i = 0; i < 4; i++) {
+        blobs.push(new Blob([new Uint8Array(1024 * 1024).fill(1)]));
+    }
+    await new Promise((resolve) => setTimeout(resolve, 100));
+    return new Response(String(blobs.length));
+}"
+            .into(),
+        )
+        .memory(16),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("4")
+    );
+}
+
+const BLOBS_CODE: &str = "export async function handler() {
+    const blobs = [];
+    for (let i = 0; i < 32; i++) {
+        blobs.push(new Blob([new Uint8Array(1024 * 1024).fill(1)]));
+    }
+    await new Promise((resolve) => setTimeout(resolve, 100));
+    return new Response(String(blobs.length));
+}";
+
+#[tokio::test]
+async fn memory_overridden_by_request() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate_with_memory(
+        IsolateOptions::new(BLOBS_CODE.into())
+            .total_timeout(Duration::from_secs(2))
+            .memory(16)
+            .max_memory(64),
+    );
+    send(Request::default(), Some(64));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("32")
+    );
+}
+
+#[tokio::test]
+async fn memory_not_overridden() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate_with_memory(
+        IsolateOptions::new(BLOBS_CODE.into())
+            .total_timeout(Duration::from_secs(2))
+            .memory(16)
+            .max_memory(64),
+    );
+    send(Request::default(), None);
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::MemoryLimit);
+}
+
+#[tokio::test]
+async fn memory_override_capped() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate_with_memory(
+        IsolateOptions::new(BLOBS_CODE.into())
+            .total_timeout(Duration::from_secs(2))
+            .memory(16)
+            .max_memory(24),
+    );
+    send(Request::default(), Some(64));
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::MemoryLimit);
+}
+
+// Stored in the V8 heap, ~32MB of doubles
+const HEAP_CODE: &str = "export async function handler() {
+    const arrays = [];
+    for (let i = 0; i < 32; i++) {
+        arrays.push(new Array(128 * 1024).fill(i + 0.5));
+    }
+    return new Response(String(arrays.length));
+}";
+
+#[tokio::test]
+async fn heap_memory_overridden_by_request() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate_with_memory(
+        IsolateOptions::new(HEAP_CODE.into())
+            .total_timeout(Duration::from_secs(2))
+            .memory(16)
+            .max_memory(64),
+    );
+    send(Request::default(), Some(64));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("32")
+    );
+}
+
+#[tokio::test]
+async fn heap_memory_not_overridden() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate_with_memory(
+        IsolateOptions::new(HEAP_CODE.into())
+            .total_timeout(Duration::from_secs(2))
+            .memory(16)
+            .max_memory(64),
+    );
+    send(Request::default(), None);
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::MemoryLimit);
+}
+
 #[tokio::test]
 async fn stacktrace() {
     utils::setup();
@@ -184,3 +371,116 @@ export function handler() {
 
     assert_eq!(receiver.recv_async().await.unwrap(), RunResult::Error("Uncaught TypeError: a is not a function\n  at test (2:12)\n  at first (6:12)\n  at handler (10:25)".into()));
 }
+
+#[tokio::test]
+async fn stacktrace_deep() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "function recurse(depth) {
+    if (depth === 0) {
+        throw new Error('Too deep');
+    }
+
+    return recurse(depth - 1);
+}
+
+export function handler() {
+    return recurse(5);
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(receiver.recv_async().await.unwrap(), RunResult::Error("Uncaught Error: Too deep\n  at recurse (3:15)\n  at recurse (6:12)\n  at recurse (6:12)\n  at recurse (6:12)\n  at recurse (6:12)\n  at recurse (6:12)\n  at handler (10:12)".into()));
+}
+
+#[tokio::test]
+async fn stacktrace_limit() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "function recurse(depth) {
+    if (depth === 0) {
+        throw new Error('Too deep');
+    }
+
+    return recurse(depth - 1);
+}
+
+export function handler() {
+    return recurse(5);
+}"
+            .into(),
+        )
+        .stack_trace_limit(2),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error(
+            "Uncaught Error: Too deep\n  at recurse (3:15)\n  at recurse (6:12)".into()
+        )
+    );
+}
+
+#[tokio::test]
+async fn stacktrace_rethrow() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "function create() {
+    return new Error('Created');
+}
+
+export function handler() {
+    const error = create();
+    throw error;
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error("Uncaught Error: Created\n  at create (2:12)\n  at handler (6:19)".into())
+    );
+}
+
+const MINIFIED_CODE: &str =
+    "function a(){throw new Error(\"Minified\")}export function handler(){return a()}";
+const MINIFIED_SOURCE_MAP: &str = r#"{
+    "version": 3,
+    "sources": ["src/index.ts"],
+    "names": [],
+    "mappings": "mBACQ,uDAIC"
+}"#;
+
+#[tokio::test]
+async fn stacktrace_source_map() {
+    utils::setup();
+    let (send, receiver) =
+        utils::create_isolate(IsolateOptions::new(MINIFIED_CODE.into()).source_map(Some(
+            Arc::new(SourceMap::from_slice(MINIFIED_SOURCE_MAP.as_bytes()).unwrap()),
+        )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error(
+            "Uncaught Error: Minified\n  at a (src/index.ts:2:9)\n  at handler (src/index.ts:6:10)"
+                .into()
+        )
+    );
+}
+
+#[tokio::test]
+async fn stacktrace_without_source_map() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(MINIFIED_CODE.into()));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error("Uncaught Error: Minified\n  at a (1:20)\n  at handler (1:75)".into())
+    );
+}
@@ -1,6 +1,14 @@
 use httptest::{matchers::*, responders::*, Expectation, Server};
-use lagon_runtime_http::{Request, Response, RunResult};
+use lagon_runtime_http::{BodySpilling, Request, Response, RunResult, StreamResult};
 use lagon_runtime_isolate::options::IsolateOptions;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    os::unix::net::UnixListener,
+    time::Duration,
+};
 
 mod utils;
 
@@ -177,6 +185,117 @@ async fn request_body() {
     );
 }
 
+#[tokio::test]
+async fn request_body_content_length() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::headers(contains(("content-length", "6"))),
+            request::headers(not(contains(key("transfer-encoding")))),
+            request::body("Hello!")
+        ])
+        .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const body = await fetch('{url}', {{
+        method: 'POST',
+        body: new Blob(['Hello!'])
+    }}).then(res => res.text());
+
+    return new Response(body);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn request_body_stream_chunked() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::headers(contains(("transfer-encoding", "chunked"))),
+            request::headers(not(contains(key("content-length")))),
+            request::body("Hello world!")
+        ])
+        .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const {{ readable, writable }} = new TransformStream();
+    const writer = writable.getWriter();
+    writer.write(new TextEncoder().encode('Hello'));
+    writer.write(new TextEncoder().encode(' world!'));
+    writer.close();
+
+    const body = await fetch('{url}', {{
+        method: 'POST',
+        body: readable
+    }}).then(res => res.text());
+
+    return new Response(body);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn request_body_stream_error() {
+    utils::setup();
+    let server = Server::run();
+    // The request may or may not have been sent when the body errors
+    server.expect(
+        Expectation::matching(request::method_path("POST", "/"))
+            .times(0..)
+            .respond_with(status_code(200)),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const {{ readable, writable }} = new TransformStream();
+    const writer = writable.getWriter();
+    writer.write(new TextEncoder().encode('Hello'));
+    writer.abort(new Error('Aborted'));
+
+    try {{
+        await fetch('{url}', {{
+            method: 'POST',
+            body: readable
+        }});
+
+        return new Response('Resolved');
+    }} catch {{
+        return new Response('Rejected');
+    }}
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Rejected")
+    );
+}
+
 #[tokio::test]
 async fn response_headers() {
     utils::setup();
@@ -295,6 +414,32 @@ async fn response_array_buffer() {
     );
 }
 
+#[tokio::test]
+async fn response_bytes() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}');
+    const body = await response.bytes();
+
+    return new Response(`${{body instanceof Uint8Array}} ${{body.length}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("true 12")
+    );
+}
+
 #[tokio::test]
 async fn throw_invalid_url() {
     utils::setup();
@@ -533,6 +678,250 @@ export async function handler() {{
     );
 }
 
+#[tokio::test]
+async fn limit_concurrent_fetches_queue() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(3)
+            .respond_with(delay_and_then(
+                Duration::from_millis(100),
+                status_code(200).body("ok"),
+            )),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const start = Date.now();
+    const responses = await Promise.all([
+        fetch('{url}').then(res => res.text()),
+        fetch('{url}').then(res => res.text()),
+        fetch('{url}').then(res => res.text()),
+    ]);
+
+    return new Response(`${{responses.join(',')}} ${{Date.now() - start >= 300}}`);
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .max_concurrent_fetches(1, 5),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("ok,ok,ok true")
+    );
+}
+
+#[tokio::test]
+async fn limit_concurrent_fetches_queue_overflow() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(2)
+            .respond_with(delay_and_then(
+                Duration::from_millis(100),
+                status_code(200).body("ok"),
+            )),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const responses = await Promise.allSettled([
+        fetch('{url}').then(res => res.text()),
+        fetch('{url}').then(res => res.text()),
+        fetch('{url}').then(res => res.text()),
+    ]);
+
+    return new Response(responses.map(result => result.status === 'fulfilled'
+        ? result.value
+        : `${{result.reason.name}}: ${{result.reason.message}}`
+    ).join(','));
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .max_concurrent_fetches(1, 1),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("ok,ok,TypeError: Too many concurrent fetch() calls")
+    );
+}
+
+#[tokio::test]
+async fn pipe_response_body() {
+    const BODY_SIZE: usize = 16 * 1024 * 1024;
+
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/")).respond_with(
+            status_code(200)
+                .append_header("content-type", "application/octet-stream")
+                .body(vec![b'a'; BODY_SIZE]),
+        ),
+    );
+    let url = server.url("/");
+
+    let (statistics_sender, statistics_receiver) = flume::unbounded();
+    let mut options = IsolateOptions::new(format!(
+        "export function handler() {{
+    return fetch('{url}');
+}}"
+    ))
+    .total_timeout(Duration::from_secs(5))
+    .on_statistics_callback(Box::new(move |_, statistics| {
+        statistics_sender.send(statistics.memory_usage).unwrap();
+    }));
+    options.statistics_interval = Duration::ZERO;
+
+    let (send, receiver) = utils::create_isolate(options);
+    send(Request::default());
+
+    let mut response = None;
+    let mut chunks = 0;
+    let mut body_size = 0;
+
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(start)) => response = Some(start),
+            RunResult::Stream(StreamResult::Data(data)) => {
+                chunks += 1;
+                body_size += data.len();
+            }
+            RunResult::Stream(StreamResult::Done(_)) => break,
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    let headers = response.unwrap().headers.unwrap();
+
+    assert_eq!(
+        headers.get("content-type"),
+        Some(&vec!["application/octet-stream".into()])
+    );
+    assert_eq!(
+        headers.get("content-length"),
+        Some(&vec![BODY_SIZE.to_string()])
+    );
+    assert!(chunks > 1);
+    assert_eq!(body_size, BODY_SIZE);
+    // The body never went through the V8 heap
+    assert!(statistics_receiver.recv_async().await.unwrap() < BODY_SIZE);
+}
+
+#[tokio::test]
+async fn response_trailers() {
+    utils::setup();
+    // httptest can't send trailers, so we write the chunked response ourselves
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+
+        // Wait for the end of the request headers
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+Transfer-Encoding: chunked\r\n\
+Trailer: grpc-status, grpc-message\r\n\
+\r\n\
+5\r\nHello\r\n\
+0\r\n\
+grpc-status: 0\r\n\
+grpc-message: OK\r\n\
+\r\n",
+            )
+            .unwrap();
+    });
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}');
+    const body = await response.text();
+    const trailers = await response.trailers;
+
+    return new Response(`${{body}} ${{trailers.get('grpc-status')}} ${{trailers.get('grpc-message')}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello 0 OK")
+    );
+}
+
+#[tokio::test]
+async fn response_without_trailers() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}');
+    const body = await response.arrayBuffer();
+    const trailers = await response.trailers;
+
+    return new Response(`${{body.byteLength}} ${{[...trailers.keys()].length}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("12 0")
+    );
+}
+
+#[tokio::test]
+async fn response_timing() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello, World")),
+    );
+    // Resolve a hostname instead of connecting to the IP directly
+    let url = format!("http://localhost:{}/", server.addr().port());
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const response = await fetch('{url}');
+    const {{ dns, connect, tls, ttfb, total }} = response.timing;
+    const monotonic = 0 < dns && dns <= connect && connect <= tls && tls <= ttfb && ttfb <= total;
+
+    return new Response(`${{monotonic}} ${{new Response().timing}}`);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("true undefined")
+    );
+}
+
 #[tokio::test]
 async fn fetch_https() {
     utils::setup();
@@ -550,3 +939,292 @@ async fn fetch_https() {
         Response::from("200")
     );
 }
+
+#[tokio::test]
+async fn integrity() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    // Only the strongest algorithm is checked
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const body = await fetch('{url}', {{
+        integrity: 'sha256-invalid sha512-RVRtTXFAfoLs2jHrpb90tlvAkrBDaiQJprYVwfeP2y09o3F1jwemW10rPuj6nqDHct0e/4hMTHfUKQF3sALM3A=='
+    }}).then(res => res.text());
+    return new Response(body);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn integrity_mismatch() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body("Hello, World")),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(format!(
+        "export async function handler() {{
+    const body = await fetch('{url}', {{
+        integrity: 'sha256-A2daxT/5zRU1zMffzfosRYxSGDcfQY3BNvLRmsH76KU= sha384-invalid'
+    }}).then(res => res.text());
+    return new Response(body);
+}}"
+    )));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error(
+            "Uncaught TypeError: Failed to match the sha384 integrity of the response".into()
+        )
+    );
+}
+
+#[tokio::test]
+async fn fetch_unix_socket() {
+    utils::setup();
+    let path = std::env::temp_dir().join(format!("lagon-fetch-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+
+        let mut line = String::new();
+
+        // Wait for the end of the request headers
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+
+        let body = format!("Hello from {}", request_line.trim());
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+    });
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    const body = await fetch('http://SideCar/hello').then(res => res.text());
+    return new Response(body);
+}"
+            .into(),
+        )
+        .unix_sockets(HashMap::from([("sidecar".into(), path.clone())])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello from GET /hello HTTP/1.1")
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn fetch_unix_socket_https() {
+    utils::setup();
+    let path = std::env::temp_dir().join(format!("lagon-fetch-https-{}.sock", std::process::id()));
+
+    for url in ["https://sidecar/hello", "https+unix://sidecar/hello"] {
+        let (send, receiver) = utils::create_isolate(
+            IsolateOptions::new(format!(
+                "export async function handler() {{
+    await fetch('{url}');
+    return new Response('ok');
+}}"
+            ))
+            .unix_sockets(HashMap::from([("sidecar".into(), path.clone())])),
+        );
+        send(Request::default());
+
+        assert_eq!(
+            receiver.recv_async().await.unwrap(),
+            RunResult::Error(
+                "Uncaught Error: Only http URLs can be sent over a Unix socket".into()
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn fetch_timeout() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/")).respond_with(delay_and_then(
+            Duration::from_millis(500),
+            status_code(200).body("Hello, World"),
+        )),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    try {{
+        await fetch('{url}');
+        return new Response('ok');
+    }} catch (error) {{
+        return new Response(`${{error.name}}: ${{error.message}}`);
+    }}
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .fetch_timeout(Duration::from_millis(100)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TimeoutError: fetch() timed out")
+    );
+}
+
+#[tokio::test]
+async fn fetch_timeout_body() {
+    utils::setup();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+
+        // The headers are sent right away, but the body is stuck
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nHello")
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+    });
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const response = await fetch('{url}');
+
+    try {{
+        await response.text();
+        return new Response('ok');
+    }} catch (error) {{
+        return new Response(`${{response.status}} ${{error.name}}`);
+    }}
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .fetch_timeout(Duration::from_millis(100)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 TimeoutError")
+    );
+}
+
+#[tokio::test]
+async fn fetch_timeout_with_signal() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/")).respond_with(delay_and_then(
+            Duration::from_millis(200),
+            status_code(200).body("Hello, World"),
+        )),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const controller = new AbortController();
+    const body = await fetch('{url}', {{ signal: controller.signal }}).then(res => res.text());
+
+    return new Response(body);
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .fetch_timeout(Duration::from_millis(100)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn large_response_body_spilled() {
+    utils::setup();
+    let dir = env::temp_dir().join("lagon-fetch-spilling-test");
+    fs::remove_dir_all(&dir).unwrap_or(());
+    fs::create_dir_all(&dir).unwrap();
+
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .respond_with(status_code(200).body(vec![b'a'; 64 * 1024])),
+    );
+    let url = server.url("/");
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const response = await fetch('{url}');
+    // Give the test time to check the spilled body
+    await new Promise(resolve => setTimeout(resolve, 200));
+    const body = await response.text();
+
+    return new Response(`${{body.length}} ${{body.slice(0, 5)}}`);
+}}"
+        ))
+        .total_timeout(Duration::from_secs(5))
+        .body_spilling(BodySpilling::new(1024).dir(dir.clone())),
+    );
+    send(Request::default());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("65536 aaaaa")
+    );
+
+    // The file is removed once the body has been read
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
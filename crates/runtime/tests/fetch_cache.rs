@@ -0,0 +1,202 @@
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::{options::IsolateOptions, FetchCache};
+use std::sync::Arc;
+
+mod utils;
+
+fn cacheable_server(times: usize) -> Server {
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(times)
+            .respond_with(
+                status_code(200)
+                    .insert_header("cache-control", "max-age=60")
+                    .body("Hello, World"),
+            ),
+    );
+
+    server
+}
+
+// Fetch the URL with each cache mode in turn, returning the statuses and bodies
+fn fetch_handler(url: String, cache_modes: &[&str]) -> String {
+    let cache_modes = cache_modes
+        .iter()
+        .map(|cache_mode| format!("'{cache_mode}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "export async function handler() {{
+    const results = [];
+
+    for (const cache of [{cache_modes}]) {{
+        const response = await fetch('{url}', {{ cache }});
+        results.push(`${{response.status}} ${{await response.text()}}`);
+    }}
+
+    return new Response(results.join('\\n'));
+}}"
+    )
+}
+
+#[tokio::test]
+async fn no_store() {
+    utils::setup();
+    // The first request is stored, but the no-store one bypasses the cache
+    let server = cacheable_server(2);
+    let url = server.url("/").to_string();
+    let fetch_cache = Arc::new(FetchCache::new(10));
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(fetch_handler(url, &["default", "no-store", "default"]))
+            .fetch_cache(Arc::clone(&fetch_cache)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 Hello, World\n200 Hello, World\n200 Hello, World")
+    );
+    assert_eq!(fetch_cache.len(), 1);
+}
+
+#[tokio::test]
+async fn no_store_not_stored() {
+    utils::setup();
+    let server = cacheable_server(1);
+    let url = server.url("/").to_string();
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(fetch_handler(url, &["no-store", "only-if-cached"]))
+            .fetch_cache(Arc::new(FetchCache::new(10))),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 Hello, World\n504 ")
+    );
+}
+
+#[tokio::test]
+async fn only_if_cached() {
+    utils::setup();
+    let server = cacheable_server(1);
+    let url = server.url("/").to_string();
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(fetch_handler(
+            url,
+            &["only-if-cached", "default", "only-if-cached"],
+        ))
+        .fetch_cache(Arc::new(FetchCache::new(10))),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("504 \n200 Hello, World\n200 Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn uncacheable_response() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(2)
+            .respond_with(
+                status_code(200)
+                    .insert_header("cache-control", "no-store")
+                    .body("Hello, World"),
+            ),
+    );
+    let url = server.url("/").to_string();
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(fetch_handler(url, &["default", "force-cache"]))
+            .fetch_cache(Arc::new(FetchCache::new(10))),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 Hello, World\n200 Hello, World")
+    );
+}
+
+#[tokio::test]
+async fn vary() {
+    utils::setup();
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(request::method_path("GET", "/"))
+            .times(2)
+            .respond_with(
+                status_code(200)
+                    .insert_header("cache-control", "max-age=60")
+                    .insert_header("vary", "Accept-Language")
+                    .body("Hello, World"),
+            ),
+    );
+    let url = server.url("/").to_string();
+    let fetch_cache = Arc::new(FetchCache::new(10));
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const results = [];
+
+    for (const language of ['en', 'fr', 'en']) {{
+        const response = await fetch('{url}', {{ headers: {{ 'accept-language': language }} }});
+        results.push(response.status);
+    }}
+
+    return new Response(results.join(' '));
+}}"
+        ))
+        .fetch_cache(Arc::clone(&fetch_cache)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 200 200")
+    );
+    assert_eq!(fetch_cache.len(), 2);
+}
+
+#[tokio::test]
+async fn credentialed_not_stored() {
+    utils::setup();
+    let server = cacheable_server(2);
+    let url = server.url("/").to_string();
+    let fetch_cache = Arc::new(FetchCache::new(10));
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(format!(
+            "export async function handler() {{
+    const results = [];
+
+    for (let i = 0; i < 2; i++) {{
+        const response = await fetch('{url}', {{ headers: {{ authorization: 'Bearer token' }} }});
+        results.push(response.status);
+    }}
+
+    return new Response(results.join(' '));
+}}"
+        ))
+        .fetch_cache(Arc::clone(&fetch_cache)),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("200 200")
+    );
+    assert!(fetch_cache.is_empty());
+}
@@ -0,0 +1,100 @@
+use httptest::{matchers::*, responders::*, Expectation, Server};
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::{options::IsolateOptions, FetchRecorder};
+use std::{env, path::PathBuf, sync::Arc};
+
+mod utils;
+
+fn records_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("lagon-fetch-recorder-{name}.json"))
+}
+
+fn handler(url: &str) -> String {
+    format!(
+        "export async function handler() {{
+    const first = await fetch('{url}', {{
+        method: 'POST',
+        body: 'first',
+    }}).then(res => res.text());
+    const second = await fetch('{url}', {{
+        method: 'POST',
+        body: 'second',
+    }});
+
+    return new Response(`${{first}} ${{await second.text()}} ${{second.status}} ${{second.headers.get('x-lagon')}}`);
+}}"
+    )
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    utils::setup();
+    let path = records_path("replay");
+    let server = Server::run();
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::body("first"),
+        ])
+        .respond_with(status_code(200).body("Hello")),
+    );
+    server.expect(
+        Expectation::matching(all_of![
+            request::method_path("POST", "/"),
+            request::body("second"),
+        ])
+        .respond_with(
+            status_code(201)
+                .insert_header("x-lagon", "recorded")
+                .body("World"),
+        ),
+    );
+    let url = server.url("/").to_string();
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(handler(&url)).fetch_recorder(Arc::new(FetchRecorder::record(&path))),
+    );
+    send(Request::default());
+
+    let recorded = receiver.recv_async().await.unwrap().as_response();
+    assert_eq!(recorded, Response::from("Hello World 201 recorded"));
+
+    // The upstream isn't reachable anymore
+    drop(server);
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(handler(&url))
+            .fetch_recorder(Arc::new(FetchRecorder::replay(&path).unwrap())),
+    );
+    send(Request::default());
+
+    assert_eq!(receiver.recv_async().await.unwrap().as_response(), recorded);
+}
+
+#[tokio::test]
+async fn replay_miss() {
+    utils::setup();
+    let path = records_path("miss");
+    std::fs::write(&path, "[]").unwrap();
+
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    try {
+        await fetch('http://localhost:1/not-recorded');
+        return new Response('Fetched');
+    } catch (error) {
+        return new Response(error.message);
+    }
+}"
+            .into(),
+        )
+        .fetch_recorder(Arc::new(FetchRecorder::replay(&path).unwrap())),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("No recorded response for GET http://localhost:1/not-recorded")
+    );
+}
@@ -0,0 +1,108 @@
+use lagon_runtime_http::{Request, Response, RunResult, StreamResult};
+use lagon_runtime_isolate::options::IsolateOptions;
+
+mod utils;
+
+async fn streamed_response(receiver: &flume::Receiver<RunResult>) -> (Response, Vec<u8>) {
+    let mut response = None;
+    let mut body = Vec::new();
+
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(start)) => response = Some(start),
+            RunResult::Stream(StreamResult::Data(data)) => body.extend_from_slice(&data),
+            RunResult::Stream(StreamResult::Done(_)) => break,
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    (response.unwrap(), body)
+}
+
+fn trailers_frame(trailers: &str) -> Vec<u8> {
+    let mut frame = vec![0x80];
+    frame.extend_from_slice(&(trailers.len() as u32).to_be_bytes());
+    frame.extend_from_slice(trailers.as_bytes());
+    frame
+}
+
+#[tokio::test]
+async fn unary_response() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const response = new GrpcWebResponse();
+    response.sendMessage(new Uint8Array([0x08, 0x96, 0x01]));
+    response.sendStatus(0);
+
+    return response;
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    let (response, body) = streamed_response(&receiver).await;
+
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.headers.unwrap()["content-type"],
+        vec!["application/grpc-web+proto".to_string()]
+    );
+
+    let mut expected = vec![0x00, 0x00, 0x00, 0x00, 0x03, 0x08, 0x96, 0x01];
+    expected.extend_from_slice(&trailers_frame("grpc-status:0\r\n"));
+
+    assert_eq!(body, expected);
+}
+
+#[tokio::test]
+async fn error_status() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const response = new GrpcWebResponse({ headers: { 'content-type': 'application/grpc-web+json' } });
+    response.sendStatus(13, 'Internal 100% error é', { 'X-Request-Id': 'abc' });
+
+    return response;
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    let (response, body) = streamed_response(&receiver).await;
+
+    assert_eq!(
+        response.headers.unwrap()["content-type"],
+        vec!["application/grpc-web+json".to_string()]
+    );
+    // grpc-message is percent-encoded
+    assert_eq!(
+        body,
+        trailers_frame(
+            "grpc-status:13\r\ngrpc-message:Internal 100%25 error %C3%A9\r\nx-request-id:abc\r\n"
+        )
+    );
+}
+
+#[tokio::test]
+async fn invalid_content_type() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    try {
+        new GrpcWebResponse({ headers: { 'content-type': 'application/grpc-web-text' } });
+    } catch (error) {
+        return new Response(error.message);
+    }
+
+    return new Response('Not thrown');
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Invalid gRPC-Web content type: application/grpc-web-text")
+    );
+}
@@ -0,0 +1,119 @@
+use httptest::bytes::Bytes;
+use lagon_runtime_http::{Method, Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::collections::HashMap;
+
+mod utils;
+
+const HANDLER: &str = "export async function handler(request) {
+    try {
+        const body = await request.json();
+        return new Response(JSON.stringify(body));
+    } catch (error) {
+        return new Response(`${error.name}: ${error.message}`);
+    }
+}";
+
+fn json_request(body: impl Into<Bytes>) -> Request {
+    Request {
+        body: body.into(),
+        headers: None,
+        method: Method::POST,
+        url: "".into(),
+        spilled_body: None,
+    }
+}
+
+#[tokio::test]
+async fn request_json() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(HANDLER.into()));
+    send(json_request(r#"{"hello":["world",{"nested":"]]}"}]}"#));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from(r#"{"hello":["world",{"nested":"]]}"}]}"#)
+    );
+}
+
+#[tokio::test]
+async fn request_json_invalid() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(HANDLER.into()));
+    send(json_request("{\"hello\":"));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("SyntaxError: Unexpected end of JSON input")
+    );
+}
+
+#[tokio::test]
+async fn request_json_max_size() {
+    utils::setup();
+    let (send, receiver) =
+        utils::create_isolate(IsolateOptions::new(HANDLER.into()).json_max_size(16));
+    send(json_request(format!("\"{}\"", "a".repeat(32))));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: JSON body exceeds the maximum size of 16 bytes")
+    );
+}
+
+#[tokio::test]
+async fn request_json_max_size_content_length() {
+    utils::setup();
+    let (send, receiver) =
+        utils::create_isolate(IsolateOptions::new(HANDLER.into()).json_max_size(16));
+    send(Request {
+        headers: Some(HashMap::from([(
+            "content-length".into(),
+            Vec::from(["1024".into()]),
+        )])),
+        ..json_request("{}")
+    });
+
+    // Rejected before reading the body
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: JSON body exceeds the maximum size of 16 bytes")
+    );
+}
+
+#[tokio::test]
+async fn request_json_max_depth() {
+    utils::setup();
+    let (send, receiver) =
+        utils::create_isolate(IsolateOptions::new(HANDLER.into()).json_max_depth(4));
+    send(json_request(format!("{}{}", "[".repeat(5), "]".repeat(5))));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: JSON body exceeds the maximum nesting depth of 4")
+    );
+
+    // Brackets inside strings are not counted
+    send(json_request(r#"[[[["[[[[{{{{"]]]]"#));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from(r#"[[[["[[[[{{{{"]]]]"#)
+    );
+}
+
+#[tokio::test]
+async fn request_json_deeply_nested() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(HANDLER.into()));
+    send(json_request(format!(
+        "{}{}",
+        "{\"a\":".repeat(100_000),
+        "}".repeat(100_000)
+    )));
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("TypeError: JSON body exceeds the maximum nesting depth of 128")
+    );
+}
@@ -0,0 +1,79 @@
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::{
+    options::IsolateOptions, DisposeReason, Isolate, IsolateEvent, IsolateLifecycleEvent,
+    IsolateRequest,
+};
+use tokio::runtime::Handle;
+
+mod utils;
+
+#[tokio::test]
+async fn lifecycle_events() {
+    utils::setup();
+    let (lifecycle_sender, lifecycle_receiver) = flume::unbounded();
+    let (request_tx, request_rx) = flume::unbounded();
+    let (sender, receiver) = flume::unbounded();
+
+    let options = IsolateOptions::new(
+        "export function handler() {
+    return new Response('Hello world');
+}"
+        .into(),
+    )
+    .metadata(Some(("deployment".into(), "function".into())))
+    .lifecycle_sender(lifecycle_sender);
+
+    let handle = Handle::current();
+    std::thread::spawn(move || {
+        handle.block_on(async move {
+            let mut isolate = Isolate::new(options, request_rx);
+            isolate.evaluate();
+            isolate.run_event_loop().await;
+        })
+    });
+
+    request_tx
+        .send(IsolateEvent::Request(IsolateRequest {
+            request: Request::default(),
+            sender,
+            cancellation_token: None,
+            memory: None,
+            max_body_size: None,
+        }))
+        .unwrap();
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+
+    request_tx
+        .send(IsolateEvent::Terminate("Terminated".into()))
+        .unwrap();
+
+    let mut events = Vec::new();
+
+    while let Ok((event, metadata)) = lifecycle_receiver.recv_async().await {
+        assert_eq!(metadata, Some(("deployment".into(), "function".into())));
+        events.push(event);
+    }
+
+    assert!(matches!(events[0], IsolateLifecycleEvent::Created { .. }));
+    assert_eq!(
+        events[1..3],
+        [
+            IsolateLifecycleEvent::Warmed,
+            IsolateLifecycleEvent::RequestStarted,
+        ]
+    );
+    assert!(matches!(
+        events[3],
+        IsolateLifecycleEvent::RequestFinished { .. }
+    ));
+    assert_eq!(
+        events[4..],
+        [IsolateLifecycleEvent::Disposed {
+            reason: DisposeReason::Error("Terminated".into())
+        }]
+    );
+}
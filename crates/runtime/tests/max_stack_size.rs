@@ -0,0 +1,48 @@
+use lagon_runtime_http::Request;
+use lagon_runtime_isolate::options::IsolateOptions;
+
+mod utils;
+
+// Set for the whole process, so it gets its own test binary
+const MAX_STACK_SIZE: usize = 128;
+// Each frame takes at least 32 bytes, while the default ~1 MB stack fits
+// way more frames than that
+const MAX_DEPTH: usize = MAX_STACK_SIZE * 1024 / 32;
+
+const RECURSION_CODE: &str = "export function handler() {
+    let depth = 0;
+    const recurse = () => {
+        depth++;
+        recurse();
+    };
+
+    try {
+        recurse();
+        return new Response('ok');
+    } catch (error) {
+        return new Response(`${error instanceof RangeError} ${error.message} ${depth}`);
+    }
+}";
+
+async fn recursion_depth() -> usize {
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(RECURSION_CODE.into()));
+    send(Request::default());
+
+    let response = receiver.recv_async().await.unwrap().as_response();
+    let body = String::from_utf8(response.body.to_vec()).unwrap();
+    let depth = body
+        .strip_prefix("true Maximum call stack size exceeded ")
+        .unwrap_or_else(|| panic!("Unexpected response: {body}"));
+
+    depth.parse().unwrap()
+}
+
+#[tokio::test]
+async fn max_stack_size() {
+    utils::setup_max_stack_size(MAX_STACK_SIZE);
+
+    let depth = recursion_depth().await;
+
+    assert!(depth > 0);
+    assert!(depth < MAX_DEPTH);
+}
@@ -0,0 +1,217 @@
+use lagon_runtime_http::{Request, Response, RunResult};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::collections::HashMap;
+
+mod utils;
+
+#[tokio::test]
+async fn import_module() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { hello } from './hello.js';
+
+export function handler() {
+    return new Response(hello('world'));
+}"
+            .into(),
+        )
+        .modules(HashMap::from([(
+            "hello.js".into(),
+            "export function hello(name) {
+    return `Hello ${name}`;
+}"
+            .into(),
+        )])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
+
+#[tokio::test]
+async fn import_nested_modules() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { hello } from './lib/hello.js';
+
+export function handler() {
+    return new Response(hello());
+}"
+            .into(),
+        )
+        .modules(HashMap::from([
+            (
+                "lib/hello.js".into(),
+                "import { name } from '../name.js';
+
+export function hello() {
+    return `Hello ${name}`;
+}"
+                .into(),
+            ),
+            ("name.js".into(), "export const name = 'world';".into()),
+        ])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
+
+#[tokio::test]
+async fn import_circular_modules() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { a } from './a.js';
+
+export function handler() {
+    return new Response(a());
+}"
+            .into(),
+        )
+        .modules(HashMap::from([
+            (
+                "a.js".into(),
+                "import { b } from './b.js';
+
+export function a() {
+    return 'a' + b();
+}
+
+export function name() {
+    return 'a';
+}"
+                .into(),
+            ),
+            (
+                "b.js".into(),
+                "import { name } from './a.js';
+
+export function b() {
+    return name() + 'b';
+}"
+                .into(),
+            ),
+        ])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("aab")
+    );
+}
+
+#[tokio::test]
+async fn import_meta_url() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { url } from './lib/url.js';
+
+export function handler() {
+    return new Response(`${url} ${import.meta.url}`);
+}"
+            .into(),
+        )
+        .modules(HashMap::from([(
+            "lib/url.js".into(),
+            "export const url = import.meta.url;".into(),
+        )])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("file:///lib/url.js file:///index.js")
+    );
+}
+
+#[tokio::test]
+async fn import_unknown_module() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { hello } from './unknown.js';
+
+export function handler() {
+    return new Response(hello());
+}"
+            .into(),
+        )
+        .modules(HashMap::from([(
+            "hello.js".into(),
+            "export function hello() {}".into(),
+        )])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error("Uncaught Error: Can't find module './unknown.js'".into())
+    );
+}
+
+#[tokio::test]
+async fn module_error_stacktrace() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { fail } from './fail.js';
+export function handler() {
+    return fail();
+}"
+            .into(),
+        )
+        .modules(HashMap::from([(
+            "fail.js".into(),
+            "export function fail() {
+    throw new Error('Oops');
+}"
+            .into(),
+        )])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error(
+            "Uncaught Error: Oops\n  at fail (fail.js:2:11)\n  at handler (3:12)".into()
+        )
+    );
+}
+
+#[tokio::test]
+async fn import_meta_env() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "import { env } from './env.js';
+
+export function handler() {
+    return new Response(`${import.meta.url} ${import.meta.env.TEST} ${env.SECRET} ${import.meta.env.UNKNOWN}`);
+}"
+            .into(),
+        )
+        .environment_variables(HashMap::from([("TEST".into(), "hello".into())]))
+        .secrets(HashMap::from([("SECRET".into(), "world".into())]))
+        .modules(HashMap::from([(
+            "env.js".into(),
+            "export const env = import.meta.env;".into(),
+        )])),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("file:///index.js hello world undefined")
+    );
+}
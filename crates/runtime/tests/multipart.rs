@@ -0,0 +1,99 @@
+use lagon_runtime_http::{Request, RunResult, StreamResult};
+use lagon_runtime_isolate::options::IsolateOptions;
+
+mod utils;
+
+#[tokio::test]
+async fn two_parts() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    return new MultipartResponse([
+        {
+            headers: { 'content-type': 'text/plain', 'content-range': 'bytes 0-4/11' },
+            body: new ReadableStream({
+                start(controller) {
+                    controller.enqueue(new TextEncoder().encode('He'));
+                    controller.enqueue(new TextEncoder().encode('llo'));
+                    controller.close();
+                },
+            }),
+        },
+        {
+            headers: { 'content-type': 'text/plain', 'content-range': 'bytes 6-10/11' },
+            body: 'World',
+        },
+    ], { status: 206, subtype: 'byteranges' });
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    let mut response = None;
+    let mut body = Vec::new();
+
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(start)) => response = Some(start),
+            RunResult::Stream(StreamResult::Data(data)) => body.extend_from_slice(&data),
+            RunResult::Stream(StreamResult::Done(_)) => break,
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    let response = response.unwrap();
+    let content_type = &response.headers.unwrap()["content-type"][0];
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+
+    assert_eq!(response.status, 206);
+    assert!(boundary.starts_with("lagon-"));
+    assert_eq!(boundary.len(), 38);
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        format!(
+            "--{boundary}\r\ncontent-range: bytes 0-4/11\r\ncontent-type: text/plain\r\n\r\nHello\r\n--{boundary}\r\ncontent-range: bytes 6-10/11\r\ncontent-type: text/plain\r\n\r\nWorld\r\n--{boundary}--\r\n"
+        )
+    );
+}
+
+#[tokio::test]
+async fn boundary_in_part() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    let boundary;
+    const response = new MultipartResponse([
+        {
+            body: new ReadableStream({
+                pull(controller) {
+                    controller.enqueue(new TextEncoder().encode(`--${boundary}`));
+                    controller.close();
+                },
+            }),
+        },
+    ]);
+
+    boundary = response.boundary;
+    return response;
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    // The part is only read while the response is streamed
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(_) | StreamResult::Data(_)) => {}
+            RunResult::Error(error) => {
+                assert!(
+                    error.starts_with("Uncaught TypeError: Multipart part contains the boundary"),
+                    "{error}"
+                );
+                break;
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
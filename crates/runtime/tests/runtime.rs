@@ -87,6 +87,7 @@ async fn get_body_streaming() {
         )])),
         method: Method::GET,
         url: "".into(),
+        spilled_body: None,
     });
 
     assert_eq!(
@@ -102,6 +103,7 @@ async fn get_body_streaming() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 }
@@ -123,6 +125,7 @@ async fn get_body() {
         )])),
         method: Method::GET,
         url: "".into(),
+        spilled_body: None,
     });
 
     assert_eq!(
@@ -131,6 +134,91 @@ async fn get_body() {
     );
 }
 
+#[tokio::test]
+async fn get_body_progress() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export async function handler(request) {
+    const progress = [];
+    request.onProgress(({ loaded, total }) => progress.push(`${loaded}/${total}`), { granularity: 100 });
+
+    const body = await request.arrayBuffer();
+
+    return new Response(`${body.byteLength} ${progress.join(',')}`);
+}"
+        .into(),
+    ));
+    send(Request {
+        body: Bytes::from(vec![b'a'; 250]),
+        headers: Some(HashMap::from([(
+            "content-length".into(),
+            Vec::from(["250".into()]),
+        )])),
+        method: Method::POST,
+        url: "".into(),
+        spilled_body: None,
+    });
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("250 100/250,200/250,250/250")
+    );
+}
+
+#[tokio::test]
+async fn get_body_progress_max_size() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export async function handler(request) {
+    const progress = [];
+    request.onProgress(({ loaded }) => progress.push(loaded), { granularity: 100, maxSize: 150 });
+
+    try {
+        await request.text();
+    } catch (error) {
+        return new Response(`${error.name}: ${error.message} ${progress.join(',')}`);
+    }
+}"
+        .into(),
+    ));
+    send(Request {
+        body: Bytes::from(vec![b'a'; 250]),
+        headers: None,
+        method: Method::POST,
+        url: "".into(),
+        spilled_body: None,
+    });
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("RangeError: Request body exceeds the size limit 100")
+    );
+}
+
+#[tokio::test]
+async fn get_body_bytes() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export async function handler(request) {
+    const body = await request.bytes();
+    return new Response(`${body instanceof Uint8Array} ${body.join(',')}`);
+}"
+        .into(),
+    ));
+    send(Request {
+        body: Bytes::from("Hello"),
+        headers: None,
+        method: Method::POST,
+        url: "".into(),
+        spilled_body: None,
+    });
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("true 72,101,108,108,111")
+    );
+}
+
 #[tokio::test]
 async fn get_input() {
     utils::setup();
@@ -148,6 +236,7 @@ async fn get_input() {
         )])),
         method: Method::GET,
         url: "https://hello.world".into(),
+        spilled_body: None,
     });
 
     assert_eq!(
@@ -173,6 +262,7 @@ async fn get_method() {
         )])),
         method: Method::POST,
         url: "".into(),
+        spilled_body: None,
     });
 
     assert_eq!(
@@ -199,6 +289,7 @@ async fn get_headers() {
         headers: Some(headers),
         method: Method::POST,
         url: "".into(),
+        spilled_body: None,
     });
 
     assert_eq!(
@@ -234,6 +325,7 @@ async fn return_headers() {
             body: "Hello world".into(),
             headers: Some(headers),
             status: 200,
+            status_text: None,
         }
     );
 }
@@ -265,6 +357,7 @@ async fn return_headers_from_headers_api() {
             body: "Hello world".into(),
             headers: Some(headers),
             status: 200,
+            status_text: None,
         }
     );
 }
@@ -291,6 +384,7 @@ async fn return_status() {
                 Vec::from(["text/plain;charset=UTF-8".into()]),
             )])),
             status: 302,
+            status_text: None,
         }
     );
 }
@@ -340,6 +434,332 @@ async fn console_log() {
     );
 }
 
+#[tokio::test]
+async fn console_log_redact_secrets() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    console.log(`Token: ${process.env.TOKEN}`);
+    console.log(JSON.stringify(process.env));
+
+    return new Response(process.env.TOKEN);
+}"
+            .into(),
+        )
+        .secrets(
+            vec![("TOKEN".into(), "s3cr3t".into())]
+                .into_iter()
+                .collect(),
+        )
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("log".into(), "Token: ***".into(), None)
+    );
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("log".into(), "{\"TOKEN\":\"***\"}".into(), None)
+    );
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("s3cr3t")
+    );
+}
+
+#[tokio::test]
+async fn console_time() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    console.time('sleep');
+    await new Promise((resolve) => setTimeout(resolve, 100));
+    console.timeLog('sleep', 'halfway');
+    await new Promise((resolve) => setTimeout(resolve, 100));
+    console.timeEnd('sleep');
+
+    // Not ended, so it shouldn't exist in the next request
+    console.time('leftover');
+    console.timeEnd('unknown');
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+
+    let parse_elapsed = |message: &str, suffix: &str| -> f64 {
+        message
+            .strip_prefix("sleep: ")
+            .and_then(|message| message.strip_suffix(suffix))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    send(Request::default());
+
+    let (level, message, _) = logs_receiver.recv_async().await.unwrap();
+    assert_eq!(level, "info");
+    let elapsed = parse_elapsed(&message, "ms halfway");
+    assert!((100.0..200.0).contains(&elapsed), "{elapsed}");
+
+    let (level, message, _) = logs_receiver.recv_async().await.unwrap();
+    assert_eq!(level, "info");
+    let elapsed = parse_elapsed(&message, "ms");
+    assert!(elapsed >= 200.0, "{elapsed}");
+
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("warn".into(), "Timer 'unknown' does not exist".into(), None)
+    );
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+
+    // Timers are cleared between requests
+    send(Request::default());
+
+    logs_receiver.recv_async().await.unwrap();
+    logs_receiver.recv_async().await.unwrap();
+
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("warn".into(), "Timer 'unknown' does not exist".into(), None)
+    );
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
+
+#[tokio::test]
+async fn console_assert() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    console.assert(true, 'Not logged');
+    console.assert(false, 'Expected %d', 1);
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("error".into(), "Assertion failed: Expected 1".into(), None)
+    );
+    assert!(logs_receiver.is_empty());
+}
+
+#[tokio::test]
+async fn console_count() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    console.count();
+    console.count();
+    console.count('other');
+    console.countReset();
+    console.count();
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+
+    // Counters are reset between requests
+    for _ in 0..2 {
+        send(Request::default());
+
+        assert_eq!(
+            receiver.recv_async().await.unwrap().as_response(),
+            Response::from("Hello world")
+        );
+
+        for message in ["default: 1", "default: 2", "other: 1", "default: 1"] {
+            assert_eq!(
+                logs_receiver.recv_async().await.unwrap(),
+                ("info".into(), message.into(), None)
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn console_inspect() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    const circular = { a: 1 };
+    circular.self = circular;
+
+    console.log({ a: { b: { c: { d: 1 } } } });
+    console.log(circular);
+    console.log(new Uint8Array([1, 2, 3]));
+    console.log(new Map([['key', [1, 'two']]]));
+    console.log('Hello %o', { 'hello-world': true });
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+
+    for message in [
+        "{ a: { b: { c: [Object] } } }",
+        "{ a: 1, self: [Circular] }",
+        "Uint8Array(3) [ 1, 2, 3 ]",
+        "Map(1) { 'key' => [ 1, 'two' ] }",
+        "Hello { 'hello-world': true }",
+    ] {
+        assert_eq!(
+            logs_receiver.recv_async().await.unwrap(),
+            ("log".into(), message.into(), None)
+        );
+    }
+}
+
+#[tokio::test]
+async fn console_inspect_limits() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    console.log({ a: { b: 1 } });
+    console.log([1, 2, 3]);
+    console.log(new Set([1, 2, 3, 4]));
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .inspect_max_depth(0)
+        .inspect_max_length(2)
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+
+    for message in [
+        "{ a: [Object] }",
+        "Array(3) [ 1, 2, ... 1 more item ]",
+        "Set(4) { 1, 2, ... 2 more items }",
+    ] {
+        assert_eq!(
+            logs_receiver.recv_async().await.unwrap(),
+            ("log".into(), message.into(), None)
+        );
+    }
+}
+
+#[tokio::test]
+async fn console_output_limits() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    console.log(['Hello world']);
+    console.log('a'.repeat(100));
+    console.log(new Array(1000).fill(1));
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .inspect_max_string_length(5)
+        .inspect_max_output_size(40)
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+
+    for message in [
+        "[ 'Hello'... 6 more characters ]".to_string(),
+        format!("{}... 60 more bytes", "a".repeat(40)),
+        "Array(1000) [ 1, 1, 1, 1, 1, 1, 1, 1, 1,... 36 more bytes".to_string(),
+    ] {
+        assert_eq!(
+            logs_receiver.recv_async().await.unwrap(),
+            ("log".into(), message, None)
+        );
+    }
+}
+
+#[tokio::test]
+async fn max_pending_ops() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export async function handler() {
+    const data = new Uint8Array([1, 2, 3]);
+    const results = await Promise.allSettled([
+        new Promise(resolve => setTimeout(resolve, 10)),
+        crypto.subtle.digest('SHA-256', data),
+        crypto.subtle.digest('SHA-256', data),
+    ]);
+
+    // The slots are released once the operations are done
+    await crypto.subtle.digest('SHA-256', data);
+
+    return new Response(results.map(result => result.status === 'fulfilled'
+        ? 'ok'
+        : `${result.reason.name}: ${result.reason.message}`
+    ).join(','));
+}"
+            .into(),
+        )
+        .max_pending_ops(2),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("ok,ok,TypeError: Too many pending async operations")
+    );
+}
+
 #[tokio::test]
 async fn atob() {
     utils::setup();
@@ -1,7 +1,7 @@
 use httptest::bytes::Bytes;
 use lagon_runtime_http::{Request, Response, RunResult, StreamResult};
 use lagon_runtime_isolate::options::IsolateOptions;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 mod utils;
 
@@ -36,6 +36,7 @@ async fn sync_streaming() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 }
@@ -78,6 +79,7 @@ async fn queue_multiple() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 }
@@ -120,6 +122,7 @@ async fn custom_response() {
         RunResult::Stream(StreamResult::Start(Response {
             body: Bytes::from("[object ReadableStream]"),
             status: 201,
+            status_text: None,
             headers: Some(headers),
         }))
     );
@@ -166,6 +169,7 @@ async fn start_and_pull() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 }
@@ -210,6 +214,7 @@ async fn response_before_write() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 
@@ -221,6 +226,145 @@ async fn response_before_write() {
     assert!(receiver.recv_async().await.unwrap().as_stream_done());
 }
 
+#[tokio::test]
+async fn streaming_response_write_end() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const response = new StreamingResponse({
+        status: 201,
+        headers: {
+            'x-lagon': 'test',
+        },
+    });
+
+    response.write('Loading...');
+
+    (async () => {
+        await new Promise(resolve => setTimeout(resolve, 200));
+        response.write(new TextEncoder().encode('Hello'));
+        response.end(' world');
+    })();
+
+    return response;
+}"
+        .into(),
+    ));
+    send(Request::default());
+    let mut headers = HashMap::new();
+    headers.insert("x-lagon".into(), vec!["test".into()]);
+
+    // The response is sent without waiting for end()
+    let first_chunk = tokio::time::timeout(Duration::from_millis(100), async {
+        assert_eq!(
+            receiver.recv_async().await.unwrap(),
+            RunResult::Stream(StreamResult::Data(b"Loading...".to_vec()))
+        );
+
+        assert_eq!(
+            receiver.recv_async().await.unwrap(),
+            RunResult::Stream(StreamResult::Start(Response {
+                body: Bytes::from("[object ReadableStream]"),
+                status: 201,
+                status_text: None,
+                headers: Some(headers),
+            }))
+        );
+    });
+    assert!(first_chunk.await.is_ok());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Stream(StreamResult::Data(b"Hello".to_vec()))
+    );
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Stream(StreamResult::Data(b" world".to_vec()))
+    );
+
+    assert!(receiver.recv_async().await.unwrap().as_stream_done());
+}
+
+#[tokio::test]
+async fn streaming_response_write_after_end() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const response = new StreamingResponse();
+    response.end();
+
+    try {
+        response.write('Hello');
+    } catch (error) {
+        return new Response(error.message);
+    }
+
+    return new Response('Not thrown');
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Cannot write to a StreamingResponse after end() has been called")
+    );
+}
+
+#[tokio::test]
+async fn streaming_response_flush() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    const response = new StreamingResponse();
+
+    (async () => {
+        response.write('data: first\\n\\n');
+        await response.flush();
+        response.write('data: second\\n\\n');
+        await response.flush();
+
+        await new Promise(resolve => setTimeout(resolve, 200));
+        response.end('data: third\\n\\n');
+        await response.flush();
+    })();
+
+    return response;
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    // Each flushed write is received as its own chunk, before the next one
+    let flushed_chunks = tokio::time::timeout(Duration::from_millis(100), async {
+        let mut chunks = Vec::new();
+
+        while chunks.len() < 2 {
+            if let RunResult::Stream(StreamResult::Data(chunk)) =
+                receiver.recv_async().await.unwrap()
+            {
+                chunks.push(chunk);
+            }
+        }
+
+        chunks
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(
+        flushed_chunks,
+        vec![b"data: first\n\n".to_vec(), b"data: second\n\n".to_vec()]
+    );
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Stream(StreamResult::Data(b"data: third\n\n".to_vec()))
+    );
+    assert!(receiver.recv_async().await.unwrap().as_stream_done());
+}
+
 #[tokio::test]
 async fn timeout_infinite_streaming() {
     utils::setup();
@@ -240,6 +384,7 @@ async fn timeout_infinite_streaming() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 
@@ -303,6 +448,7 @@ async fn promise_reject_callback_after_response() {
             headers: None,
             body: Bytes::from("[object ReadableStream]"),
             status: 200,
+            status_text: None,
         }))
     );
 
@@ -9,7 +9,7 @@ pub fn setup() {
     static START: Once = Once::new();
 
     START.call_once(|| {
-        Runtime::new(RuntimeOptions::default());
+        Runtime::new(RuntimeOptions::default()).expect("Failed to start runtime");
     });
 }
 
@@ -18,7 +18,18 @@ pub fn setup_allow_codegen() {
     static START: Once = Once::new();
 
     START.call_once(|| {
-        Runtime::new(RuntimeOptions::default().allow_code_generation(true));
+        Runtime::new(RuntimeOptions::default().allow_code_generation(true))
+            .expect("Failed to start runtime");
+    });
+}
+
+#[allow(dead_code)]
+pub fn setup_max_stack_size(max_stack_size: usize) {
+    static START: Once = Once::new();
+
+    START.call_once(|| {
+        Runtime::new(RuntimeOptions::default().max_stack_size(max_stack_size))
+            .expect("Failed to start runtime");
     });
 }
 
@@ -46,6 +57,9 @@ pub fn create_isolate(options: IsolateOptions) -> (SendRequest, flume::Receiver<
             .send(IsolateEvent::Request(IsolateRequest {
                 request,
                 sender: sender.clone(),
+                cancellation_token: None,
+                memory: None,
+                max_body_size: None,
             }))
             .unwrap();
     });
@@ -74,6 +88,45 @@ pub fn create_isolate_without_snapshot(
             .send(IsolateEvent::Request(IsolateRequest {
                 request,
                 sender: sender.clone(),
+                cancellation_token: None,
+                memory: None,
+                max_body_size: None,
+            }))
+            .unwrap();
+    });
+
+    (send_isolate_event, receiver)
+}
+
+type SendRequestWithMemory = Box<dyn Fn(Request, Option<usize>)>;
+
+#[allow(dead_code)]
+pub fn create_isolate_with_memory(
+    options: IsolateOptions,
+) -> (SendRequestWithMemory, flume::Receiver<RunResult>) {
+    let (request_tx, request_rx) = flume::unbounded();
+    let (sender, receiver) = flume::unbounded();
+
+    let handle = Handle::current();
+    std::thread::spawn(move || {
+        handle.block_on(async move {
+            let mut isolate = Isolate::new(
+                options.snapshot_blob(include_bytes!("../../../serverless/snapshot.bin")),
+                request_rx,
+            );
+            isolate.evaluate();
+            isolate.run_event_loop().await;
+        })
+    });
+
+    let send_isolate_event = Box::new(move |request: Request, memory: Option<usize>| {
+        request_tx
+            .send(IsolateEvent::Request(IsolateRequest {
+                request,
+                sender: sender.clone(),
+                cancellation_token: None,
+                memory,
+                max_body_size: None,
             }))
             .unwrap();
     });
@@ -0,0 +1,137 @@
+use lagon_runtime_http::{Request, RunResult};
+use lagon_runtime_isolate::options::IsolateOptions;
+use lagon_runtime_v8_utils::{parse_v8_flags, V8Flag, V8FlagScope};
+
+mod utils;
+
+const RECURSE_CODE: &str = "function recurse(depth) {
+    if (depth === 0) {
+        throw new Error('Too deep');
+    }
+
+    return recurse(depth - 1);
+}
+
+export function handler() {
+    return recurse(5);
+}";
+
+#[test]
+fn parse_flags() {
+    assert_eq!(
+        parse_v8_flags(
+            &["--max_semi_space_size=32".into(), "--jitless".into()],
+            V8FlagScope::Global
+        )
+        .unwrap(),
+        vec![
+            V8Flag {
+                name: "max-semi-space-size".into(),
+                value: Some("32".into()),
+            },
+            V8Flag {
+                name: "jitless".into(),
+                value: None,
+            },
+        ]
+    );
+    assert_eq!(
+        V8Flag::parse("--max-semi-space-size=32", V8FlagScope::Global)
+            .unwrap()
+            .to_string(),
+        "--max-semi-space-size=32"
+    );
+}
+
+#[test]
+fn reject_flags() {
+    let error = |flag: &str, scope| V8Flag::parse(flag, scope).unwrap_err().to_string();
+
+    assert_eq!(
+        error("--allow-natives-syntax", V8FlagScope::Global),
+        "V8 flag --allow-natives-syntax is not allowed"
+    );
+    assert_eq!(
+        error("jitless", V8FlagScope::Global),
+        "Invalid V8 flag jitless, expected --name[=value]"
+    );
+    assert_eq!(
+        error(
+            "--max-semi-space-size=1 --allow-natives-syntax",
+            V8FlagScope::Global
+        ),
+        "Invalid value for V8 flag --max-semi-space-size=1 --allow-natives-syntax"
+    );
+    assert_eq!(
+        error("--jitless", V8FlagScope::Isolate),
+        "V8 flag --jitless is process-global and can't be set per isolate"
+    );
+    assert_eq!(
+        error("--stack-trace-limit=2", V8FlagScope::Global),
+        "V8 flag --stack-trace-limit=2 can only be set per isolate"
+    );
+}
+
+#[tokio::test]
+async fn isolate_flag_applied() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(RECURSE_CODE.into()).v8_flags(vec!["--stack-trace-limit=2".into()]),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error(
+            "Uncaught Error: Too deep\n  at recurse (3:15)\n  at recurse (6:12)".into()
+        )
+    );
+}
+
+#[tokio::test]
+async fn initial_heap_size_flag() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler() {
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .v8_flags(vec!["--initial-heap-size=16".into()]),
+    );
+    send(Request::default());
+
+    assert!(matches!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Response(..)
+    ));
+}
+
+#[tokio::test]
+async fn disallowed_flag_rejected() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(RECURSE_CODE.into()).v8_flags(vec!["--allow-natives-syntax".into()]),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error("V8 flag --allow-natives-syntax is not allowed".into())
+    );
+}
+
+#[tokio::test]
+async fn invalid_flag_value_rejected() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(RECURSE_CODE.into()).v8_flags(vec!["--stack-trace-limit=a".into()]),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap(),
+        RunResult::Error("V8 flag --stack-trace-limit expects a number".into())
+    );
+}
@@ -0,0 +1,63 @@
+use lagon_runtime_isolate::{options::IsolateOptions, validate_deployment, ErrorLocation};
+
+mod utils;
+
+#[tokio::test]
+async fn valid_deployment() {
+    utils::setup();
+
+    assert_eq!(
+        validate_deployment(IsolateOptions::new(
+            "export function handler() {
+    return new Response('Hello world');
+}"
+            .into(),
+        )),
+        Ok(())
+    );
+}
+
+#[tokio::test]
+async fn syntax_error() {
+    utils::setup();
+    let error = validate_deployment(IsolateOptions::new(
+        "export function handler() {
+    this syntax is invalid
+}"
+        .into(),
+    ))
+    .unwrap_err();
+
+    assert_eq!(
+        error.message,
+        "Uncaught SyntaxError: Unexpected identifier 'syntax'"
+    );
+    assert_eq!(
+        error.location,
+        Some(ErrorLocation {
+            source: None,
+            line: 2,
+            column: 10,
+        })
+    );
+}
+
+#[tokio::test]
+async fn top_level_error() {
+    utils::setup();
+    let error = validate_deployment(IsolateOptions::new(
+        "const config = JSON.parse('{}');
+config.missing.value;
+
+export function handler() {
+    return new Response('Hello world');
+}"
+        .into(),
+    ))
+    .unwrap_err();
+
+    assert!(error
+        .message
+        .starts_with("Uncaught TypeError: Cannot read properties of undefined"));
+    assert_eq!(error.location.map(|location| location.line), Some(2));
+}
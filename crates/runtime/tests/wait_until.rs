@@ -0,0 +1,135 @@
+use lagon_runtime_http::{Request, Response};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn wait_until_after_response() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler(request, event) {
+    event.waitUntil(new Promise(resolve => {
+        setTimeout(() => {
+            console.log('background')
+            resolve();
+        }, 100);
+    }));
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+    assert!(logs_receiver.is_empty());
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        ("log".into(), "background".into(), None)
+    );
+}
+
+#[tokio::test]
+async fn wait_until_rejected() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler(request, event) {
+    event.waitUntil(new Promise((_, reject) => {
+        setTimeout(() => reject(new Error('Background failed')), 10);
+    }));
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        (
+            "error".into(),
+            "Uncaught (in waitUntil) Error: Background failed".into(),
+            None
+        )
+    );
+
+    // Rejections shouldn't be reported to the next requests
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
+
+#[tokio::test]
+async fn wait_until_timeout() {
+    let (logs_sender, logs_receiver) = flume::unbounded();
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(
+        IsolateOptions::new(
+            "export function handler(request, event) {
+    event.waitUntil(new Promise(resolve => {
+        setTimeout(() => {
+            console.log('background')
+            resolve();
+        }, 1000);
+    }));
+
+    return new Response('Hello world');
+}"
+            .into(),
+        )
+        .wait_until_timeout(Duration::from_millis(100))
+        .log_sender(logs_sender),
+    );
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+    assert_eq!(
+        logs_receiver.recv_async().await.unwrap(),
+        (
+            "error".into(),
+            "waitUntil() promise did not settle before the timeout".into(),
+            None
+        )
+    );
+}
+
+#[tokio::test]
+async fn wait_until_non_promise_value() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler(request, event) {
+    event.waitUntil('test');
+
+    return new Response('Hello world');
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    assert_eq!(
+        receiver.recv_async().await.unwrap().as_response(),
+        Response::from("Hello world")
+    );
+}
@@ -0,0 +1,112 @@
+use lagon_runtime_http::{Request, RunResult, StreamResult};
+use lagon_runtime_isolate::options::IsolateOptions;
+use std::process::Command;
+
+mod utils;
+
+// The archives are checked with the system `unzip`
+fn has_unzip() -> bool {
+    Command::new("unzip")
+        .arg("-v")
+        .output()
+        .map_or(false, |output| output.status.success())
+}
+
+fn unzip(archive: &[u8], args: &[&str]) -> String {
+    let path = std::env::temp_dir().join(format!("lagon-zip-response-{}.zip", std::process::id()));
+    std::fs::write(&path, archive).unwrap();
+
+    let output = Command::new("unzip")
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[tokio::test]
+async fn zip_extractable() {
+    if !has_unzip() {
+        eprintln!("Skipping zip_extractable: `unzip` isn't installed");
+        return;
+    }
+
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    return new ZipResponse([
+        { name: 'hello.txt', body: 'Hello world' },
+        {
+            name: 'assets/lorem.txt',
+            compress: true,
+            body: new ReadableStream({
+                start(controller) {
+                    controller.enqueue(new TextEncoder().encode('Lorem ipsum '.repeat(100)));
+                    controller.enqueue(new TextEncoder().encode('dolor'));
+                    controller.close();
+                },
+            }),
+        },
+    ]);
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    let mut response = None;
+    let mut archive = Vec::new();
+
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(start)) => response = Some(start),
+            RunResult::Stream(StreamResult::Data(data)) => archive.extend_from_slice(&data),
+            RunResult::Stream(StreamResult::Done(_)) => break,
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    let response = response.unwrap();
+
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.headers.unwrap()["content-type"],
+        vec!["application/zip".to_string()]
+    );
+    assert!(unzip(&archive, &["-t"]).contains("No errors detected"));
+    assert_eq!(unzip(&archive, &["-p"]), {
+        let mut content = "Hello world".to_owned();
+        content.push_str(&"Lorem ipsum ".repeat(100));
+        content.push_str("dolor");
+        content
+    });
+}
+
+#[tokio::test]
+async fn empty_name() {
+    utils::setup();
+    let (send, receiver) = utils::create_isolate(IsolateOptions::new(
+        "export function handler() {
+    return new ZipResponse([{ name: '', body: 'Hello' }]);
+}"
+        .into(),
+    ));
+    send(Request::default());
+
+    // The entries are only read while the response is streamed
+    loop {
+        match receiver.recv_async().await.unwrap() {
+            RunResult::Stream(StreamResult::Start(_) | StreamResult::Data(_)) => {}
+            RunResult::Error(error) => {
+                assert!(
+                    error.starts_with("Uncaught TypeError: ZIP entry name can't be empty"),
+                    "{error}"
+                );
+                break;
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Languages with a quality above 1 or below 0 are invalid, and so are
+// the ones with q=0, which means "not acceptable"
+fn parse_quality(params: &str) -> Option<f64> {
+    let mut quality = 1.0;
+
+    for param in params.split(';') {
+        let param = param.trim();
+
+        if param.is_empty() {
+            continue;
+        }
+
+        let (name, value) = param.split_once('=')?;
+
+        if name.trim().eq_ignore_ascii_case("q") {
+            quality = value.trim().parse::<f64>().ok()?;
+        }
+    }
+
+    match quality > 0.0 && quality <= 1.0 {
+        true => Some(quality),
+        false => None,
+    }
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    tag == "*"
+        || (!tag.starts_with('-')
+            && !tag.ends_with('-')
+            && !tag.contains("--")
+            && tag
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '-'))
+}
+
+// Parse an Accept-Language header into its languages and their quality, sorted
+// by quality with the order of the header kept for equal ones. Invalid entries
+// are skipped instead of failing the whole header
+pub fn parse_accept_language(header: &str) -> Vec<(String, f64)> {
+    let mut languages = header
+        .split(',')
+        .filter_map(|language| {
+            let (tag, params) = language.split_once(';').unwrap_or((language, ""));
+            let tag = tag.trim();
+
+            if tag.is_empty() || !is_valid_tag(tag) {
+                return None;
+            }
+
+            Some((tag.to_string(), parse_quality(params)?))
+        })
+        .collect::<Vec<_>>();
+
+    languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    languages
+}
+
+// Find the supported locale best matching the parsed languages, either
+// exactly (`fr-CA`) or by their primary subtag (`fr` for `fr-CA` and
+// `fr-CA` for `fr`). A wildcard matches the first supported locale
+pub fn match_locale<'a>(languages: &[(String, f64)], supported: &'a [String]) -> Option<&'a str> {
+    for (tag, _) in languages {
+        if tag == "*" {
+            return supported.first().map(String::as_str);
+        }
+
+        if let Some(locale) = supported
+            .iter()
+            .find(|locale| locale.eq_ignore_ascii_case(tag))
+        {
+            return Some(locale.as_str());
+        }
+
+        let primary = tag.split('-').next().unwrap_or(tag);
+
+        if let Some(locale) = supported.iter().find(|locale| {
+            locale
+                .split('-')
+                .next()
+                .map_or(false, |locale| locale.eq_ignore_ascii_case(primary))
+        }) {
+            return Some(locale.as_str());
+        }
+    }
+
+    None
+}
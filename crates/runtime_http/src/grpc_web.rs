@@ -0,0 +1,76 @@
+use anyhow::{anyhow, Result};
+
+pub const GRPC_WEB_CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+// https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+const DATA_FRAME: u8 = 0x00;
+const TRAILERS_FRAME: u8 = 0x80;
+
+// `application/grpc-web` defaults to protobuf, but other formats can
+// be used like `application/grpc-web+json`. The text (base64) variants
+// aren't supported since they need a different framing
+pub fn is_grpc_web_content_type(content_type: &str) -> bool {
+    let content_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    content_type == "application/grpc-web"
+        || (content_type.starts_with("application/grpc-web+")
+            && content_type.len() > "application/grpc-web+".len())
+}
+
+// Each frame is prefixed by a flag byte and its length as a big-endian u32
+fn encode_frame(flag: u8, payload: &[u8]) -> Result<Vec<u8>> {
+    let length = u32::try_from(payload.len())
+        .map_err(|_| anyhow!("gRPC-Web frame is too large: {} bytes", payload.len()))?;
+
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(flag);
+    frame.extend_from_slice(&length.to_be_bytes());
+    frame.extend_from_slice(payload);
+
+    Ok(frame)
+}
+
+pub fn encode_grpc_web_message(message: &[u8]) -> Result<Vec<u8>> {
+    encode_frame(DATA_FRAME, message)
+}
+
+// grpc-message is percent-encoded, since it can contain any unicode character
+fn encode_grpc_message(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+
+    for byte in message.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'%' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+// Trailers are sent in the body as the last frame, formatted as HTTP/1 headers
+pub fn encode_grpc_web_trailers(
+    status: u32,
+    message: Option<&str>,
+    trailers: &[(String, String)],
+) -> Result<Vec<u8>> {
+    let mut payload = format!("grpc-status:{}\r\n", status);
+
+    if let Some(message) = message {
+        payload.push_str(&format!(
+            "grpc-message:{}\r\n",
+            encode_grpc_message(message)
+        ));
+    }
+
+    for (name, value) in trailers {
+        payload.push_str(&format!("{}:{}\r\n", name.to_lowercase(), value));
+    }
+
+    encode_frame(TRAILERS_FRAME, payload.as_bytes())
+}
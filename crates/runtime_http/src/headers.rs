@@ -1,5 +1,11 @@
 pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
 pub const X_REAL_IP: &str = "x-real-ip";
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+pub const FORWARDED: &str = "forwarded";
+pub const CONTENT_DIGEST: &str = "content-digest";
 
 pub const X_LAGON_REGION: &str = "x-lagon-region";
 pub const X_LAGON_ID: &str = "x-lagon-id";
+pub const X_LAGON_MEMORY: &str = "x-lagon-memory";
+pub const X_LAGON_MAX_BODY_SIZE: &str = "x-lagon-max-body-size";
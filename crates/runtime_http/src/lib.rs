@@ -2,15 +2,27 @@ use std::time::Duration;
 
 use anyhow::Result;
 
+mod accept_language;
+mod grpc_web;
 mod headers;
 mod method;
+mod multipart;
 mod request;
 mod response;
+mod spilled_body;
+mod websocket;
+mod zip;
 
+pub use accept_language::*;
+pub use grpc_web::*;
 pub use headers::*;
 pub use method::*;
+pub use multipart::*;
 pub use request::*;
 pub use response::*;
+pub use spilled_body::*;
+pub use websocket::*;
+pub use zip::*;
 
 pub trait IntoV8 {
     fn into_v8<'a>(self, scope: &mut v8::HandleScope<'a>) -> v8::Local<'a, v8::Object>;
@@ -41,12 +53,15 @@ pub enum RunResult {
     Timeout,
     MemoryLimit,
     Error(String),
+    // The code of the deployment couldn't be loaded or compiled,
+    // so no isolate could handle the request
+    LoadError(String),
     NotFound,
 }
 
 impl RunResult {
     pub fn as_error(self) -> String {
-        if let RunResult::Error(error) = self {
+        if let RunResult::Error(error) | RunResult::LoadError(error) = self {
             return error;
         }
 
@@ -0,0 +1,5 @@
+// 128 random bits, so the boundary can't be guessed, and can't collide
+// with the content of the parts unless it is read from the response
+pub fn multipart_boundary() -> String {
+    format!("lagon-{:032x}", rand::random::<u128>())
+}
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use hyper::{
-    body::{self, Bytes},
+    body::Bytes,
     header::HeaderName,
     http::{self, HeaderValue},
     Body, Request as HyperRequest,
@@ -9,9 +9,9 @@ use lagon_runtime_v8_utils::{
     extract_v8_headers_object, extract_v8_string, extract_v8_uint8array, v8_headers_object,
     v8_string, v8_uint8array,
 };
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 
-use crate::X_LAGON_ID;
+use crate::{read_body, BodySpilling, SpilledBody, X_LAGON_ID};
 
 use super::{FromV8, IntoV8, Method};
 
@@ -21,6 +21,8 @@ pub struct Request {
     pub method: Method,
     pub body: Bytes,
     pub url: String,
+    // Set instead of `body` when the body has been spilled to disk
+    pub spilled_body: Option<Arc<SpilledBody>>,
 }
 
 impl Default for Request {
@@ -30,6 +32,7 @@ impl Default for Request {
             method: Method::GET,
             body: Bytes::new(),
             url: "".into(),
+            spilled_body: None,
         }
     }
 }
@@ -118,6 +121,7 @@ impl FromV8 for Request {
             method,
             body,
             url,
+            spilled_body: None,
         })
     }
 }
@@ -152,10 +156,14 @@ impl Request {
     // TODO: Return the full request length
     pub fn len(&self) -> usize {
         self.body.len()
+            + self
+                .spilled_body
+                .as_ref()
+                .map_or(0, |spilled_body| spilled_body.len())
     }
 
     pub fn is_empty(&self) -> bool {
-        self.body.is_empty()
+        self.len() == 0
     }
 
     pub async fn from_hyper(request: HyperRequest<Body>) -> Result<Self> {
@@ -165,6 +173,28 @@ impl Request {
     pub async fn from_hyper_with_capacity(
         request: HyperRequest<Body>,
         capacity: usize,
+    ) -> Result<Self> {
+        Self::from_hyper_with_spilling(request, capacity, None).await
+    }
+
+    pub async fn from_hyper_with_spilling(
+        request: HyperRequest<Body>,
+        capacity: usize,
+        spilling: Option<&BodySpilling>,
+    ) -> Result<Self> {
+        Self::from_hyper_with_limits(request, capacity, spilling, None, None, None).await
+    }
+
+    // Fails with `BodyTooLarge` when the body is larger than `max_body_size` bytes, and
+    // with `BodyReadTimeout` when the body stalls for longer than `body_read_timeout`
+    // or isn't entirely received within `body_total_timeout`
+    pub async fn from_hyper_with_limits(
+        request: HyperRequest<Body>,
+        capacity: usize,
+        spilling: Option<&BodySpilling>,
+        max_body_size: Option<usize>,
+        body_read_timeout: Option<Duration>,
+        body_total_timeout: Option<Duration>,
     ) -> Result<Self> {
         let mut headers =
             HashMap::<String, Vec<String>>::with_capacity(request.headers().keys_len() + capacity);
@@ -185,7 +215,14 @@ impl Request {
         });
         let url = format!("http://{}{}", host, request.uri().to_string().as_str());
 
-        let body = body::to_bytes(request.into_body()).await?;
+        let (body, spilled_body) = read_body(
+            request.into_body(),
+            spilling,
+            max_body_size,
+            body_read_timeout,
+            body_total_timeout,
+        )
+        .await?;
 
         Ok(Request {
             headers: if !headers.is_empty() {
@@ -196,6 +233,7 @@ impl Request {
             method,
             body,
             url,
+            spilled_body: spilled_body.map(Arc::new),
         })
     }
 
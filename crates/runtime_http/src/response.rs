@@ -1,17 +1,19 @@
 use anyhow::{anyhow, Result};
+use futures::{stream, Stream, StreamExt};
 use hyper::{
     body::{self, Bytes},
+    ext::ReasonPhrase,
     header::HeaderName,
     http::{self, HeaderValue},
     Body, Response as HyperResponse,
 };
 use lagon_runtime_v8_utils::{
-    extract_v8_headers_object, extract_v8_integer, extract_v8_uint8array, v8_headers_object,
-    v8_integer, v8_string, v8_uint8array,
+    extract_v8_headers_object, extract_v8_integer, extract_v8_string, extract_v8_uint8array,
+    v8_headers_object, v8_integer, v8_string, v8_uint8array,
 };
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
-use crate::{FromV8, IntoV8};
+use crate::{FromV8, IntoV8, RunResult, StreamResult};
 
 static READABLE_STREAM_STR: &[u8] = b"[object ReadableStream]";
 
@@ -20,6 +22,8 @@ pub struct Response {
     pub headers: Option<HashMap<String, Vec<String>>>,
     pub body: Bytes,
     pub status: u16,
+    // A custom reason phrase, only sent to HTTP/1.1 clients
+    pub status_text: Option<String>,
 }
 
 impl Default for Response {
@@ -28,6 +32,7 @@ impl Default for Response {
             headers: None,
             body: Bytes::new(),
             status: 200,
+            status_text: None,
         }
     }
 }
@@ -41,6 +46,7 @@ impl From<&str> for Response {
             )])),
             body: Bytes::from(body.to_string()),
             status: 200,
+            status_text: None,
         }
     }
 }
@@ -49,7 +55,11 @@ impl From<&str> for Response {
 // We can safely use unwrap here because set only return Just(true) or Empty(), so if it should never fail
 impl IntoV8 for Response {
     fn into_v8<'a>(self, scope: &mut v8::HandleScope<'a>) -> v8::Local<'a, v8::Object> {
-        let len = if self.headers.is_some() { 3 } else { 2 };
+        let mut len = if self.headers.is_some() { 3 } else { 2 };
+
+        if self.status_text.is_some() {
+            len += 1;
+        }
 
         let mut names = Vec::with_capacity(len);
         let mut values = Vec::with_capacity(len);
@@ -60,6 +70,11 @@ impl IntoV8 for Response {
         names.push(v8_string(scope, "s").into());
         values.push(v8_integer(scope, self.status.into()).into());
 
+        if let Some(status_text) = self.status_text {
+            names.push(v8_string(scope, "st").into());
+            values.push(v8_string(scope, &status_text).into());
+        }
+
         if let Some(headers) = self.headers {
             names.push(v8_string(scope, "h").into());
             values.push(v8_headers_object(scope, headers).into());
@@ -115,10 +130,20 @@ impl FromV8 for Response {
             return Err(anyhow!("Could not find status"));
         }
 
+        let mut status_text = None;
+        let status_text_key = v8_string(scope, "st");
+
+        if let Some(status_text_value) = response.get(scope, status_text_key.into()) {
+            if !status_text_value.is_null_or_undefined() {
+                status_text = Some(extract_v8_string(status_text_value, scope)?);
+            }
+        }
+
         Ok(Self {
             headers,
             body: Bytes::from(body),
             status,
+            status_text,
         })
     }
 }
@@ -129,6 +154,11 @@ impl TryFrom<&Response> for http::response::Builder {
     fn try_from(response: &Response) -> Result<Self, Self::Error> {
         let mut builder = HyperResponse::builder().status(response.status);
 
+        // HTTP/2 has no reason phrase, so this is ignored there
+        if let Some(status_text) = &response.status_text {
+            builder = builder.extension(ReasonPhrase::try_from(status_text.as_bytes())?);
+        }
+
         let builder_headers = match builder.headers_mut() {
             Some(headers) => headers,
             None => return Err(anyhow!("Invalid headers")),
@@ -161,7 +191,27 @@ impl Response {
         self.body == READABLE_STREAM_STR
     }
 
+    // Case-insensitive, returning the first value of the header
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers.as_ref().and_then(|headers| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, values)| values.first())
+                .map(|value| value.as_str())
+        })
+    }
+
     pub async fn from_hyper(response: HyperResponse<Body>) -> Result<Self> {
+        let (mut response, body) = Self::from_hyper_streamed(response)?;
+        response.body = body::to_bytes(body).await?;
+
+        Ok(response)
+    }
+
+    // Extract the status and headers, and return the body untouched
+    // so it can be read lazily
+    pub fn from_hyper_streamed(response: HyperResponse<Body>) -> Result<(Self, Body)> {
         let mut headers =
             HashMap::<String, Vec<String>>::with_capacity(response.headers().keys_len());
 
@@ -173,16 +223,49 @@ impl Response {
         }
 
         let status = response.status().as_u16();
-        let body = body::to_bytes(response.into_body()).await?;
 
-        Ok(Response {
+        Ok((
+            Response {
+                status,
+                status_text: None,
+                headers: if !headers.is_empty() {
+                    Some(headers)
+                } else {
+                    None
+                },
+                body: Bytes::new(),
+            },
+            response.into_body(),
+        ))
+    }
+
+    // Build a streamed response from the host, producing the same sequence of
+    // results as an isolate streaming a response: Start, Data for each chunk, then Done
+    pub fn from_parts<S, B>(
+        status: u16,
+        headers: HashMap<String, Vec<String>>,
+        body: S,
+    ) -> impl Stream<Item = RunResult>
+    where
+        S: Stream<Item = B>,
+        B: Into<Vec<u8>>,
+    {
+        let response = Response {
             status,
+            status_text: None,
             headers: if !headers.is_empty() {
                 Some(headers)
             } else {
                 None
             },
-            body,
-        })
+            body: Bytes::from_static(READABLE_STREAM_STR),
+        };
+
+        stream::once(async move { RunResult::Stream(StreamResult::Start(response)) })
+            .chain(body.map(|chunk| RunResult::Stream(StreamResult::Data(chunk.into()))))
+            // No time has been spent in an isolate
+            .chain(stream::once(async {
+                RunResult::Stream(StreamResult::Done(Duration::from_secs(0)))
+            }))
     }
 }
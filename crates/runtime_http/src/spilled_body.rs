@@ -0,0 +1,221 @@
+use anyhow::Result;
+use futures::{StreamExt, TryStreamExt};
+use hyper::{
+    body::{self, Bytes, HttpBody},
+    Body,
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    time::Instant,
+};
+use tokio_util::io::ReaderStream;
+
+// Bodies larger than `threshold` bytes are written to a temporary file
+// in `dir` while they are received, instead of being buffered in memory
+#[derive(Debug, Clone)]
+pub struct BodySpilling {
+    pub threshold: usize,
+    pub dir: PathBuf,
+}
+
+impl BodySpilling {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            dir: env::temp_dir(),
+        }
+    }
+
+    pub fn dir(mut self, dir: PathBuf) -> Self {
+        self.dir = dir;
+        self
+    }
+}
+
+// The temporary file is removed once the body is dropped
+#[derive(Debug)]
+pub struct SpilledBody {
+    path: PathBuf,
+    len: usize,
+}
+
+impl SpilledBody {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The file is read lazily, and kept until the returned body is dropped
+    pub fn into_body(self: Arc<Self>) -> Body {
+        let path = self.path.clone();
+        let stream = futures::stream::once(async move { File::open(path).await })
+            .map_ok(ReaderStream::new)
+            .try_flatten()
+            .map(move |chunk| {
+                let _spilled_body = &self;
+                chunk
+            });
+
+        Body::wrap_stream(stream)
+    }
+}
+
+// Only readable by the current user, and never an existing file (e.g a
+// symlink planted in a shared temporary directory)
+async fn create_spilled_body(dir: &Path, len: usize) -> Result<(SpilledBody, File)> {
+    let spilled_body = SpilledBody {
+        path: dir.join(format!("lagon-body-{:032x}", rand::random::<u128>())),
+        len,
+    };
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let file = options.open(&spilled_body.path).await?;
+
+    Ok((spilled_body, file))
+}
+
+impl Drop for SpilledBody {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).unwrap_or(());
+    }
+}
+
+// Returned while reading a body larger than the limit, with the limit in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyTooLarge(pub usize);
+
+impl std::fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Body exceeds the limit of {} bytes", self.0)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+// Returned when no chunk of a body is received before the read timeout,
+// or when the whole body isn't received before the total timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyReadTimeout(pub Duration);
+
+impl std::fmt::Display for BodyReadTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Body not received within {}ms", self.0.as_millis())
+    }
+}
+
+impl std::error::Error for BodyReadTimeout {}
+
+// The deadline of the whole body, with the total timeout it has been computed from
+type BodyDeadline = (Instant, Duration);
+
+async fn next_chunk(
+    body: &mut Body,
+    read_timeout: Option<Duration>,
+    deadline: Option<BodyDeadline>,
+) -> Result<Option<Bytes>> {
+    // Whichever of the read timeout and the deadline elapses first,
+    // with the timeout to report when it does
+    let timeout = match (read_timeout, deadline) {
+        (Some(read_timeout), Some((deadline, total_timeout))) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match remaining < read_timeout {
+                true => Some((remaining, total_timeout)),
+                false => Some((read_timeout, read_timeout)),
+            }
+        }
+        (Some(read_timeout), None) => Some((read_timeout, read_timeout)),
+        (None, Some((deadline, total_timeout))) => Some((
+            deadline.saturating_duration_since(Instant::now()),
+            total_timeout,
+        )),
+        (None, None) => None,
+    };
+
+    let chunk = match timeout {
+        Some((timeout, reported_timeout)) => tokio::time::timeout(timeout, body.data())
+            .await
+            .map_err(|_| BodyReadTimeout(reported_timeout))?,
+        None => body.data().await,
+    };
+
+    Ok(chunk.transpose()?)
+}
+
+// Read a whole body in memory, or spill it to disk once it grows above the threshold.
+// Reading stops as soon as the body grows above `max_size` bytes, when waiting for
+// the next chunk takes longer than `read_timeout`, or when the whole body takes
+// longer than `total_timeout`, so slow bodies that never stall are bounded too
+pub async fn read_body(
+    mut body: Body,
+    spilling: Option<&BodySpilling>,
+    max_size: Option<usize>,
+    read_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+) -> Result<(Bytes, Option<SpilledBody>)> {
+    if spilling.is_none() && max_size.is_none() && read_timeout.is_none() && total_timeout.is_none()
+    {
+        return Ok((body::to_bytes(body).await?, None));
+    }
+
+    let max_size = max_size.unwrap_or(usize::MAX);
+    let deadline =
+        total_timeout.map(|total_timeout| (Instant::now() + total_timeout, total_timeout));
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = next_chunk(&mut body, read_timeout, deadline).await? {
+        if buffer.len() + chunk.len() > max_size {
+            return Err(BodyTooLarge(max_size).into());
+        }
+
+        let spilling = match spilling {
+            Some(spilling) if buffer.len() + chunk.len() > spilling.threshold => spilling,
+            _ => {
+                buffer.extend_from_slice(&chunk);
+                continue;
+            }
+        };
+
+        // Removed once dropped if reading the rest of the body fails
+        let (mut spilled_body, mut file) =
+            create_spilled_body(&spilling.dir, buffer.len() + chunk.len()).await?;
+
+        file.write_all(&buffer).await?;
+        file.write_all(&chunk).await?;
+
+        while let Some(chunk) = next_chunk(&mut body, read_timeout, deadline).await? {
+            spilled_body.len += chunk.len();
+
+            if spilled_body.len > max_size {
+                return Err(BodyTooLarge(max_size).into());
+            }
+
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+
+        return Ok((Bytes::new(), Some(spilled_body)));
+    }
+
+    Ok((buffer.into(), None))
+}
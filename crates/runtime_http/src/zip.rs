@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Result};
+use flate2::{write::DeflateEncoder, Compression};
+use std::io::Write;
+
+pub const ZIP_CONTENT_TYPE: &str = "application/zip";
+
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+// 2.0 is needed for deflate and data descriptors
+const VERSION: u16 = 20;
+// Bit 3: the CRC and sizes follow the data in a data descriptor, since they
+// aren't known when the local header is sent. Bit 11: names are UTF-8
+const FLAGS: u16 = 0x0808;
+// Entries don't have a modification time, which defaults to 1980-01-01 00:00
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZipCompression {
+    #[default]
+    Store,
+    Deflate,
+}
+
+impl ZipCompression {
+    fn method(self) -> u16 {
+        match self {
+            ZipCompression::Store => 0,
+            ZipCompression::Deflate => 8,
+        }
+    }
+}
+
+// An entry once its data has been sent, as listed in the central directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntryRecord {
+    pub name: String,
+    pub compression: ZipCompression,
+    pub crc: u32,
+    pub compressed_size: u32,
+    pub uncompressed_size: u32,
+    // Of the local header in the archive
+    pub offset: u32,
+}
+
+// ZIP64 isn't supported, so archives and entries are limited to 4 GiB
+pub fn to_zip_size(value: u64, what: &str) -> Result<u32> {
+    u32::try_from(value).map_err(|_| anyhow!("ZIP {} is too large: {} bytes", what, value))
+}
+
+// The CRC, compressed and uncompressed sizes are in the data descriptor
+pub fn encode_zip_local_header(name: &str, compression: ZipCompression) -> Result<Vec<u8>> {
+    if name.is_empty() {
+        return Err(anyhow!("ZIP entry name can't be empty"));
+    }
+
+    let name_length = u16::try_from(name.len())
+        .map_err(|_| anyhow!("ZIP entry name is too long: {} bytes", name.len()))?;
+
+    let mut header = Vec::with_capacity(30 + name.len());
+    header.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&FLAGS.to_le_bytes());
+    header.extend_from_slice(&compression.method().to_le_bytes());
+    header.extend_from_slice(&DOS_TIME.to_le_bytes());
+    header.extend_from_slice(&DOS_DATE.to_le_bytes());
+    header.extend_from_slice(&[0; 12]);
+    header.extend_from_slice(&name_length.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(name.as_bytes());
+
+    Ok(header)
+}
+
+// Continue the CRC of an entry with the next chunk of its data
+pub fn update_zip_crc(crc: u32, chunk: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(crc);
+    hasher.update(chunk);
+    hasher.finalize()
+}
+
+// Each chunk is compressed on its own and flushed to a byte boundary, so the
+// chunks can be concatenated without keeping a compressor between them. The
+// last chunk ends the deflate stream
+pub fn deflate_zip_chunk(chunk: &[u8], last: bool) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(chunk)?;
+
+    if last {
+        return Ok(encoder.finish()?);
+    }
+
+    encoder.flush()?;
+    Ok(std::mem::take(encoder.get_mut()))
+}
+
+pub fn encode_zip_data_descriptor(entry: &ZipEntryRecord) -> Vec<u8> {
+    let mut descriptor = Vec::with_capacity(16);
+    descriptor.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+    descriptor.extend_from_slice(&entry.crc.to_le_bytes());
+    descriptor.extend_from_slice(&entry.compressed_size.to_le_bytes());
+    descriptor.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+
+    descriptor
+}
+
+// Lists an entry in the central directory, once its data has been sent
+pub fn encode_zip_central_directory_header(entry: &ZipEntryRecord) -> Result<Vec<u8>> {
+    let name_length = u16::try_from(entry.name.len())
+        .map_err(|_| anyhow!("ZIP entry name is too long: {} bytes", entry.name.len()))?;
+
+    let mut header = Vec::with_capacity(46 + entry.name.len());
+    header.extend_from_slice(&CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes());
+    // Version made by, then version needed to extract
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&FLAGS.to_le_bytes());
+    header.extend_from_slice(&entry.compression.method().to_le_bytes());
+    header.extend_from_slice(&DOS_TIME.to_le_bytes());
+    header.extend_from_slice(&DOS_DATE.to_le_bytes());
+    header.extend_from_slice(&entry.crc.to_le_bytes());
+    header.extend_from_slice(&entry.compressed_size.to_le_bytes());
+    header.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+    header.extend_from_slice(&name_length.to_le_bytes());
+    // Extra field and comment lengths, disk number, internal and
+    // external attributes
+    header.extend_from_slice(&[0; 12]);
+    header.extend_from_slice(&entry.offset.to_le_bytes());
+    header.extend_from_slice(entry.name.as_bytes());
+
+    Ok(header)
+}
+
+// Ends the archive, after the central directory of the given size starting
+// at the given offset
+pub fn encode_zip_end_of_central_directory(
+    entries: usize,
+    size: u64,
+    offset: u64,
+) -> Result<Vec<u8>> {
+    let entries = u16::try_from(entries)
+        .map_err(|_| anyhow!("ZIP archive can't have more than 65535 entries"))?;
+    let size = to_zip_size(size, "central directory")?;
+    let offset = to_zip_size(offset, "archive")?;
+
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    // Number of this disk, and of the disk with the central directory
+    end.extend_from_slice(&[0; 4]);
+    end.extend_from_slice(&entries.to_le_bytes());
+    end.extend_from_slice(&entries.to_le_bytes());
+    end.extend_from_slice(&size.to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    // Comment length
+    end.extend_from_slice(&0u16.to_le_bytes());
+
+    to_zip_size(offset as u64 + size as u64 + end.len() as u64, "archive")?;
+
+    Ok(end)
+}
+
+// The central directory and its end, starting at the given offset
+pub fn encode_zip_central_directory(entries: &[ZipEntryRecord], offset: u64) -> Result<Vec<u8>> {
+    let mut central_directory = Vec::new();
+
+    for entry in entries {
+        central_directory.extend_from_slice(&encode_zip_central_directory_header(entry)?);
+    }
+
+    let end =
+        encode_zip_end_of_central_directory(entries.len(), central_directory.len() as u64, offset)?;
+    central_directory.extend_from_slice(&end);
+
+    Ok(central_directory)
+}
@@ -1 +0,0 @@
-../../packages/js-runtime/dist/index.js
\ No newline at end of file
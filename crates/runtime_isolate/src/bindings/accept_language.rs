@@ -0,0 +1,49 @@
+use lagon_runtime_http::{match_locale, parse_accept_language};
+use lagon_runtime_v8_utils::v8_string;
+
+use crate::Isolate;
+
+pub fn parse_accept_language_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let header = args.get(0).to_rust_string_lossy(scope);
+    let languages = parse_accept_language(&header)
+        .into_iter()
+        .map(|(tag, quality)| {
+            let entry = [
+                v8_string(scope, &tag).into(),
+                v8::Number::new(scope, quality).into(),
+            ];
+
+            v8::Array::new_with_elements(scope, &entry).into()
+        })
+        .collect::<Vec<v8::Local<v8::Value>>>();
+
+    retval.set(v8::Array::new_with_elements(scope, &languages).into());
+}
+
+// Falls back to the first supported locale when none match, and
+// returns undefined when there are no supported locales
+pub fn match_locale_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let header = args.get(0).to_rust_string_lossy(scope);
+    let languages = parse_accept_language(&header);
+
+    let locale = {
+        let state = Isolate::state(scope);
+        let state = state.borrow();
+
+        match_locale(&languages, &state.supported_locales)
+            .or_else(|| state.supported_locales.first().map(String::as_str))
+            .map(str::to_string)
+    };
+
+    if let Some(locale) = locale {
+        retval.set(v8_string(scope, &locale).into());
+    }
+}
@@ -0,0 +1,30 @@
+use crate::Isolate;
+
+// The limit of the request currently being handled, which trusted
+// proxies can raise, or the limit of the isolate
+pub fn get_max_body_size(scope: &mut v8::HandleScope) -> Option<usize> {
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let state = Isolate::state(scope);
+    let state = state.borrow();
+
+    match state.handler_results.get(&id) {
+        Some(handler_result) => handler_result.max_body_size,
+        None => state.max_body_size,
+    }
+}
+
+// Checked by arrayBuffer(), text(), etc while reading streamed bodies
+pub fn max_body_size_binding(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    match get_max_body_size(scope) {
+        Some(max_body_size) => retval.set(v8::Number::new(scope, max_body_size as f64).into()),
+        None => retval.set(v8::undefined(scope).into()),
+    }
+}
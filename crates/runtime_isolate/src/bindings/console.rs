@@ -1,6 +1,9 @@
+use lagon_runtime_v8_utils::{v8_boolean, v8_integer, v8_string};
 use log::error;
+use std::{collections::hash_map::Entry, time::Instant};
 
-use crate::Isolate;
+use super::inspect::truncate_output;
+use crate::{format_stack_trace, Isolate};
 
 pub fn console_binding(
     scope: &mut v8::HandleScope,
@@ -8,13 +11,177 @@ pub fn console_binding(
     mut _retval: v8::ReturnValue,
 ) {
     let level = args.get(0).to_rust_string_lossy(scope);
-    let message = args.get(1).to_rust_string_lossy(scope);
+    let mut message = args.get(1).to_rust_string_lossy(scope);
     let state = Isolate::state(scope);
     let state = state.borrow();
 
     if let Some(log_sender) = state.log_sender.as_ref() {
+        // Best-effort redaction of secrets accidentally logged,
+        // before the message leaves the isolate
+        for secret in &state.secrets {
+            if message.contains(secret.as_str()) {
+                message = message.replace(secret.as_str(), "***");
+            }
+        }
+
+        // Messages can format many values. Truncated after the redaction
+        // to not leave a part of a secret
+        truncate_output(&mut message, state.inspect_max_output_size);
+
         if let Err(error) = log_sender.send((level, message, state.metadata.as_ref().clone())) {
             error!("Failed to send log message: {}", error)
         }
     }
 }
+
+// The current stack trace, used by console.trace()
+pub fn stack_trace_binding(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let (lines, source_map, stack_trace_limit) = {
+        let state = Isolate::state(scope);
+        let state = state.borrow();
+
+        (
+            state.lines,
+            state.source_map.clone(),
+            state.stack_trace_limit,
+        )
+    };
+
+    let stack_trace = match v8::StackTrace::current_stack_trace(scope, stack_trace_limit) {
+        Some(stack_trace) => format_stack_trace(scope, stack_trace, lines, source_map.as_deref()),
+        None => String::new(),
+    };
+
+    retval.set(v8_string(scope, &stack_trace).into());
+}
+
+// Timers of console.time() are tracked per request, so they are
+// cleared once the request has been handled
+pub fn console_time_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let label = args.get(0).to_rust_string_lossy(scope);
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let state = Isolate::state(scope);
+    let mut state = state.borrow_mut();
+
+    // Returns false if the timer already exists
+    let started = match state.handler_results.get_mut(&id) {
+        Some(handler_result) => match handler_result.context.console_timers.entry(label) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Instant::now());
+                true
+            }
+        },
+        None => false,
+    };
+
+    retval.set(v8_boolean(scope, started).into());
+}
+
+// Used by console.timeLog() and console.timeEnd(), which also removes the timer.
+// Returns the elapsed time in milliseconds, or undefined if the timer doesn't exist
+pub fn console_time_end_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let label = args.get(0).to_rust_string_lossy(scope);
+    let end = args.get(1).is_true();
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let elapsed = {
+        let state = Isolate::state(scope);
+        let mut state = state.borrow_mut();
+
+        state
+            .handler_results
+            .get_mut(&id)
+            .and_then(|handler_result| match end {
+                true => handler_result.context.console_timers.remove(&label),
+                false => handler_result.context.console_timers.get(&label).copied(),
+            })
+            .map(|started_at| started_at.elapsed())
+    };
+
+    match elapsed {
+        Some(elapsed) => {
+            retval.set(v8::Number::new(scope, elapsed.as_secs_f64() * 1000.0).into());
+        }
+        None => retval.set(v8::undefined(scope).into()),
+    }
+}
+
+// Counters of console.count(), tracked per request like the timers.
+// Returns the incremented count
+pub fn console_count_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let label = args.get(0).to_rust_string_lossy(scope);
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let count = {
+        let state = Isolate::state(scope);
+        let mut state = state.borrow_mut();
+
+        state.handler_results.get_mut(&id).map(|handler_result| {
+            let count = handler_result
+                .context
+                .console_counters
+                .entry(label)
+                .or_default();
+            *count += 1;
+            *count
+        })
+    };
+
+    match count {
+        Some(count) => retval.set(v8_integer(scope, count as i32).into()),
+        None => retval.set(v8::undefined(scope).into()),
+    }
+}
+
+// Returns false if the counter doesn't exist
+pub fn console_count_reset_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let label = args.get(0).to_rust_string_lossy(scope);
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let reset = {
+        let state = Isolate::state(scope);
+        let mut state = state.borrow_mut();
+
+        state
+            .handler_results
+            .get_mut(&id)
+            .and_then(|handler_result| handler_result.context.console_counters.remove(&label))
+            .is_some()
+    };
+
+    retval.set(v8_boolean(scope, reset).into());
+}
@@ -0,0 +1,41 @@
+use base64::{
+    alphabet,
+    engine::{general_purpose::GeneralPurposeConfig, DecodePaddingMode, GeneralPurpose},
+    Engine,
+};
+use lagon_runtime_v8_utils::{v8_string, v8_uint8array};
+
+// Forgiving like atob(): the padding is optional and the unused bits are ignored
+const CONFIG: GeneralPurposeConfig = GeneralPurposeConfig::new()
+    .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+    .with_decode_allow_trailing_bits(true);
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, CONFIG);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, CONFIG);
+
+// Decode large base64 strings (e.g images) without looping over each
+// character in JS. ASCII whitespace is ignored
+pub fn decode_base64_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let data = args.get(0).to_rust_string_lossy(scope);
+    let engine = match args.get(1).is_true() {
+        true => &URL_SAFE,
+        false => &STANDARD,
+    };
+
+    let data = data
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+
+    match engine.decode(data) {
+        Ok(bytes) => retval.set(v8_uint8array(scope, bytes).into()),
+        Err(error) => {
+            let message = v8_string(scope, &format!("Invalid base64 string: {error}"));
+            let exception = v8::Exception::syntax_error(scope, message);
+            scope.throw_exception(exception);
+        }
+    }
+}
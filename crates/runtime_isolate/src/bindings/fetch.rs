@@ -1,22 +1,163 @@
 use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
+use futures::StreamExt;
 use hyper::{
-    client::HttpConnector,
+    body::{self, Bytes, HttpBody},
+    header::CONTENT_LENGTH,
     http::{request::Builder, Uri},
-    Body, Client, Response as HyperResponse,
+    Body, Client, HeaderMap, Method, Response as HyperResponse,
 };
-use hyper_tls::HttpsConnector;
-use lagon_runtime_http::{FromV8, Request, Response};
+use lagon_runtime_http::{
+    read_body, BodySpilling, FromV8, Method as RequestMethod, Request, Response,
+};
+use lagon_runtime_v8_utils::{extract_v8_uint8array, v8_exception, v8_string};
 use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, OwnedSemaphorePermit, Semaphore},
+    time::{error::Elapsed, timeout_at, Instant},
+};
 
-use crate::{bindings::PromiseResult, Isolate};
+#[cfg(unix)]
+use crate::unix_socket::unix_socket_client;
+use crate::{
+    bindings::{body::get_max_body_size, PromiseResult},
+    content_encoding::decode_body,
+    fetch_timing::{record_fetch_phase, timed_connector, FetchPhase, FetchTimer, TimedConnector},
+    host_memory::{HostAllocation, HostMemory},
+    FetchCache, FetchCacheMode, FetchRecorder, FetchRecorderMode, Isolate,
+    FETCH_CACHE_MAX_ENTRY_SIZE,
+};
 
 use super::BindingResult;
 
-static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
-    Lazy::new(|| Client::builder().build::<_, Body>(HttpsConnector::new()));
+static CLIENT: Lazy<Client<TimedConnector>> =
+    Lazy::new(|| Client::builder().build::<_, Body>(timed_connector()));
+
+// Chunks are accounted in the isolate memory until they are sent to the upstream.
+// An error while reading the body from JS aborts the request
+type FetchBodyChunk = Result<(Bytes, HostAllocation), String>;
+pub type FetchBodySender = flume::Sender<FetchBodyChunk>;
+type FetchBodyReceiver = flume::Receiver<FetchBodyChunk>;
+
+// How many chunks of a piped response body can wait to be sent
+// to the client before we stop reading from the upstream
+pub const MAX_PIPED_CHUNKS: usize = 16;
+
+// The chunks of a piped body, accounted in the isolate memory until they
+// are sent to the client, or the error that interrupted it. The channel
+// is disconnected once the whole body has been read
+pub type PipedBody = flume::Receiver<Result<(Bytes, HostAllocation), String>>;
+
+const FETCH_TIMEOUT_ERROR: &str = "fetch() timed out";
+
+// Response bodies are read lazily, either from JS using `readFetchBody`,
+// or piped directly to the client when returned untouched
+pub struct FetchBody {
+    pub request_id: u32,
+    body: Body,
+    trailers_sender: Option<FetchTrailersSender>,
+    // Keep the permit until the whole response body has been read
+    _permit: Option<OwnedSemaphorePermit>,
+    // When the fetch() timeout expires. Piped bodies aren't bound by it,
+    // since the function has already returned its response
+    deadline: Option<Instant>,
+}
+
+impl FetchBody {
+    // Request bodies spilled to disk are read the same way
+    pub fn new(request_id: u32, body: Body) -> Self {
+        Self {
+            request_id,
+            body,
+            trailers_sender: None,
+            _permit: None,
+            deadline: None,
+        }
+    }
+
+    async fn data(&mut self) -> Result<Option<Result<Bytes, hyper::Error>>, Elapsed> {
+        match self.deadline {
+            Some(deadline) => timeout_at(deadline, self.body.data()).await,
+            None => Ok(self.body.data().await),
+        }
+    }
+}
+
+pub type FetchBodies = Arc<Mutex<HashMap<u32, FetchBody>>>;
+
+type Trailers = HashMap<String, Vec<String>>;
+type FetchTrailersSender = oneshot::Sender<Trailers>;
+
+// Trailers are sent by the upstream after the body, so they can only be
+// read once the whole body has been read. The sender is dropped without
+// sending anything when the body is piped or never read entirely
+pub struct FetchTrailers {
+    pub request_id: u32,
+    receiver: oneshot::Receiver<Trailers>,
+}
 
-type Arg = Request;
+pub struct Arg {
+    request: Request,
+    body_receiver: Option<FetchBodyReceiver>,
+    fetch_limiter: Option<FetchLimiter>,
+    request_id: u32,
+    body_id: u32,
+    fetch_bodies: FetchBodies,
+    fetch_recorder: Option<Arc<FetchRecorder>>,
+    fetch_cache: Option<(Arc<FetchCache>, FetchCacheMode)>,
+    host_memory: HostMemory,
+    // Decode the compressed response bodies, unless opted out with `decompress: false`
+    decompress: bool,
+    timeout: Option<Duration>,
+    unix_sockets: Arc<HashMap<String, PathBuf>>,
+    body_spilling: Option<BodySpilling>,
+    trailers_sender: FetchTrailersSender,
+}
+
+// Limit the number of concurrent fetch() calls per isolate, so a single
+// function can't exhaust the sockets of the host. Calls above the limit
+// wait for a slot, up to `max_queued` calls.
+#[derive(Debug, Clone)]
+pub struct FetchLimiter {
+    semaphore: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+impl FetchLimiter {
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            max_queued,
+        }
+    }
+
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(anyhow!("Too many concurrent fetch() calls"));
+        }
+
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+
+        Ok(permit?)
+    }
+}
 
 pub fn fetch_init(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments) -> Result<Arg> {
     let id = scope
@@ -25,15 +166,59 @@ pub fn fetch_init(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArgumen
         .map_or(0, |value| value.value());
 
     let state = Isolate::state(scope);
-    let fetch_calls = {
+    let (
+        fetch_calls,
+        fetch_limiter,
+        body_id,
+        fetch_bodies,
+        fetch_recorder,
+        fetch_cache,
+        host_memory,
+        unix_sockets,
+        body_spilling,
+        trailers_sender,
+    ) = {
         let mut state = state.borrow_mut();
+        let fetch_limiter = state.fetch_limiter.clone();
+        let fetch_bodies = Arc::clone(&state.fetch_bodies);
+        let fetch_recorder = state.fetch_recorder.clone();
+        let fetch_cache = state.fetch_cache.clone();
+        let host_memory = state.host_memory.clone();
+        let unix_sockets = Arc::clone(&state.unix_sockets);
+        let body_spilling = state.body_spilling.clone();
 
-        if let Some(mut handler_result) = state.handler_results.get_mut(&id) {
-            handler_result.context.fetch_calls += 1;
-            handler_result.context.fetch_calls
-        } else {
-            0
-        }
+        state.fetch_bodies_count += 1;
+        let body_id = state.fetch_bodies_count;
+
+        let (trailers_sender, receiver) = oneshot::channel();
+        state.fetch_trailers.insert(
+            body_id,
+            FetchTrailers {
+                request_id: id,
+                receiver,
+            },
+        );
+
+        let fetch_calls = match state.handler_results.get_mut(&id) {
+            Some(handler_result) => {
+                handler_result.context.fetch_calls += 1;
+                handler_result.context.fetch_calls
+            }
+            None => 0,
+        };
+
+        (
+            fetch_calls,
+            fetch_limiter,
+            body_id,
+            fetch_bodies,
+            fetch_recorder,
+            fetch_cache,
+            host_memory,
+            unix_sockets,
+            body_spilling,
+            trailers_sender,
+        )
     };
 
     if fetch_calls > 20 {
@@ -45,12 +230,133 @@ pub fn fetch_init(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArgumen
         None => return Err(anyhow!("Invalid request")),
     };
 
-    Request::from_v8(scope, request.into())
+    // Streamed bodies have an unknown length: the JS side pushes
+    // the chunks with `pullFetchBody` using the provided id
+    let stream_key = v8_string(scope, "s");
+    let body_receiver = match request.get(scope, stream_key.into()) {
+        Some(stream_id) if !stream_id.is_null_or_undefined() => {
+            let stream_id = stream_id.uint32_value(scope).unwrap_or(0);
+            let (body_sender, body_receiver) = flume::unbounded();
+
+            state
+                .borrow_mut()
+                .fetch_body_senders
+                .insert(stream_id, body_sender);
+
+            Some(body_receiver)
+        }
+        _ => None,
+    };
+
+    let cache_key = v8_string(scope, "c");
+    let fetch_cache = fetch_cache.map(|fetch_cache| {
+        let cache_mode = match request.get(scope, cache_key.into()) {
+            Some(cache_mode) if cache_mode.is_string() => {
+                FetchCacheMode::from(cache_mode.to_rust_string_lossy(scope).as_str())
+            }
+            _ => FetchCacheMode::Default,
+        };
+
+        (fetch_cache, cache_mode)
+    });
+
+    let decompress_key = v8_string(scope, "d");
+    let decompress = !request
+        .get(scope, decompress_key.into())
+        .map_or(false, |decompress| decompress.is_false());
+
+    // Calls given their own signal aren't bound by the default timeout
+    let signal_key = v8_string(scope, "a");
+    let timeout = match request.get(scope, signal_key.into()) {
+        Some(signal) if signal.is_true() => None,
+        _ => state.borrow().fetch_timeout,
+    };
+
+    Ok(Arg {
+        request: Request::from_v8(scope, request.into())?,
+        body_receiver,
+        fetch_limiter,
+        request_id: id,
+        body_id,
+        fetch_bodies,
+        fetch_recorder,
+        fetch_cache,
+        host_memory,
+        decompress,
+        timeout,
+        unix_sockets,
+        body_spilling,
+        trailers_sender,
+    })
+}
+
+pub fn pull_fetch_body_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut _retval: v8::ReturnValue,
+) {
+    let isolate_state = Isolate::state(scope);
+    let mut state = isolate_state.borrow_mut();
+
+    let id = args.get(0).uint32_value(scope).unwrap_or(0);
+    let done = args.get(1).to_boolean(scope);
+    let error = args.get(3);
+
+    if error.is_string() {
+        if let Some(body_sender) = state.fetch_body_senders.remove(&id) {
+            body_sender
+                .send(Err(error.to_rust_string_lossy(scope)))
+                .unwrap_or(());
+        }
+
+        return;
+    }
+
+    if done.is_true() {
+        // Dropping the sender ends the body
+        state.fetch_body_senders.remove(&id);
+        return;
+    }
+
+    match extract_v8_uint8array(args.get(2)) {
+        Ok(buf) => {
+            if let Some(body_sender) = state.fetch_body_senders.get(&id) {
+                let allocation = state.host_memory.allocate(buf.len());
+
+                body_sender
+                    .send(Ok((Bytes::from(buf), allocation)))
+                    .unwrap_or(());
+            }
+        }
+        Err(error) => {
+            if let Some(body_sender) = state.fetch_body_senders.remove(&id) {
+                body_sender.send(Err(error.to_string())).unwrap_or(());
+            }
+
+            let exception = v8_exception(scope, error.to_string().as_str());
+            scope.throw_exception(exception);
+        }
+    }
+}
+
+fn request_body(request: &Request, body_receiver: Option<FetchBodyReceiver>) -> Body {
+    match body_receiver {
+        // Unknown length, sent using chunked transfer encoding
+        Some(body_receiver) => {
+            Body::wrap_stream(body_receiver.into_stream().map(|chunk| match chunk {
+                Ok((chunk, _allocation)) => Ok(chunk),
+                Err(error) => Err(std::io::Error::new(std::io::ErrorKind::Other, error)),
+            }))
+        }
+        None => Body::from(request.body.clone()),
+    }
 }
 
 #[async_recursion]
 async fn make_request(
     request: &Request,
+    body_receiver: Option<FetchBodyReceiver>,
+    unix_sockets: &HashMap<String, PathBuf>,
     url: Option<String>,
     mut count: u8,
 ) -> Result<HyperResponse<Body>> {
@@ -58,17 +364,52 @@ async fn make_request(
         return Err(anyhow!("Too many redirects"));
     }
 
+    let is_streamed = body_receiver.is_some();
     let mut hyper_request = Builder::try_from(request)?;
 
     if let Some(url) = url {
         hyper_request = hyper_request.uri(url);
     }
 
-    let hyper_request = hyper_request.body(Body::from(request.body.clone()))?;
+    // The body length is known when it isn't streamed, so we always send
+    // a Content-Length since some upstreams reject chunked uploads
+    if !is_streamed
+        && (!request.body.is_empty() || hyper_request.method_ref() != Some(&Method::GET))
+    {
+        if let Some(headers) = hyper_request.headers_mut() {
+            if !headers.contains_key(CONTENT_LENGTH) {
+                headers.insert(CONTENT_LENGTH, request.body.len().into());
+            }
+        }
+    }
+
+    let hyper_request = hyper_request.body(request_body(request, body_receiver))?;
     let uri = hyper_request.uri().clone();
-    let response = CLIENT.request(hyper_request).await?;
+    let unix_socket = uri
+        .host()
+        .and_then(|host| unix_sockets.get(&host.to_lowercase()));
+
+    let response = match unix_socket {
+        // Requests are sent in plain text over the socket, so a request
+        // expecting an encrypted connection is never sent
+        Some(_) if uri.scheme_str() != Some("http") => {
+            return Err(anyhow!("Only http URLs can be sent over a Unix socket"))
+        }
+        #[cfg(unix)]
+        Some(path) => unix_socket_client(path).request(hyper_request).await?,
+        #[cfg(not(unix))]
+        Some(_) => return Err(anyhow!("Unix sockets are not supported on this platform")),
+        None => CLIENT.request(hyper_request).await?,
+    };
+
+    record_fetch_phase(FetchPhase::Ttfb);
 
     if response.status().is_redirection() {
+        // A streamed body has already been consumed and can't be replayed
+        if is_streamed {
+            return Err(anyhow!("Can't follow a redirect with a streamed body"));
+        }
+
         let mut redirect_url = match response.headers().get("location") {
             Some(location) => location.to_str()?.to_string(),
             None => return Err(anyhow!("Got a redirect without Location header")),
@@ -83,27 +424,463 @@ async fn make_request(
         }
 
         count += 1;
-        return make_request(request, Some(redirect_url), count).await;
+        return make_request(request, None, unix_sockets, Some(redirect_url), count).await;
     }
 
     Ok(response)
 }
 
-pub async fn fetch_binding(id: usize, arg: Arg) -> BindingResult {
-    let hyper_response = match make_request(&arg, None, 0).await {
-        Ok(hyper_response) => hyper_response,
-        Err(error) => {
-            return BindingResult {
-                id,
-                result: PromiseResult::Error(error.to_string()),
+async fn fetch(
+    request: &Request,
+    body_receiver: Option<FetchBodyReceiver>,
+    unix_sockets: &HashMap<String, PathBuf>,
+) -> Result<(Response, Body)> {
+    let hyper_response = make_request(request, body_receiver, unix_sockets, None, 0).await?;
+
+    Response::from_hyper_streamed(hyper_response)
+}
+
+// Recorded requests are keyed by their body, so streamed
+// bodies have to be read entirely before being sent
+async fn recorded_fetch(
+    fetch_recorder: &FetchRecorder,
+    mut request: Request,
+    body_receiver: Option<FetchBodyReceiver>,
+    unix_sockets: &HashMap<String, PathBuf>,
+) -> Result<(Response, Body)> {
+    if let Some(body_receiver) = body_receiver {
+        let mut body = Vec::new();
+
+        // The request fails like when its body is streamed, instead of
+        // recording a truncated body
+        while let Ok(chunk) = body_receiver.recv_async().await {
+            match chunk {
+                Ok((chunk, _allocation)) => body.extend_from_slice(&chunk),
+                Err(error) => return Err(anyhow!(error)),
             }
         }
+
+        request.body = body.into();
+    }
+
+    match fetch_recorder.mode() {
+        FetchRecorderMode::Replay => {
+            let mut response = fetch_recorder.find(&request)?;
+            let body = Body::from(std::mem::take(&mut response.body));
+
+            Ok((response, body))
+        }
+        FetchRecorderMode::Record => {
+            let (mut response, body) = fetch(&request, None, unix_sockets).await?;
+            response.body = body::to_bytes(body).await?;
+
+            fetch_recorder.save(&request, &response)?;
+
+            let body = Body::from(std::mem::take(&mut response.body));
+
+            Ok((response, body))
+        }
+    }
+}
+
+// Read the body until it exceeds the maximum size of cache entries, in which
+// case the chunks read so far are returned followed by the rest of the body
+async fn read_cacheable_body(response: &Response, mut body: Body) -> Result<Result<Bytes, Body>> {
+    let content_length = response
+        .get_header("content-length")
+        .and_then(|content_length| content_length.parse::<usize>().ok());
+
+    if content_length.unwrap_or_default() > FETCH_CACHE_MAX_ENTRY_SIZE {
+        return Ok(Err(body));
+    }
+
+    let mut buffer = Vec::with_capacity(content_length.unwrap_or_default());
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+
+        if buffer.len() + chunk.len() > FETCH_CACHE_MAX_ENTRY_SIZE {
+            let chunks = [Ok::<_, hyper::Error>(Bytes::from(buffer)), Ok(chunk)];
+            let body = futures::stream::iter(chunks).chain(body);
+
+            return Ok(Err(Body::wrap_stream(body)));
+        }
+
+        buffer.extend_from_slice(&chunk);
+    }
+
+    Ok(Ok(Bytes::from(buffer)))
+}
+
+// Only GET requests without a streamed body are cached. Responses that can
+// be stored are read entirely before being returned, up to the maximum size
+// of cache entries. Stored responses are accounted in the memory of the
+// isolate that made the request, until evicted
+async fn cached_fetch(
+    fetch_cache: &FetchCache,
+    cache_mode: FetchCacheMode,
+    host_memory: &HostMemory,
+    request: Request,
+    body_receiver: Option<FetchBodyReceiver>,
+    unix_sockets: &HashMap<String, PathBuf>,
+) -> Result<(Response, Body)> {
+    if !matches!(request.method, RequestMethod::GET)
+        || body_receiver.is_some()
+        || cache_mode == FetchCacheMode::NoStore
+    {
+        return fetch(&request, body_receiver, unix_sockets).await;
+    }
+
+    if cache_mode.reads_cache() {
+        if let Some(mut response) = fetch_cache.get(&request, cache_mode.allows_stale()) {
+            let body = Body::from(std::mem::take(&mut response.body));
+
+            return Ok((response, body));
+        }
+    }
+
+    if cache_mode == FetchCacheMode::OnlyIfCached {
+        let response = Response {
+            status: 504,
+            ..Response::default()
+        };
+
+        return Ok((response, Body::empty()));
+    }
+
+    let (mut response, body) = fetch(&request, None, unix_sockets).await?;
+
+    if !FetchCache::is_cacheable(&request, &response) {
+        return Ok((response, body));
+    }
+
+    response.body = match read_cacheable_body(&response, body).await? {
+        Ok(body) => body,
+        Err(body) => return Ok((response, body)),
+    };
+    fetch_cache.put(&request, &response, host_memory);
+
+    let body = Body::from(std::mem::take(&mut response.body));
+
+    Ok((response, body))
+}
+
+// Bodies known to be larger than the spilling threshold are written to disk
+// before fetch() resolves, instead of being kept by the upstream connection
+async fn spill_body(
+    response: Response,
+    body: Body,
+    body_spilling: Option<&BodySpilling>,
+) -> Result<(Response, Body)> {
+    let body_spilling = match body_spilling {
+        Some(body_spilling) => body_spilling,
+        None => return Ok((response, body)),
     };
 
-    let result = match Response::from_hyper(hyper_response).await {
-        Ok(response) => PromiseResult::Response(response),
+    let content_length = response
+        .get_header("content-length")
+        .and_then(|content_length| content_length.parse::<usize>().ok())
+        .unwrap_or_default();
+
+    if content_length <= body_spilling.threshold {
+        return Ok((response, body));
+    }
+
+    let body = match read_body(body, Some(body_spilling), None, None, None).await? {
+        (_, Some(spilled_body)) => Arc::new(spilled_body).into_body(),
+        (bytes, None) => Body::from(bytes),
+    };
+
+    Ok((response, body))
+}
+
+pub async fn fetch_binding(id: usize, arg: Arg) -> BindingResult {
+    let Arg {
+        request,
+        body_receiver,
+        fetch_limiter,
+        request_id,
+        body_id,
+        fetch_bodies,
+        fetch_recorder,
+        fetch_cache,
+        host_memory,
+        decompress,
+        timeout,
+        unix_sockets,
+        body_spilling,
+        trailers_sender,
+    } = arg;
+    let is_head = matches!(request.method, RequestMethod::HEAD);
+
+    let permit = match fetch_limiter {
+        Some(fetch_limiter) => match fetch_limiter.acquire().await {
+            Ok(permit) => Some(permit),
+            Err(error) => {
+                return BindingResult {
+                    id,
+                    result: PromiseResult::TypeError(error.to_string()),
+                }
+            }
+        },
+        None => None,
+    };
+
+    // The time spent waiting for a slot isn't counted
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let timer = Arc::new(FetchTimer::default());
+
+    let response = async {
+        let (response, body) = match (fetch_recorder, fetch_cache) {
+            (Some(fetch_recorder), _) => {
+                recorded_fetch(&fetch_recorder, request, body_receiver, &unix_sockets).await
+            }
+            (None, Some((fetch_cache, cache_mode))) => {
+                cached_fetch(
+                    &fetch_cache,
+                    cache_mode,
+                    &host_memory,
+                    request,
+                    body_receiver,
+                    &unix_sockets,
+                )
+                .await
+            }
+            (None, None) => fetch(&request, body_receiver, &unix_sockets).await,
+        }?;
+
+        spill_body(response, body, body_spilling.as_ref()).await
+    };
+    let response = timer.scope(response);
+
+    let response = match deadline {
+        Some(deadline) => match timeout_at(deadline, response).await {
+            Ok(response) => response,
+            Err(_) => {
+                return BindingResult {
+                    id,
+                    result: PromiseResult::TimeoutError(FETCH_TIMEOUT_ERROR.into()),
+                }
+            }
+        },
+        None => response.await,
+    };
+
+    let result = match response {
+        Ok((mut response, body)) => {
+            let body = match decompress {
+                true => decode_body(&mut response, body, is_head),
+                false => body,
+            };
+
+            fetch_bodies.lock().unwrap().insert(
+                body_id,
+                FetchBody {
+                    request_id,
+                    body,
+                    trailers_sender: Some(trailers_sender),
+                    _permit: permit,
+                    deadline,
+                },
+            );
+
+            PromiseResult::FetchResponse(response, body_id, timer.finish())
+        }
         Err(error) => PromiseResult::Error(error.to_string()),
     };
 
     BindingResult { id, result }
 }
+
+pub fn read_fetch_body_init(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+) -> Result<(u32, bool, Option<usize>, FetchBodies, HostMemory)> {
+    let body_id = match args.get(0).uint32_value(scope) {
+        Some(body_id) => body_id,
+        None => return Err(anyhow!("Invalid body id")),
+    };
+    let all = args.get(1).is_true();
+    let max_body_size = get_max_body_size(scope);
+
+    let state = Isolate::state(scope);
+    let state = state.borrow();
+    let fetch_bodies = Arc::clone(&state.fetch_bodies);
+
+    Ok((
+        body_id,
+        all,
+        max_body_size,
+        fetch_bodies,
+        state.host_memory.clone(),
+    ))
+}
+
+// Read the rest of the body, but stop as soon as it exceeds the size
+// limit instead of buffering all of it. The upstream connection is
+// closed when the body is dropped. The buffer is accounted in the
+// isolate memory until it's copied to the V8 heap
+async fn read_whole_fetch_body(
+    mut fetch_body: FetchBody,
+    max_body_size: Option<usize>,
+    host_memory: HostMemory,
+) -> PromiseResult {
+    let mut bytes = Vec::new();
+    let mut allocation = host_memory.allocate(0);
+
+    loop {
+        let chunk = match fetch_body.data().await {
+            Ok(Some(Ok(chunk))) => chunk,
+            Ok(Some(Err(error))) => return PromiseResult::Error(error.to_string()),
+            Ok(None) => break,
+            Err(_) => return PromiseResult::TimeoutError(FETCH_TIMEOUT_ERROR.into()),
+        };
+
+        if let Some(max_body_size) = max_body_size {
+            if bytes.len() + chunk.len() > max_body_size {
+                return PromiseResult::TypeError(format!(
+                    "Body exceeds the maximum size of {max_body_size} bytes"
+                ));
+            }
+        }
+
+        allocation.grow(chunk.len());
+        bytes.extend_from_slice(&chunk);
+    }
+
+    send_trailers(fetch_body).await;
+    PromiseResult::ArrayBuffer(bytes)
+}
+
+pub async fn read_fetch_body_binding(
+    id: usize,
+    arg: (u32, bool, Option<usize>, FetchBodies, HostMemory),
+) -> BindingResult {
+    let (body_id, all, max_body_size, fetch_bodies, host_memory) = arg;
+
+    // Chunks are read one at a time, so we can take the body out of the
+    // map while it's being polled
+    let fetch_body = fetch_bodies.lock().unwrap().remove(&body_id);
+
+    let result = match fetch_body {
+        // Read the rest of the body at once
+        Some(fetch_body) if all => {
+            read_whole_fetch_body(fetch_body, max_body_size, host_memory).await
+        }
+        Some(mut fetch_body) => match fetch_body.data().await {
+            Ok(Some(Ok(chunk))) => {
+                fetch_bodies.lock().unwrap().insert(body_id, fetch_body);
+                PromiseResult::ArrayBuffer(chunk.to_vec())
+            }
+            Ok(Some(Err(error))) => PromiseResult::Error(error.to_string()),
+            Ok(None) => {
+                send_trailers(fetch_body).await;
+                PromiseResult::Undefined
+            }
+            Err(_) => PromiseResult::TimeoutError(FETCH_TIMEOUT_ERROR.into()),
+        },
+        None => PromiseResult::Undefined,
+    };
+
+    BindingResult { id, result }
+}
+
+fn trailers_to_headers(trailers: HeaderMap) -> Trailers {
+    let mut headers = Trailers::with_capacity(trailers.keys_len());
+
+    for (key, value) in trailers.iter() {
+        if let Ok(value) = value.to_str() {
+            headers
+                .entry(key.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+    }
+
+    headers
+}
+
+async fn send_trailers(mut fetch_body: FetchBody) {
+    let trailers = match fetch_body.body.trailers().await {
+        Ok(Some(trailers)) => trailers_to_headers(trailers),
+        _ => Trailers::new(),
+    };
+
+    if let Some(trailers_sender) = fetch_body.trailers_sender.take() {
+        trailers_sender.send(trailers).unwrap_or(());
+    }
+}
+
+pub fn read_fetch_trailers_init(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+) -> Result<oneshot::Receiver<Trailers>> {
+    let body_id = match args.get(0).uint32_value(scope) {
+        Some(body_id) => body_id,
+        None => return Err(anyhow!("Invalid body id")),
+    };
+
+    let state = Isolate::state(scope);
+    let fetch_trailers = state.borrow_mut().fetch_trailers.remove(&body_id);
+
+    match fetch_trailers {
+        Some(fetch_trailers) => Ok(fetch_trailers.receiver),
+        None => Err(anyhow!("Trailers have already been read")),
+    }
+}
+
+pub async fn read_fetch_trailers_binding(
+    id: usize,
+    receiver: oneshot::Receiver<Trailers>,
+) -> BindingResult {
+    let result = match receiver.await {
+        Ok(trailers) => PromiseResult::Headers(trailers),
+        Err(_) => PromiseResult::Headers(Trailers::new()),
+    };
+
+    BindingResult { id, result }
+}
+
+// Get the fetch() body id of a response returned by the handler,
+// meaning the body hasn't been touched and can be piped
+pub fn extract_fetch_body_id(
+    scope: &mut v8::HandleScope,
+    response: v8::Local<v8::Value>,
+) -> Option<u32> {
+    let response = response.to_object(scope)?;
+    let fetch_body_key = v8_string(scope, "f");
+
+    match response.get(scope, fetch_body_key.into()) {
+        Some(body_id) if !body_id.is_null_or_undefined() => body_id.uint32_value(scope),
+        _ => None,
+    }
+}
+
+// Send the chunks straight from the upstream to the client, without going
+// through the V8 heap. We stop reading from the upstream while the client
+// is slower to receive the chunks than the upstream is to send them, and
+// the dropped receiver stops it entirely
+pub fn pipe_fetch_body(fetch_body: FetchBody, host_memory: HostMemory) -> PipedBody {
+    let FetchBody {
+        mut body, _permit, ..
+    } = fetch_body;
+    let (sender, receiver) = flume::bounded(MAX_PIPED_CHUNKS);
+
+    tokio::spawn(async move {
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk
+                .map(|chunk| {
+                    let allocation = host_memory.allocate(chunk.len());
+                    (chunk, allocation)
+                })
+                .map_err(|error| error.to_string());
+            let is_error = chunk.is_err();
+
+            if sender.send_async(chunk).await.is_err() || is_error {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
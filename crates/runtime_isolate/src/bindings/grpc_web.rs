@@ -0,0 +1,61 @@
+use lagon_runtime_http::{
+    encode_grpc_web_message, encode_grpc_web_trailers, is_grpc_web_content_type,
+};
+use lagon_runtime_v8_utils::{
+    extract_v8_headers_object, extract_v8_uint8array, v8_boolean, v8_exception, v8_uint8array,
+};
+
+pub fn is_grpc_web_content_type_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let content_type = args.get(0).to_rust_string_lossy(scope);
+    let is_grpc_web = v8_boolean(scope, is_grpc_web_content_type(&content_type));
+
+    retval.set(is_grpc_web.into());
+}
+
+pub fn grpc_web_message_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    match extract_v8_uint8array(args.get(0)).and_then(|message| encode_grpc_web_message(&message)) {
+        Ok(frame) => retval.set(v8_uint8array(scope, frame).into()),
+        Err(error) => {
+            let exception = v8_exception(scope, &error.to_string());
+            scope.throw_exception(exception);
+        }
+    }
+}
+
+pub fn grpc_web_trailers_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let status = args.get(0).uint32_value(scope).unwrap_or(0);
+    let message = match args.get(1) {
+        message if message.is_string() => Some(message.to_rust_string_lossy(scope)),
+        _ => None,
+    };
+
+    // Sorted so the trailers are always sent in the same order
+    let mut trailers = extract_v8_headers_object(args.get(2), scope)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(name, values)| values.into_iter().map(move |value| (name.clone(), value)))
+        .collect::<Vec<_>>();
+    trailers.sort();
+
+    match encode_grpc_web_trailers(status, message.as_deref(), &trailers) {
+        Ok(frame) => retval.set(v8_uint8array(scope, frame).into()),
+        Err(error) => {
+            let exception = v8_exception(scope, &error.to_string());
+            scope.throw_exception(exception);
+        }
+    }
+}
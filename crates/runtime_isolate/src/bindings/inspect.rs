@@ -0,0 +1,482 @@
+use lagon_runtime_v8_utils::v8_string;
+
+use crate::Isolate;
+
+// Formats values logged with console.log() and friends like Node's util.inspect(),
+// on a single line. Objects nested deeper than `max_depth` are replaced with
+// `[Object]`, and only the first `max_length` entries of each object are shown.
+// No more entries are added once the output reaches `max_output_size` bytes,
+// the logged message being truncated to this size afterwards
+struct Inspector<'s> {
+    max_depth: usize,
+    max_length: usize,
+    max_string_length: usize,
+    max_output_size: usize,
+    // Size of the entries formatted so far
+    output_size: usize,
+    // The objects currently being formatted, to detect circular references
+    seen: Vec<v8::Local<'s, v8::Object>>,
+}
+
+// Truncate a message to `max_size` bytes, on a character boundary
+pub fn truncate_output(message: &mut String, max_size: usize) {
+    if message.len() <= max_size {
+        return;
+    }
+
+    let mut end = max_size;
+
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let truncated = message.len() - end;
+    message.truncate(end);
+    message.push_str(&format!("... {} more bytes", truncated));
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    match chars.next() {
+        Some(char) if char.is_ascii_alphabetic() || char == '_' || char == '$' => {
+            chars.all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '$')
+        }
+        _ => false,
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!(
+        "'{}'",
+        value
+            .replace('\\', "\\\\")
+            .replace('\'', "\\'")
+            .replace('\n', "\\n")
+    )
+}
+
+fn format_entries(prefix: &str, entries: Vec<String>, remaining: usize, empty: &str) -> String {
+    if entries.is_empty() && remaining == 0 {
+        return match prefix.is_empty() {
+            true => empty.to_string(),
+            false => format!("{} {}", prefix, empty),
+        };
+    }
+
+    let mut entries = entries;
+
+    if remaining > 0 {
+        entries.push(format!(
+            "... {} more item{}",
+            remaining,
+            if remaining > 1 { "s" } else { "" }
+        ));
+    }
+
+    let (open, close) = empty.split_at(1);
+    let entries = format!("{} {} {}", open, entries.join(", "), close);
+
+    match prefix.is_empty() {
+        true => entries,
+        false => format!("{} {}", prefix, entries),
+    }
+}
+
+fn call_method<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    object: v8::Local<'s, v8::Object>,
+    name: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let key = v8_string(scope, name);
+    let method = object.get(scope, key.into())?;
+    let method = v8::Local::<v8::Function>::try_from(method).ok()?;
+
+    method.call(scope, object.into(), &[])
+}
+
+impl<'s> Inspector<'s> {
+    fn inspect(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+        depth: usize,
+    ) -> String {
+        if value.is_string() {
+            let value = value.to_rust_string_lossy(scope);
+
+            // Only nested strings are quoted
+            if depth == 0 {
+                return value;
+            }
+
+            return match value.char_indices().nth(self.max_string_length) {
+                Some((end, _)) => {
+                    let remaining = value[end..].chars().count();
+
+                    format!(
+                        "{}... {} more character{}",
+                        quote(&value[..end]),
+                        remaining,
+                        if remaining > 1 { "s" } else { "" }
+                    )
+                }
+                None => quote(&value),
+            };
+        }
+
+        if value.is_big_int() {
+            return value.to_rust_string_lossy(scope) + "n";
+        }
+
+        if value.is_symbol() {
+            return value.to_detail_string(scope).map_or_else(
+                || "Symbol()".into(),
+                |value| value.to_rust_string_lossy(scope),
+            );
+        }
+
+        if value.is_function() {
+            let function = v8::Local::<v8::Function>::try_from(value).unwrap();
+            let name = function.get_name(scope).to_rust_string_lossy(scope);
+
+            return match name.is_empty() {
+                true => "[Function (anonymous)]".into(),
+                false => format!("[Function: {}]", name),
+            };
+        }
+
+        // Numbers, booleans, null and undefined
+        if !value.is_object() {
+            return value.to_rust_string_lossy(scope);
+        }
+
+        let object = match value.to_object(scope) {
+            Some(object) => object,
+            None => return value.to_rust_string_lossy(scope),
+        };
+
+        if self.seen.iter().any(|seen| seen.strict_equals(value)) {
+            return "[Circular]".into();
+        }
+
+        if value.is_date() {
+            return call_method(scope, object, "toISOString").map_or_else(
+                || "Invalid Date".into(),
+                |value| value.to_rust_string_lossy(scope),
+            );
+        }
+
+        if value.is_reg_exp() {
+            return value.to_rust_string_lossy(scope);
+        }
+
+        if value.is_native_error() {
+            let stack_key = v8_string(scope, "stack");
+
+            return match object.get(scope, stack_key.into()) {
+                Some(stack) if stack.is_string() => stack.to_rust_string_lossy(scope),
+                _ => value.to_rust_string_lossy(scope),
+            };
+        }
+
+        if value.is_promise() {
+            let promise = v8::Local::<v8::Promise>::try_from(value).unwrap();
+
+            return match promise.state() {
+                v8::PromiseState::Pending => "Promise { <pending> }".into(),
+                v8::PromiseState::Fulfilled => {
+                    let result = promise.result(scope);
+                    format!(
+                        "Promise {{ {} }}",
+                        self.inspect_nested(scope, object, result, depth)
+                    )
+                }
+                v8::PromiseState::Rejected => {
+                    let result = promise.result(scope);
+                    format!(
+                        "Promise {{ <rejected> {} }}",
+                        self.inspect_nested(scope, object, result, depth)
+                    )
+                }
+            };
+        }
+
+        if value.is_array_buffer() {
+            let byte_length_key = v8_string(scope, "byteLength");
+            let byte_length = object
+                .get(scope, byte_length_key.into())
+                .map_or_else(|| "0".into(), |value| value.to_rust_string_lossy(scope));
+
+            return format!("ArrayBuffer {{ byteLength: {} }}", byte_length);
+        }
+
+        let constructor_name = object.get_constructor_name().to_rust_string_lossy(scope);
+
+        if depth > self.max_depth {
+            return match value.is_array() {
+                true => "[Array]".into(),
+                false => format!("[{}]", constructor_name),
+            };
+        }
+
+        self.seen.push(object);
+
+        let result = if value.is_array() || value.is_typed_array() {
+            self.inspect_array(scope, object, &constructor_name, depth)
+        } else if value.is_map() {
+            self.inspect_map(scope, value, depth)
+        } else if value.is_set() {
+            self.inspect_set(scope, object, depth)
+        } else {
+            self.inspect_object(scope, object, &constructor_name, depth)
+        };
+
+        self.seen.pop();
+
+        result
+    }
+
+    // Inspect an entry of an object, accounting for its size in the output
+    fn inspect_entry(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+        depth: usize,
+    ) -> String {
+        let output_size = self.output_size;
+        let entry = self.inspect(scope, value, depth + 1);
+
+        // Replaces the size of the nested entries, which are part of this one
+        self.output_size = output_size + entry.len() + 2;
+
+        entry
+    }
+
+    fn is_full(&self) -> bool {
+        self.output_size >= self.max_output_size
+    }
+
+    // Inspect a value contained by `parent`, which is not in `seen` yet
+    fn inspect_nested(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        parent: v8::Local<'s, v8::Object>,
+        value: v8::Local<'s, v8::Value>,
+        depth: usize,
+    ) -> String {
+        self.seen.push(parent);
+        let result = self.inspect(scope, value, depth + 1);
+        self.seen.pop();
+
+        result
+    }
+
+    fn inspect_array(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+        constructor_name: &str,
+        depth: usize,
+    ) -> String {
+        let length_key = v8_string(scope, "length");
+        let length = object
+            .get(scope, length_key.into())
+            .and_then(|length| length.uint32_value(scope))
+            .unwrap_or(0) as usize;
+        let mut shown = length.min(self.max_length);
+
+        let mut entries = Vec::with_capacity(shown);
+
+        for index in 0..shown {
+            if self.is_full() {
+                shown = index;
+                break;
+            }
+
+            let entry = object
+                .get_index(scope, index as u32)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+
+            entries.push(self.inspect_entry(scope, entry, depth));
+        }
+
+        // Arrays are the default, so only their length is shown
+        let prefix = match constructor_name {
+            "Array" => match length > shown {
+                true => format!("Array({})", length),
+                false => String::new(),
+            },
+            _ => format!("{}({})", constructor_name, length),
+        };
+
+        format_entries(&prefix, entries, length - shown, "[]")
+    }
+
+    fn inspect_map(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        value: v8::Local<'s, v8::Value>,
+        depth: usize,
+    ) -> String {
+        let map = v8::Local::<v8::Map>::try_from(value).unwrap();
+        let size = map.size();
+        let entries_array = map.as_array(scope);
+        let mut shown = size.min(self.max_length);
+
+        let mut entries = Vec::with_capacity(shown);
+
+        for index in 0..shown {
+            if self.is_full() {
+                shown = index;
+                break;
+            }
+
+            let key = entries_array
+                .get_index(scope, (index * 2) as u32)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            let value = entries_array
+                .get_index(scope, (index * 2 + 1) as u32)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+
+            entries.push(format!(
+                "{} => {}",
+                self.inspect_entry(scope, key, depth),
+                self.inspect_entry(scope, value, depth)
+            ));
+        }
+
+        format_entries(&format!("Map({})", size), entries, size - shown, "{}")
+    }
+
+    fn inspect_set(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+        depth: usize,
+    ) -> String {
+        let size_key = v8_string(scope, "size");
+        let size = object
+            .get(scope, size_key.into())
+            .and_then(|size| size.uint32_value(scope))
+            .unwrap_or(0) as usize;
+        let mut shown = size.min(self.max_length);
+
+        let mut entries = Vec::with_capacity(shown);
+
+        // Iterate the values of the set with Array.from()
+        let global = scope.get_current_context().global(scope);
+        let array_key = v8_string(scope, "Array");
+        let values = global
+            .get(scope, array_key.into())
+            .and_then(|array| array.to_object(scope))
+            .and_then(|array| {
+                let from_key = v8_string(scope, "from");
+                let from = array.get(scope, from_key.into())?;
+                let from = v8::Local::<v8::Function>::try_from(from).ok()?;
+
+                from.call(scope, array.into(), &[object.into()])
+            })
+            .and_then(|values| values.to_object(scope));
+
+        if let Some(values) = values {
+            for index in 0..shown {
+                if self.is_full() {
+                    shown = index;
+                    break;
+                }
+
+                let entry = values
+                    .get_index(scope, index as u32)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+
+                entries.push(self.inspect_entry(scope, entry, depth));
+            }
+        }
+
+        format_entries(&format!("Set({})", size), entries, size - shown, "{}")
+    }
+
+    fn inspect_object(
+        &mut self,
+        scope: &mut v8::HandleScope<'s>,
+        object: v8::Local<'s, v8::Object>,
+        constructor_name: &str,
+        depth: usize,
+    ) -> String {
+        let keys = object
+            .get_own_property_names(scope, Default::default())
+            .unwrap_or_else(|| v8::Array::new(scope, 0));
+        let length = keys.length() as usize;
+        let mut shown = length.min(self.max_length);
+
+        let mut entries = Vec::with_capacity(shown);
+
+        for index in 0..shown {
+            if self.is_full() {
+                shown = index;
+                break;
+            }
+
+            let key = match keys.get_index(scope, index as u32) {
+                Some(key) => key,
+                None => continue,
+            };
+            let value = object
+                .get(scope, key)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+
+            let key = key.to_rust_string_lossy(scope);
+            let key = match is_identifier(&key) {
+                true => key,
+                false => quote(&key),
+            };
+
+            let value = self.inspect_entry(scope, value, depth);
+            self.output_size += key.len();
+
+            entries.push(format!("{}: {}", key, value));
+        }
+
+        // Plain objects don't show their constructor
+        let prefix = match constructor_name {
+            "Object" => "",
+            constructor_name => constructor_name,
+        };
+
+        format_entries(prefix, entries, length - shown, "{}")
+    }
+}
+
+pub fn inspect_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let (max_depth, max_length, max_string_length, max_output_size) = {
+        let state = Isolate::state(scope);
+        let state = state.borrow();
+
+        (
+            state.inspect_max_depth,
+            state.inspect_max_length,
+            state.inspect_max_string_length,
+            state.inspect_max_output_size,
+        )
+    };
+
+    // Getters can throw while being inspected
+    let scope = &mut v8::TryCatch::new(scope);
+    let mut inspector = Inspector {
+        max_depth,
+        max_length,
+        max_string_length,
+        max_output_size,
+        output_size: 0,
+        seen: Vec::new(),
+    };
+
+    let value = v8::Local::new(scope, args.get(0));
+    let result = inspector.inspect(scope, value, 0);
+    retval.set(v8_string(scope, &result).into());
+}
@@ -0,0 +1,91 @@
+use lagon_runtime_v8_utils::v8_exception;
+
+use crate::Isolate;
+
+// Scan the nesting depth without parsing, ignoring brackets inside strings
+fn exceeds_max_depth(json: &[u8], max_depth: usize) -> bool {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for byte in json {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            // Invalid JSON is reported by V8 afterwards
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    false
+}
+
+// Checked by request.json() against the Content-Length and while reading the body
+pub fn json_max_size_binding(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let max_size = Isolate::state(scope).borrow().json_max_size;
+
+    retval.set(v8::Number::new(scope, max_size as f64).into());
+}
+
+pub fn parse_json_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let (max_size, max_depth) = {
+        let state = Isolate::state(scope);
+        let state = state.borrow();
+
+        (state.json_max_size, state.json_max_depth)
+    };
+
+    let json = match args.get(0).to_string(scope) {
+        Some(json) => json,
+        None => return,
+    };
+
+    if json.utf8_length(scope) > max_size {
+        let exception = v8_exception(
+            scope,
+            &format!("JSON body exceeds the maximum size of {max_size} bytes"),
+        );
+        scope.throw_exception(exception);
+        return;
+    }
+
+    if exceeds_max_depth(json.to_rust_string_lossy(scope).as_bytes(), max_depth) {
+        let exception = v8_exception(
+            scope,
+            &format!("JSON body exceeds the maximum nesting depth of {max_depth}"),
+        );
+        scope.throw_exception(exception);
+        return;
+    }
+
+    // A SyntaxError is thrown by V8 when the JSON is invalid
+    if let Some(value) = v8::json::parse(scope, json) {
+        retval.set(value);
+    }
+}
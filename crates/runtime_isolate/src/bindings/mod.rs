@@ -1,24 +1,61 @@
-use console::console_binding;
+use accept_language::{match_locale_binding, parse_accept_language_binding};
+use body::max_body_size_binding;
+use console::{
+    console_binding, console_count_binding, console_count_reset_binding, console_time_binding,
+    console_time_end_binding, stack_trace_binding,
+};
 use crypto::{
     decrypt_binding, decrypt_init, digest_binding, encrypt_binding, encrypt_init,
     get_key_value_binding, random_values_binding, sign_binding, sign_init, uuid_binding,
     verify_binding, verify_init,
 };
-use fetch::{fetch_binding, fetch_init};
+use decode_base64::decode_base64_binding;
+use fetch::{
+    fetch_binding, fetch_init, pull_fetch_body_binding, read_fetch_body_binding,
+    read_fetch_body_init, read_fetch_trailers_binding, read_fetch_trailers_init,
+};
+use grpc_web::{
+    grpc_web_message_binding, grpc_web_trailers_binding, is_grpc_web_content_type_binding,
+};
+use inspect::inspect_binding;
+use json::{json_max_size_binding, parse_json_binding};
 use lagon_runtime_http::{IntoV8, Response};
-use lagon_runtime_v8_utils::{v8_boolean, v8_string, v8_uint8array};
+use lagon_runtime_v8_utils::{
+    v8_boolean, v8_exception, v8_headers_object, v8_integer, v8_string, v8_uint8array,
+};
+use multipart::multipart_boundary_binding;
 use pull_stream::pull_stream_binding;
 use queue_microtask::queue_microtask_binding;
 use sleep::{sleep_binding, sleep_init};
+use std::collections::HashMap;
+use wait_until::wait_until_binding;
+use websocket::{
+    upgrade_websocket_binding, websocket_close_binding, websocket_receive_binding,
+    websocket_receive_init, websocket_send_binding,
+};
+use zip::{
+    zip_central_directory_header_binding, zip_crc32_binding, zip_data_descriptor_binding,
+    zip_deflate_binding, zip_end_of_central_directory_binding, zip_local_header_binding,
+};
 
-use crate::{bindings::crypto::digest_init, Isolate};
+use crate::{bindings::crypto::digest_init, fetch_timing::FetchTiming, Isolate};
 
+pub mod accept_language;
+pub mod body;
 pub mod console;
 pub mod crypto;
+pub mod decode_base64;
 pub mod fetch;
+pub mod grpc_web;
+pub mod inspect;
+pub mod json;
+pub mod multipart;
 pub mod pull_stream;
 pub mod queue_microtask;
 pub mod sleep;
+pub mod wait_until;
+pub mod websocket;
+pub mod zip;
 
 pub struct BindingResult {
     pub id: usize,
@@ -26,25 +63,85 @@ pub struct BindingResult {
 }
 
 pub enum PromiseResult {
-    Response(Response),
+    // A response with a body that can be read using its id
+    FetchResponse(Response, u32, FetchTiming),
     ArrayBuffer(Vec<u8>),
+    Headers(HashMap<String, Vec<String>>),
+    String(String),
     Boolean(bool),
     Error(String),
+    TypeError(String),
+    // An Error named `TimeoutError`, like the ones of AbortSignal.timeout()
+    TimeoutError(String),
     Undefined,
 }
 
 impl PromiseResult {
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            PromiseResult::Error(_) | PromiseResult::TypeError(_) | PromiseResult::TimeoutError(_)
+        )
+    }
+
     pub fn into_value<'a>(self, scope: &mut v8::HandleScope<'a>) -> v8::Local<'a, v8::Value> {
         match self {
-            PromiseResult::Response(response) => response.into_v8(scope).into(),
+            PromiseResult::FetchResponse(response, body_id, timing) => {
+                let response = response.into_v8(scope);
+                let body_id_key = v8_string(scope, "f");
+                let body_id = v8_integer(scope, body_id as i32);
+                response.set(scope, body_id_key.into(), body_id.into());
+
+                let timing_key = v8_string(scope, "t");
+                let timing = fetch_timing_object(scope, timing);
+                response.set(scope, timing_key.into(), timing.into());
+
+                response.into()
+            }
             PromiseResult::ArrayBuffer(bytes) => v8_uint8array(scope, bytes).into(),
+            PromiseResult::Headers(headers) => v8_headers_object(scope, headers).into(),
+            PromiseResult::String(string) => v8_string(scope, &string).into(),
             PromiseResult::Boolean(boolean) => v8_boolean(scope, boolean).into(),
             PromiseResult::Error(error) => v8_string(scope, &error).into(),
+            PromiseResult::TypeError(error) => v8_exception(scope, &error),
+            PromiseResult::TimeoutError(error) => {
+                let message = v8_string(scope, &error);
+                let exception = v8::Exception::error(scope, message);
+
+                if let Some(object) = exception.to_object(scope) {
+                    let name_key = v8_string(scope, "name");
+                    let name = v8_string(scope, "TimeoutError");
+                    object.set(scope, name_key.into(), name.into());
+                }
+
+                exception
+            }
             PromiseResult::Undefined => v8::undefined(scope).into(),
         }
     }
 }
 
+fn fetch_timing_object<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    timing: FetchTiming,
+) -> v8::Local<'a, v8::Object> {
+    let object = v8::Object::new(scope);
+
+    for (key, value) in [
+        ("dns", timing.dns),
+        ("connect", timing.connect),
+        ("tls", timing.tls),
+        ("ttfb", timing.ttfb),
+        ("total", timing.total),
+    ] {
+        let key = v8_string(scope, key);
+        let value = v8::Number::new(scope, value);
+        object.set(scope, key.into(), value.into());
+    }
+
+    object
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum BindStrategy {
     All,
@@ -71,10 +168,26 @@ macro_rules! async_binding {
 
             let isolate_state = Isolate::state(scope);
             let mut state = isolate_state.borrow_mut();
+
+            // Fetches, timers, crypto, etc all count towards the limit
+            if let Some(max_pending_ops) = state.max_pending_ops {
+                if state.promises.len() >= max_pending_ops {
+                    drop(state);
+
+                    let error = v8_exception(scope, "Too many pending async operations");
+                    promise.reject(scope, error);
+                    return;
+                }
+            }
+
             let id = state.js_promises.len() + 1;
+            let request_id = scope
+                .get_continuation_preserved_embedder_data()
+                .to_uint32(scope)
+                .map_or(0, |value| value.value());
 
             let global_promise = v8::Global::new(scope, promise);
-            state.js_promises.insert(id, global_promise);
+            state.js_promises.insert(id, (global_promise, request_id));
 
             // Drop the state so we can borrow
             // it mutably inside init()
@@ -83,8 +196,14 @@ macro_rules! async_binding {
             match $init(scope, args) {
                 Ok(args) => {
                     let future = $binding(id, args);
+                    let mut state = isolate_state.borrow_mut();
+
+                    // Counted in the time the request spends waiting
+                    if let Some(handler_result) = state.handler_results.get_mut(&request_id) {
+                        handler_result.context.start_async_op();
+                    }
 
-                    isolate_state.borrow_mut().promises.push(Box::pin(future));
+                    state.promises.push(Box::pin(future));
                 }
                 Err(error) => {
                     let error = v8_string(scope, &error.to_string());
@@ -110,6 +229,22 @@ pub fn bind<'a>(
 
     if bind_strategy == BindStrategy::All || bind_strategy == BindStrategy::Sync {
         binding!(scope, lagon_object, "log", console_binding);
+        binding!(scope, lagon_object, "stackTrace", stack_trace_binding);
+        binding!(scope, lagon_object, "inspect", inspect_binding);
+        binding!(scope, lagon_object, "consoleTime", console_time_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "consoleTimeEnd",
+            console_time_end_binding
+        );
+        binding!(scope, lagon_object, "consoleCount", console_count_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "consoleCountReset",
+            console_count_reset_binding
+        );
         binding!(scope, lagon_object, "pullStream", pull_stream_binding);
         binding!(scope, lagon_object, "uuid", uuid_binding);
         binding!(scope, lagon_object, "randomValues", random_values_binding);
@@ -120,6 +255,87 @@ pub fn bind<'a>(
             "queueMicrotask",
             queue_microtask_binding
         );
+        binding!(
+            scope,
+            lagon_object,
+            "pullFetchBody",
+            pull_fetch_body_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "upgradeWebSocket",
+            upgrade_websocket_binding
+        );
+        binding!(scope, lagon_object, "webSocketSend", websocket_send_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "webSocketClose",
+            websocket_close_binding
+        );
+        binding!(scope, lagon_object, "waitUntil", wait_until_binding);
+        binding!(scope, lagon_object, "parseJson", parse_json_binding);
+        binding!(scope, lagon_object, "jsonMaxSize", json_max_size_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "parseAcceptLanguage",
+            parse_accept_language_binding
+        );
+        binding!(scope, lagon_object, "matchLocale", match_locale_binding);
+        binding!(scope, lagon_object, "maxBodySize", max_body_size_binding);
+        binding!(scope, lagon_object, "decodeBase64", decode_base64_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "multipartBoundary",
+            multipart_boundary_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "isGrpcWebContentType",
+            is_grpc_web_content_type_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "grpcWebMessage",
+            grpc_web_message_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "grpcWebTrailers",
+            grpc_web_trailers_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "zipLocalHeader",
+            zip_local_header_binding
+        );
+        binding!(scope, lagon_object, "zipCrc32", zip_crc32_binding);
+        binding!(scope, lagon_object, "zipDeflate", zip_deflate_binding);
+        binding!(
+            scope,
+            lagon_object,
+            "zipDataDescriptor",
+            zip_data_descriptor_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "zipCentralDirectoryHeader",
+            zip_central_directory_header_binding
+        );
+        binding!(
+            scope,
+            lagon_object,
+            "zipEndOfCentralDirectory",
+            zip_end_of_central_directory_binding
+        );
 
         global.set(v8_string(scope, "LagonSync").into(), lagon_object.into());
     }
@@ -146,6 +362,27 @@ pub fn bind<'a>(
             decrypt_binding
         );
         async_binding!(scope, lagon_object, "sleep", sleep_init, sleep_binding);
+        async_binding!(
+            scope,
+            lagon_object,
+            "readFetchBody",
+            read_fetch_body_init,
+            read_fetch_body_binding
+        );
+        async_binding!(
+            scope,
+            lagon_object,
+            "readFetchTrailers",
+            read_fetch_trailers_init,
+            read_fetch_trailers_binding
+        );
+        async_binding!(
+            scope,
+            lagon_object,
+            "webSocketReceive",
+            websocket_receive_init,
+            websocket_receive_binding
+        );
 
         global.set(v8_string(scope, "LagonAsync").into(), lagon_object.into());
     }
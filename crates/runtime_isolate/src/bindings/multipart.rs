@@ -0,0 +1,12 @@
+use lagon_runtime_http::multipart_boundary;
+use lagon_runtime_v8_utils::v8_string;
+
+pub fn multipart_boundary_binding(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let boundary = v8_string(scope, &multipart_boundary());
+
+    retval.set(boundary.into());
+}
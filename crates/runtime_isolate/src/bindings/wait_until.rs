@@ -0,0 +1,27 @@
+use lagon_runtime_v8_utils::v8_exception;
+use std::time::Instant;
+
+use crate::{Isolate, WaitUntil};
+
+pub fn wait_until_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut _retval: v8::ReturnValue,
+) {
+    let promise = match v8::Local::<v8::Promise>::try_from(args.get(0)) {
+        Ok(promise) => promise,
+        Err(_) => {
+            let exception = v8_exception(scope, "waitUntil() expects a Promise");
+            scope.throw_exception(exception);
+            return;
+        }
+    };
+
+    let promise = v8::Global::new(scope, promise);
+    let state = Isolate::state(scope);
+
+    state.borrow_mut().wait_until.push(WaitUntil {
+        promise,
+        start_time: Instant::now(),
+    });
+}
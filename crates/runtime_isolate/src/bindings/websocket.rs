@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+use lagon_runtime_http::WebSocketMessage;
+use lagon_runtime_v8_utils::{extract_v8_uint8array, v8_exception, v8_integer};
+
+use crate::{bindings::PromiseResult, Isolate};
+
+use super::BindingResult;
+
+pub fn upgrade_websocket_binding(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let id = scope
+        .get_continuation_preserved_embedder_data()
+        .to_uint32(scope)
+        .map_or(0, |value| value.value());
+
+    let isolate_state = Isolate::state(scope);
+    let is_websocket = isolate_state.borrow().websockets.contains_key(&id);
+
+    if !is_websocket {
+        let exception = v8_exception(scope, "Request is not a WebSocket upgrade request");
+        scope.throw_exception(exception);
+        return;
+    }
+
+    retval.set(v8_integer(scope, id as i32).into());
+}
+
+pub fn websocket_send_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut _retval: v8::ReturnValue,
+) {
+    let id = args.get(0).uint32_value(scope).unwrap_or(0);
+    let data = args.get(1);
+
+    let message = if data.is_string() {
+        WebSocketMessage::Text(data.to_rust_string_lossy(scope))
+    } else {
+        match extract_v8_uint8array(data) {
+            Ok(buf) => WebSocketMessage::Binary(buf),
+            Err(error) => {
+                let exception = v8_exception(scope, error.to_string().as_str());
+                scope.throw_exception(exception);
+                return;
+            }
+        }
+    };
+
+    let isolate_state = Isolate::state(scope);
+    let state = isolate_state.borrow();
+
+    match state.websockets.get(&id) {
+        Some(websocket) => websocket.outgoing.send(message).unwrap_or(()),
+        None => {
+            drop(state);
+
+            let exception = v8_exception(scope, "WebSocket is closed");
+            scope.throw_exception(exception);
+        }
+    }
+}
+
+pub fn websocket_close_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut _retval: v8::ReturnValue,
+) {
+    let id = args.get(0).uint32_value(scope).unwrap_or(0);
+
+    let isolate_state = Isolate::state(scope);
+    let mut state = isolate_state.borrow_mut();
+
+    if let Some(websocket) = state.websockets.remove(&id) {
+        websocket
+            .outgoing
+            .send(WebSocketMessage::Close)
+            .unwrap_or(());
+    }
+}
+
+pub fn websocket_receive_init(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+) -> Result<flume::Receiver<WebSocketMessage>> {
+    let id = match args.get(0).uint32_value(scope) {
+        Some(id) => id,
+        None => return Err(anyhow!("Invalid WebSocket id")),
+    };
+
+    let state = Isolate::state(scope);
+    let state = state.borrow();
+
+    match state.websockets.get(&id) {
+        Some(websocket) => Ok(websocket.incoming.clone()),
+        None => Err(anyhow!("WebSocket is closed")),
+    }
+}
+
+pub async fn websocket_receive_binding(
+    id: usize,
+    incoming: flume::Receiver<WebSocketMessage>,
+) -> BindingResult {
+    // The connection being closed by the client, or dropped
+    // by the host, both resolve with undefined
+    let result = match incoming.recv_async().await {
+        Ok(WebSocketMessage::Text(text)) => PromiseResult::String(text),
+        Ok(WebSocketMessage::Binary(bytes)) => PromiseResult::ArrayBuffer(bytes),
+        Ok(WebSocketMessage::Close) | Err(_) => PromiseResult::Undefined,
+    };
+
+    BindingResult { id, result }
+}
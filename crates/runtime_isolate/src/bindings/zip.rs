@@ -0,0 +1,131 @@
+use anyhow::Result;
+use lagon_runtime_http::{
+    deflate_zip_chunk, encode_zip_central_directory_header, encode_zip_data_descriptor,
+    encode_zip_end_of_central_directory, encode_zip_local_header, to_zip_size, update_zip_crc,
+    ZipCompression, ZipEntryRecord,
+};
+use lagon_runtime_v8_utils::{extract_v8_uint8array, v8_exception, v8_uint8array};
+
+fn extract_compression(value: v8::Local<v8::Value>) -> ZipCompression {
+    match value.is_true() {
+        true => ZipCompression::Deflate,
+        false => ZipCompression::Store,
+    }
+}
+
+fn extract_size(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+    what: &str,
+) -> Result<u32> {
+    let size = value.number_value(scope).unwrap_or_default();
+
+    to_zip_size(size as u64, what)
+}
+
+// The entry is passed as the arguments `name, deflate, crc, compressedSize,
+// uncompressedSize, offset`
+fn extract_entry_record(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+) -> Result<ZipEntryRecord> {
+    Ok(ZipEntryRecord {
+        name: args.get(0).to_rust_string_lossy(scope),
+        compression: extract_compression(args.get(1)),
+        crc: args.get(2).uint32_value(scope).unwrap_or_default(),
+        compressed_size: extract_size(scope, args.get(3), "entry")?,
+        uncompressed_size: extract_size(scope, args.get(4), "entry")?,
+        offset: extract_size(scope, args.get(5), "archive")?,
+    })
+}
+
+fn set_bytes_or_throw(
+    scope: &mut v8::HandleScope,
+    mut retval: v8::ReturnValue,
+    bytes: Result<Vec<u8>>,
+) {
+    match bytes {
+        Ok(bytes) => retval.set(v8_uint8array(scope, bytes).into()),
+        Err(error) => {
+            let exception = v8_exception(scope, &error.to_string());
+            scope.throw_exception(exception);
+        }
+    }
+}
+
+pub fn zip_local_header_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    retval: v8::ReturnValue,
+) {
+    let name = args.get(0).to_rust_string_lossy(scope);
+    let header = encode_zip_local_header(&name, extract_compression(args.get(1)));
+
+    set_bytes_or_throw(scope, retval, header);
+}
+
+pub fn zip_crc32_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let crc = args.get(0).uint32_value(scope).unwrap_or_default();
+
+    match extract_v8_uint8array(args.get(1)) {
+        Ok(chunk) => {
+            let crc = v8::Integer::new_from_unsigned(scope, update_zip_crc(crc, &chunk));
+            retval.set(crc.into());
+        }
+        Err(error) => {
+            let exception = v8_exception(scope, &error.to_string());
+            scope.throw_exception(exception);
+        }
+    }
+}
+
+pub fn zip_deflate_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    retval: v8::ReturnValue,
+) {
+    let last = args.get(1).is_true();
+    let data = extract_v8_uint8array(args.get(0)).and_then(|chunk| deflate_zip_chunk(&chunk, last));
+
+    set_bytes_or_throw(scope, retval, data);
+}
+
+pub fn zip_data_descriptor_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    retval: v8::ReturnValue,
+) {
+    let descriptor =
+        extract_entry_record(scope, &args).map(|entry| encode_zip_data_descriptor(&entry));
+
+    set_bytes_or_throw(scope, retval, descriptor);
+}
+
+pub fn zip_central_directory_header_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    retval: v8::ReturnValue,
+) {
+    let header = extract_entry_record(scope, &args)
+        .and_then(|entry| encode_zip_central_directory_header(&entry));
+
+    set_bytes_or_throw(scope, retval, header);
+}
+
+pub fn zip_end_of_central_directory_binding(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    retval: v8::ReturnValue,
+) {
+    let entries = args.get(0).uint32_value(scope).unwrap_or_default() as usize;
+    let size = args.get(1).number_value(scope).unwrap_or_default() as u64;
+    let offset = args.get(2).number_value(scope).unwrap_or_default() as u64;
+
+    let end = encode_zip_end_of_central_directory(entries, size, offset);
+
+    set_bytes_or_throw(scope, retval, end);
+}
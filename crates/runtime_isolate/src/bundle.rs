@@ -0,0 +1,56 @@
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use crate::options::IsolateOptions;
+
+// Computed once when the deployment's code is evaluated,
+// to help diagnose deployments issues
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleMetadata {
+    // Hex-encoded SHA-256 of the deployment's code and modules
+    pub hash: String,
+    // In bytes, including the modules
+    pub size: usize,
+    // Spent compiling the code and its modules
+    pub compilation_time: Duration,
+    // Spent running the top-level code of the modules, excluding compilation
+    pub evaluation_time: Duration,
+    // Whether the compilation used a code cache of a previous isolate
+    pub code_cache: bool,
+    pub modules: usize,
+    pub has_source_map: bool,
+}
+
+impl BundleMetadata {
+    pub fn new(
+        options: &IsolateOptions,
+        compilation_time: Duration,
+        evaluation_time: Duration,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(options.code.as_bytes());
+
+        // Sort the modules to always get the same hash
+        let mut modules = options.modules.iter().collect::<Vec<_>>();
+        modules.sort();
+
+        for (path, source) in &modules {
+            hasher.update(path.as_bytes());
+            hasher.update(source.as_bytes());
+        }
+
+        Self {
+            hash: format!("{:x}", hasher.finalize()),
+            size: options.code.len()
+                + modules
+                    .iter()
+                    .map(|(_, source)| source.len())
+                    .sum::<usize>(),
+            compilation_time,
+            evaluation_time,
+            code_cache: false,
+            modules: modules.len() + 1,
+            has_source_map: options.source_map.is_some(),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use lagon_runtime_v8_utils::v8_string;
+use std::time::Instant;
 
-use crate::get_exception_message;
+use crate::{get_exception_message, resolve_module_path, MODULES_BASE_URL};
 
 use super::Isolate;
 
@@ -28,8 +29,11 @@ pub extern "C" fn promise_reject_callback(message: v8::PromiseRejectMessage) {
         v8::PromiseRejectEvent::PromiseRejectWithNoHandler => {
             let try_catch = &mut v8::TryCatch::new(scope);
 
+            let source_map = state.source_map.clone();
             let exception_message = match message.get_value() {
-                Some(exception) => get_exception_message(try_catch, exception, state.lines),
+                Some(exception) => {
+                    get_exception_message(try_catch, exception, state.lines, source_map.as_deref())
+                }
                 None => "Unknown error".to_string(),
             };
 
@@ -42,24 +46,128 @@ pub extern "C" fn promise_reject_callback(message: v8::PromiseRejectMessage) {
     }
 }
 
-// We don't allow imports at all, so we return None and throw an error
-// so it can be catched later. As the error message suggests, all code
-// should be bundled into a single file.
+// Imports are resolved against the modules provided with the isolate's options.
+// Modules are compiled once and cached using their path, which also allows
+// circular imports. Unknown modules return None and throw an error so it
+// can be catched later.
 pub fn resolve_module_callback<'a>(
     context: v8::Local<'a, v8::Context>,
-    _: v8::Local<'a, v8::String>,
+    specifier: v8::Local<'a, v8::String>,
     _: v8::Local<'a, v8::FixedArray>,
-    _: v8::Local<'a, v8::Module>,
+    referrer: v8::Local<'a, v8::Module>,
 ) -> Option<v8::Local<'a, v8::Module>> {
     let scope = &mut unsafe { v8::CallbackScope::new(context) };
+    let specifier = specifier.to_rust_string_lossy(scope);
 
-    let message = v8_string(
-        scope,
-        "Can't import modules, everything should be bundled in a single file",
+    let isolate_state = Isolate::state(scope);
+    let (path, source) = {
+        let state = isolate_state.borrow();
+        let referrer = state
+            .module_paths
+            .get(&referrer.get_identity_hash())
+            .cloned()
+            .unwrap_or_default();
+
+        match resolve_module_path(&referrer, &specifier) {
+            Some(path) => {
+                if let Some(module) = state.module_cache.get(&path) {
+                    return Some(v8::Local::new(scope, module));
+                }
+
+                let source = state.modules.get(&path).cloned();
+                (path, source)
+            }
+            None => (String::new(), None),
+        }
+    };
+
+    let source = match source {
+        Some(source) => source,
+        None => {
+            let message = match isolate_state.borrow().modules.is_empty() {
+                true => String::from(
+                    "Can't import modules, everything should be bundled in a single file",
+                ),
+                false => format!("Can't find module '{specifier}'"),
+            };
+
+            let message = v8_string(scope, &message);
+            let exception = v8::Exception::error(scope, message);
+            scope.throw_exception(exception);
+
+            return None;
+        }
+    };
+
+    let resource_name = v8_string(scope, &path);
+    let source_map_url = v8_string(scope, "");
+    let source = v8::script_compiler::Source::new(
+        v8_string(scope, &source),
+        Some(&v8::ScriptOrigin::new(
+            scope,
+            resource_name.into(),
+            0,
+            0,
+            false,
+            0,
+            source_map_url.into(),
+            false,
+            false,
+            true,
+        )),
     );
 
-    let exception = v8::Exception::error(scope, message);
-    scope.throw_exception(exception);
+    // Syntax errors are thrown by V8
+    let compilation_start = Instant::now();
+    let module = v8::script_compiler::compile_module(scope, source);
+    isolate_state.borrow_mut().compilation_time += compilation_start.elapsed();
+    let module = module?;
+
+    let mut state = isolate_state.borrow_mut();
+    state
+        .module_paths
+        .insert(module.get_identity_hash(), path.clone());
+    state
+        .module_cache
+        .insert(path, v8::Global::new(scope, module));
+
+    Some(module)
+}
+
+// Set `import.meta.url` to the module URL, and `import.meta.env` to the environment variables
+pub extern "C" fn import_meta_callback(
+    context: v8::Local<v8::Context>,
+    module: v8::Local<v8::Module>,
+    meta: v8::Local<v8::Object>,
+) {
+    let scope = &mut unsafe { v8::CallbackScope::new(context) };
+
+    let (path, import_meta_env) = {
+        let state = Isolate::state(scope);
+        let state = state.borrow();
+
+        (
+            state
+                .module_paths
+                .get(&module.get_identity_hash())
+                .cloned()
+                .unwrap_or_default(),
+            state.import_meta_env.clone(),
+        )
+    };
+
+    let url_key = v8_string(scope, "url");
+    let url = v8_string(scope, &format!("{MODULES_BASE_URL}{path}"));
+    meta.create_data_property(scope, url_key.into(), url.into());
+
+    let env = v8::Object::new(scope);
+
+    for (key, value) in import_meta_env {
+        let key = v8_string(scope, &key);
+        let value = v8_string(scope, &value);
+        env.create_data_property(scope, key.into(), value.into());
+    }
 
-    None
+    let env_key = v8_string(scope, "env");
+    meta.create_data_property(scope, env_key.into(), env.into());
 }
@@ -0,0 +1,137 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+// Distinguishes the temporary files written concurrently by the isolates of this process
+static TEMPORARY_FILES_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// V8 code caches of the compiled bundles, written to a directory so the next
+// isolates created for the same bundle skip most of the compilation. Caches are
+// keyed by the hash of the compiled source, the V8 version and the V8 flags,
+// since a cache produced for another bundle or V8 configuration is rejected
+#[derive(Debug, Clone)]
+pub struct CodeCache {
+    dir: PathBuf,
+    // The least recently used caches are removed once the directory grows past this size
+    max_size: Option<u64>,
+}
+
+impl CodeCache {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_size: None,
+        }
+    }
+
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn get_key(source: &str, v8_flags: &[String]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(v8::V8::get_version().as_bytes());
+        // Changes with the global V8 flags
+        hasher.update(v8::script_compiler::cached_data_version_tag().to_le_bytes());
+
+        for flag in v8_flags {
+            hasher.update(flag.as_bytes());
+            hasher.update([0]);
+        }
+
+        hasher.update(source.as_bytes());
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn get_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.bin"))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.get_path(key);
+        let data = fs::read(&path).ok()?;
+
+        // Mark the cache as recently used, to evict it last
+        if self.max_size.is_some() {
+            if let Ok(file) = File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+        }
+
+        Some(data)
+    }
+
+    // Caching is best-effort, so failing to write
+    // a cache only makes the next compilations slower
+    pub fn insert(&self, key: &str, data: &[u8]) {
+        if fs::create_dir_all(&self.dir).is_ok() {
+            // Write to a temporary file first, so other isolates
+            // never read a partially written cache
+            let path = self.get_path(key);
+            let temporary_path = path.with_extension(format!(
+                "{}.{}.tmp",
+                std::process::id(),
+                TEMPORARY_FILES_COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+
+            if fs::write(&temporary_path, data).is_err()
+                || fs::rename(&temporary_path, path).is_err()
+            {
+                let _ = fs::remove_file(&temporary_path);
+            }
+
+            self.evict();
+        }
+    }
+
+    fn evict(&self) {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return,
+        };
+
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        let mut caches = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+
+                if path.extension()? != "bin" {
+                    return None;
+                }
+
+                let metadata = entry.metadata().ok()?;
+
+                Some((path, metadata.len(), metadata.modified().ok()?))
+            })
+            .collect::<Vec<_>>();
+
+        let mut size = caches.iter().map(|(_, len, _)| len).sum::<u64>();
+
+        if size <= max_size {
+            return;
+        }
+
+        caches.sort_by_key(|(_, _, modified)| *modified);
+
+        for (path, len, _) in caches {
+            if size <= max_size {
+                break;
+            }
+
+            if fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
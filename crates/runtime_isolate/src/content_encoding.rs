@@ -0,0 +1,62 @@
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use futures::TryStreamExt;
+use hyper::Body;
+use lagon_runtime_http::Response;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentEncoding {
+    Gzip,
+    Brotli,
+    // Zlib-wrapped, as defined by RFC 9110
+    Deflate,
+}
+
+impl ContentEncoding {
+    // Multiple encodings (e.g `gzip, br`) are left as-is
+    fn from_response(response: &Response) -> Option<Self> {
+        match response
+            .get_header("content-encoding")?
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "br" => Some(Self::Brotli),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+}
+
+// Decompress the body of a fetch() response on the fly, like browsers do. The
+// Content-Encoding and Content-Length headers are removed since they describe
+// the encoded body. Responses without a body are returned untouched, and the
+// trailers of decoded bodies are dropped
+pub fn decode_body(response: &mut Response, body: Body, is_head: bool) -> Body {
+    if is_head || matches!(response.status, 204 | 304) {
+        return body;
+    }
+
+    let content_encoding = match ContentEncoding::from_response(response) {
+        Some(content_encoding) => content_encoding,
+        None => return body,
+    };
+
+    if let Some(headers) = &mut response.headers {
+        headers.retain(|name, _| {
+            !name.eq_ignore_ascii_case("content-encoding")
+                && !name.eq_ignore_ascii_case("content-length")
+        });
+    }
+
+    let reader = StreamReader::new(
+        body.map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error)),
+    );
+
+    match content_encoding {
+        ContentEncoding::Gzip => Body::wrap_stream(ReaderStream::new(GzipDecoder::new(reader))),
+        ContentEncoding::Brotli => Body::wrap_stream(ReaderStream::new(BrotliDecoder::new(reader))),
+        ContentEncoding::Deflate => Body::wrap_stream(ReaderStream::new(ZlibDecoder::new(reader))),
+    }
+}
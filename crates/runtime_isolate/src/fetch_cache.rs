@@ -0,0 +1,242 @@
+use crate::host_memory::{HostAllocation, HostMemory};
+use lagon_runtime_http::{Request, Response};
+use linked_hash_map::LinkedHashMap;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Larger responses are returned without being stored
+pub const FETCH_CACHE_MAX_ENTRY_SIZE: usize = 1024 * 1024;
+
+// Always part of the key, so responses to credentialed requests are never
+// served to other credentials
+const CREDENTIAL_HEADERS: [&str; 2] = ["authorization", "cookie"];
+
+// The `cache` option of fetch(), how the request interacts with the fetch cache
+// https://fetch.spec.whatwg.org/#concept-request-cache-mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchCacheMode {
+    // Serve fresh responses from the cache, and store the new ones
+    #[default]
+    Default,
+    // Ignore the cache entirely, without storing the response
+    NoStore,
+    // Always make the request, and store the response
+    Reload,
+    // Same as `Reload`, since cached responses aren't revalidated
+    NoCache,
+    // Serve responses from the cache even when stale
+    ForceCache,
+    // Like `ForceCache`, but never make the request: a 504 is returned instead
+    OnlyIfCached,
+}
+
+// Unknown modes are ignored, like fetch() would reject them
+impl From<&str> for FetchCacheMode {
+    fn from(mode: &str) -> Self {
+        match mode {
+            "no-store" => Self::NoStore,
+            "reload" => Self::Reload,
+            "no-cache" => Self::NoCache,
+            "force-cache" => Self::ForceCache,
+            "only-if-cached" => Self::OnlyIfCached,
+            _ => Self::Default,
+        }
+    }
+}
+
+impl FetchCacheMode {
+    pub(crate) fn allows_stale(&self) -> bool {
+        matches!(self, Self::ForceCache | Self::OnlyIfCached)
+    }
+
+    pub(crate) fn reads_cache(&self) -> bool {
+        !matches!(self, Self::NoStore | Self::Reload | Self::NoCache)
+    }
+}
+
+struct FetchCacheEntry {
+    response: Response,
+    expires_at: Instant,
+    // The body is accounted in the memory of the isolate that stored it
+    _allocation: HostAllocation,
+}
+
+// Responses of the fetch() GET requests, by URL and the values of the request
+// headers listed in their `Vary` header. Only 200 responses with a `max-age`
+// (and without `no-store` or `private`) are stored. Stale entries are kept to
+// be served with `force-cache`, until evicted when the cache is full, starting
+// with the least recently used ones
+pub struct FetchCache {
+    max_entries: usize,
+    entries: Mutex<FetchCacheEntries>,
+}
+
+#[derive(Default)]
+struct FetchCacheEntries {
+    entries: LinkedHashMap<String, FetchCacheEntry>,
+    // The `Vary` header names of the last stored response of each URL, with
+    // the number of entries of the URL
+    vary: HashMap<String, (Vec<String>, usize)>,
+}
+
+fn get_request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .as_ref()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+fn get_vary(response: &Response) -> Vec<String> {
+    response
+        .get_header("vary")
+        .map(|vary| {
+            vary.split(',')
+                .map(|name| name.trim().to_ascii_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn get_key(request: &Request, vary: &[String]) -> String {
+    let mut key = request.url.clone();
+
+    for name in CREDENTIAL_HEADERS
+        .iter()
+        .copied()
+        .chain(vary.iter().map(String::as_str))
+    {
+        key.push('\n');
+        key.push_str(name);
+        key.push(':');
+        key.push_str(get_request_header(request, name).unwrap_or_default());
+    }
+
+    key
+}
+
+fn is_credentialed(request: &Request) -> bool {
+    CREDENTIAL_HEADERS
+        .iter()
+        .any(|name| get_request_header(request, name).is_some())
+}
+
+// The freshness lifetime of the response, or `None` when it can't be stored.
+// Responses to credentialed requests are only stored when marked `public`
+fn get_max_age(response: &Response, credentialed: bool) -> Option<Duration> {
+    if response.status != 200 || get_vary(response).iter().any(|name| name == "*") {
+        return None;
+    }
+
+    let cache_control = response.get_header("cache-control")?.to_ascii_lowercase();
+    let mut max_age = None;
+    let mut public = false;
+
+    for directive in cache_control.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            Some(("max-age", value)) => max_age = value.trim_matches('"').parse().ok(),
+            // `private="set-cookie"` too, since the fields can't be stripped
+            Some(("private", _)) => return None,
+            None if directive == "no-store" || directive == "private" => return None,
+            None if directive == "public" => public = true,
+            _ => {}
+        }
+    }
+
+    if credentialed && !public {
+        return None;
+    }
+
+    max_age.map(Duration::from_secs)
+}
+
+impl FetchCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            entries: Mutex::new(FetchCacheEntries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn is_cacheable(request: &Request, response: &Response) -> bool {
+        get_max_age(response, is_credentialed(request)).is_some()
+    }
+
+    pub(crate) fn get(&self, request: &Request, allow_stale: bool) -> Option<Response> {
+        let mut entries = self.entries.lock().unwrap();
+        let (vary, _) = entries.vary.get(&request.url)?;
+        let key = get_key(request, vary);
+        let entry = entries.entries.get_refresh(&key)?;
+
+        if !allow_stale && entry.expires_at <= Instant::now() {
+            return None;
+        }
+
+        Some(entry.response.clone())
+    }
+
+    // The response must contain the whole body
+    pub(crate) fn put(&self, request: &Request, response: &Response, host_memory: &HostMemory) {
+        let max_age = match get_max_age(response, is_credentialed(request)) {
+            Some(max_age) => max_age,
+            None => return,
+        };
+
+        if response.body.len() > FETCH_CACHE_MAX_ENTRY_SIZE {
+            return;
+        }
+
+        let vary = get_vary(response);
+        let key = get_key(request, &vary);
+        let mut entries = self.entries.lock().unwrap();
+
+        let inserted = entries
+            .entries
+            .insert(
+                key,
+                FetchCacheEntry {
+                    response: response.clone(),
+                    expires_at: Instant::now() + max_age,
+                    _allocation: host_memory.allocate(response.body.len()),
+                },
+            )
+            .is_none();
+
+        let (url_vary, count) = entries.vary.entry(request.url.clone()).or_default();
+        *url_vary = vary;
+
+        if inserted {
+            *count += 1;
+        }
+
+        while entries.entries.len() > self.max_entries {
+            let (key, _) = match entries.entries.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            let url = key.split('\n').next().unwrap_or_default();
+
+            if let Some((_, count)) = entries.vary.get_mut(url) {
+                *count -= 1;
+
+                if *count == 0 {
+                    entries.vary.remove(url);
+                }
+            }
+        }
+    }
+}
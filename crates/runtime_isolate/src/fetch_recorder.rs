@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use lagon_runtime_http::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchRecord {
+    pub method: String,
+    pub url: String,
+    // Hex-encoded SHA-256 of the request body
+    pub body_hash: String,
+    pub status: u16,
+    pub headers: HashMap<String, Vec<String>>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchRecorderMode {
+    // Make the requests and save them with their responses
+    Record,
+    // Serve the saved responses, failing when a request wasn't recorded
+    Replay,
+}
+
+// Record the fetch() calls to a file to replay them later, for deterministic testing
+// of functions calling external APIs. Requests are matched by method, URL and
+// body. The same request recorded multiple times is replayed in the same order
+pub struct FetchRecorder {
+    mode: FetchRecorderMode,
+    path: PathBuf,
+    records: Mutex<Vec<FetchRecord>>,
+    // How many times each request has been replayed
+    replays: Mutex<HashMap<(String, String, String), usize>>,
+}
+
+fn get_body_hash(request: &Request) -> String {
+    format!("{:x}", Sha256::digest(&request.body))
+}
+
+impl FetchRecorder {
+    // Previous records in the file are overwritten
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Self {
+            mode: FetchRecorderMode::Record,
+            path: path.as_ref().to_path_buf(),
+            records: Mutex::new(Vec::new()),
+            replays: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let records = serde_json::from_str(&fs::read_to_string(&path)?)?;
+
+        Ok(Self {
+            mode: FetchRecorderMode::Replay,
+            path: path.as_ref().to_path_buf(),
+            records: Mutex::new(records),
+            replays: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn mode(&self) -> FetchRecorderMode {
+        self.mode
+    }
+
+    pub fn get_records(&self) -> Vec<FetchRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    // The response must contain the whole body
+    pub(crate) fn save(&self, request: &Request, response: &Response) -> Result<()> {
+        let mut records = self.records.lock().unwrap();
+
+        records.push(FetchRecord {
+            method: <&str>::from(request.method).to_string(),
+            url: request.url.clone(),
+            body_hash: get_body_hash(request),
+            status: response.status,
+            headers: response.headers.clone().unwrap_or_default(),
+            body: response.body.to_vec(),
+        });
+
+        fs::write(&self.path, serde_json::to_string_pretty(&*records)?)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn find(&self, request: &Request) -> Result<Response> {
+        let method = <&str>::from(request.method).to_string();
+        let body_hash = get_body_hash(request);
+
+        let records = self.records.lock().unwrap();
+        let matching = records
+            .iter()
+            .filter(|record| {
+                record.method == method
+                    && record.url == request.url
+                    && record.body_hash == body_hash
+            })
+            .collect::<Vec<_>>();
+
+        if matching.is_empty() {
+            return Err(anyhow!(
+                "No recorded response for {} {}",
+                method,
+                request.url
+            ));
+        }
+
+        let mut replays = self.replays.lock().unwrap();
+        let replay = replays
+            .entry((method, request.url.clone(), body_hash))
+            .or_default();
+
+        // Keep replaying the last record once all of them have been used
+        let record = matching[(*replay).min(matching.len() - 1)];
+        *replay += 1;
+
+        Ok(Response {
+            status: record.status,
+            status_text: None,
+            headers: if !record.headers.is_empty() {
+                Some(record.headers.clone())
+            } else {
+                None
+            },
+            body: record.body.clone().into(),
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use hyper::{
+    client::{connect::dns::GaiResolver, HttpConnector},
+    service::Service,
+};
+use hyper_tls::HttpsConnector;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::time::Instant;
+
+tokio::task_local! {
+    // The timer of the fetch() call whose connection is being established
+    static FETCH_TIMER: Arc<FetchTimer>;
+}
+
+// When each phase of a fetch() call ended, in milliseconds since it started,
+// like the Resource Timing API. Phases that didn't happen (e.g DNS and
+// connect for a reused connection, or TLS for plain HTTP) end at the same
+// time as the previous one, so the timings are always increasing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FetchTiming {
+    pub dns: f64,
+    pub connect: f64,
+    pub tls: f64,
+    // When the response headers have been received
+    pub ttfb: f64,
+    // When the response was returned to the function, before reading its body
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FetchPhase {
+    Dns,
+    Connect,
+    Tls,
+    Ttfb,
+}
+
+#[derive(Debug)]
+pub struct FetchTimer {
+    started_at: Instant,
+    timing: Mutex<FetchTiming>,
+}
+
+impl Default for FetchTimer {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            timing: Mutex::new(FetchTiming::default()),
+        }
+    }
+}
+
+impl FetchTimer {
+    fn elapsed(&self) -> f64 {
+        self.started_at.elapsed().as_secs_f64() * 1000.0
+    }
+
+    pub fn record(&self, phase: FetchPhase) {
+        let elapsed = self.elapsed();
+        let mut timing = self.timing.lock().unwrap();
+
+        // Redirects overwrite the phases of the previous requests
+        match phase {
+            FetchPhase::Dns => timing.dns = elapsed,
+            FetchPhase::Connect => timing.connect = elapsed,
+            FetchPhase::Tls => timing.tls = elapsed,
+            FetchPhase::Ttfb => timing.ttfb = elapsed,
+        }
+    }
+
+    pub fn finish(&self) -> FetchTiming {
+        let mut timing = *self.timing.lock().unwrap();
+        timing.total = self.elapsed();
+
+        // Fill the phases that didn't happen, e.g for cached responses
+        timing.connect = timing.connect.max(timing.dns);
+        timing.tls = timing.tls.max(timing.connect);
+        timing.ttfb = timing.ttfb.max(timing.tls);
+
+        timing
+    }
+
+    // Record the phases of the connections established by `future`
+    pub async fn scope<F: Future>(self: &Arc<Self>, future: F) -> F::Output {
+        FETCH_TIMER.scope(Arc::clone(self), future).await
+    }
+}
+
+pub fn record_fetch_phase(phase: FetchPhase) {
+    FETCH_TIMER
+        .try_with(|timer| timer.record(phase))
+        .unwrap_or(());
+}
+
+// Records when the wrapped resolver or connector completes. Connections
+// established in the background by hyper aren't bound to any fetch() call
+#[derive(Debug, Clone)]
+pub struct Timed<S> {
+    inner: S,
+    phase: FetchPhase,
+}
+
+impl<S> Timed<S> {
+    pub fn new(inner: S, phase: FetchPhase) -> Self {
+        Self { inner, phase }
+    }
+}
+
+impl<S, R> Service<R> for Timed<S>
+where
+    S: Service<R>,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let timer = FETCH_TIMER.try_with(Arc::clone).ok();
+        let phase = self.phase;
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await;
+
+            if let Some(timer) = timer {
+                timer.record(phase);
+            }
+
+            response
+        })
+    }
+}
+
+pub type TimedConnector = Timed<HttpsConnector<Timed<HttpConnector<Timed<GaiResolver>>>>>;
+
+pub fn timed_connector() -> TimedConnector {
+    let mut http =
+        HttpConnector::new_with_resolver(Timed::new(GaiResolver::new(), FetchPhase::Dns));
+    // The scheme is checked by the TLS connector
+    http.enforce_http(false);
+
+    let https = HttpsConnector::new_with_connector(Timed::new(http, FetchPhase::Connect));
+
+    Timed::new(https, FetchPhase::Tls)
+}
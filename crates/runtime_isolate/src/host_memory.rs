@@ -0,0 +1,47 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+// Buffers held by the host on behalf of an isolate (e.g the chunks of a fetch()
+// body waiting to be sent, or the responses it stored in the fetch cache), which
+// V8 doesn't know about. They are counted with the V8 memory when checking the
+// memory limit of the isolate
+#[derive(Debug, Clone, Default)]
+pub struct HostMemory(Arc<AtomicUsize>);
+
+impl HostMemory {
+    pub fn allocate(&self, bytes: usize) -> HostAllocation {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+
+        HostAllocation {
+            memory: self.clone(),
+            bytes,
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Released once dropped, alongside the buffer it accounts for
+#[derive(Debug)]
+pub struct HostAllocation {
+    memory: HostMemory,
+    bytes: usize,
+}
+
+impl HostAllocation {
+    // For buffers growing as they are read
+    pub fn grow(&mut self, bytes: usize) {
+        self.memory.0.fetch_add(bytes, Ordering::Relaxed);
+        self.bytes += bytes;
+    }
+}
+
+impl Drop for HostAllocation {
+    fn drop(&mut self) {
+        self.memory.0.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
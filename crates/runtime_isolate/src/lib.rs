@@ -1,44 +1,136 @@
-use futures::{future::poll_fn, stream::FuturesUnordered, Future, StreamExt};
-use lagon_runtime_http::{FromV8, IntoV8, Request, Response, RunResult, StreamResult};
-use lagon_runtime_v8_utils::v8_string;
+use futures::{future::poll_fn, stream::FuturesUnordered, Future, FutureExt, StreamExt};
+use lagon_runtime_http::{
+    BodySpilling, FromV8, IntoV8, Request, Response, RunResult, StreamResult, WebSocketMessage,
+};
+use lagon_runtime_v8_utils::{v8_integer, v8_string};
 use linked_hash_map::LinkedHashMap;
 use std::{
-    cell::{RefCell, RefMut},
+    cell::{Cell, RefCell, RefMut},
     collections::HashMap,
+    num::NonZeroI32,
+    path::PathBuf,
     pin::Pin,
     rc::Rc,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
+use tokio_util::sync::CancellationToken;
 use v8::MapFnTo;
 
 use self::{
-    bindings::{BindingResult, PromiseResult},
-    callbacks::{heap_limit_callback, promise_reject_callback, resolve_module_callback},
-    options::{IsolateOptions, Metadata},
+    bindings::{
+        fetch::{
+            extract_fetch_body_id, pipe_fetch_body, FetchBodies, FetchBody, FetchBodySender,
+            FetchLimiter, FetchTrailers, PipedBody, MAX_PIPED_CHUNKS,
+        },
+        BindingResult,
+    },
+    callbacks::{
+        heap_limit_callback, import_meta_callback, promise_reject_callback, resolve_module_callback,
+    },
+    host_memory::HostMemory,
+    options::{IsolateFlags, IsolateOptions, Metadata},
 };
 
 mod bindings;
+mod bundle;
 mod callbacks;
+mod code_cache;
+mod content_encoding;
+mod fetch_cache;
+mod fetch_recorder;
+mod fetch_timing;
+mod host_memory;
+mod lifecycle;
 pub mod options;
+mod statistics;
+#[cfg(unix)]
+mod unix_socket;
+mod validation;
+
+pub use bundle::BundleMetadata;
+pub use code_cache::CodeCache;
+pub use fetch_cache::{FetchCache, FetchCacheMode, FETCH_CACHE_MAX_ENTRY_SIZE};
+pub use fetch_recorder::{FetchRecord, FetchRecorder, FetchRecorderMode};
+pub use lifecycle::{DisposeReason, IsolateLifecycleEvent};
+pub use sourcemap::SourceMap;
+pub use statistics::{IsolateStatistics, Timings};
+pub use validation::{validate_deployment, ErrorLocation, ValidationError};
 
 const RUNTIME_ONLY_SCRIPT_NAME: &str = "runtime.js";
 const CODE_ONLY_SCRIPT_NAME: &str = "code.js";
 const ISOLATE_SCRIPT_NAME: &str = "isolate.js";
+// Imported modules paths are resolved relative to the entry module
+const ENTRY_MODULE_PATH: &str = "index.js";
+const MODULES_BASE_URL: &str = "file:///";
 
 #[derive(Debug, Default)]
 pub struct RequestContext {
     fetch_calls: usize,
+    // Timers started with console.time(), by label
+    console_timers: HashMap<String, Instant>,
+    // Counters incremented by console.count(), by label
+    console_counters: HashMap<String, u32>,
+    // Async operations started by the request that haven't resolved yet
+    pending_async_ops: usize,
+    // Time spent with at least one async operation pending, excluding the current wait
+    async_time: Duration,
+    awaiting_since: Option<Instant>,
+}
+
+impl RequestContext {
+    fn start_async_op(&mut self) {
+        if self.pending_async_ops == 0 {
+            self.awaiting_since = Some(Instant::now());
+        }
+
+        self.pending_async_ops += 1;
+    }
+
+    fn end_async_op(&mut self) {
+        self.pending_async_ops = self.pending_async_ops.saturating_sub(1);
+
+        if self.pending_async_ops == 0 {
+            if let Some(awaiting_since) = self.awaiting_since.take() {
+                self.async_time += awaiting_since.elapsed();
+            }
+        }
+    }
+
+    // Concurrent operations are only counted once
+    fn async_time(&self) -> Duration {
+        self.async_time
+            + self
+                .awaiting_since
+                .map_or(Duration::ZERO, |awaiting_since| awaiting_since.elapsed())
+    }
 }
 
 pub struct IsolateRequest {
     pub request: Request,
     pub sender: flume::Sender<RunResult>,
+    // Stop handling this request once cancelled, without affecting the
+    // other requests. Its pending promises are left unresolved
+    pub cancellation_token: Option<CancellationToken>,
+    // Overrides the memory limit of the isolate while this request is being
+    // handled, in MB. Capped by the isolate's `max_memory`
+    pub memory: Option<usize>,
+    // Overrides the max body size of the isolate while this request is
+    // being handled, e.g for the uploads of a trusted proxy
+    pub max_body_size: Option<usize>,
+}
+
+// The isolate side of an upgraded WebSocket connection
+pub struct IsolateWebSocket {
+    pub incoming: flume::Receiver<WebSocketMessage>,
+    pub outgoing: flume::Sender<WebSocketMessage>,
 }
 
 pub enum IsolateEvent {
     Request(IsolateRequest),
+    // A request asking to be upgraded to a WebSocket
+    WebSocket(IsolateRequest, IsolateWebSocket),
     Terminate(String),
 }
 
@@ -50,6 +142,66 @@ pub struct HandlerResult {
     stream_response_sent: RefCell<bool>,
     stream_status: RefCell<StreamStatus>,
     context: RequestContext,
+    cancellation_token: Option<CancellationToken>,
+    // In MB
+    memory: usize,
+    max_body_size: Option<usize>,
+    // The body of a fetch() response returned as-is
+    piped_body: Option<PipedBody>,
+}
+
+impl HandlerResult {
+    // Forward the chunks of the piped body while the client keeps up with
+    // them, returning whether the body has ended or been interrupted
+    fn forward_piped_body(&self) -> bool {
+        let piped_body = match &self.piped_body {
+            Some(piped_body) => piped_body,
+            None => return false,
+        };
+
+        while self.sender.len() < MAX_PIPED_CHUNKS {
+            let result = match piped_body.try_recv() {
+                Ok(Ok((chunk, _allocation))) => {
+                    RunResult::Stream(StreamResult::Data(chunk.to_vec()))
+                }
+                // The client can tell the response is incomplete
+                Ok(Err(error)) => {
+                    self.sender.send(RunResult::Error(error)).unwrap_or(());
+                    return true;
+                }
+                Err(flume::TryRecvError::Empty) => return false,
+                Err(flume::TryRecvError::Disconnected) => {
+                    self.sender
+                        .send(RunResult::Stream(StreamResult::Done(
+                            self.start_time.elapsed(),
+                        )))
+                        .unwrap_or(());
+                    return true;
+                }
+            };
+
+            if self.sender.send(result).is_err() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn is_timed_out(&self, options: &IsolateOptions) -> bool {
+        self.start_time.elapsed() >= options.total_timeout
+            || options.async_timeout.map_or(false, |async_timeout| {
+                self.context.async_time() >= async_timeout
+            })
+    }
+}
+
+// Background work registered with waitUntil(), which keeps
+// being polled after the response has been sent
+#[derive(Debug)]
+pub struct WaitUntil {
+    promise: v8::Global<v8::Promise>,
+    start_time: Instant,
 }
 
 #[derive(Debug, Clone)]
@@ -58,7 +210,8 @@ struct Global(v8::Global<v8::Context>);
 pub struct IsolateState {
     global: Option<Global>,
     promises: FuturesUnordered<Pin<Box<dyn Future<Output = BindingResult>>>>,
-    js_promises: HashMap<usize, v8::Global<v8::PromiseResolver>>,
+    // With the id of the request that created them
+    js_promises: HashMap<usize, (v8::Global<v8::PromiseResolver>, u32)>,
     handler_results: HashMap<u32, HandlerResult>,
     stream_sender: flume::Sender<(u32, StreamResult)>,
     metadata: Rc<Metadata>,
@@ -66,6 +219,40 @@ pub struct IsolateState {
     lines: usize,
     requests_count: u32,
     log_sender: Option<flume::Sender<(String, String, Metadata)>>,
+    secrets: Vec<String>,
+    fetch_body_senders: HashMap<u32, FetchBodySender>,
+    fetch_limiter: Option<FetchLimiter>,
+    max_pending_ops: Option<usize>,
+    fetch_timeout: Option<Duration>,
+    fetch_bodies: FetchBodies,
+    fetch_bodies_count: u32,
+    fetch_trailers: HashMap<u32, FetchTrailers>,
+    websockets: HashMap<u32, IsolateWebSocket>,
+    wait_until: Vec<WaitUntil>,
+    // Sources of the modules that can be imported, and the ones already compiled
+    modules: HashMap<String, String>,
+    // Exposed to modules as `import.meta.env`
+    import_meta_env: HashMap<String, String>,
+    module_cache: HashMap<String, v8::Global<v8::Module>>,
+    module_paths: HashMap<NonZeroI32, String>,
+    source_map: Option<Arc<SourceMap>>,
+    stack_trace_limit: usize,
+    json_max_size: usize,
+    json_max_depth: usize,
+    max_body_size: Option<usize>,
+    inspect_max_depth: usize,
+    inspect_max_length: usize,
+    inspect_max_string_length: usize,
+    inspect_max_output_size: usize,
+    fetch_recorder: Option<Arc<FetchRecorder>>,
+    fetch_cache: Option<Arc<FetchCache>>,
+    unix_sockets: Arc<HashMap<String, PathBuf>>,
+    body_spilling: Option<BodySpilling>,
+    supported_locales: Vec<String>,
+    host_memory: HostMemory,
+    // Spent compiling the entry module and the imported ones
+    compilation_time: Duration,
+    consumed_code_cache: bool,
 }
 
 #[derive(Debug)]
@@ -103,14 +290,34 @@ pub struct Isolate {
     isolate: Option<v8::OwnedIsolate>,
     handler: Option<v8::Global<v8::Function>>,
     compilation_error: Option<String>,
+    compilation_error_location: Option<ErrorLocation>,
+    // The compilation error was thrown while compiling or linking the
+    // code, and not by its top-level code while evaluating it
+    compilation_failed: bool,
+    bundle_metadata: Option<BundleMetadata>,
     stream_receiver: flume::Receiver<(u32, StreamResult)>,
     termination_result: Arc<RwLock<Option<RunResult>>>,
     heartbeat: Arc<RwLock<Heartbeat>>,
     rx: flume::Receiver<IsolateEvent>,
+    // Waiting for the next event without blocking, while WebSockets are open
+    next_event: Option<flume::r#async::RecvFut<'static, IsolateEvent>>,
     near_heap_limit_callback_data: Option<Box<RefCell<dyn std::any::Any>>>,
+    // Re-registers the near heap limit callback to restore the given limit
+    restore_heap_limit: Option<Box<dyn Fn(&mut v8::OwnedIsolate, usize)>>,
+    heap_limit: Rc<HeapLimit>,
+    // The memory used at the last check of the memory limit, in bytes
+    last_used_memory: usize,
     last_statistic_sent: Instant,
 }
 
+// The heap is limited to `memory`, and only raised up to `max_memory` while
+// requests overriding the memory limit are being handled
+struct HeapLimit {
+    // In bytes, the highest limit of the requests being handled
+    allowed: Cell<usize>,
+    raised: Cell<bool>,
+}
+
 unsafe impl Send for Isolate {}
 unsafe impl Sync for Isolate {}
 
@@ -120,13 +327,35 @@ unsafe impl Sync for Isolate {}
 // That's why we use .unwrap_or(()) to silently discard any error.
 impl Isolate {
     pub fn new(options: IsolateOptions, rx: flume::Receiver<IsolateEvent>) -> Self {
+        let start_time = Instant::now();
+
+        // Raised by the near heap limit callback for the requests
+        // overriding the memory limit
         let memory_mb = options.memory * 1024 * 1024;
-        let mut params = v8::CreateParams::default().heap_limits(0, memory_mb);
+
+        // Invalid flags are reported like compilation errors, rejecting all the requests
+        let (isolate_flags, flags_error) = match options.get_isolate_flags() {
+            Ok(isolate_flags) => (isolate_flags, None),
+            Err(error) => (IsolateFlags::default(), Some(error.to_string())),
+        };
+        let initial_heap_size = isolate_flags
+            .initial_heap_size
+            .map_or(0, |initial_heap_size| {
+                (initial_heap_size * 1024 * 1024).min(memory_mb)
+            });
+        let stack_trace_limit = isolate_flags
+            .stack_trace_limit
+            .unwrap_or(options.stack_trace_limit);
+
+        let mut params = v8::CreateParams::default().heap_limits(initial_heap_size, memory_mb);
 
         let references = vec![
             v8::ExternalReference {
                 function: bindings::console::console_binding.map_fn_to(),
             },
+            v8::ExternalReference {
+                function: bindings::console::stack_trace_binding.map_fn_to(),
+            },
             v8::ExternalReference {
                 function: bindings::pull_stream::pull_stream_binding.map_fn_to(),
             },
@@ -142,6 +371,27 @@ impl Isolate {
             v8::ExternalReference {
                 function: bindings::queue_microtask::queue_microtask_binding.map_fn_to(),
             },
+            v8::ExternalReference {
+                function: bindings::fetch::pull_fetch_body_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::websocket::upgrade_websocket_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::websocket::websocket_send_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::websocket::websocket_close_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::wait_until::wait_until_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::json::parse_json_binding.map_fn_to(),
+            },
+            v8::ExternalReference {
+                function: bindings::json::json_max_size_binding.map_fn_to(),
+            },
         ];
 
         let refs = v8::ExternalReferences::new(&references);
@@ -161,8 +411,9 @@ impl Isolate {
             }
         };
 
-        isolate.set_capture_stack_trace_for_uncaught_exceptions(true, 4);
+        isolate.set_capture_stack_trace_for_uncaught_exceptions(true, stack_trace_limit as i32);
         isolate.set_promise_reject_callback(promise_reject_callback);
+        isolate.set_host_initialize_import_meta_object_callback(import_meta_callback);
 
         let (stream_sender, stream_receiver) = flume::unbounded();
 
@@ -192,6 +443,39 @@ impl Isolate {
                 lines: 0,
                 requests_count: 0,
                 log_sender: options.log_sender.clone(),
+                secrets: options.get_secrets(),
+                fetch_body_senders: HashMap::new(),
+                fetch_limiter: options.max_concurrent_fetches.map(
+                    |(max_concurrent, max_queued)| FetchLimiter::new(max_concurrent, max_queued),
+                ),
+                max_pending_ops: options.max_pending_ops,
+                fetch_timeout: options.fetch_timeout,
+                fetch_bodies: Arc::new(Mutex::new(HashMap::new())),
+                fetch_bodies_count: 0,
+                fetch_trailers: HashMap::new(),
+                websockets: HashMap::new(),
+                wait_until: Vec::new(),
+                modules: options.modules.clone(),
+                import_meta_env: options.get_import_meta_env(),
+                module_cache: HashMap::new(),
+                module_paths: HashMap::new(),
+                source_map: options.source_map.clone(),
+                stack_trace_limit,
+                json_max_size: options.json_max_size,
+                json_max_depth: options.json_max_depth,
+                max_body_size: options.max_body_size,
+                inspect_max_depth: options.inspect_max_depth,
+                inspect_max_length: options.inspect_max_length,
+                inspect_max_string_length: options.inspect_max_string_length,
+                inspect_max_output_size: options.inspect_max_output_size,
+                fetch_recorder: options.fetch_recorder.clone(),
+                fetch_cache: options.fetch_cache.clone(),
+                unix_sockets: Arc::new(options.unix_sockets.clone()),
+                body_spilling: options.body_spilling.clone(),
+                supported_locales: options.supported_locales.clone(),
+                host_memory: HostMemory::default(),
+                compilation_time: Duration::ZERO,
+                consumed_code_cache: false,
             }
         };
 
@@ -201,19 +485,37 @@ impl Isolate {
             options,
             isolate: Some(isolate),
             handler: None,
-            compilation_error: None,
+            compilation_error: flags_error,
+            compilation_error_location: None,
+            compilation_failed: false,
+            bundle_metadata: None,
             stream_receiver,
             termination_result: Arc::new(RwLock::new(None)),
             heartbeat: Arc::new(RwLock::new(Heartbeat::None)),
             rx,
+            next_event: None,
             near_heap_limit_callback_data: None,
+            restore_heap_limit: None,
+            heap_limit: Rc::new(HeapLimit {
+                allowed: Cell::new(memory_mb),
+                raised: Cell::new(false),
+            }),
+            last_used_memory: 0,
             last_statistic_sent: Instant::now(),
         };
 
         let thread_safe_handle = this.isolate.as_ref().unwrap().thread_safe_handle();
         let termination_result_handle = Arc::clone(&this.termination_result);
+        let heap_limit = Rc::clone(&this.heap_limit);
 
         this.set_heap_limit_callback(move |current: usize| {
+            let allowed = heap_limit.allowed.get();
+
+            if current < allowed {
+                heap_limit.raised.set(true);
+                return allowed;
+            }
+
             termination_result_handle
                 .write()
                 .unwrap()
@@ -228,6 +530,13 @@ impl Isolate {
             current * 2
         });
 
+        send_lifecycle_event(
+            &this.options,
+            IsolateLifecycleEvent::Created {
+                cold_start_us: start_time.elapsed().as_micros() as u64,
+            },
+        );
+
         this
     }
 
@@ -243,6 +552,26 @@ impl Isolate {
             .as_mut()
             .unwrap()
             .add_near_heap_limit_callback(heap_limit_callback::<C>, data);
+
+        self.restore_heap_limit = Some(Box::new(move |isolate, heap_limit| {
+            isolate.remove_near_heap_limit_callback(heap_limit_callback::<C>, heap_limit);
+            isolate.add_near_heap_limit_callback(heap_limit_callback::<C>, data);
+        }));
+    }
+
+    // Allow the heap to grow up to the highest memory limit of the requests
+    // being handled, restoring the base limit once they are all finished
+    fn update_heap_limit(&mut self, memory: Option<usize>) {
+        let base = self.options.memory * 1024 * 1024;
+        let allowed = memory.map_or(base, |memory| memory * 1024 * 1024).max(base);
+
+        self.heap_limit.allowed.set(allowed);
+
+        if allowed == base && self.heap_limit.raised.replace(false) {
+            if let Some(restore_heap_limit) = &self.restore_heap_limit {
+                restore_heap_limit(self.isolate.as_mut().unwrap(), base);
+            }
+        }
     }
 
     pub fn get_metadata(&self) -> Rc<Metadata> {
@@ -265,6 +594,54 @@ impl Isolate {
     }
 
     pub fn evaluate(&mut self) {
+        // The V8 flags were invalid
+        if self.compilation_error.is_some() {
+            return;
+        }
+
+        let start_time = Instant::now();
+
+        self.evaluate_code();
+
+        // Snapshots only contain the runtime code
+        if !self.options.snapshot {
+            let (compilation_time, consumed_code_cache) = {
+                let state = Isolate::state(self.isolate.as_ref().unwrap());
+                let state = state.borrow();
+
+                (state.compilation_time, state.consumed_code_cache)
+            };
+            let evaluation_time = start_time.elapsed().saturating_sub(compilation_time);
+
+            let mut bundle_metadata =
+                BundleMetadata::new(&self.options, compilation_time, evaluation_time);
+            bundle_metadata.code_cache = consumed_code_cache;
+
+            self.bundle_metadata = Some(bundle_metadata);
+
+            if self.compilation_error.is_none() {
+                send_lifecycle_event(&self.options, IsolateLifecycleEvent::Warmed);
+            }
+        }
+    }
+
+    pub fn get_bundle_metadata(&self) -> Option<&BundleMetadata> {
+        self.bundle_metadata.as_ref()
+    }
+
+    // Set when the code failed to compile or evaluate, in which
+    // case all the requests are answered with this error
+    pub fn get_compilation_error(&self) -> Option<&str> {
+        self.compilation_error.as_deref()
+    }
+
+    // Whether the code itself is invalid (e.g a syntax error or a missing
+    // import), unlike errors thrown by its top-level code
+    pub fn has_compilation_failed(&self) -> bool {
+        self.compilation_failed
+    }
+
+    fn evaluate_code(&mut self) {
         let isolate_state = Isolate::state(self.isolate.as_ref().unwrap());
         let global = {
             let state = isolate_state.borrow();
@@ -275,7 +652,21 @@ impl Isolate {
             &mut v8::HandleScope::with_context(self.isolate.as_mut().unwrap(), global.clone());
         let try_catch = &mut v8::TryCatch::new(scope);
 
-        let (code, lines) = self.options.get_runtime_code(try_catch);
+        let (code, lines) = self.options.get_runtime_code();
+
+        // Snapshots only contain the runtime code
+        let code_cache = match (&self.options.code_cache, self.options.snapshot) {
+            (Some(code_cache), false) => Some((
+                Arc::clone(code_cache),
+                CodeCache::get_key(&code, &self.options.v8_flags),
+            )),
+            _ => None,
+        };
+        let cached_data = code_cache
+            .as_ref()
+            .and_then(|(code_cache, key)| code_cache.get(key));
+
+        let code = v8_string(try_catch, &code);
         let resource_name = v8_string(
             try_catch,
             if self.options.snapshot {
@@ -289,20 +680,17 @@ impl Isolate {
         let source_map_url = v8_string(try_catch, "");
         isolate_state.borrow_mut().lines = lines;
 
-        let source = v8::script_compiler::Source::new(
-            code,
-            Some(&v8::ScriptOrigin::new(
-                try_catch,
-                resource_name.into(),
-                0,
-                0,
-                false,
-                i32::from(self.options.snapshot_blob.is_some()),
-                source_map_url.into(),
-                false,
-                false,
-                true,
-            )),
+        let origin = v8::ScriptOrigin::new(
+            try_catch,
+            resource_name.into(),
+            0,
+            0,
+            false,
+            i32::from(self.options.snapshot_blob.is_some()),
+            source_map_url.into(),
+            false,
+            false,
+            true,
         );
 
         let thread_safe_handle = try_catch.thread_safe_handle();
@@ -351,21 +739,81 @@ impl Isolate {
             }
         });
 
-        match v8::script_compiler::compile_module(try_catch, source) {
+        let compilation_start = Instant::now();
+        // V8 falls back to a full compilation when it rejects the cache, which
+        // can't be detected, but is unlikely since the keys contain everything
+        // the cache depends on
+        let consumed_code_cache = cached_data.is_some();
+        let module = match &cached_data {
+            Some(cached_data) => v8::script_compiler::compile_module2(
+                try_catch,
+                v8::script_compiler::Source::new_with_cached_data(
+                    code,
+                    Some(&origin),
+                    v8::script_compiler::CachedData::new(cached_data),
+                ),
+                v8::script_compiler::CompileOptions::ConsumeCodeCache,
+                v8::script_compiler::NoCacheReason::NoReason,
+            ),
+            None => v8::script_compiler::compile_module(
+                try_catch,
+                v8::script_compiler::Source::new(code, Some(&origin)),
+            ),
+        };
+        {
+            let mut state = isolate_state.borrow_mut();
+            state.compilation_time += compilation_start.elapsed();
+            state.consumed_code_cache = consumed_code_cache;
+        }
+
+        match module {
             Some(module) => {
+                {
+                    let mut state = isolate_state.borrow_mut();
+                    state
+                        .module_paths
+                        .insert(module.get_identity_hash(), ENTRY_MODULE_PATH.to_string());
+                    state.module_cache.insert(
+                        ENTRY_MODULE_PATH.to_string(),
+                        v8::Global::new(try_catch, module),
+                    );
+                }
+
                 if module
                     .instantiate_module(try_catch, resolve_module_callback)
                     .is_none()
                 {
-                    self.compilation_error = Some(handle_error(try_catch, lines).as_error());
+                    self.compilation_failed = true;
+                    self.compilation_error_location =
+                        get_error_location(try_catch, lines, self.options.source_map.as_deref());
+                    self.compilation_error = Some(
+                        handle_error(try_catch, lines, self.options.source_map.as_deref())
+                            .as_error(),
+                    );
                     return;
                 }
 
                 if module.evaluate(try_catch).is_none() {
-                    self.compilation_error = Some(handle_error(try_catch, lines).as_error());
+                    self.compilation_error_location =
+                        get_error_location(try_catch, lines, self.options.source_map.as_deref());
+                    self.compilation_error = Some(
+                        handle_error(try_catch, lines, self.options.source_map.as_deref())
+                            .as_error(),
+                    );
                     return;
                 }
 
+                // The cache is created after the evaluation, to also contain
+                // the functions compiled lazily by the top-level code
+                if let (Some((code_cache, key)), false) = (code_cache, consumed_code_cache) {
+                    if let Some(data) = module
+                        .get_unbound_module_script(try_catch)
+                        .create_code_cache()
+                    {
+                        code_cache.insert(&key, &data);
+                    }
+                }
+
                 if !self.options.snapshot {
                     let global = global.open(try_catch);
                     let global = global.global(try_catch);
@@ -378,76 +826,136 @@ impl Isolate {
                 }
             }
             None => {
-                self.compilation_error = Some(handle_error(try_catch, lines).as_error());
+                self.compilation_failed = true;
+                self.compilation_error_location =
+                    get_error_location(try_catch, lines, self.options.source_map.as_deref());
+                self.compilation_error = Some(
+                    handle_error(try_catch, lines, self.options.source_map.as_deref()).as_error(),
+                );
             }
         };
     }
 
     pub fn handle_event(&mut self, event: IsolateEvent, state: &Rc<RefCell<IsolateState>>) {
         match event {
-            IsolateEvent::Request(IsolateRequest { request, sender }) => {
-                let (global, requests_count) = {
-                    let mut isolate_state = state.borrow_mut();
-                    let global = isolate_state.global.as_ref().unwrap().0.clone();
+            IsolateEvent::Request(request) => {
+                self.handle_request(request, None, state);
+            }
+            IsolateEvent::WebSocket(request, websocket) => {
+                self.handle_request(request, Some(websocket), state);
+            }
+            IsolateEvent::Terminate(reason) => {
+                self.terminate(RunResult::Error(reason));
+            }
+        }
+    }
 
-                    isolate_state.requests_count += 1;
+    fn handle_request(
+        &mut self,
+        IsolateRequest {
+            mut request,
+            sender,
+            cancellation_token,
+            memory,
+            max_body_size,
+        }: IsolateRequest,
+        websocket: Option<IsolateWebSocket>,
+        state: &Rc<RefCell<IsolateState>>,
+    ) {
+        let (global, requests_count, spilled_body_id) = {
+            let mut isolate_state = state.borrow_mut();
+            let global = isolate_state.global.as_ref().unwrap().0.clone();
 
-                    (global, isolate_state.requests_count)
-                };
-                let scope = &mut v8::HandleScope::with_context(
-                    self.isolate.as_mut().unwrap(),
-                    global.clone(),
-                );
-                let try_catch = &mut v8::TryCatch::new(scope);
-
-                let handler = self.handler.as_ref().unwrap();
-                let handler = handler.open(try_catch);
-
-                let global = global.open(try_catch);
-                let global = global.global(try_catch);
-
-                let request = request.into_v8(try_catch);
-                let id = v8::Integer::new(try_catch, requests_count as i32);
-                try_catch.set_continuation_preserved_embedder_data(id.into());
-
-                state.borrow_mut().handler_results.insert(
-                    requests_count,
-                    HandlerResult {
-                        promise: None,
-                        sender,
-                        start_time: Instant::now(),
-                        stream_response_sent: RefCell::new(false),
-                        stream_status: RefCell::new(StreamStatus::None),
-                        context: RequestContext::default(),
-                    },
+            isolate_state.requests_count += 1;
+            let requests_count = isolate_state.requests_count;
+
+            if let Some(websocket) = websocket {
+                isolate_state.websockets.insert(requests_count, websocket);
+            }
+
+            // Bodies spilled to disk are read lazily from their file, like
+            // fetch() bodies, instead of being copied to the V8 heap
+            let spilled_body_id = request.spilled_body.take().map(|spilled_body| {
+                isolate_state.fetch_bodies_count += 1;
+                let body_id = isolate_state.fetch_bodies_count;
+
+                isolate_state.fetch_bodies.lock().unwrap().insert(
+                    body_id,
+                    FetchBody::new(requests_count, spilled_body.into_body()),
                 );
 
-                match handler.call(try_catch, global.into(), &[id.into(), request.into()]) {
-                    Some(response) => {
-                        let promise = v8::Local::<v8::Promise>::try_from(response)
-                            .expect("Handler did not return a promise");
-                        let promise = v8::Global::new(try_catch, promise);
+                body_id
+            });
 
-                        if let Some(handler_result) =
-                            state.borrow_mut().handler_results.get_mut(&requests_count)
-                        {
-                            handler_result.promise = Some(promise);
-                        }
-                    }
-                    None => {
-                        // Use the current termination result (e.g timeout or memory),
-                        // or try to handle an error
-                        self.termination_result
-                            .write()
-                            .unwrap()
-                            .get_or_insert_with(|| handle_error(try_catch, 0));
-                    }
-                };
+            (global, requests_count, spilled_body_id)
+        };
+        let scope =
+            &mut v8::HandleScope::with_context(self.isolate.as_mut().unwrap(), global.clone());
+        let try_catch = &mut v8::TryCatch::new(scope);
+
+        let handler = self.handler.as_ref().unwrap();
+        let handler = handler.open(try_catch);
+
+        let global = global.open(try_catch);
+        let global = global.global(try_catch);
+
+        let request = request.into_v8(try_catch);
+
+        if let Some(body_id) = spilled_body_id {
+            let body_id_key = v8_string(try_catch, "f");
+            let body_id = v8_integer(try_catch, body_id as i32);
+            request.set(try_catch, body_id_key.into(), body_id.into());
+        }
+
+        let id = v8::Integer::new(try_catch, requests_count as i32);
+        try_catch.set_continuation_preserved_embedder_data(id.into());
+
+        send_lifecycle_event(&self.options, IsolateLifecycleEvent::RequestStarted);
+
+        // Before the handler is called, which may already allocate
+        let memory = self.options.get_request_memory(memory);
+        let allowed = self.heap_limit.allowed.get().max(memory * 1024 * 1024);
+        self.heap_limit.allowed.set(allowed);
+
+        state.borrow_mut().handler_results.insert(
+            requests_count,
+            HandlerResult {
+                promise: None,
+                sender,
+                start_time: Instant::now(),
+                stream_response_sent: RefCell::new(false),
+                stream_status: RefCell::new(StreamStatus::None),
+                context: RequestContext::default(),
+                cancellation_token,
+                memory,
+                max_body_size: max_body_size.or(self.options.max_body_size),
+                piped_body: None,
+            },
+        );
+
+        match handler.call(try_catch, global.into(), &[id.into(), request.into()]) {
+            Some(response) => {
+                let promise = v8::Local::<v8::Promise>::try_from(response)
+                    .expect("Handler did not return a promise");
+                let promise = v8::Global::new(try_catch, promise);
+
+                if let Some(handler_result) =
+                    state.borrow_mut().handler_results.get_mut(&requests_count)
+                {
+                    handler_result.promise = Some(promise);
+                }
             }
-            IsolateEvent::Terminate(reason) => {
-                self.terminate(RunResult::Error(reason));
+            None => {
+                // Use the current termination result (e.g timeout or memory),
+                // or try to handle an error
+                self.termination_result
+                    .write()
+                    .unwrap()
+                    .get_or_insert_with(|| {
+                        handle_error(try_catch, 0, self.options.source_map.as_deref())
+                    });
             }
-        }
+        };
     }
 
     fn poll_v8(&mut self, global: &v8::Global<v8::Context>) {
@@ -457,12 +965,13 @@ impl Isolate {
         scope.perform_microtask_checkpoint();
     }
 
+    // Returns whether any promise has been settled
     fn resolve_promises(
         &mut self,
         cx: &mut Context,
         global: &v8::Global<v8::Context>,
         state: &Rc<RefCell<IsolateState>>,
-    ) {
+    ) -> bool {
         let mut promises = None;
 
         {
@@ -474,28 +983,71 @@ impl Isolate {
                 while let Poll::Ready(Some(BindingResult { id, result })) =
                     state.promises.poll_next_unpin(cx)
                 {
-                    if let Some(promise) = state.js_promises.remove(&id) {
+                    if let Some((promise, request_id)) = state.js_promises.remove(&id) {
+                        if let Some(handler_result) = state.handler_results.get_mut(&request_id) {
+                            handler_result.context.end_async_op();
+                        }
+
                         promises.as_mut().unwrap().push((result, promise));
                     }
                 }
             }
         }
 
-        if let Some(promises) = promises {
-            let scope = &mut v8::HandleScope::with_context(self.isolate.as_mut().unwrap(), global);
+        let promises = match promises {
+            Some(promises) if !promises.is_empty() => promises,
+            _ => return false,
+        };
 
-            for (result, promise) in promises {
-                let promise = promise.open(scope);
-                let should_reject = matches!(result, PromiseResult::Error(_));
-                let value = result.into_value(scope);
+        let scope = &mut v8::HandleScope::with_context(self.isolate.as_mut().unwrap(), global);
 
-                if should_reject {
-                    promise.reject(scope, value);
-                } else {
-                    promise.resolve(scope, value);
-                }
+        for (result, promise) in promises {
+            let promise = promise.open(scope);
+            let should_reject = result.is_error();
+            let value = result.into_value(scope);
+
+            if should_reject {
+                promise.reject(scope, value);
+            } else {
+                promise.resolve(scope, value);
             }
         }
+
+        true
+    }
+
+    // V8's heap limit doesn't include the ArrayBuffers backing stores (e.g the
+    // bytes of a Blob) nor the buffers held by the host, which are checked here.
+    // The memory is shared by all the requests being handled, so the highest
+    // limit of these requests applies
+    fn check_memory_limit(&mut self, host_memory: &HostMemory, memory: Option<usize>) {
+        let memory_limit = memory.unwrap_or(self.options.memory) * 1024 * 1024;
+        let isolate = self.isolate.as_mut().unwrap();
+
+        let get_used_memory = |isolate: &mut v8::OwnedIsolate| {
+            let mut statistics = v8::HeapStatistics::default();
+            isolate.get_heap_statistics(&mut statistics);
+
+            statistics.used_heap_size() + statistics.external_memory() + host_memory.used()
+        };
+
+        let used_memory = get_used_memory(isolate);
+        let previous_used_memory = std::mem::replace(&mut self.last_used_memory, used_memory);
+
+        if used_memory <= memory_limit {
+            return;
+        }
+
+        // Unreachable buffers are counted until they are garbage collected,
+        // which only happens once the limit is passed, not on every poll
+        if previous_used_memory <= memory_limit {
+            isolate.low_memory_notification();
+            self.last_used_memory = get_used_memory(isolate);
+        }
+
+        if self.last_used_memory > memory_limit {
+            self.terminate(RunResult::MemoryLimit);
+        }
     }
 
     fn poll_stream(&self, state: &RefMut<IsolateState>) {
@@ -532,7 +1084,11 @@ impl Isolate {
 
     fn poll_event_loop(&mut self, cx: &mut Context) -> Poll<()> {
         if let Some(compilation_error) = &self.compilation_error {
-            if let Ok(IsolateEvent::Request(IsolateRequest { sender, .. })) = self.rx.try_recv() {
+            if let Ok(
+                IsolateEvent::Request(IsolateRequest { sender, .. })
+                | IsolateEvent::WebSocket(IsolateRequest { sender, .. }, _),
+            ) = self.rx.try_recv()
+            {
                 let termination_result = match self.termination_result.read().unwrap().as_ref() {
                     Some(termination_result) => termination_result.clone(),
                     None => RunResult::Error(compilation_error.to_string()),
@@ -546,14 +1102,50 @@ impl Isolate {
 
         let state = Isolate::state(self.isolate.as_ref().unwrap());
 
-        // If no requests are being processed, we can block this thread (`rx.recv`)
-        // while we wait for a new request. The heartbeat status is set to Waiting
-        // to avoid the isolate being terminated. If we are already processing requests,
-        // try to receive any other request
-        if state.borrow().handler_results.is_empty() {
+        // If no requests or background work are being processed, we can block this
+        // thread (`rx.recv`) while we wait for a new request. The heartbeat status is
+        // set to Waiting to avoid the isolate being terminated. If we are already
+        // processing requests, try to receive any other request
+        let is_idle = {
+            let state = state.borrow();
+            state.handler_results.is_empty() && state.wait_until.is_empty()
+        };
+
+        // Set when the thread waits to be woken up by a new event or a
+        // WebSocket message, instead of polling the event loop again
+        let mut should_wait = false;
+
+        if is_idle {
             *self.heartbeat.write().unwrap() = Heartbeat::Waiting;
 
-            if let Ok(event) = self.rx.recv() {
+            // Open WebSockets still need their messages to be received, so we
+            // can't block the thread: wait for the next event asynchronously,
+            // along with the pending WebSocket receives
+            let event = match state.borrow().websockets.is_empty() {
+                true => {
+                    // Dropped so that it doesn't receive the event instead
+                    self.next_event = None;
+                    self.rx.recv().ok()
+                }
+                false => {
+                    let next_event = self
+                        .next_event
+                        .get_or_insert_with(|| self.rx.clone().into_recv_async());
+
+                    match next_event.poll_unpin(cx) {
+                        Poll::Ready(event) => {
+                            self.next_event = None;
+                            event.ok()
+                        }
+                        Poll::Pending => {
+                            should_wait = true;
+                            None
+                        }
+                    }
+                }
+            };
+
+            if let Some(event) = event {
                 *self.heartbeat.write().unwrap() = Heartbeat::Some;
                 self.handle_event(event, &state);
             }
@@ -571,7 +1163,23 @@ impl Isolate {
         };
 
         self.poll_v8(&global);
-        self.resolve_promises(cx, &global, &state);
+
+        if self.resolve_promises(cx, &global, &state) {
+            should_wait = false;
+        }
+
+        let (host_memory, memory) = {
+            let state = state.borrow();
+            let memory = state
+                .handler_results
+                .values()
+                .map(|handler_result| handler_result.memory)
+                .max();
+
+            (state.host_memory.clone(), memory)
+        };
+        self.update_heap_limit(memory);
+        self.check_memory_limit(&host_memory, memory);
 
         let mut state = state.borrow_mut();
         self.poll_stream(&state);
@@ -582,6 +1190,16 @@ impl Isolate {
                     .sender
                     .send(termination_result.clone())
                     .unwrap_or(());
+
+                send_lifecycle_event(
+                    &self.options,
+                    IsolateLifecycleEvent::RequestFinished {
+                        timings: Timings::new(
+                            self.bundle_metadata.as_ref(),
+                            handler_result.start_time.elapsed(),
+                        ),
+                    },
+                );
             }
 
             return Poll::Ready(());
@@ -614,17 +1232,45 @@ impl Isolate {
                 false => false,
             };
 
-        state.handler_results.retain(|_, handler_result| {
+        let fetch_bodies = Arc::clone(&state.fetch_bodies);
+        let host_memory = state.host_memory.clone();
+        let handler_results_count = state.handler_results.len();
+        let bundle_metadata = self.bundle_metadata.as_ref();
+        let mut finished_handler_times = Vec::new();
+
+        let mut poll_handler_result = |handler_result: &mut HandlerResult| -> bool {
+            if let Some(cancellation_token) = &handler_result.cancellation_token {
+                if cancellation_token.is_cancelled() {
+                    handler_result
+                        .sender
+                        .send(RunResult::Error("Request cancelled".into()))
+                        .unwrap_or(());
+
+                    return false;
+                }
+            }
+
             if *handler_result.stream_response_sent.borrow() {
+                if handler_result.forward_piped_body() {
+                    *handler_result.stream_status.borrow_mut() = StreamStatus::Done;
+                }
+
                 if handler_result.stream_status.borrow().is_done() {
                     if should_send_statistics {
-                        send_statistics(options, try_catch);
+                        send_statistics(
+                            options,
+                            try_catch,
+                            Some(Timings::new(
+                                bundle_metadata,
+                                handler_result.start_time.elapsed(),
+                            )),
+                        );
                     }
 
                     return false;
                 }
 
-                if handler_result.start_time.elapsed() >= options.total_timeout {
+                if handler_result.is_timed_out(options) {
                     handler_result.sender.send(RunResult::Timeout).unwrap_or(());
                     return false;
                 }
@@ -638,6 +1284,7 @@ impl Isolate {
             match promise.state() {
                 v8::PromiseState::Fulfilled => {
                     let response = promise.result(try_catch);
+                    let fetch_body_id = extract_fetch_body_id(try_catch, response);
                     let run_result = match Response::from_v8(try_catch, response) {
                         Ok(response) => {
                             RunResult::Response(response, Some(handler_result.start_time.elapsed()))
@@ -654,6 +1301,14 @@ impl Isolate {
 
                             *handler_result.stream_response_sent.borrow_mut() = true;
 
+                            // The body of a fetch() response has been returned as-is
+                            if let Some(fetch_body) = fetch_body_id
+                                .and_then(|body_id| fetch_bodies.lock().unwrap().remove(&body_id))
+                            {
+                                handler_result.piped_body =
+                                    Some(pipe_fetch_body(fetch_body, host_memory.clone()));
+                            }
+
                             return true;
                         }
                     }
@@ -661,7 +1316,14 @@ impl Isolate {
                     handler_result.sender.send(run_result).unwrap_or(());
 
                     if should_send_statistics {
-                        send_statistics(options, try_catch);
+                        send_statistics(
+                            options,
+                            try_catch,
+                            Some(Timings::new(
+                                bundle_metadata,
+                                handler_result.start_time.elapsed(),
+                            )),
+                        );
                     }
 
                     false
@@ -672,18 +1334,28 @@ impl Isolate {
                     handler_result
                         .sender
                         .send(RunResult::Error(get_exception_message(
-                            try_catch, exception, lines,
+                            try_catch,
+                            exception,
+                            lines,
+                            options.source_map.as_deref(),
                         )))
                         .unwrap_or(());
 
                     if should_send_statistics {
-                        send_statistics(options, try_catch);
+                        send_statistics(
+                            options,
+                            try_catch,
+                            Some(Timings::new(
+                                bundle_metadata,
+                                handler_result.start_time.elapsed(),
+                            )),
+                        );
                     }
 
                     false
                 }
                 v8::PromiseState::Pending => {
-                    if handler_result.start_time.elapsed() >= options.total_timeout {
+                    if handler_result.is_timed_out(options) {
                         handler_result.sender.send(RunResult::Timeout).unwrap_or(());
                         return false;
                     }
@@ -691,9 +1363,84 @@ impl Isolate {
                     true
                 }
             }
+        };
+
+        state.handler_results.retain(|_, handler_result| {
+            let keep = poll_handler_result(handler_result);
+
+            if !keep {
+                finished_handler_times.push(handler_result.start_time.elapsed());
+            }
+
+            keep
         });
 
-        cx.waker().wake_by_ref();
+        for handler_time in finished_handler_times {
+            send_lifecycle_event(
+                options,
+                IsolateLifecycleEvent::RequestFinished {
+                    timings: Timings::new(bundle_metadata, handler_time),
+                },
+            );
+        }
+
+        let wait_until_count = state.wait_until.len();
+        let log_sender = state.log_sender.clone();
+        let metadata = Rc::clone(&state.metadata);
+
+        state.wait_until.retain(|wait_until| {
+            let promise = wait_until.promise.open(try_catch);
+
+            if promise.state() != v8::PromiseState::Pending {
+                return false;
+            }
+
+            if wait_until.start_time.elapsed() >= options.wait_until_timeout {
+                if let Some(log_sender) = log_sender.as_ref() {
+                    log_sender
+                        .send((
+                            String::from("error"),
+                            String::from("waitUntil() promise did not settle before the timeout"),
+                            metadata.as_ref().clone(),
+                        ))
+                        .unwrap_or(());
+                }
+
+                return false;
+            }
+
+            true
+        });
+
+        if should_send_statistics && state.wait_until.len() != wait_until_count {
+            send_statistics(options, try_catch, None);
+        }
+
+        // Drop the WebSockets closed by the host, e.g when the handler
+        // didn't upgrade the request or the connection timed out
+        state
+            .websockets
+            .retain(|_, websocket| !websocket.outgoing.is_disconnected());
+
+        // Drop the fetch() bodies that haven't been read by finished requests
+        if state.handler_results.len() != handler_results_count {
+            fetch_bodies
+                .lock()
+                .unwrap()
+                .retain(|_, fetch_body| state.handler_results.contains_key(&fetch_body.request_id));
+
+            let state = &mut *state;
+            state.fetch_trailers.retain(|_, fetch_trailers| {
+                state
+                    .handler_results
+                    .contains_key(&fetch_trailers.request_id)
+            });
+        }
+
+        if !should_wait {
+            cx.waker().wake_by_ref();
+        }
+
         Poll::Pending
     }
 
@@ -721,63 +1468,162 @@ impl Isolate {
 
 impl Drop for Isolate {
     fn drop(&mut self) {
+        // The reason is the first termination result, before it's replaced below
+        let reason = match self.termination_result.read().unwrap().as_ref() {
+            Some(termination_result) => DisposeReason::from_run_result(Some(termination_result)),
+            None => match &self.compilation_error {
+                Some(compilation_error) => DisposeReason::Error(compilation_error.clone()),
+                None => DisposeReason::Dropped,
+            },
+        };
+
         self.terminate(RunResult::Error(String::from("Dropped")));
 
         if let Some(on_drop) = &self.options.on_drop {
             on_drop(Rc::clone(&self.options.metadata));
         }
+
+        send_lifecycle_event(&self.options, IsolateLifecycleEvent::Disposed { reason });
     }
 }
 
-pub fn send_statistics(options: &IsolateOptions, isolate: &mut v8::Isolate) {
+fn send_lifecycle_event(options: &IsolateOptions, event: IsolateLifecycleEvent) {
+    if let Some(lifecycle_sender) = &options.lifecycle_sender {
+        lifecycle_sender
+            .send((event, options.metadata.as_ref().clone()))
+            .unwrap_or(());
+    }
+}
+
+pub fn send_statistics(
+    options: &IsolateOptions,
+    isolate: &mut v8::Isolate,
+    timings: Option<Timings>,
+) {
     if let Some(on_statistics) = &options.on_statistics {
         let mut statistics = v8::HeapStatistics::default();
         isolate.get_heap_statistics(&mut statistics);
 
-        on_statistics(Rc::clone(&options.metadata), statistics.used_heap_size())
+        on_statistics(
+            Rc::clone(&options.metadata),
+            IsolateStatistics {
+                memory_usage: statistics.used_heap_size(),
+                timings,
+            },
+        )
     }
 }
 
-pub fn get_exception_message(
-    scope: &mut v8::TryCatch<v8::HandleScope>,
-    exception: v8::Local<v8::Value>,
+// Format the frames of a stack trace, skipping the frames of the JS runtime and
+// remapping positions to the original sources when a sourcemap is available
+pub fn format_stack_trace(
+    scope: &mut v8::HandleScope,
+    stack_trace: v8::Local<v8::StackTrace>,
     lines: usize,
+    source_map: Option<&SourceMap>,
 ) -> String {
-    let exception_message = v8::Exception::create_message(scope, exception);
-    let message = exception_message.get(scope).to_rust_string_lossy(scope);
+    let frames = stack_trace.get_frame_count();
+    let mut formatted = String::new();
+
+    for i in 0..frames {
+        if let Some(frame) = stack_trace.get_frame(scope, i) {
+            let script_name = frame
+                .get_script_name(scope)
+                .map_or_else(String::new, |script_name| {
+                    script_name.to_rust_string_lossy(scope)
+                });
+
+            // Skip script containg JS runtime, used when generating the snapshot blob
+            if script_name == RUNTIME_ONLY_SCRIPT_NAME {
+                continue;
+            }
 
-    if let Some(stack_trace) = exception_message.get_stack_trace(scope) {
-        let frames = stack_trace.get_frame_count();
-        let mut formatted = String::new();
+            // Imported modules only contain their own code
+            if !script_name.is_empty()
+                && script_name != CODE_ONLY_SCRIPT_NAME
+                && script_name != ISOLATE_SCRIPT_NAME
+            {
+                let location = format!(
+                    "{}:{}:{}",
+                    script_name,
+                    frame.get_line_number(),
+                    frame.get_column()
+                );
 
-        for i in 0..frames {
-            if let Some(frame) = stack_trace.get_frame(scope, i) {
-                let script_name = frame
-                    .get_script_name(scope)
-                    .unwrap()
-                    .to_rust_string_lossy(scope);
+                formatted.push_str(&match frame.get_function_name(scope) {
+                    Some(function_name) => format!(
+                        "\n  at {} ({})",
+                        function_name.to_rust_string_lossy(scope),
+                        location
+                    ),
+                    None => format!("\n  at {location}"),
+                });
 
-                // Skip script containg JS runtime, used when generating the snapshot blob
-                if script_name == RUNTIME_ONLY_SCRIPT_NAME || lines > frame.get_line_number() {
-                    continue;
-                }
+                continue;
+            }
+
+            if lines > frame.get_line_number() {
+                continue;
+            }
+
+            let line = frame.get_line_number() - lines;
+            let column = frame.get_column();
 
-                let location =
-                    format!("{}:{}", frame.get_line_number() - lines, frame.get_column());
+            // Positions are 1-based, while sourcemaps are 0-based
+            let token = source_map.and_then(|source_map| {
+                source_map.lookup_token(
+                    line.saturating_sub(1) as u32,
+                    column.saturating_sub(1) as u32,
+                )
+            });
 
-                let frame = if let Some(function_name) = frame.get_function_name(scope) {
+            let location = match token.and_then(|token| {
+                token.get_source().map(|source| {
                     format!(
-                        "\n  at {} ({})",
-                        function_name.to_rust_string_lossy(scope),
-                        location,
+                        "{}:{}:{}",
+                        source,
+                        token.get_src_line() + 1,
+                        token.get_src_col() + 1
                     )
-                } else {
-                    format!("\n  at {location}")
-                };
+                })
+            }) {
+                Some(location) => location,
+                None => format!("{line}:{column}"),
+            };
 
-                formatted.push_str(&frame);
-            }
+            let frame = if let Some(function_name) = frame.get_function_name(scope) {
+                format!(
+                    "\n  at {} ({})",
+                    function_name.to_rust_string_lossy(scope),
+                    location,
+                )
+            } else {
+                format!("\n  at {location}")
+            };
+
+            formatted.push_str(&frame);
         }
+    }
+
+    formatted
+}
+
+pub fn get_exception_message(
+    scope: &mut v8::TryCatch<v8::HandleScope>,
+    exception: v8::Local<v8::Value>,
+    lines: usize,
+    source_map: Option<&SourceMap>,
+) -> String {
+    let exception_message = v8::Exception::create_message(scope, exception);
+    let message = exception_message.get(scope).to_rust_string_lossy(scope);
+
+    // Errors keep the stack trace of where they have been created, which
+    // is more accurate than where they were thrown (e.g when rethrowing)
+    let stack_trace = v8::Exception::get_stack_trace(scope, exception)
+        .or_else(|| exception_message.get_stack_trace(scope));
+
+    if let Some(stack_trace) = stack_trace {
+        let formatted = format_stack_trace(scope, stack_trace, lines, source_map);
 
         return format!("{message}{formatted}");
     }
@@ -789,9 +1635,78 @@ pub fn get_exception_message(
     message
 }
 
-fn handle_error(scope: &mut v8::TryCatch<v8::HandleScope>, lines: usize) -> RunResult {
+// Resolve an import specifier relative to the path of the module importing it.
+// Only relative and absolute paths are supported, e.g `./utils.js`,
+// `../utils.js` or `/utils.js`
+pub fn resolve_module_path(referrer: &str, specifier: &str) -> Option<String> {
+    let specifier = specifier.strip_prefix(MODULES_BASE_URL).map_or_else(
+        || specifier.to_string(),
+        |specifier| format!("/{specifier}"),
+    );
+
+    let mut segments = match specifier.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None if specifier.starts_with("./") || specifier.starts_with("../") => {
+            let mut segments = referrer.split('/').collect::<Vec<_>>();
+            // Remove the referrer's file name
+            segments.pop();
+            segments
+        }
+        None => return None,
+    };
+
+    for segment in specifier.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+// The position of the exception being handled, in the code of the
+// deployment. `None` when thrown by the runtime code prepended to it
+fn get_error_location(
+    scope: &mut v8::TryCatch<v8::HandleScope>,
+    lines: usize,
+    source_map: Option<&SourceMap>,
+) -> Option<ErrorLocation> {
+    let message = scope.message()?;
+    let line = message
+        .get_line_number(scope)?
+        .checked_sub(lines)
+        .filter(|line| *line > 0)?;
+    // 0-based, unlike the line
+    let column = message.get_start_column() + 1;
+
+    let token = source_map
+        .and_then(|source_map| source_map.lookup_token((line - 1) as u32, (column - 1) as u32));
+
+    Some(match token {
+        Some(token) => ErrorLocation {
+            source: token.get_source().map(String::from),
+            line: token.get_src_line() as usize + 1,
+            column: token.get_src_col() as usize + 1,
+        },
+        None => ErrorLocation {
+            source: None,
+            line,
+            column,
+        },
+    })
+}
+
+fn handle_error(
+    scope: &mut v8::TryCatch<v8::HandleScope>,
+    lines: usize,
+    source_map: Option<&SourceMap>,
+) -> RunResult {
     if let Some(exception) = scope.exception() {
-        return RunResult::Error(get_exception_message(scope, exception, lines));
+        return RunResult::Error(get_exception_message(scope, exception, lines, source_map));
     }
 
     RunResult::Error("Unknown error".into())
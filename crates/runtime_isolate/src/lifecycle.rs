@@ -0,0 +1,49 @@
+use lagon_runtime_http::RunResult;
+
+use crate::Timings;
+
+// Sent to the lifecycle sender of the isolate options, from the creation
+// of an isolate to its disposal. Unlike the response events, they are
+// about the isolate itself, e.g to know how long isolates live
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IsolateLifecycleEvent {
+    // The V8 isolate and its context have been created
+    Created { cold_start_us: u64 },
+    // The code has been evaluated, so the isolate can handle requests
+    Warmed,
+    RequestStarted,
+    // A response, error or timeout has been sent for the request
+    RequestFinished { timings: Timings },
+    Disposed { reason: DisposeReason },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisposeReason {
+    // Dropped by its owner, e.g when it was idle for too long
+    Dropped,
+    Timeout,
+    MemoryLimit,
+    // Terminated with an error, e.g the code failed to compile
+    Error(String),
+}
+
+impl DisposeReason {
+    pub fn from_run_result(run_result: Option<&RunResult>) -> Self {
+        match run_result {
+            Some(RunResult::Timeout) => Self::Timeout,
+            Some(RunResult::MemoryLimit) => Self::MemoryLimit,
+            Some(RunResult::Error(error)) => Self::Error(error.clone()),
+            _ => Self::Dropped,
+        }
+    }
+
+    // Used as a metrics label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Dropped => "dropped",
+            Self::Timeout => "timeout",
+            Self::MemoryLimit => "memory_limit",
+            Self::Error(_) => "error",
+        }
+    }
+}
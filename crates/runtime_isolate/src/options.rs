@@ -1,25 +1,99 @@
-use lagon_runtime_v8_utils::v8_string;
-use std::{collections::HashMap, rc::Rc, time::Duration};
+use anyhow::Result;
+use lagon_runtime_http::BodySpilling;
+use lagon_runtime_v8_utils::{parse_v8_flags, V8FlagScope};
+use sourcemap::SourceMap;
+use std::{collections::HashMap, path::PathBuf, rc::Rc, sync::Arc, time::Duration};
+
+use crate::{CodeCache, FetchCache, FetchRecorder, IsolateLifecycleEvent, IsolateStatistics};
 
 const JS_RUNTIME: &str = include_str!("../runtime.js");
+const DEFAULT_JSON_MAX_SIZE: usize = 10 * 1024 * 1024;
+const DEFAULT_JSON_MAX_DEPTH: usize = 128;
+const DEFAULT_INSPECT_MAX_DEPTH: usize = 2;
+const DEFAULT_INSPECT_MAX_LENGTH: usize = 100;
+const DEFAULT_INSPECT_MAX_STRING_LENGTH: usize = 10_000;
+const DEFAULT_INSPECT_MAX_OUTPUT_SIZE: usize = 64 * 1024;
 
 pub type Metadata = Option<(String, String)>;
 type OnIsolateDropCallback = Box<dyn Fn(Rc<Metadata>)>;
-type OnIsolateStatisticsCallback = Box<dyn Fn(Rc<Metadata>, usize)>;
+type OnIsolateStatisticsCallback = Box<dyn Fn(Rc<Metadata>, IsolateStatistics)>;
 
 pub struct IsolateOptions {
     pub code: String,
     pub environment_variables: Option<HashMap<String, String>>,
+    // Exposed like environment variables, but redacted from logs
+    pub secrets: Option<HashMap<String, String>>,
     pub memory: usize, // in MB (MegaBytes)
+    // Requests can override `memory` up to this limit, in MB
+    pub max_memory: Option<usize>,
     pub tick_timeout: Duration,
     pub total_timeout: Duration,
+    // Wall-clock time a request can spend waiting for async operations (fetch(), timers,
+    // etc), so functions idling without using CPU time can't hold the isolate
+    pub async_timeout: Option<Duration>,
+    // Connecting, receiving the headers and reading the body of a fetch() response,
+    // unless the call is given its own `signal`
+    pub fetch_timeout: Option<Duration>,
+    // How long promises passed to waitUntil() can run after the response
+    pub wait_until_timeout: Duration,
     pub statistics_interval: Duration,
     pub metadata: Rc<Metadata>,
     pub on_drop: Option<OnIsolateDropCallback>,
     pub on_statistics: Option<OnIsolateStatisticsCallback>,
     pub log_sender: Option<flume::Sender<(String, String, Metadata)>>,
+    pub lifecycle_sender: Option<flume::Sender<(IsolateLifecycleEvent, Metadata)>>,
     pub snapshot: bool,
     pub snapshot_blob: Option<&'static [u8]>,
+    // Concurrent fetch() calls, and how many can wait for a slot
+    pub max_concurrent_fetches: Option<(usize, usize)>,
+    // Async operations of any kind pending at the same time,
+    // after which new ones are rejected
+    pub max_pending_ops: Option<usize>,
+    // Maximum number of frames captured in errors stack traces
+    pub stack_trace_limit: usize,
+    // Used to remap stack traces positions to the original sources
+    pub source_map: Option<Arc<SourceMap>>,
+    // Modules that can be imported by the code, by path relative to it
+    pub modules: HashMap<String, String>,
+    // Enforced by request.json() before parsing the body, with the size in bytes
+    pub json_max_size: usize,
+    pub json_max_depth: usize,
+    // Bodies read at once with arrayBuffer(), text(), etc are rejected
+    // once they exceed this size in bytes, instead of being buffered entirely
+    pub max_body_size: Option<usize>,
+    // How deep objects logged with console.log() are expanded,
+    // and how many of their entries are shown
+    pub inspect_max_depth: usize,
+    pub inspect_max_length: usize,
+    // Characters of the strings nested in logged objects, and bytes
+    // of each logged message, after which they are truncated
+    pub inspect_max_string_length: usize,
+    pub inspect_max_output_size: usize,
+    // Record fetch() calls, or replay them without reaching the network
+    pub fetch_recorder: Option<Arc<FetchRecorder>>,
+    // Serve the responses of fetch() GET requests from this cache, following
+    // their `cache` mode. Shared by the isolates of a deployment
+    pub fetch_cache: Option<Arc<FetchCache>>,
+    // Reuse the V8 code cache of the bundle when it was already compiled
+    pub code_cache: Option<Arc<CodeCache>>,
+    // fetch() calls to these hosts are sent over the mapped Unix socket
+    pub unix_sockets: HashMap<String, PathBuf>,
+    // fetch() response bodies larger than the threshold are written to disk
+    // as they are received, and read lazily from the file by the function
+    pub body_spilling: Option<BodySpilling>,
+    // Locales request.locale is matched against, in order of preference
+    pub supported_locales: Vec<String>,
+    // Only the allowed per-isolate flags, e.g `--stack-trace-limit=20`. Process-global
+    // flags have to be set once with the runtime options instead
+    pub v8_flags: Vec<String>,
+}
+
+// Settings overridden by the per-isolate V8 flags
+#[derive(Debug, Default)]
+pub(crate) struct IsolateFlags {
+    // In MB, never above the memory limit
+    pub initial_heap_size: Option<usize>,
+    pub stack_trace_limit: Option<usize>,
 }
 
 unsafe impl Send for IsolateOptions {}
@@ -29,16 +103,41 @@ impl IsolateOptions {
         Self {
             code,
             environment_variables: None,
+            secrets: None,
             tick_timeout: Duration::from_millis(200),
             total_timeout: Duration::from_secs(1),
+            async_timeout: None,
+            fetch_timeout: None,
+            wait_until_timeout: Duration::from_secs(30),
             statistics_interval: Duration::from_secs(1),
             memory: 128,
+            max_memory: None,
             metadata: Rc::new(None),
             on_drop: None,
             on_statistics: None,
             snapshot: false,
             snapshot_blob: None,
             log_sender: None,
+            lifecycle_sender: None,
+            max_concurrent_fetches: None,
+            max_pending_ops: None,
+            stack_trace_limit: 10,
+            source_map: None,
+            modules: HashMap::new(),
+            json_max_size: DEFAULT_JSON_MAX_SIZE,
+            json_max_depth: DEFAULT_JSON_MAX_DEPTH,
+            max_body_size: None,
+            inspect_max_depth: DEFAULT_INSPECT_MAX_DEPTH,
+            inspect_max_length: DEFAULT_INSPECT_MAX_LENGTH,
+            inspect_max_string_length: DEFAULT_INSPECT_MAX_STRING_LENGTH,
+            inspect_max_output_size: DEFAULT_INSPECT_MAX_OUTPUT_SIZE,
+            fetch_recorder: None,
+            fetch_cache: None,
+            code_cache: None,
+            unix_sockets: HashMap::new(),
+            body_spilling: None,
+            supported_locales: Vec::new(),
+            v8_flags: Vec::new(),
         }
     }
 
@@ -47,6 +146,31 @@ impl IsolateOptions {
         self
     }
 
+    pub fn secrets(mut self, secrets: HashMap<String, String>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    pub fn get_secrets(&self) -> Vec<String> {
+        self.secrets.as_ref().map_or_else(Vec::new, |secrets| {
+            secrets
+                .values()
+                .filter(|secret| !secret.is_empty())
+                .cloned()
+                .collect()
+        })
+    }
+
+    // Environment variables and secrets, like `process.env`
+    pub fn get_import_meta_env(&self) -> HashMap<String, String> {
+        self.environment_variables
+            .iter()
+            .chain(self.secrets.iter())
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
     pub fn tick_timeout(mut self, tick_timeout: Duration) -> Self {
         self.tick_timeout = tick_timeout;
         self
@@ -57,11 +181,39 @@ impl IsolateOptions {
         self
     }
 
+    pub fn async_timeout(mut self, async_timeout: Duration) -> Self {
+        self.async_timeout = Some(async_timeout);
+        self
+    }
+
+    pub fn fetch_timeout(mut self, fetch_timeout: Duration) -> Self {
+        self.fetch_timeout = Some(fetch_timeout);
+        self
+    }
+
+    pub fn wait_until_timeout(mut self, wait_until_timeout: Duration) -> Self {
+        self.wait_until_timeout = wait_until_timeout;
+        self
+    }
+
     pub fn memory(mut self, memory: usize) -> Self {
         self.memory = memory;
         self
     }
 
+    pub fn max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    // The memory limit of a request, in MB
+    pub fn get_request_memory(&self, memory: Option<usize>) -> usize {
+        match memory {
+            Some(memory) => memory.min(self.max_memory.unwrap_or(self.memory)),
+            None => self.memory,
+        }
+    }
+
     pub fn metadata(mut self, metadata: Metadata) -> Self {
         self.metadata = Rc::new(metadata);
         self
@@ -82,6 +234,126 @@ impl IsolateOptions {
         self
     }
 
+    pub fn lifecycle_sender(
+        mut self,
+        lifecycle_sender: flume::Sender<(IsolateLifecycleEvent, Metadata)>,
+    ) -> Self {
+        self.lifecycle_sender = Some(lifecycle_sender);
+        self
+    }
+
+    pub fn max_concurrent_fetches(mut self, max_concurrent: usize, max_queued: usize) -> Self {
+        self.max_concurrent_fetches = Some((max_concurrent, max_queued));
+        self
+    }
+
+    pub fn max_pending_ops(mut self, max_pending_ops: usize) -> Self {
+        self.max_pending_ops = Some(max_pending_ops);
+        self
+    }
+
+    pub fn stack_trace_limit(mut self, stack_trace_limit: usize) -> Self {
+        self.stack_trace_limit = stack_trace_limit;
+        self
+    }
+
+    pub fn source_map(mut self, source_map: Option<Arc<SourceMap>>) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    pub fn modules(mut self, modules: HashMap<String, String>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    pub fn json_max_size(mut self, json_max_size: usize) -> Self {
+        self.json_max_size = json_max_size;
+        self
+    }
+
+    pub fn json_max_depth(mut self, json_max_depth: usize) -> Self {
+        self.json_max_depth = json_max_depth;
+        self
+    }
+
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    pub fn inspect_max_depth(mut self, inspect_max_depth: usize) -> Self {
+        self.inspect_max_depth = inspect_max_depth;
+        self
+    }
+
+    pub fn inspect_max_length(mut self, inspect_max_length: usize) -> Self {
+        self.inspect_max_length = inspect_max_length;
+        self
+    }
+
+    pub fn inspect_max_string_length(mut self, inspect_max_string_length: usize) -> Self {
+        self.inspect_max_string_length = inspect_max_string_length;
+        self
+    }
+
+    pub fn inspect_max_output_size(mut self, inspect_max_output_size: usize) -> Self {
+        self.inspect_max_output_size = inspect_max_output_size;
+        self
+    }
+
+    pub fn fetch_recorder(mut self, fetch_recorder: Arc<FetchRecorder>) -> Self {
+        self.fetch_recorder = Some(fetch_recorder);
+        self
+    }
+
+    pub fn fetch_cache(mut self, fetch_cache: Arc<FetchCache>) -> Self {
+        self.fetch_cache = Some(fetch_cache);
+        self
+    }
+
+    pub fn code_cache(mut self, code_cache: Arc<CodeCache>) -> Self {
+        self.code_cache = Some(code_cache);
+        self
+    }
+
+    pub fn unix_sockets(mut self, unix_sockets: HashMap<String, PathBuf>) -> Self {
+        self.unix_sockets = unix_sockets
+            .into_iter()
+            .map(|(host, path)| (host.to_lowercase(), path))
+            .collect();
+        self
+    }
+
+    pub fn body_spilling(mut self, body_spilling: BodySpilling) -> Self {
+        self.body_spilling = Some(body_spilling);
+        self
+    }
+
+    pub fn supported_locales(mut self, supported_locales: Vec<String>) -> Self {
+        self.supported_locales = supported_locales;
+        self
+    }
+
+    pub fn v8_flags(mut self, v8_flags: Vec<String>) -> Self {
+        self.v8_flags = v8_flags;
+        self
+    }
+
+    pub(crate) fn get_isolate_flags(&self) -> Result<IsolateFlags> {
+        let mut isolate_flags = IsolateFlags::default();
+
+        for flag in parse_v8_flags(&self.v8_flags, V8FlagScope::Isolate)? {
+            match flag.name.as_str() {
+                "initial-heap-size" => isolate_flags.initial_heap_size = Some(flag.number_value()?),
+                "stack-trace-limit" => isolate_flags.stack_trace_limit = Some(flag.number_value()?),
+                _ => {}
+            }
+        }
+
+        Ok(isolate_flags)
+    }
+
     pub fn snapshot(mut self, snapshot: bool) -> Self {
         self.snapshot = snapshot;
         self
@@ -98,57 +370,48 @@ impl IsolateOptions {
         self
     }
 
-    pub fn get_runtime_code<'a>(
-        &self,
-        scope: &mut v8::HandleScope<'a>,
-    ) -> (v8::Local<'a, v8::String>, usize) {
+    pub fn get_runtime_code(&self) -> (String, usize) {
         let IsolateOptions {
             code,
             environment_variables,
+            secrets,
             snapshot,
             snapshot_blob,
             ..
         } = self;
 
-        let environment_variables = match environment_variables {
-            Some(environment_variables) => environment_variables
-                .iter()
-                .map(|(k, v)| format!("globalThis.process.env.{k} = '{v}'"))
-                .collect::<Vec<String>>()
-                .join("\n"),
-            None => "".to_string(),
-        };
+        let environment_variables = environment_variables
+            .iter()
+            .chain(secrets.iter())
+            .flatten()
+            .map(|(k, v)| format!("globalThis.process.env.{k} = '{v}'"))
+            .collect::<Vec<String>>()
+            .join("\n");
 
         if snapshot_blob.is_some() {
             // If we have a snapshot, only return the isolate's code
             // and the environment variables
             (
-                v8_string(
-                    scope,
-                    &format!(
-                        r"{environment_variables}
+                format!(
+                    r"{environment_variables}
 {code}
 globalThis.handler = handler;"
-                    ),
                 ),
                 environment_variables.lines().count() + 1,
             )
         } else if *snapshot {
             // If we are currently making a snapshot, only return
             // the js runtime code
-            (v8_string(scope, JS_RUNTIME), 0)
+            (JS_RUNTIME.to_string(), 0)
         } else {
             // Else, that means we don't care about snapshots at all
             // and we can return all the code
             (
-                v8_string(
-                    scope,
-                    &format!(
-                        r"{JS_RUNTIME}
+                format!(
+                    r"{JS_RUNTIME}
 {environment_variables}
 {code}
 globalThis.handler = handler;"
-                    ),
                 ),
                 JS_RUNTIME.lines().count() + environment_variables.lines().count() + 2,
             )
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use crate::BundleMetadata;
+
+// Where the time of a request has been spent. The code is compiled and
+// its top-level code is run once per isolate, before its first request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub compilation_time: Duration,
+    pub evaluation_time: Duration,
+    // Until the response has been returned, or its stream has completed
+    pub handler_time: Duration,
+}
+
+impl Timings {
+    pub(crate) fn new(bundle_metadata: Option<&BundleMetadata>, handler_time: Duration) -> Self {
+        Self {
+            compilation_time: bundle_metadata
+                .map_or(Duration::ZERO, |bundle| bundle.compilation_time),
+            evaluation_time: bundle_metadata
+                .map_or(Duration::ZERO, |bundle| bundle.evaluation_time),
+            handler_time,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IsolateStatistics {
+    // The used heap size, in bytes
+    pub memory_usage: usize,
+    // Unset when the statistics aren't sent for a request,
+    // e.g when a waitUntil() promise has settled
+    pub timings: Option<Timings>,
+}
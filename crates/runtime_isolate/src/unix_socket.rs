@@ -0,0 +1,91 @@
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Client, Uri,
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UnixStream,
+};
+
+// Clients are kept per socket to reuse their connections
+static CLIENTS: Lazy<Mutex<HashMap<PathBuf, Client<UnixConnector>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// fetch() calls to the hosts mapped to a socket in the isolate options (e.g
+// to call sidecar services) are sent over it instead of the network. Other
+// hosts can never reach a Unix socket
+pub fn unix_socket_client(path: &Path) -> Client<UnixConnector> {
+    CLIENTS
+        .lock()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_insert_with(|| Client::builder().build(UnixConnector(path.to_path_buf())))
+        .clone()
+}
+
+// Connects to the same socket whatever the URI is
+#[derive(Debug, Clone)]
+pub struct UnixConnector(PathBuf);
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.0.clone();
+
+        Box::pin(async move { Ok(UnixConnection(UnixStream::connect(path).await?)) })
+    }
+}
+
+pub struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{options::IsolateOptions, Isolate};
+
+// Where an error was thrown in the code of the deployment, 1-based. The
+// position is in the original sources when the code has a source map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub source: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub message: String,
+    // Unknown when the error was thrown by the runtime, e.g for invalid V8 flags
+    pub location: Option<ErrorLocation>,
+}
+
+// Compile and evaluate the code of a deployment without serving any request,
+// reporting the syntax, compilation and top-level errors. The async operations
+// started by the top-level code are never run. V8 has to be initialized, and the
+// thread is blocked for the evaluation, which is bounded by the tick timeout
+pub fn validate_deployment(options: IsolateOptions) -> Result<(), ValidationError> {
+    let (_tx, rx) = flume::unbounded();
+    let mut isolate = Isolate::new(options, rx);
+
+    isolate.evaluate();
+
+    match isolate.get_compilation_error() {
+        Some(message) => Err(ValidationError {
+            message: message.to_string(),
+            location: isolate.compilation_error_location.clone(),
+        }),
+        None => Ok(()),
+    }
+}
@@ -7,6 +7,120 @@ use std::{
     path::{Path, PathBuf},
 };
 
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+pub const NO_CACHE_CONTROL: &str = "no-cache";
+
+// Minimum length of a file name segment to be considered a content hash
+const MIN_HASH_LENGTH: usize = 8;
+
+// Default Cache-Control headers of the assets, when the function doesn't handle them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetsCacheControl {
+    // Assets with a content hash in their file name, e.g `app.3f9a1c2e.js`
+    pub hashed: Option<String>,
+    // HTML files reference the other assets, so they should be revalidated
+    pub html: Option<String>,
+    pub default: Option<String>,
+    // Patterns (with `*` wildcards) matched against the asset path, in order
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Default for AssetsCacheControl {
+    fn default() -> Self {
+        Self {
+            hashed: Some(IMMUTABLE_CACHE_CONTROL.into()),
+            html: Some(NO_CACHE_CONTROL.into()),
+            default: None,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl AssetsCacheControl {
+    pub fn hashed(mut self, cache_control: Option<String>) -> Self {
+        self.hashed = cache_control;
+        self
+    }
+
+    pub fn html(mut self, cache_control: Option<String>) -> Self {
+        self.html = cache_control;
+        self
+    }
+
+    pub fn default_cache_control(mut self, cache_control: Option<String>) -> Self {
+        self.default = cache_control;
+        self
+    }
+
+    pub fn override_pattern(mut self, pattern: String, cache_control: String) -> Self {
+        self.overrides.push((pattern, cache_control));
+        self
+    }
+
+    pub fn get(&self, asset: &str) -> Option<&str> {
+        if let Some((_, cache_control)) = self
+            .overrides
+            .iter()
+            .find(|(pattern, _)| matches_pattern(pattern, asset))
+        {
+            return Some(cache_control);
+        }
+
+        let cache_control = if asset.ends_with(".html") {
+            &self.html
+        } else if is_hashed_asset(asset) {
+            &self.hashed
+        } else {
+            &self.default
+        };
+
+        cache_control.as_deref()
+    }
+}
+
+// Heuristic: one of the file name segments (separated by `.`, `-` or `_`),
+// other than the first one, looks like a content hash
+pub fn is_hashed_asset(asset: &str) -> bool {
+    let file_name = Path::new(asset)
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+        .unwrap_or("");
+
+    file_name.split(['.', '-', '_']).skip(1).any(|segment| {
+        segment.len() >= MIN_HASH_LENGTH
+            && segment.chars().all(|char| char.is_ascii_alphanumeric())
+            && segment.chars().any(|char| char.is_ascii_digit())
+            && segment.chars().any(|char| char.is_ascii_alphabetic())
+    })
+}
+
+// `*` matches any sequence of characters, including `/`
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+
+    for (index, part) in parts.iter().enumerate() {
+        // The last part has to match the end of the path
+        if index == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.is_empty()
+}
+
 pub fn find_asset<'a>(url: &'a str, assets: &'a HashSet<String>) -> Option<&'a String> {
     // Remove the leading '/' from the url
     let url = &url[1..];
@@ -19,7 +133,11 @@ pub fn find_asset<'a>(url: &'a str, assets: &'a HashSet<String>) -> Option<&'a S
     })
 }
 
-pub fn handle_asset(root: PathBuf, asset: &String) -> Result<Response> {
+pub fn handle_asset(
+    root: PathBuf,
+    asset: &String,
+    cache_control: Option<&str>,
+) -> Result<Response> {
     let path = root.join(asset);
     let body = fs::read(path)?;
 
@@ -39,11 +157,16 @@ pub fn handle_asset(root: PathBuf, asset: &String) -> Result<Response> {
         },
     );
 
-    let mut headers = HashMap::with_capacity(1);
+    let mut headers = HashMap::with_capacity(2);
     headers.insert("content-type".into(), vec![content_type.into()]);
 
+    if let Some(cache_control) = cache_control {
+        headers.insert("cache-control".into(), vec![cache_control.into()]);
+    }
+
     Ok(Response {
         status: 200,
+        status_text: None,
         headers: Some(headers),
         body: Bytes::from(body),
     })
@@ -122,4 +245,60 @@ mod tests {
         assert_eq!(find_asset("/hello/none", &assets), None);
         assert_eq!(find_asset("/hello/world/none", &assets), None);
     }
+
+    #[test]
+    fn hashed_assets() {
+        assert!(is_hashed_asset("app.3f9a1c2e.js"));
+        assert!(is_hashed_asset("static/index-4b1a2c3d.css"));
+        assert!(is_hashed_asset("chunk_a1b2c3d4e5.js"));
+        assert!(!is_hashed_asset("app.js"));
+        assert!(!is_hashed_asset("3f9a1c2e.js"));
+        assert!(!is_hashed_asset("jquery.min.js"));
+        assert!(!is_hashed_asset("logo-20230101.png"));
+        assert!(!is_hashed_asset("my-component.js"));
+    }
+
+    #[test]
+    fn assets_cache_control() {
+        let cache_control = AssetsCacheControl::default();
+
+        assert_eq!(
+            cache_control.get("app.3f9a1c2e.js"),
+            Some(IMMUTABLE_CACHE_CONTROL)
+        );
+        assert_eq!(cache_control.get("index.html"), Some(NO_CACHE_CONTROL));
+        assert_eq!(
+            cache_control.get("hello/index.3f9a1c2e.html"),
+            Some(NO_CACHE_CONTROL)
+        );
+        assert_eq!(cache_control.get("app.js"), None);
+
+        let cache_control = cache_control
+            .default_cache_control(Some("public, max-age=3600".into()))
+            .override_pattern("images/*.png".into(), "public, max-age=86400".into())
+            .override_pattern("*.html".into(), "no-store".into());
+
+        assert_eq!(cache_control.get("app.js"), Some("public, max-age=3600"));
+        assert_eq!(
+            cache_control.get("images/logos/lagon.png"),
+            Some("public, max-age=86400")
+        );
+        assert_eq!(
+            cache_control.get("images/lagon.jpg"),
+            Some("public, max-age=3600")
+        );
+        assert_eq!(cache_control.get("index.html"), Some("no-store"));
+    }
+
+    #[test]
+    fn cache_control_patterns() {
+        assert!(matches_pattern("*", "index.html"));
+        assert!(matches_pattern("static/*", "static/app.js"));
+        assert!(matches_pattern("static/*.js", "static/nested/app.js"));
+        assert!(matches_pattern("*.min.*", "jquery.min.js"));
+        assert!(matches_pattern("robots.txt", "robots.txt"));
+        assert!(!matches_pattern("robots.txt", "static/robots.txt"));
+        assert!(!matches_pattern("static/*.js", "static/app.css"));
+        assert!(!matches_pattern("*.min.*", "jquery.js"));
+    }
 }
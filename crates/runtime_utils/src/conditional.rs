@@ -0,0 +1,197 @@
+use anyhow::Result;
+use hyper::{
+    header::{CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    http::response::Builder,
+    Body, HeaderMap, Method, Response as HyperResponse,
+};
+use lagon_runtime_http::Response;
+
+// Conditional headers of a GET or HEAD request, compared against
+// the validators (ETag and Last-Modified) set by the function
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConditionalHeaders {
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+}
+
+impl ConditionalHeaders {
+    pub fn from_request(method: &Method, headers: &HeaderMap) -> Option<Self> {
+        if method != Method::GET && method != Method::HEAD {
+            return None;
+        }
+
+        let get_header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        let conditional_headers = Self {
+            if_none_match: get_header(IF_NONE_MATCH),
+            if_modified_since: get_header(IF_MODIFIED_SINCE),
+        };
+
+        if conditional_headers == Self::default() {
+            return None;
+        }
+
+        Some(conditional_headers)
+    }
+
+    // Only successful responses with validators can be converted. If-Modified-Since
+    // is ignored when If-None-Match is present, as defined by RFC 9110
+    pub fn is_not_modified(&self, response: &Response) -> bool {
+        if response.status != 200 {
+            return false;
+        }
+
+        if let Some(if_none_match) = &self.if_none_match {
+            return match response.get_header(ETAG.as_str()) {
+                Some(etag) => if_none_match
+                    .split(',')
+                    .map(|tag| tag.trim())
+                    .any(|tag| tag == "*" || weak_eq(tag, etag)),
+                None => false,
+            };
+        }
+
+        match (
+            &self.if_modified_since,
+            response.get_header(LAST_MODIFIED.as_str()),
+        ) {
+            (Some(if_modified_since), Some(last_modified)) => match (
+                httpdate::parse_http_date(if_modified_since),
+                httpdate::parse_http_date(last_modified),
+            ) {
+                (Ok(if_modified_since), Ok(last_modified)) => last_modified <= if_modified_since,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+// Weak comparison, as required for If-None-Match: the opaque tags are
+// compared whether or not they have the `W/` prefix of weak validators
+fn weak_eq(a: &str, b: &str) -> bool {
+    fn opaque_tag(tag: &str) -> &str {
+        tag.strip_prefix("W/").unwrap_or(tag)
+    }
+
+    opaque_tag(a) == opaque_tag(b.trim())
+}
+
+// Keep the headers of the full response, without its body
+pub fn not_modified(response: &Response) -> Result<HyperResponse<Body>> {
+    let mut hyper_response = Builder::try_from(response)?
+        .status(304)
+        .body(Body::empty())?;
+    hyper_response.headers_mut().remove(CONTENT_LENGTH);
+
+    Ok(hyper_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn response(headers: &[(&str, &str)]) -> Response {
+        Response {
+            status: 200,
+            status_text: None,
+            headers: Some(HashMap::from_iter(
+                headers
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), vec![value.to_string()])),
+            )),
+            body: "Hello world".into(),
+        }
+    }
+
+    #[test]
+    fn from_request() {
+        let mut headers = HeaderMap::new();
+
+        assert!(ConditionalHeaders::from_request(&Method::GET, &headers).is_none());
+
+        headers.insert(IF_NONE_MATCH, "\"abc\"".parse().unwrap());
+
+        assert_eq!(
+            ConditionalHeaders::from_request(&Method::GET, &headers),
+            Some(ConditionalHeaders {
+                if_none_match: Some("\"abc\"".into()),
+                if_modified_since: None,
+            })
+        );
+        assert!(ConditionalHeaders::from_request(&Method::POST, &headers).is_none());
+    }
+
+    #[test]
+    fn etag() {
+        let conditional_headers = ConditionalHeaders {
+            if_none_match: Some("\"xyz\", W/\"abc\"".into()),
+            if_modified_since: None,
+        };
+
+        assert!(conditional_headers.is_not_modified(&response(&[("etag", "\"abc\"")])));
+        assert!(!conditional_headers.is_not_modified(&response(&[("etag", "\"def\"")])));
+        assert!(!conditional_headers.is_not_modified(&response(&[])));
+
+        let conditional_headers = ConditionalHeaders {
+            if_none_match: Some("*".into()),
+            if_modified_since: None,
+        };
+
+        assert!(conditional_headers.is_not_modified(&response(&[("ETag", "\"abc\"")])));
+    }
+
+    #[test]
+    fn weak_etag() {
+        let strong = ConditionalHeaders {
+            if_none_match: Some("\"abc\"".into()),
+            if_modified_since: None,
+        };
+        let weak = ConditionalHeaders {
+            if_none_match: Some("W/\"abc\"".into()),
+            if_modified_since: None,
+        };
+
+        assert!(strong.is_not_modified(&response(&[("etag", "W/\"abc\"")])));
+        assert!(weak.is_not_modified(&response(&[("etag", "\"abc\"")])));
+        assert!(weak.is_not_modified(&response(&[("etag", "W/\"abc\"")])));
+        assert!(!weak.is_not_modified(&response(&[("etag", "W/\"abcd\"")])));
+        // Only a single prefix is allowed, and it's case-sensitive
+        assert!(!weak.is_not_modified(&response(&[("etag", "W/W/\"abc\"")])));
+        assert!(!weak.is_not_modified(&response(&[("etag", "w/\"abc\"")])));
+    }
+
+    #[test]
+    fn last_modified() {
+        let conditional_headers = ConditionalHeaders {
+            if_none_match: None,
+            if_modified_since: Some("Sat, 22 Apr 2023 10:00:00 GMT".into()),
+        };
+
+        assert!(conditional_headers.is_not_modified(&response(&[(
+            "last-modified",
+            "Sat, 22 Apr 2023 10:00:00 GMT"
+        )])));
+        assert!(conditional_headers.is_not_modified(&response(&[(
+            "last-modified",
+            "Fri, 21 Apr 2023 10:00:00 GMT"
+        )])));
+        assert!(!conditional_headers.is_not_modified(&response(&[(
+            "last-modified",
+            "Sun, 23 Apr 2023 10:00:00 GMT"
+        )])));
+        assert!(!conditional_headers.is_not_modified(&response(&[("last-modified", "invalid")])));
+        assert!(!conditional_headers.is_not_modified(&response(&[])));
+
+        let mut not_found = response(&[("last-modified", "Fri, 21 Apr 2023 10:00:00 GMT")]);
+        not_found.status = 404;
+
+        assert!(!conditional_headers.is_not_modified(&not_found));
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::Result;
+use hyper::{
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Response as HyperResponse, StatusCode,
+};
+use std::collections::HashMap;
+
+const DEFAULT_TEMPLATE: &str =
+    r#"{"status":"{{status}}","message":"{{message}}","requestId":"{{requestId}}"}"#;
+
+// Marks the responses using one of the error pages, unlike the
+// ones returned by functions, even with the same status
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorPage;
+
+pub fn error_page(status: u16, page: &'static str) -> Result<HyperResponse<Body>> {
+    Ok(HyperResponse::builder()
+        .status(status)
+        .extension(ErrorPage)
+        .body(page.into())?)
+}
+
+// Served instead of the 502 error page when a function times out. It isn't
+// an error page, so it's always sent as-is, even to clients preferring JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl Default for TimeoutResponse {
+    fn default() -> Self {
+        Self {
+            status: 502,
+            content_type: None,
+            body: String::new(),
+        }
+    }
+}
+
+impl TimeoutResponse {
+    pub fn to_response(&self) -> Result<HyperResponse<Body>> {
+        let mut builder = HyperResponse::builder().status(self.status);
+
+        if let Some(content_type) = &self.content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+
+        Ok(builder.body(self.body.clone().into())?)
+    }
+}
+
+// JSON bodies of the error pages, by status. The `{{status}}`, `{{message}}`
+// and `{{requestId}}` placeholders of the templates are replaced when sent,
+// with `"{{status}}"` being replaced by the status as a number
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorSchema {
+    pub templates: HashMap<u16, String>,
+    // Used for the statuses without a template
+    pub default: Option<String>,
+}
+
+impl ErrorSchema {
+    fn render(&self, status: StatusCode, request_id: &str) -> String {
+        let template = self
+            .templates
+            .get(&status.as_u16())
+            .or(self.default.as_ref())
+            .map_or(DEFAULT_TEMPLATE, String::as_str);
+
+        render_template(template, status, request_id)
+    }
+}
+
+// Only the content of JSON strings is inserted, the quotes being part of the template
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for char in value.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            char if char.is_control() => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+fn render_template(template: &str, status: StatusCode, request_id: &str) -> String {
+    template
+        .replace("\"{{status}}\"", status.as_str())
+        .replace("{{status}}", status.as_str())
+        .replace(
+            "{{message}}",
+            &escape_json(status.canonical_reason().unwrap_or_default()),
+        )
+        .replace("{{requestId}}", &escape_json(request_id))
+}
+
+// Clients preferring JSON over HTML, e.g `Accept: application/json`,
+// get the error pages as JSON. Wildcards match both equally
+pub fn prefers_json(accept: &str) -> bool {
+    let mut json_quality: f32 = 0.0;
+    let mut html_quality: f32 = 0.0;
+
+    for media_range in accept.split(',') {
+        let mut params = media_range.split(';');
+        let media_type = params
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let quality = params
+            .find_map(|param| {
+                let (name, value) = param.split_once('=')?;
+
+                match name.trim().eq_ignore_ascii_case("q") {
+                    true => value.trim().parse::<f32>().ok(),
+                    false => None,
+                }
+            })
+            .unwrap_or(1.0);
+
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            json_quality = json_quality.max(quality);
+        } else if media_type == "text/html" {
+            html_quality = html_quality.max(quality);
+        }
+    }
+
+    json_quality > 0.0 && json_quality > html_quality
+}
+
+// Replace the body of the error pages with their JSON version. Other responses are kept as-is
+pub fn json_error_response(
+    response: HyperResponse<Body>,
+    schema: Option<&ErrorSchema>,
+    request_id: &str,
+) -> Result<HyperResponse<Body>> {
+    if response.extensions().get::<ErrorPage>().is_none() {
+        return Ok(response);
+    }
+
+    let body = match schema {
+        Some(schema) => schema.render(response.status(), request_id),
+        None => render_template(DEFAULT_TEMPLATE, response.status(), request_id),
+    };
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    Ok(HyperResponse::from_parts(parts, body.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::to_bytes;
+
+    #[test]
+    fn negotiation() {
+        assert!(prefers_json("application/json"));
+        assert!(prefers_json("application/problem+json"));
+        assert!(prefers_json("text/html;q=0.5, application/json"));
+        assert!(!prefers_json("text/html, application/json;q=0.9"));
+        assert!(!prefers_json("*/*"));
+        assert!(!prefers_json("application/json;q=0"));
+        assert!(!prefers_json(""));
+    }
+
+    #[test]
+    fn escaped_values() {
+        assert_eq!(
+            render_template(
+                r#"{"id":"{{requestId}}"}"#,
+                StatusCode::NOT_FOUND,
+                "a\"b\\c\n"
+            ),
+            r#"{"id":"a\"b\\c\u000a"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn default_schema() {
+        let response =
+            json_error_response(error_page(404, "<h1>404</h1>").unwrap(), None, "abc").unwrap();
+
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        assert_eq!(
+            to_bytes(response.into_body()).await.unwrap(),
+            r#"{"status":404,"message":"Not Found","requestId":"abc"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_schema() {
+        let schema = ErrorSchema {
+            templates: HashMap::from([(
+                404,
+                r#"{"code":"not_found","correlationId":"{{requestId}}"}"#.into(),
+            )]),
+            default: Some(
+                r#"{"code":"{{status}}","docs":"https://example.com/errors/{{status}}"}"#.into(),
+            ),
+        };
+
+        let response =
+            json_error_response(error_page(404, "").unwrap(), Some(&schema), "abc").unwrap();
+        assert_eq!(
+            to_bytes(response.into_body()).await.unwrap(),
+            r#"{"code":"not_found","correlationId":"abc"}"#
+        );
+
+        let response =
+            json_error_response(error_page(500, "").unwrap(), Some(&schema), "abc").unwrap();
+        assert_eq!(
+            to_bytes(response.into_body()).await.unwrap(),
+            r#"{"code":500,"docs":"https://example.com/errors/500"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn function_response() {
+        let response = HyperResponse::builder()
+            .status(404)
+            .body(Body::from("Not here"))
+            .unwrap();
+        let response = json_error_response(response, None, "abc").unwrap();
+
+        assert!(response.headers().get(CONTENT_TYPE).is_none());
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), "Not here");
+    }
+}
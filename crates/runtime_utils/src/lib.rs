@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use error_page::ErrorSchema;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -6,10 +7,16 @@ use std::{
     fs::{self, File},
     io::Write,
     path::Path,
+    time::Duration,
 };
 
 pub mod assets;
+pub mod conditional;
+pub mod error_page;
+pub mod range;
+pub mod request_context;
 pub mod response;
+pub mod zip;
 
 #[cfg(not(feature = "test"))]
 pub const DEPLOYMENTS_DIR: &str = "deployments";
@@ -17,7 +24,107 @@ pub const DEPLOYMENTS_DIR: &str = "deployments";
 #[cfg(feature = "test")]
 pub const DEPLOYMENTS_DIR: &str = "deployments_test";
 
-#[derive(Debug, Clone)]
+// Token bucket refilled with `rate` tokens per second, up to `burst` tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub rate: u32,
+    pub burst: u32,
+}
+
+// How a header of the deployment is combined with the ones set by the function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderPolicy {
+    // Replace all the values set by the function
+    Override,
+    // Add the value after the ones set by the function
+    Append,
+}
+
+// Added to the responses of the deployment's isolates, e.g security headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHeader {
+    pub name: String,
+    pub value: String,
+    pub policy: HeaderPolicy,
+    // Only added to the responses of this path (e.g Clear-Site-Data
+    // on /logout), or of the paths it prefixes when it ends with `*`
+    pub path: Option<String>,
+}
+
+impl ResponseHeader {
+    pub fn matches_path(&self, path: &str) -> bool {
+        match &self.path {
+            Some(header_path) => match header_path.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == header_path,
+            },
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFilterMode {
+    // Only the listed headers can be set
+    Allow,
+    // All the headers but the listed ones can be set
+    Deny,
+}
+
+// Restricts the headers functions can set, e.g so tenants can't override the
+// platform-managed ones. The headers of the deployment are never stripped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderFilter {
+    pub mode: HeaderFilterMode,
+    // Lowercase
+    pub names: HashSet<String>,
+    // Report the stripped headers
+    pub log_violations: bool,
+}
+
+impl HeaderFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        let listed = self.names.contains(&name.to_ascii_lowercase());
+
+        match self.mode {
+            HeaderFilterMode::Allow => listed,
+            HeaderFilterMode::Deny => !listed,
+        }
+    }
+}
+
+// Served when the function doesn't start responding before the deadline,
+// abandoning its invocation. Unlike the timeouts, the client gets a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fallback {
+    pub deadline: Duration,
+    // Serve the last response stored in the edge cache, even if expired
+    pub from_cache: bool,
+    // Served when there is no cached response. The function is
+    // awaited past the deadline when no response is available
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Option<String>,
+}
+
+// How requests are distributed across the warm isolates of a deployment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BalancingPolicy {
+    #[default]
+    RoundRobin,
+    // The isolate handling the fewest requests, e.g to
+    // avoid queuing requests behind long-running streams
+    LeastInFlight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsolatePool {
+    // Number of isolates created for the deployment, at least one
+    pub size: usize,
+    pub policy: BalancingPolicy,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Deployment {
     pub id: String,
     pub function_id: String,
@@ -28,8 +135,32 @@ pub struct Deployment {
     pub memory: usize,        // in MB (MegaBytes)
     pub tick_timeout: usize,  // in ms (MilliSeconds)
     pub total_timeout: usize, // in ms (MilliSeconds)
+    // Trusted requests can override `memory` up to this limit, in MB
+    pub max_memory: Option<usize>,
+    // Default timeout of the fetch() calls without a signal, in ms
+    pub fetch_timeout: Option<usize>,
     pub is_production: bool,
     pub cron: Option<String>,
+    // Per client IP
+    pub rate_limit: Option<RateLimit>,
+    // Requests bodies with another Content-Type are rejected, e.g
+    // `application/json` or `application/*`. Accept all when unset
+    pub accepted_content_types: Option<Vec<String>>,
+    // Locales request.locale is matched against, e.g `en-US` or `fr`
+    pub supported_locales: Option<Vec<String>>,
+    // Name of the access log formatter, using the server's default when unset
+    pub access_log_format: Option<String>,
+    // Headers added to the responses of the isolates, after the ones set by the function
+    pub response_headers: Option<Vec<ResponseHeader>>,
+    // Functions can set any header when unset
+    pub header_filter: Option<HeaderFilter>,
+    pub fallback: Option<Fallback>,
+    // A single isolate handles all the requests when unset
+    pub isolate_pool: Option<IsolatePool>,
+    // JSON bodies of the error pages, for the clients preferring JSON
+    pub error_schema: Option<ErrorSchema>,
+    // Send the timings measured by the platform with the Server-Timing header
+    pub server_timing: bool,
 }
 
 impl Deployment {
@@ -60,6 +191,38 @@ impl Deployment {
         self.is_production && self.cron.is_some()
     }
 
+    pub fn accepts_content_type(&self, content_type: &str) -> bool {
+        let accepted_content_types = match &self.accepted_content_types {
+            Some(accepted_content_types) => accepted_content_types,
+            None => return true,
+        };
+
+        // Ignore parameters, e.g `; charset=utf-8`
+        let content_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+
+        let (kind, subtype) = match content_type.split_once('/') {
+            Some(media_type) => media_type,
+            None => return false,
+        };
+
+        accepted_content_types.iter().any(|accepted_content_type| {
+            let accepted_content_type = accepted_content_type.trim().to_lowercase();
+
+            match accepted_content_type.split_once('/') {
+                Some((accepted_kind, accepted_subtype)) => {
+                    (accepted_kind == "*" || accepted_kind == kind)
+                        && (accepted_subtype == "*" || accepted_subtype == subtype)
+                }
+                None => false,
+            }
+        })
+    }
+
     pub fn get_code(&self) -> Result<String> {
         let path = Path::new(env::current_dir()?.as_path())
             .join(DEPLOYMENTS_DIR)
@@ -69,6 +232,15 @@ impl Deployment {
         Ok(code)
     }
 
+    // Sourcemaps are optional, so a missing one isn't an error
+    pub fn get_source_map(&self) -> Option<Vec<u8>> {
+        let path = Path::new(env::current_dir().ok()?.as_path())
+            .join(DEPLOYMENTS_DIR)
+            .join(self.id.clone() + ".js.map");
+
+        fs::read(path).ok()
+    }
+
     pub fn has_code(&self) -> bool {
         let path = Path::new(DEPLOYMENTS_DIR).join(self.id.clone() + ".js");
 
@@ -83,6 +255,14 @@ impl Deployment {
         Ok(())
     }
 
+    pub fn write_source_map(&self, source_map: &[u8]) -> Result<()> {
+        let mut file = File::create(Path::new(DEPLOYMENTS_DIR).join(self.id.clone() + ".js.map"))?;
+
+        file.write_all(source_map)?;
+
+        Ok(())
+    }
+
     pub fn write_asset(&self, asset: &str, content: &[u8]) -> Result<()> {
         let asset = asset.replace("public/", "");
         let asset = asset.as_str();
@@ -118,15 +298,65 @@ mod tests {
             assets: HashSet::new(),
             environment_variables: HashMap::new(),
             memory: 128,
+            max_memory: None,
+            fetch_timeout: None,
             tick_timeout: 1000,
             total_timeout: 1000,
             is_production: false,
             cron: None,
+            rate_limit: None,
+            accepted_content_types: None,
+            supported_locales: None,
+            error_schema: None,
+            access_log_format: None,
+            response_headers: None,
+            header_filter: None,
+            fallback: None,
+            isolate_pool: None,
+            server_timing: false,
         };
 
         assert_eq!(deployment.get_domains(), vec!["123.lagon.test".to_owned()]);
     }
 
+    #[test]
+    fn header_filter() {
+        let mut header_filter = HeaderFilter {
+            mode: HeaderFilterMode::Deny,
+            names: HashSet::from(["strict-transport-security".into()]),
+            log_violations: false,
+        };
+
+        assert!(header_filter.allows("content-type"));
+        assert!(!header_filter.allows("Strict-Transport-Security"));
+
+        header_filter.mode = HeaderFilterMode::Allow;
+        assert!(!header_filter.allows("content-type"));
+        assert!(header_filter.allows("strict-transport-security"));
+    }
+
+    #[test]
+    fn response_header_paths() {
+        let mut response_header = ResponseHeader {
+            name: "clear-site-data".into(),
+            value: "\"cookies\"".into(),
+            policy: HeaderPolicy::Override,
+            path: None,
+        };
+
+        assert!(response_header.matches_path("/"));
+        assert!(response_header.matches_path("/logout"));
+
+        response_header.path = Some("/logout".into());
+        assert!(response_header.matches_path("/logout"));
+        assert!(!response_header.matches_path("/logout/all"));
+        assert!(!response_header.matches_path("/"));
+
+        response_header.path = Some("/account/*".into());
+        assert!(response_header.matches_path("/account/logout"));
+        assert!(!response_header.matches_path("/account"));
+    }
+
     #[test]
     fn deployment_domains() {
         env::set_var("LAGON_ROOT_DOMAIN", "lagon.test");
@@ -139,10 +369,22 @@ mod tests {
             assets: HashSet::new(),
             environment_variables: HashMap::new(),
             memory: 128,
+            max_memory: None,
+            fetch_timeout: None,
             tick_timeout: 1000,
             total_timeout: 1000,
             is_production: false,
             cron: None,
+            rate_limit: None,
+            accepted_content_types: None,
+            supported_locales: None,
+            error_schema: None,
+            access_log_format: None,
+            response_headers: None,
+            header_filter: None,
+            fallback: None,
+            isolate_pool: None,
+            server_timing: false,
         };
 
         assert_eq!(deployment.get_domains(), vec!["123.lagon.test".to_owned(),]);
@@ -160,10 +402,22 @@ mod tests {
             assets: HashSet::new(),
             environment_variables: HashMap::new(),
             memory: 128,
+            max_memory: None,
+            fetch_timeout: None,
             tick_timeout: 1000,
             total_timeout: 1000,
             is_production: true,
             cron: None,
+            rate_limit: None,
+            accepted_content_types: None,
+            supported_locales: None,
+            error_schema: None,
+            access_log_format: None,
+            response_headers: None,
+            header_filter: None,
+            fallback: None,
+            isolate_pool: None,
+            server_timing: false,
         };
 
         assert_eq!(
@@ -175,4 +429,49 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn deployment_accepted_content_types() {
+        let mut deployment = Deployment {
+            id: "123".into(),
+            function_id: "456".into(),
+            function_name: "hello".into(),
+            domains: HashSet::new(),
+            assets: HashSet::new(),
+            environment_variables: HashMap::new(),
+            memory: 128,
+            max_memory: None,
+            fetch_timeout: None,
+            tick_timeout: 1000,
+            total_timeout: 1000,
+            is_production: true,
+            cron: None,
+            rate_limit: None,
+            accepted_content_types: None,
+            supported_locales: None,
+            error_schema: None,
+            access_log_format: None,
+            response_headers: None,
+            header_filter: None,
+            fallback: None,
+            isolate_pool: None,
+            server_timing: false,
+        };
+
+        assert!(deployment.accepts_content_type("text/plain"));
+
+        deployment.accepted_content_types = Some(vec!["application/json".into(), "image/*".into()]);
+
+        assert!(deployment.accepts_content_type("application/json"));
+        assert!(deployment.accepts_content_type("Application/JSON; charset=utf-8"));
+        assert!(deployment.accepts_content_type("image/png"));
+        assert!(!deployment.accepts_content_type("application/xml"));
+        assert!(!deployment.accepts_content_type("text/plain"));
+        assert!(!deployment.accepts_content_type("invalid"));
+
+        deployment.accepted_content_types = Some(vec!["*/*".into()]);
+
+        assert!(deployment.accepts_content_type("text/plain"));
+        assert!(!deployment.accepts_content_type(""));
+    }
 }
@@ -0,0 +1,351 @@
+use anyhow::Result;
+use hyper::{
+    body::{Bytes, HttpBody},
+    ext::ReasonPhrase,
+    header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, RANGE, TRAILER},
+    http::response::Parts,
+    Body, HeaderMap, Method, Response as HyperResponse, StatusCode,
+};
+use lagon_runtime_http::CONTENT_DIGEST;
+
+// Ranges of bodies without a length are read before sending the response, to
+// know if the body is long enough. Larger ranges are ignored and the
+// full response is sent instead
+const MAX_BUFFERED_RANGE: u64 = 10 * 1024 * 1024;
+
+// The single byte range requested by a GET request. Multiple ranges
+// aren't supported, and the full response is sent instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // `bytes=start-end` or `bytes=start-`, where the end is inclusive
+    FromTo(u64, Option<u64>),
+    // `bytes=-length`, the last `length` bytes
+    Suffix(u64),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ResolvedRange {
+    Partial {
+        start: u64,
+        end: u64,
+        length: Option<u64>,
+    },
+    Unsatisfiable(u64),
+    // The range can't be resolved without knowing the length of the body
+    Unknown,
+}
+
+impl ByteRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let range = value.trim().strip_prefix("bytes=")?;
+
+        if range.contains(',') {
+            return None;
+        }
+
+        let (start, end) = range.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            return match end.parse().ok()? {
+                0 => None,
+                length => Some(Self::Suffix(length)),
+            };
+        }
+
+        let start = start.parse().ok()?;
+        let end = match end.is_empty() {
+            true => None,
+            false => Some(end.parse().ok()?),
+        };
+
+        match end {
+            Some(end) if end < start => None,
+            end => Some(Self::FromTo(start, end)),
+        }
+    }
+
+    pub fn from_request(method: &Method, headers: &HeaderMap) -> Option<Self> {
+        if method != Method::GET {
+            return None;
+        }
+
+        headers
+            .get(RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse)
+    }
+
+    // Resolve the first and last bytes of the range using the length of the body,
+    // which is only known when the function sets a Content-Length
+    fn resolve(&self, length: Option<u64>) -> ResolvedRange {
+        match (*self, length) {
+            (Self::FromTo(start, _), Some(length)) if start >= length => {
+                ResolvedRange::Unsatisfiable(length)
+            }
+            (Self::FromTo(start, end), Some(length)) => ResolvedRange::Partial {
+                start,
+                end: end.map_or(length - 1, |end| end.min(length - 1)),
+                length: Some(length),
+            },
+            (Self::FromTo(start, Some(end)), None) if end - start < MAX_BUFFERED_RANGE => {
+                ResolvedRange::Partial {
+                    start,
+                    end,
+                    length: None,
+                }
+            }
+            (Self::Suffix(_), Some(0)) => ResolvedRange::Unsatisfiable(0),
+            (Self::Suffix(suffix), Some(length)) => ResolvedRange::Partial {
+                start: length.saturating_sub(suffix),
+                end: length - 1,
+                length: Some(length),
+            },
+            _ => ResolvedRange::Unknown,
+        }
+    }
+}
+
+// Skip the bytes before `start` and stop reading the body after
+// `end`, which also stops the isolate from streaming it
+fn slice_body(mut body: Body, start: u64, end: u64) -> Body {
+    let (mut sender, sliced_body) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut offset = 0;
+
+        while offset <= end {
+            let chunk = match body.data().await {
+                Some(Ok(chunk)) => chunk,
+                _ => return,
+            };
+
+            let chunk_start = offset;
+            offset += chunk.len() as u64;
+
+            if chunk.is_empty() || offset <= start {
+                continue;
+            }
+
+            let from = start.saturating_sub(chunk_start) as usize;
+            let to = (end + 1 - chunk_start).min(chunk.len() as u64) as usize;
+
+            if sender.send_data(chunk.slice(from..to)).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    sliced_body
+}
+
+// Read the bytes of the range from a body without a length, stopping after
+// `end`. Also returns the length of the body when it ends before `end`
+async fn buffer_range(body: &mut Body, start: u64, end: u64) -> Result<(Bytes, Option<u64>)> {
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+
+    while offset <= end {
+        let chunk = match body.data().await {
+            Some(chunk) => chunk?,
+            None => return Ok((buffer.into(), Some(offset))),
+        };
+
+        let chunk_start = offset;
+        offset += chunk.len() as u64;
+
+        if offset <= start {
+            continue;
+        }
+
+        let from = start.saturating_sub(chunk_start) as usize;
+        let to = (end + 1 - chunk_start).min(chunk.len() as u64) as usize;
+
+        buffer.extend_from_slice(&chunk[from..to]);
+    }
+
+    Ok((buffer.into(), None))
+}
+
+fn unsatisfiable_response(
+    response: HyperResponse<Body>,
+    length: u64,
+) -> Result<HyperResponse<Body>> {
+    let (mut parts, _) = into_partial_parts(response);
+
+    parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+    parts
+        .headers
+        .insert(CONTENT_RANGE, format!("bytes */{length}").parse()?);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Ok(HyperResponse::from_parts(parts, Body::empty()))
+}
+
+// Send only the requested range of successful responses opting in with
+// `Accept-Ranges: bytes`. Others are sent as-is, ignoring the range
+pub async fn range_response(
+    range: &ByteRange,
+    response: HyperResponse<Body>,
+) -> Result<HyperResponse<Body>> {
+    let accepts_ranges = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .map_or(false, |value| {
+            value.as_bytes().eq_ignore_ascii_case(b"bytes")
+        });
+
+    if response.status() != StatusCode::OK || !accepts_ranges {
+        return Ok(response);
+    }
+
+    let length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+
+    match range.resolve(length) {
+        ResolvedRange::Partial {
+            start,
+            end,
+            length: None,
+        } => {
+            let (mut parts, mut body) = into_partial_parts(response);
+            let (range, length) = buffer_range(&mut body, start, end).await?;
+
+            // The body is shorter than the range
+            let (end, length) = match length {
+                Some(length) if start >= length => {
+                    return unsatisfiable_response(HyperResponse::from_parts(parts, body), length);
+                }
+                Some(length) => (length - 1, length.to_string()),
+                None => (end, "*".into()),
+            };
+
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                format!("bytes {start}-{end}/{length}").parse()?,
+            );
+            parts.headers.insert(CONTENT_LENGTH, range.len().into());
+
+            Ok(HyperResponse::from_parts(parts, range.into()))
+        }
+        ResolvedRange::Partial {
+            start,
+            end,
+            length: Some(length),
+        } => {
+            let (mut parts, body) = into_partial_parts(response);
+
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                format!("bytes {start}-{end}/{length}").parse()?,
+            );
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, (end - start + 1).into());
+
+            Ok(HyperResponse::from_parts(
+                parts,
+                slice_body(body, start, end),
+            ))
+        }
+        ResolvedRange::Unsatisfiable(length) => unsatisfiable_response(response, length),
+        ResolvedRange::Unknown => Ok(response),
+    }
+}
+
+// The digest and status text are the ones of the full response
+fn into_partial_parts(response: HyperResponse<Body>) -> (Parts, Body) {
+    let (mut parts, body) = response.into_parts();
+
+    parts.headers.remove(CONTENT_DIGEST);
+    parts.headers.remove(TRAILER);
+    parts.extensions.remove::<ReasonPhrase>();
+
+    (parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-99"),
+            Some(ByteRange::FromTo(0, Some(99)))
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=100-"),
+            Some(ByteRange::FromTo(100, None))
+        );
+        assert_eq!(ByteRange::parse("bytes=-50"), Some(ByteRange::Suffix(50)));
+        assert_eq!(ByteRange::parse("bytes=0-0, 10-20"), None);
+        assert_eq!(ByteRange::parse("bytes=20-10"), None);
+        assert_eq!(ByteRange::parse("bytes=-0"), None);
+        assert_eq!(ByteRange::parse("bytes=-"), None);
+        assert_eq!(ByteRange::parse("items=0-10"), None);
+        assert_eq!(ByteRange::parse("bytes=a-b"), None);
+    }
+
+    #[test]
+    fn from_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, "bytes=0-99".parse().unwrap());
+
+        assert_eq!(
+            ByteRange::from_request(&Method::GET, &headers),
+            Some(ByteRange::FromTo(0, Some(99)))
+        );
+        assert_eq!(ByteRange::from_request(&Method::POST, &headers), None);
+        assert_eq!(
+            ByteRange::from_request(&Method::GET, &HeaderMap::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(
+            ByteRange::FromTo(10, Some(200)).resolve(Some(100)),
+            ResolvedRange::Partial {
+                start: 10,
+                end: 99,
+                length: Some(100)
+            }
+        );
+        assert_eq!(
+            ByteRange::FromTo(10, Some(20)).resolve(None),
+            ResolvedRange::Partial {
+                start: 10,
+                end: 20,
+                length: None
+            }
+        );
+        assert_eq!(
+            ByteRange::Suffix(200).resolve(Some(100)),
+            ResolvedRange::Partial {
+                start: 0,
+                end: 99,
+                length: Some(100)
+            }
+        );
+        assert_eq!(
+            ByteRange::FromTo(100, None).resolve(Some(100)),
+            ResolvedRange::Unsatisfiable(100)
+        );
+        assert_eq!(
+            ByteRange::FromTo(10, None).resolve(None),
+            ResolvedRange::Unknown
+        );
+        assert_eq!(ByteRange::Suffix(10).resolve(None), ResolvedRange::Unknown);
+        assert_eq!(
+            ByteRange::FromTo(0, Some(MAX_BUFFERED_RANGE)).resolve(None),
+            ResolvedRange::Unknown
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::Deployment;
+
+#[derive(Debug)]
+struct RequestContextInner<T> {
+    deployment_id: String,
+    function_id: String,
+    request_id: String,
+    start_time: Instant,
+    data: T,
+}
+
+// Request-scoped data passed to the `on_event` callback of `handle_response`. It is
+// cloned for every event of the request, so everything is behind an Arc. `T` holds
+// the additional data of the caller, and can be accessed directly through Deref
+#[derive(Debug)]
+pub struct RequestContext<T = ()>(Arc<RequestContextInner<T>>);
+
+impl<T> Clone for RequestContext<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl RequestContext {
+    pub fn new(
+        deployment_id: impl Into<String>,
+        function_id: impl Into<String>,
+        request_id: impl Into<String>,
+    ) -> Self {
+        Self::with_data(deployment_id, function_id, request_id, ())
+    }
+
+    pub fn from_deployment(deployment: &Deployment, request_id: impl Into<String>) -> Self {
+        Self::new(
+            deployment.id.clone(),
+            deployment.function_id.clone(),
+            request_id,
+        )
+    }
+}
+
+// Requests handled outside of a deployment, e.g by the CLI
+impl Default for RequestContext {
+    fn default() -> Self {
+        Self::new("", "", "")
+    }
+}
+
+impl<T> RequestContext<T> {
+    // The request starts being timed when the context is created
+    pub fn with_data(
+        deployment_id: impl Into<String>,
+        function_id: impl Into<String>,
+        request_id: impl Into<String>,
+        data: T,
+    ) -> Self {
+        Self(Arc::new(RequestContextInner {
+            deployment_id: deployment_id.into(),
+            function_id: function_id.into(),
+            request_id: request_id.into(),
+            start_time: Instant::now(),
+            data,
+        }))
+    }
+
+    pub fn deployment_id(&self) -> &str {
+        &self.0.deployment_id
+    }
+
+    pub fn function_id(&self) -> &str {
+        &self.0.function_id
+    }
+
+    pub fn request_id(&self) -> &str {
+        &self.0.request_id
+    }
+
+    pub fn start_time(&self) -> Instant {
+        self.0.start_time
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.0.start_time.elapsed()
+    }
+}
+
+impl<T> Deref for RequestContext<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_context() {
+        let context = RequestContext::with_data("deployment", "function", "request", vec![1]);
+        let clone = context.clone();
+
+        assert!(Arc::ptr_eq(&context.0, &clone.0));
+        assert_eq!(clone.deployment_id(), "deployment");
+        assert_eq!(clone.function_id(), "function");
+        assert_eq!(clone.request_id(), "request");
+        assert_eq!(clone.start_time(), context.start_time());
+        assert_eq!(*clone, vec![1]);
+    }
+
+    #[test]
+    fn default_context() {
+        let context = RequestContext::default();
+
+        assert_eq!(context.deployment_id(), "");
+        assert_eq!(context.request_id(), "");
+    }
+}
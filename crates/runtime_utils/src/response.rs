@@ -1,237 +1,2719 @@
 use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use flume::Receiver;
-use hyper::{body::Bytes, http::response::Builder, Body, Response as HyperResponse};
-use lagon_runtime_http::{RunResult, StreamResult};
-use std::{future::Future, pin::Pin};
+use futures::{Stream, StreamExt};
+use hyper::{
+    body::Bytes,
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH, TRAILER},
+    http::response::Builder,
+    Body, HeaderMap, Response as HyperResponse,
+};
+use lagon_runtime_http::{Response, RunResult, StreamResult, CONTENT_DIGEST};
+
+use crate::{
+    conditional::{not_modified, ConditionalHeaders},
+    error_page::{error_page, TimeoutResponse},
+    range::{range_response, ByteRange},
+    HeaderFilter, HeaderPolicy, ResponseHeader,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time::Instant};
 
 pub const PAGE_404: &str = include_str!("../public/404.html");
 pub const PAGE_403: &str = include_str!("../public/403.html");
+pub const PAGE_408: &str = include_str!("../public/408.html");
+pub const PAGE_413: &str = include_str!("../public/413.html");
+pub const PAGE_414: &str = include_str!("../public/414.html");
+pub const PAGE_415: &str = include_str!("../public/415.html");
+pub const PAGE_429: &str = include_str!("../public/429.html");
 pub const PAGE_502: &str = include_str!("../public/502.html");
 pub const PAGE_500: &str = include_str!("../public/500.html");
+pub const PAGE_503: &str = include_str!("../public/503.html");
+pub const PAGE_503_LOAD_ERROR: &str = include_str!("../public/503-load-error.html");
+
+pub const FAVICON_URL: &str = "/favicon.ico";
+
+pub enum ResponseEvent {
+    // Bytes sent, CPU time in microseconds and status of the response
+    Bytes(usize, Option<u128>, u16),
+    StreamDoneNoDataError,
+    UnexpectedStreamResult(RunResult),
+    LimitsReached(RunResult),
+    Error(RunResult),
+    // Names of the headers removed by the header filter
+    HeadersStripped(Vec<String>),
+}
+
+// Number of chunks buffered before the isolate has to wait
+// for the client to read the stream
+const STREAM_BUFFER_SIZE: usize = 16;
+
+// The body isn't polled until the response has been returned, so the chunks
+// received before the response are kept aside to not fill the stream
+struct StreamBody {
+    tx: flume::Sender<Result<Bytes, std::io::Error>>,
+    pending: Option<Vec<Bytes>>,
+    max_chunk_size: Option<usize>,
+    // Notified when the stream has completed successfully
+    done_tx: Option<oneshot::Sender<()>>,
+}
+
+impl StreamBody {
+    fn new(
+        tx: flume::Sender<Result<Bytes, std::io::Error>>,
+        max_chunk_size: Option<usize>,
+        done_tx: Option<oneshot::Sender<()>>,
+    ) -> Self {
+        Self {
+            tx,
+            pending: Some(Vec::new()),
+            max_chunk_size: max_chunk_size.filter(|max_chunk_size| *max_chunk_size > 0),
+            done_tx,
+        }
+    }
+
+    async fn send(&mut self, mut bytes: Bytes) {
+        // Split oversized chunks so they are flushed to the client incrementally,
+        // without copying them. Empty bytes (closing the stream) are kept as-is
+        if let Some(max_chunk_size) = self.max_chunk_size {
+            while bytes.len() > max_chunk_size {
+                let chunk = bytes.split_to(max_chunk_size);
+                self.send_chunk(chunk).await;
+            }
+        }
+
+        self.send_chunk(bytes).await;
+    }
+
+    async fn send_chunk(&mut self, bytes: Bytes) {
+        match &mut self.pending {
+            Some(pending) => pending.push(bytes),
+            None => self.tx.send_async(Ok(bytes)).await.unwrap_or(()),
+        }
+    }
+
+    async fn start(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            for bytes in pending {
+                self.tx.send_async(Ok(bytes)).await.unwrap_or(());
+            }
+        }
+    }
+
+    // Aborting the body closes the connection, so the client
+    // can tell the response is incomplete
+    async fn abort(&mut self, error: String) {
+        self.pending = None;
+        self.done_tx.take();
+
+        let error = std::io::Error::new(std::io::ErrorKind::Other, error);
+        self.tx.send_async(Err(error)).await.unwrap_or(());
+    }
+
+    async fn end(&mut self) {
+        if let Some(done_tx) = self.done_tx.take() {
+            done_tx.send(()).unwrap_or(());
+        }
+
+        // Close the stream by sending empty bytes
+        self.send(Bytes::new()).await;
+    }
+}
+
+// Formatted as defined by RFC 9530
+fn format_content_digest(hash: &[u8]) -> String {
+    format!("sha-256=:{}:", STANDARD.encode(hash))
+}
+
+fn insert_content_digest(hyper_response: &mut HyperResponse<Body>, body: &[u8]) -> Result<()> {
+    let content_digest = format_content_digest(&Sha256::digest(body));
+    hyper_response
+        .headers_mut()
+        .insert(CONTENT_DIGEST, HeaderValue::from_str(&content_digest)?);
+
+    Ok(())
+}
+
+// Hash the chunks while forwarding them to the client, then send the
+// digest as a trailer. Incomplete streams are sent without a digest
+fn digest_body(
+    stream_rx: Receiver<Result<Bytes, std::io::Error>>,
+    done_rx: oneshot::Receiver<()>,
+) -> Body {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut hasher = Sha256::new();
+
+        while let Ok(chunk) = stream_rx.recv_async().await {
+            let chunk = match chunk {
+                Ok(chunk) if chunk.is_empty() => continue,
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    sender.abort();
+                    return;
+                }
+            };
+
+            hasher.update(&chunk);
+
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+
+        if done_rx.await.is_ok() {
+            let content_digest = format_content_digest(&hasher.finalize());
+
+            if let Ok(content_digest) = HeaderValue::from_str(&content_digest) {
+                let mut trailers = HeaderMap::new();
+                trailers.insert(CONTENT_DIGEST, content_digest);
+
+                sender.send_trailers(trailers).await.unwrap_or(());
+            }
+        }
+    });
+
+    body
+}
+
+// Streams completing with at most `max_bytes` within `max_duration`
+// are sent as a single response with a Content-Length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamBuffering {
+    pub max_bytes: usize,
+    pub max_duration: Duration,
+}
+
+// How invalid UTF-8 in the bodies of `text/*` responses is handled. Only
+// the responses sent at once are checked, streamed chunks are sent as-is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utf8Policy {
+    // Replace the invalid sequences with U+FFFD
+    Replace,
+    // Send the 500 error page instead, and report the error
+    Reject,
+}
+
+// The status of the responses without a body (and not streamed) returning
+// the default status. An explicit 200 can't be told apart from the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyResponsePolicy {
+    #[default]
+    Ok,
+    // Sent as 204 No Content
+    NoContent,
+}
+
+type ChunkTransformFn = dyn FnMut(Bytes) -> Result<Bytes> + Send;
+
+// Applied by the host to each chunk of the streamed responses, e.g to rewrite
+// their content. An error aborts the response and is reported. Buffered streams
+// are transformed as a single chunk
+#[derive(Clone)]
+pub struct ChunkTransform(Arc<Mutex<Box<ChunkTransformFn>>>);
+
+impl ChunkTransform {
+    pub fn new<F>(transform: F) -> Self
+    where
+        F: FnMut(Bytes) -> Result<Bytes> + Send + 'static,
+    {
+        Self(Arc::new(Mutex::new(Box::new(transform))))
+    }
+
+    fn apply(&self, bytes: Bytes) -> Result<Bytes> {
+        let mut transform = self.0.lock().unwrap();
+        (*transform)(bytes)
+    }
+}
+
+impl fmt::Debug for ChunkTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ChunkTransform")
+    }
+}
+
+fn max_stream_chunks_error(max_stream_chunks: usize) -> String {
+    format!("Response stream exceeded the limit of {max_stream_chunks} chunks")
+}
+
+fn transform_chunk(chunk_transform: Option<&ChunkTransform>, bytes: Bytes) -> Result<Bytes> {
+    match chunk_transform {
+        Some(chunk_transform) => chunk_transform.apply(bytes),
+        None => Ok(bytes),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResponseOptions {
+    pub stream_buffering: Option<StreamBuffering>,
+    // Streamed chunks larger than this are split before being sent
+    pub max_chunk_size: Option<usize>,
+    // Streams sending more chunks are aborted and reported. Chunks are counted as
+    // sent by the function, before being split. Buffered streams aren't limited
+    pub max_stream_chunks: Option<usize>,
+    // Send a SHA-256 Content-Digest of the body, as a
+    // header or as a trailer for streamed responses
+    pub content_digest: bool,
+    // Convert responses to 304 Not Modified when their
+    // validators match these headers of the request
+    pub conditional_headers: Option<ConditionalHeaders>,
+    // Added to the responses of the isolate, including the error pages sent on timeouts
+    // or errors. The error pages sent before invoking the isolate (e.g rate limits) don't have them
+    pub response_headers: Vec<ResponseHeader>,
+    // Range of the request, sent from streamed responses opting in
+    pub range: Option<ByteRange>,
+    // Replaces the 502 error page of the timeouts
+    pub timeout_response: Option<TimeoutResponse>,
+    // Text bodies aren't checked when unset
+    pub utf8_policy: Option<Utf8Policy>,
+    // Strips the headers set by the function that aren't allowed
+    pub header_filter: Option<HeaderFilter>,
+    // The bytes sent are counted after the transform
+    pub chunk_transform: Option<ChunkTransform>,
+    pub empty_response_policy: EmptyResponsePolicy,
+}
+
+impl ResponseOptions {
+    pub fn stream_buffering(mut self, max_bytes: usize, max_duration: Duration) -> Self {
+        self.stream_buffering = Some(StreamBuffering {
+            max_bytes,
+            max_duration,
+        });
+        self
+    }
+
+    pub fn max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = Some(max_chunk_size);
+        self
+    }
+
+    pub fn max_stream_chunks(mut self, max_stream_chunks: usize) -> Self {
+        self.max_stream_chunks = Some(max_stream_chunks);
+        self
+    }
+
+    pub fn content_digest(mut self, content_digest: bool) -> Self {
+        self.content_digest = content_digest;
+        self
+    }
+
+    pub fn conditional_headers(mut self, conditional_headers: ConditionalHeaders) -> Self {
+        self.conditional_headers = Some(conditional_headers);
+        self
+    }
+
+    pub fn response_headers(mut self, response_headers: Vec<ResponseHeader>) -> Self {
+        self.response_headers = response_headers;
+        self
+    }
+
+    pub fn range(mut self, range: ByteRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn timeout_response(mut self, timeout_response: TimeoutResponse) -> Self {
+        self.timeout_response = Some(timeout_response);
+        self
+    }
+
+    pub fn utf8_policy(mut self, utf8_policy: Utf8Policy) -> Self {
+        self.utf8_policy = Some(utf8_policy);
+        self
+    }
+
+    pub fn header_filter(mut self, header_filter: HeaderFilter) -> Self {
+        self.header_filter = Some(header_filter);
+        self
+    }
+
+    pub fn chunk_transform(mut self, chunk_transform: ChunkTransform) -> Self {
+        self.chunk_transform = Some(chunk_transform);
+        self
+    }
+
+    pub fn empty_response_policy(mut self, empty_response_policy: EmptyResponsePolicy) -> Self {
+        self.empty_response_policy = empty_response_policy;
+        self
+    }
+
+    async fn apply_range(&self, response: HyperResponse<Body>) -> Result<HyperResponse<Body>> {
+        match &self.range {
+            Some(range) => range_response(range, response).await,
+            None => Ok(response),
+        }
+    }
+
+    fn is_not_modified(&self, response: &Response) -> bool {
+        self.conditional_headers
+            .as_ref()
+            .map_or(false, |conditional_headers| {
+                conditional_headers.is_not_modified(response)
+            })
+    }
+}
+
+// Try to receive the whole stream, or return the results received
+// so far when it's too large or too long to complete
+async fn buffer_stream(
+    rx: &Receiver<RunResult>,
+    stream_result: StreamResult,
+    buffering: StreamBuffering,
+) -> Result<(Response, Duration), Vec<RunResult>> {
+    let deadline = Instant::now() + buffering.max_duration;
+    let mut results = vec![RunResult::Stream(stream_result)];
+    let mut response = None;
+    let mut body = Vec::new();
+
+    loop {
+        match results.last() {
+            Some(RunResult::Stream(StreamResult::Start(start))) => {
+                response = Some(start.clone());
+            }
+            Some(RunResult::Stream(StreamResult::Data(bytes))) => {
+                body.extend_from_slice(bytes);
+
+                if body.len() > buffering.max_bytes {
+                    return Err(results);
+                }
+            }
+            Some(RunResult::Stream(StreamResult::Done(elapsed))) => {
+                return match response {
+                    Some(mut response) => {
+                        response.body = Bytes::from(body);
+                        Ok((response, *elapsed))
+                    }
+                    None => Err(results),
+                };
+            }
+            _ => return Err(results),
+        }
+
+        match tokio::time::timeout_at(deadline, rx.recv_async()).await {
+            Ok(Ok(result)) => results.push(result),
+            _ => return Err(results),
+        }
+    }
+}
+
+type OnEventReturnType = Pin<Box<(dyn Future<Output = Result<()>> + Send + Sync)>>;
+type OnEvent<D> = Box<dyn Fn(ResponseEvent, D) -> OnEventReturnType + Send + Sync>;
+
+// Functions can return any status, but hyper only accepts the ones in this range
+fn validate_status(response: &Response) -> Result<(), RunResult> {
+    match response.status {
+        100..=599 => Ok(()),
+        status => Err(RunResult::Error(format!(
+            "Invalid response status code {status}, expected a status between 100 and 599"
+        ))),
+    }
+}
+
+// CR, LF and NUL could be used to split the response or inject headers,
+// so they are rejected rather than relying on hyper to catch them
+fn validate_headers(response: &Response) -> Result<(), RunResult> {
+    let is_unsafe = |value: &str| value.contains(['\r', '\n', '\0']);
+
+    if let Some(headers) = &response.headers {
+        for (name, values) in headers {
+            if is_unsafe(name) || values.iter().any(|value| is_unsafe(value)) {
+                return Err(RunResult::Error(format!(
+                    "Invalid response header {name:?}, names and values can't contain CR, LF or NUL characters"
+                )));
+            }
+        }
+    }
+
+    if response.status_text.as_deref().map_or(false, is_unsafe) {
+        return Err(RunResult::Error(
+            "Invalid response status text, it can't contain CR, LF or NUL characters".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+// Send a 500 response (and report the error) when the status or headers are invalid
+async fn handle_invalid_response<D>(
+    response: &Response,
+    data: D,
+    on_event: &OnEvent<D>,
+) -> Result<Option<HyperResponse<Body>>> {
+    match validate_status(response).and_then(|()| validate_headers(response)) {
+        Ok(()) => Ok(None),
+        Err(result) => {
+            on_event(ResponseEvent::Error(result), data).await?;
+
+            Ok(Some(error_page(500, PAGE_500)?))
+        }
+    }
+}
+
+// `text/*` responses without a charset, or with a UTF-8 charset. The
+// bodies of the other charsets can't be checked as UTF-8
+fn is_text(response: &Response) -> bool {
+    let content_type = match response.get_header("content-type") {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+
+    let mut parts = content_type.split(';');
+    let media_type = parts.next().unwrap_or("").trim();
+
+    let is_text = media_type
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| kind.eq_ignore_ascii_case("text") && !subtype.is_empty());
+
+    if !is_text {
+        return false;
+    }
+
+    let charset = parts.find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    });
+
+    match charset {
+        Some(charset) => {
+            charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("utf8")
+        }
+        None => true,
+    }
+}
+
+// Binary responses are skipped without looking at their body,
+// and valid text bodies are sent without being copied
+async fn handle_invalid_utf8<D>(
+    response: &mut Response,
+    utf8_policy: Option<Utf8Policy>,
+    data: D,
+    on_event: &OnEvent<D>,
+) -> Result<Option<HyperResponse<Body>>> {
+    let utf8_policy = match utf8_policy {
+        Some(utf8_policy) if is_text(response) => utf8_policy,
+        _ => return Ok(None),
+    };
+
+    if std::str::from_utf8(&response.body).is_ok() {
+        return Ok(None);
+    }
+
+    match utf8_policy {
+        Utf8Policy::Replace => {
+            response.body = Bytes::from(String::from_utf8_lossy(&response.body).into_owned());
+
+            Ok(None)
+        }
+        Utf8Policy::Reject => {
+            on_event(
+                ResponseEvent::Error(RunResult::Error(
+                    "Invalid UTF-8 in the body of a text response".into(),
+                )),
+                data,
+            )
+            .await?;
+
+            Ok(Some(error_page(500, PAGE_500)?))
+        }
+    }
+}
+
+// Only the headers set by the function are filtered, the
+// ones added afterwards (e.g Content-Length) are kept
+async fn filter_headers<D>(
+    response: &mut Response,
+    header_filter: Option<&HeaderFilter>,
+    data: D,
+    on_event: &OnEvent<D>,
+) -> Result<()> {
+    let (header_filter, headers) = match (header_filter, &mut response.headers) {
+        (Some(header_filter), Some(headers)) => (header_filter, headers),
+        _ => return Ok(()),
+    };
+
+    let mut stripped = headers
+        .keys()
+        .filter(|name| !header_filter.allows(name))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if stripped.is_empty() {
+        return Ok(());
+    }
+
+    for name in &stripped {
+        headers.remove(name);
+    }
+
+    if header_filter.log_violations {
+        stripped.sort();
+        on_event(ResponseEvent::HeadersStripped(stripped), data).await?;
+    }
+
+    Ok(())
+}
+
+// 204 and 304 responses can't have a body (RFC 9110), so the
+// body and Content-Length set by the function are dropped
+fn is_bodyless(response: &Response) -> bool {
+    matches!(response.status, 204 | 304)
+}
+
+fn drop_forbidden_body(response: &mut Response) {
+    if !is_bodyless(response) {
+        return;
+    }
+
+    response.body = Bytes::new();
+
+    if let Some(headers) = &mut response.headers {
+        headers.retain(|name, _| !name.eq_ignore_ascii_case("content-length"));
+    }
+}
+
+// Forward results produced host-side, e.g using `Response::from_parts`,
+// so they can be handled the same as the ones sent by isolates
+pub fn forward_results<S>(results: S) -> Receiver<RunResult>
+where
+    S: Stream<Item = RunResult> + Send + 'static,
+{
+    let (tx, rx) = flume::unbounded();
+
+    tokio::spawn(async move {
+        let mut results = Box::pin(results);
+
+        while let Some(result) = results.next().await {
+            if tx.send_async(result).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+pub async fn handle_response<D>(
+    rx: Receiver<RunResult>,
+    data: D,
+    on_event: OnEvent<D>,
+) -> Result<HyperResponse<Body>>
+where
+    D: Send + Sync + Clone + 'static,
+{
+    handle_response_with_options(rx, data, on_event, ResponseOptions::default()).await
+}
+
+// Overridden headers first remove all the values set by the function, so
+// a header can be overridden with multiple values. Invalid headers are ignored
+pub fn insert_response_headers(headers: &mut HeaderMap, response_headers: &[ResponseHeader]) {
+    let response_headers = response_headers
+        .iter()
+        .filter_map(|response_header| {
+            match (
+                HeaderName::from_str(&response_header.name),
+                HeaderValue::from_str(&response_header.value),
+            ) {
+                (Ok(name), Ok(value)) => Some((name, value, response_header.policy)),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    for (name, _, policy) in &response_headers {
+        if *policy == HeaderPolicy::Override {
+            headers.remove(name);
+        }
+    }
+
+    for (name, value, _) in response_headers {
+        headers.append(name, value);
+    }
+}
+
+pub async fn handle_response_with_options<D>(
+    rx: Receiver<RunResult>,
+    data: D,
+    on_event: OnEvent<D>,
+    options: ResponseOptions,
+) -> Result<HyperResponse<Body>>
+where
+    D: Send + Sync + Clone + 'static,
+{
+    let response_headers = options.response_headers.clone();
+    let mut response = handle_run_results(rx, data, on_event, options).await?;
+
+    insert_response_headers(response.headers_mut(), &response_headers);
+
+    Ok(response)
+}
+
+async fn handle_run_results<D>(
+    rx: Receiver<RunResult>,
+    data: D,
+    on_event: OnEvent<D>,
+    options: ResponseOptions,
+) -> Result<HyperResponse<Body>>
+where
+    D: Send + Sync + Clone + 'static,
+{
+    let result = rx.recv_async().await?;
+
+    // Results already received while trying to buffer the stream
+    let (result, pending) = match (result, options.stream_buffering) {
+        (RunResult::Stream(stream_result), Some(buffering)) => {
+            match buffer_stream(&rx, stream_result, buffering).await {
+                Ok((mut response, elapsed)) => {
+                    match transform_chunk(
+                        options.chunk_transform.as_ref(),
+                        std::mem::take(&mut response.body),
+                    ) {
+                        Ok(body) => response.body = body,
+                        Err(error) => {
+                            on_event(
+                                ResponseEvent::Error(RunResult::Error(error.to_string())),
+                                data,
+                            )
+                            .await?;
+
+                            return error_page(500, PAGE_500);
+                        }
+                    }
+
+                    filter_headers(
+                        &mut response,
+                        options.header_filter.as_ref(),
+                        data.clone(),
+                        &on_event,
+                    )
+                    .await?;
+
+                    if let Some(hyper_response) =
+                        handle_invalid_response(&response, data.clone(), &on_event).await?
+                    {
+                        return Ok(hyper_response);
+                    }
+
+                    if let Some(hyper_response) = handle_invalid_utf8(
+                        &mut response,
+                        options.utf8_policy,
+                        data.clone(),
+                        &on_event,
+                    )
+                    .await?
+                    {
+                        return Ok(hyper_response);
+                    }
+
+                    drop_forbidden_body(&mut response);
+
+                    if options.is_not_modified(&response) {
+                        on_event(
+                            ResponseEvent::Bytes(0, Some(elapsed.as_micros()), 304),
+                            data,
+                        )
+                        .await?;
+
+                        return not_modified(&response);
+                    }
+
+                    on_event(
+                        ResponseEvent::Bytes(
+                            response.len(),
+                            Some(elapsed.as_micros()),
+                            response.status,
+                        ),
+                        data,
+                    )
+                    .await?;
+
+                    if is_bodyless(&response) {
+                        return Ok(Builder::try_from(&response)?.body(Body::empty())?);
+                    }
+
+                    let content_length = response.len();
+                    let mut hyper_response =
+                        Builder::try_from(&response)?.body(response.body.clone().into())?;
+                    hyper_response
+                        .headers_mut()
+                        .insert(CONTENT_LENGTH, content_length.into());
+
+                    if options.content_digest {
+                        insert_content_digest(&mut hyper_response, &response.body)?;
+                    }
+
+                    return options.apply_range(hyper_response).await;
+                }
+                Err(mut results) => (results.remove(0), results),
+            }
+        }
+        (result, _) => (result, Vec::new()),
+    };
+
+    match result {
+        RunResult::Stream(stream_result) => {
+            // Bounded so a slow client stops the isolate from sending more chunks
+            let (stream_tx, stream_rx) =
+                flume::bounded::<Result<Bytes, std::io::Error>>(STREAM_BUFFER_SIZE);
+            let (body, done_tx) = match options.content_digest {
+                true => {
+                    let (done_tx, done_rx) = oneshot::channel();
+                    (digest_body(stream_rx, done_rx), Some(done_tx))
+                }
+                false => (Body::wrap_stream(stream_rx.into_stream()), None),
+            };
+            let mut stream_body = StreamBody::new(stream_tx, options.max_chunk_size, done_tx);
+
+            let (response_tx, response_rx) = flume::bounded(1);
+            let mut total_bytes = 0;
+            let mut chunks = 0;
+            // Known once the stream has started
+            let mut status = 0;
+            // Whether the body is dropped, in which case the
+            // Bytes event is sent before returning the response
+            let (body_dropped_tx, body_dropped_rx) = oneshot::channel();
+            let mut body_dropped_rx = Some(body_dropped_rx);
+            let on_event = Arc::new(on_event);
+            let stream_on_event = Arc::clone(&on_event);
+            let stream_data = data.clone();
+
+            match stream_result {
+                StreamResult::Start(response) => {
+                    status = response.status;
+                    response_tx.send_async(response).await.unwrap_or(());
+                    stream_body.start().await;
+                }
+                StreamResult::Data(bytes) => {
+                    chunks += 1;
+
+                    if let Some(max_stream_chunks) =
+                        options.max_stream_chunks.filter(|max| chunks > *max)
+                    {
+                        on_event(
+                            ResponseEvent::Error(RunResult::Error(max_stream_chunks_error(
+                                max_stream_chunks,
+                            ))),
+                            data,
+                        )
+                        .await?;
+
+                        return error_page(500, PAGE_500);
+                    }
+
+                    let bytes =
+                        match transform_chunk(options.chunk_transform.as_ref(), Bytes::from(bytes))
+                        {
+                            Ok(bytes) => bytes,
+                            Err(error) => {
+                                on_event(
+                                    ResponseEvent::Error(RunResult::Error(error.to_string())),
+                                    data,
+                                )
+                                .await?;
+
+                                return error_page(500, PAGE_500);
+                            }
+                        };
+
+                    total_bytes += bytes.len();
+                    stream_body.send(bytes).await;
+                }
+                StreamResult::Done(_) => {
+                    on_event(ResponseEvent::StreamDoneNoDataError, data.clone()).await?;
+
+                    // Close the stream by sending empty bytes
+                    stream_body.send(Bytes::new()).await;
+                }
+            }
+
+            let chunk_transform = options.chunk_transform.clone();
+            let max_stream_chunks = options.max_stream_chunks;
+
+            tokio::spawn(async move {
+                let mut pending = pending.into_iter();
+
+                while let Some(result) = match pending.next() {
+                    Some(result) => Some(result),
+                    None => rx.recv_async().await.ok(),
+                } {
+                    match result {
+                        RunResult::Stream(StreamResult::Start(response)) => {
+                            status = response.status;
+                            response_tx.send_async(response).await.unwrap_or(());
+                            stream_body.start().await;
+                        }
+                        RunResult::Stream(StreamResult::Data(bytes)) => {
+                            chunks += 1;
+
+                            // Dropping the receiver stops forwarding the chunks of the isolate
+                            if let Some(max_stream_chunks) =
+                                max_stream_chunks.filter(|max| chunks > *max)
+                            {
+                                let error = max_stream_chunks_error(max_stream_chunks);
+
+                                stream_on_event(
+                                    ResponseEvent::Error(RunResult::Error(error.clone())),
+                                    stream_data.clone(),
+                                )
+                                .await
+                                .expect("Failed to send event");
+
+                                stream_body.abort(error).await;
+                                break;
+                            }
+
+                            let bytes =
+                                match transform_chunk(chunk_transform.as_ref(), Bytes::from(bytes))
+                                {
+                                    Ok(bytes) => bytes,
+                                    Err(error) => {
+                                        stream_on_event(
+                                            ResponseEvent::Error(RunResult::Error(
+                                                error.to_string(),
+                                            )),
+                                            stream_data.clone(),
+                                        )
+                                        .await
+                                        .expect("Failed to send event");
+
+                                        stream_body.abort(error.to_string()).await;
+                                        break;
+                                    }
+                                };
+
+                            total_bytes += bytes.len();
+                            stream_body.send(bytes).await;
+                        }
+                        RunResult::Stream(StreamResult::Done(elapsed)) => {
+                            // Only known once the response has been received
+                            let body_dropped = match body_dropped_rx.take() {
+                                Some(body_dropped_rx) if status != 0 => {
+                                    body_dropped_rx.await.unwrap_or(false)
+                                }
+                                _ => false,
+                            };
+
+                            if !body_dropped {
+                                stream_on_event(
+                                    ResponseEvent::Bytes(
+                                        total_bytes,
+                                        Some(elapsed.as_micros()),
+                                        status,
+                                    ),
+                                    stream_data.clone(),
+                                )
+                                .await
+                                .expect("Failed to send event");
+                            }
+
+                            stream_body.end().await;
+                        }
+                        // E.g the upstream of a piped fetch() body failing
+                        RunResult::Error(error) => {
+                            stream_on_event(
+                                ResponseEvent::Error(RunResult::Error(error.clone())),
+                                stream_data.clone(),
+                            )
+                            .await
+                            .expect("Failed to send event");
+
+                            stream_body.abort(error).await;
+                            break;
+                        }
+                        _ => {
+                            stream_on_event(
+                                ResponseEvent::UnexpectedStreamResult(result),
+                                stream_data.clone(),
+                            )
+                            .await
+                            .expect("Failed to send event");
+
+                            // Close the stream by sending empty bytes
+                            stream_body.send(Bytes::new()).await;
+                            break;
+                        }
+                    }
+                }
+            });
+
+            let mut response = response_rx.recv_async().await?;
+
+            // The transformed chunks don't add up to the length of the original body
+            if options.chunk_transform.is_some() {
+                if let Some(headers) = &mut response.headers {
+                    headers.retain(|name, _| !name.eq_ignore_ascii_case("content-length"));
+                }
+            }
+
+            filter_headers(
+                &mut response,
+                options.header_filter.as_ref(),
+                data.clone(),
+                &on_event,
+            )
+            .await?;
+
+            if let Some(hyper_response) =
+                handle_invalid_response(&response, data.clone(), &on_event).await?
+            {
+                return Ok(hyper_response);
+            }
+
+            // The stream keeps running in the background but its body is dropped,
+            // so the response is counted without bytes and CPU time
+            if options.is_not_modified(&response) {
+                on_event(ResponseEvent::Bytes(0, None, 304), data).await?;
+                body_dropped_tx.send(true).unwrap_or(());
+
+                return not_modified(&response);
+            }
+
+            if is_bodyless(&response) {
+                drop_forbidden_body(&mut response);
+
+                on_event(ResponseEvent::Bytes(0, None, response.status), data).await?;
+                body_dropped_tx.send(true).unwrap_or(());
+
+                return Ok(Builder::try_from(&response)?.body(Body::empty())?);
+            }
+
+            body_dropped_tx.send(false).unwrap_or(());
+
+            let mut hyper_response = Builder::try_from(&response)?.body(body)?;
+
+            if options.content_digest {
+                hyper_response
+                    .headers_mut()
+                    .insert(TRAILER, HeaderValue::from_static(CONTENT_DIGEST));
+            }
+
+            options.apply_range(hyper_response).await
+        }
+        RunResult::Response(mut response, elapsed) => {
+            filter_headers(
+                &mut response,
+                options.header_filter.as_ref(),
+                data.clone(),
+                &on_event,
+            )
+            .await?;
+
+            if let Some(hyper_response) =
+                handle_invalid_response(&response, data.clone(), &on_event).await?
+            {
+                return Ok(hyper_response);
+            }
+
+            if let Some(hyper_response) =
+                handle_invalid_utf8(&mut response, options.utf8_policy, data.clone(), &on_event)
+                    .await?
+            {
+                return Ok(hyper_response);
+            }
+
+            if response.status == 200
+                && response.body.is_empty()
+                && options.empty_response_policy == EmptyResponsePolicy::NoContent
+            {
+                response.status = 204;
+            }
+
+            drop_forbidden_body(&mut response);
+
+            if options.is_not_modified(&response) {
+                on_event(
+                    ResponseEvent::Bytes(0, elapsed.map(|duration| duration.as_micros()), 304),
+                    data,
+                )
+                .await?;
+
+                return not_modified(&response);
+            }
+
+            on_event(
+                ResponseEvent::Bytes(
+                    response.len(),
+                    elapsed.map(|duration| duration.as_micros()),
+                    response.status,
+                ),
+                data,
+            )
+            .await?;
+
+            let mut hyper_response =
+                Builder::try_from(&response)?.body(response.body.clone().into())?;
+
+            if options.content_digest && !is_bodyless(&response) {
+                insert_content_digest(&mut hyper_response, &response.body)?;
+            }
+
+            Ok(hyper_response)
+        }
+        RunResult::Timeout | RunResult::MemoryLimit => {
+            let timeout_response = match result {
+                RunResult::Timeout => options.timeout_response.as_ref(),
+                _ => None,
+            };
+
+            on_event(ResponseEvent::LimitsReached(result), data).await?;
+
+            match timeout_response {
+                Some(timeout_response) => timeout_response.to_response(),
+                None => error_page(502, PAGE_502),
+            }
+        }
+        RunResult::Error(_) => {
+            on_event(ResponseEvent::Error(result), data).await?;
+
+            error_page(500, PAGE_500)
+        }
+        RunResult::LoadError(_) => {
+            on_event(ResponseEvent::Error(result), data).await?;
+
+            error_page(503, PAGE_503_LOAD_ERROR)
+        }
+        RunResult::NotFound => error_page(404, PAGE_404),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::stream;
+    use hyper::body::{to_bytes, HttpBody};
+    use lagon_runtime_http::Response;
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+    use crate::{request_context::RequestContext, HeaderFilterMode};
+
+    #[tokio::test]
+    async fn sequential() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response =
+                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                    .await
+                    .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello World")
+            );
+        });
+
+        tx.send_async(RunResult::Response(Response::from("Hello World"), None))
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response =
+                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                    .await
+                    .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello world")
+            );
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_error_aborts_body() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response =
+                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                    .await
+                    .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert!(to_bytes(response.body_mut()).await.is_err());
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Error("Connection reset".into()))
+            .await
+            .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_data_before_response() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response =
+                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                    .await
+                    .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello world")
+            );
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn buffer_small_stream() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response_with_options(
+                rx,
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().stream_buffering(1024, Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "11");
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello world")
+            );
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_larger_than_buffer() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response_with_options(
+                rx,
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().stream_buffering(8, Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert!(response.headers().get(CONTENT_LENGTH).is_none());
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello world")
+            );
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_slower_than_buffer() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response_with_options(
+                rx,
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().stream_buffering(1024, Duration::from_millis(10)),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert!(response.headers().get(CONTENT_LENGTH).is_none());
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from("Hello world")
+            );
+        });
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_status() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+        let (events_tx, events_rx) = flume::unbounded::<String>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response(
+                rx,
+                events_tx,
+                Box::new(|event, events_tx| {
+                    Box::pin(async move {
+                        if let ResponseEvent::Error(RunResult::Error(error)) = event {
+                            events_tx.send(error).unwrap();
+                        }
+
+                        Ok(())
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 500);
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from(PAGE_500)
+            );
+        });
+
+        let mut response = Response::from("Hello World");
+        response.status = 0;
+
+        tx.send_async(RunResult::Response(response, None))
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+
+        assert_eq!(
+            events_rx.recv_async().await.unwrap(),
+            "Invalid response status code 0, expected a status between 100 and 599"
+        );
+    }
+
+    #[tokio::test]
+    async fn injected_header() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+        let (events_tx, events_rx) = flume::unbounded::<String>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response(
+                rx,
+                events_tx,
+                Box::new(|event, events_tx| {
+                    Box::pin(async move {
+                        if let ResponseEvent::Error(RunResult::Error(error)) = event {
+                            events_tx.send(error).unwrap();
+                        }
+
+                        Ok(())
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 500);
+            assert!(response.headers().get("set-cookie").is_none());
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from(PAGE_500)
+            );
+        });
+
+        let mut response = Response::from("Hello World");
+        response.headers.as_mut().unwrap().insert(
+            "x-injected".into(),
+            vec!["value\r\nset-cookie: session=evil".into()],
+        );
+
+        tx.send_async(RunResult::Response(response, None))
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+
+        assert_eq!(
+            events_rx.recv_async().await.unwrap(),
+            "Invalid response header \"x-injected\", names and values can't contain CR, LF or NUL characters"
+        );
+    }
+
+    #[tokio::test]
+    async fn load_error() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+        let (events_tx, events_rx) = flume::unbounded::<String>();
+
+        let handle = tokio::spawn(async move {
+            let mut response = handle_response(
+                rx,
+                events_tx,
+                Box::new(|event, events_tx| {
+                    Box::pin(async move {
+                        if let ResponseEvent::Error(RunResult::LoadError(error)) = event {
+                            events_tx.send(error).unwrap();
+                        }
+
+                        Ok(())
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), 503);
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from(PAGE_503_LOAD_ERROR)
+            );
+        });
+
+        tx.send_async(RunResult::LoadError("Bundle not found".into()))
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+
+        assert_eq!(events_rx.recv_async().await.unwrap(), "Bundle not found");
+    }
+
+    #[tokio::test]
+    async fn invalid_stream_status() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        let handle = tokio::spawn(async move {
+            let response =
+                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                    .await
+                    .unwrap();
+
+            assert_eq!(response.status(), 500);
+        });
+
+        let mut response = Response::from("");
+        response.status = 999;
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(response)))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn custom_timeout_response() {
+        let timeout_response = TimeoutResponse {
+            status: 504,
+            content_type: Some("application/json".into()),
+            body: r#"{"error":"timeout"}"#.into(),
+        };
+
+        for (result, status, content_type, body) in [
+            (
+                RunResult::Timeout,
+                504,
+                Some("application/json"),
+                r#"{"error":"timeout"}"#,
+            ),
+            // Only the timeouts are customized
+            (RunResult::MemoryLimit, 502, None, PAGE_502),
+        ] {
+            let (tx, rx) = flume::unbounded::<RunResult>();
+            tx.send_async(result).await.unwrap();
+
+            let mut response = handle_response_with_options(
+                rx,
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().timeout_response(timeout_response.clone()),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response
+                    .headers()
+                    .get("content-type")
+                    .map(|value| value.to_str().unwrap()),
+                content_type
+            );
+            assert_eq!(
+                to_bytes(response.body_mut()).await.unwrap(),
+                Bytes::from(body)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn request_context_events() {
+        let (events_tx, events_rx) = flume::unbounded();
+
+        for (request_id, expected_event, results) in [
+            (
+                "stream",
+                "bytes",
+                vec![
+                    RunResult::Stream(StreamResult::Start(Response::from(""))),
+                    RunResult::Stream(StreamResult::Data(b"Hello".to_vec())),
+                    RunResult::Stream(StreamResult::Done(Duration::from_secs(0))),
+                ],
+            ),
+            (
+                "unexpected",
+                "unexpected",
+                vec![
+                    RunResult::Stream(StreamResult::Start(Response::from(""))),
+                    RunResult::Timeout,
+                ],
+            ),
+            (
+                "error",
+                "error",
+                vec![RunResult::Error("Uncaught Error".into())],
+            ),
+        ] {
+            let (tx, rx) = flume::unbounded::<RunResult>();
+
+            for result in results {
+                tx.send_async(result).await.unwrap();
+            }
+
+            let context =
+                RequestContext::with_data("deployment", "function", request_id, events_tx.clone());
+            let start_time = context.start_time();
+
+            let mut response = handle_response(
+                rx,
+                context,
+                Box::new(|event, context| {
+                    Box::pin(async move {
+                        let event = match event {
+                            ResponseEvent::Bytes(..) => "bytes",
+                            ResponseEvent::UnexpectedStreamResult(_) => "unexpected",
+                            _ => "error",
+                        };
+
+                        context
+                            .send((
+                                context.deployment_id().to_string(),
+                                context.request_id().to_string(),
+                                context.start_time(),
+                                event,
+                            ))
+                            .unwrap();
+
+                        Ok(())
+                    })
+                }),
+            )
+            .await
+            .unwrap();
+            to_bytes(response.body_mut()).await.unwrap();
+
+            assert_eq!(
+                events_rx.recv_async().await.unwrap(),
+                (
+                    "deployment".to_string(),
+                    request_id.to_string(),
+                    start_time,
+                    expected_event
+                )
+            );
+        }
+
+        // Each request only had a single event
+        drop(events_tx);
+        assert!(events_rx.recv_async().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_from_parts() {
+        let (tx, rx) = flume::unbounded::<Bytes>();
+        let headers = HashMap::from([("x-lagon".into(), vec!["test".into()])]);
+
+        let results = Response::from_parts(201, headers, rx.into_stream());
+        let mut response = handle_response(
+            forward_results(results),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+        )
+        .await
+        .unwrap();
+
+        // The response is sent before the body is complete
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.headers().get("x-lagon").unwrap(), "test");
+
+        tx.send_async(Bytes::from("Hello")).await.unwrap();
+        tx.send_async(Bytes::from(" world")).await.unwrap();
+        drop(tx);
+
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello world")
+        );
+    }
+
+    #[tokio::test]
+    async fn from_parts_results() {
+        let results = Response::from_parts(200, HashMap::new(), stream::iter(["Hello", " world"]))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            results,
+            vec![
+                RunResult::Stream(StreamResult::Start(Response {
+                    headers: None,
+                    body: Bytes::from("[object ReadableStream]"),
+                    status: 200,
+                    status_text: None,
+                })),
+                RunResult::Stream(StreamResult::Data(b"Hello".to_vec())),
+                RunResult::Stream(StreamResult::Data(b" world".to_vec())),
+                RunResult::Stream(StreamResult::Done(Duration::from_secs(0))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_max_chunk_size() {
+        const BODY_SIZE: usize = 10 * 1024 * 1024;
+        const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+        let (tx, rx) = flume::unbounded::<RunResult>();
+        let (bytes_tx, bytes_rx) = flume::unbounded::<usize>();
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(vec![b'a'; BODY_SIZE])))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        let mut response = handle_response_with_options(
+            rx,
+            bytes_tx,
+            Box::new(|event, bytes_tx| {
+                Box::pin(async move {
+                    if let ResponseEvent::Bytes(bytes, _, _) = event {
+                        bytes_tx.send_async(bytes).await.unwrap();
+                    }
+
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().max_chunk_size(MAX_CHUNK_SIZE),
+        )
+        .await
+        .unwrap();
+
+        let mut chunks = 0;
+        let mut total_bytes = 0;
+
+        while let Some(chunk) = response.body_mut().data().await {
+            let chunk = chunk.unwrap();
+
+            assert!(chunk.len() <= MAX_CHUNK_SIZE);
+
+            chunks += 1;
+            total_bytes += chunk.len();
+        }
+
+        assert_eq!(chunks, BODY_SIZE / MAX_CHUNK_SIZE);
+        assert_eq!(total_bytes, BODY_SIZE);
+        assert_eq!(bytes_rx.recv_async().await.unwrap(), BODY_SIZE);
+    }
+
+    #[tokio::test]
+    async fn content_digest() {
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([RunResult::Response(
+                Response::from("Hello world"),
+                None,
+            )])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().content_digest(true),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.headers().get(CONTENT_DIGEST).unwrap(),
+            "sha-256=:ZOyIygCyaOW6GjVnihtTFtIS9PNmskdyMlNKiuyjfzw=:"
+        );
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello world")
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_content_digest() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
+
+        drop(tx);
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default()
+                .content_digest(true)
+                .max_chunk_size(2),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.headers().get(TRAILER).unwrap(), CONTENT_DIGEST);
+        assert!(response.headers().get(CONTENT_DIGEST).is_none());
+
+        let mut body = Vec::new();
+
+        while let Some(chunk) = response.body_mut().data().await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+
+        assert_eq!(body, b"Hello world");
+
+        let trailers = response.body_mut().trailers().await.unwrap().unwrap();
+
+        assert_eq!(
+            trailers.get(CONTENT_DIGEST).unwrap(),
+            format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(&body))).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_content_digest_incomplete() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
+            .await
+            .unwrap();
+
+        tx.send_async(RunResult::Error("Uncaught Error".into()))
+            .await
+            .unwrap();
+
+        drop(tx);
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().content_digest(true),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello")
+        );
+        assert!(response.body_mut().trailers().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn etag_not_modified() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([("etag".into(), vec!["\"abc\"".into()])])),
+                ..Response::from("Hello World")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().conditional_headers(ConditionalHeaders {
+                if_none_match: Some("W/\"abc\"".into()),
+                if_modified_since: None,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers().get("etag").unwrap(), "\"abc\"");
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("")
+        );
+    }
+
+    #[tokio::test]
+    async fn last_modified_not_modified() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([(
+                    "last-modified".into(),
+                    vec!["Wed, 21 Oct 2015 07:28:00 GMT".into()],
+                )])),
+                ..Response::from("Hello World")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().conditional_headers(ConditionalHeaders {
+                if_none_match: None,
+                if_modified_since: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 304);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("")
+        );
+    }
+
+    #[tokio::test]
+    async fn modified_since() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([(
+                    "last-modified".into(),
+                    vec!["Thu, 22 Oct 2015 07:28:00 GMT".into()],
+                )])),
+                ..Response::from("Hello World")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().conditional_headers(ConditionalHeaders {
+                if_none_match: None,
+                if_modified_since: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello World")
+        );
+    }
+
+    fn response_header(name: &str, value: &str, policy: HeaderPolicy) -> ResponseHeader {
+        ResponseHeader {
+            name: name.into(),
+            value: value.into(),
+            policy,
+            path: None,
+        }
+    }
+
+    fn get_values(response: &HyperResponse<Body>, name: &str) -> Vec<String> {
+        response
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn response_headers_injected() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(Response::from("Hello World"), None))
+            .await
+            .unwrap();
+
+        let mut response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().response_headers(vec![
+                response_header("x-frame-options", "DENY", HeaderPolicy::Override),
+                response_header("invalid header", "value", HeaderPolicy::Append),
+            ]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(get_values(&response, "x-frame-options"), vec!["DENY"]);
+        assert!(response.headers().get("invalid header").is_none());
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello World")
+        );
+    }
+
+    #[tokio::test]
+    async fn clear_site_data_preserved() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([(
+                    "clear-site-data".into(),
+                    vec!["\"cache\", \"cookies\", \"storage\"".into()],
+                )])),
+                ..Response::from("Logged out")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().response_headers(vec![response_header(
+                "x-frame-options",
+                "DENY",
+                HeaderPolicy::Override,
+            )]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            get_values(&response, "clear-site-data"),
+            vec!["\"cache\", \"cookies\", \"storage\""]
+        );
+        assert_eq!(get_values(&response, "x-frame-options"), vec!["DENY"]);
+    }
+
+    #[tokio::test]
+    async fn response_headers_override() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([(
+                    "cache-control".into(),
+                    vec!["no-cache".into(), "no-store".into()],
+                )])),
+                ..Response::from("Hello World")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().response_headers(vec![
+                response_header("Cache-Control", "public", HeaderPolicy::Override),
+                response_header("cache-control", "max-age=60", HeaderPolicy::Override),
+            ]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            get_values(&response, "cache-control"),
+            vec!["public", "max-age=60"]
+        );
+    }
+
+    #[tokio::test]
+    async fn response_headers_append() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tx.send_async(RunResult::Response(
+            Response {
+                headers: Some(HashMap::from([(
+                    "set-cookie".into(),
+                    vec!["a=1".into(), "b=2".into()],
+                )])),
+                ..Response::from("Hello World")
+            },
+            None,
+        ))
+        .await
+        .unwrap();
+
+        let response = handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().response_headers(vec![
+                response_header("set-cookie", "c=3", HeaderPolicy::Append),
+                response_header("x-powered-by", "lagon", HeaderPolicy::Append),
+            ]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            get_values(&response, "set-cookie"),
+            vec!["a=1", "b=2", "c=3"]
+        );
+        assert_eq!(get_values(&response, "x-powered-by"), vec!["lagon"]);
+    }
 
-pub const FAVICON_URL: &str = "/favicon.ico";
+    async fn stream_with_range(accept_ranges: bool, range: ByteRange) -> HyperResponse<Body> {
+        let (tx, rx) = flume::unbounded::<RunResult>();
 
-pub enum ResponseEvent {
-    Bytes(usize, Option<u128>),
-    StreamDoneNoDataError,
-    UnexpectedStreamResult(RunResult),
-    LimitsReached(RunResult),
-    Error(RunResult),
-}
+        let mut response = Response::from("");
 
-type OnEventReturnType = Pin<Box<(dyn Future<Output = Result<()>> + Send + Sync)>>;
-type OnEvent<D> = Box<dyn Fn(ResponseEvent, D) -> OnEventReturnType + Send + Sync>;
+        if accept_ranges {
+            response.headers = Some(HashMap::from([(
+                "accept-ranges".into(),
+                vec!["bytes".into()],
+            )]));
+        }
 
-pub async fn handle_response<D>(
-    rx: Receiver<RunResult>,
-    data: D,
-    on_event: OnEvent<D>,
-) -> Result<HyperResponse<Body>>
-where
-    D: Send + Sync + Clone + 'static,
-{
-    let result = rx.recv_async().await?;
+        tx.send_async(RunResult::Stream(StreamResult::Start(response)))
+            .await
+            .unwrap();
 
-    match result {
-        RunResult::Stream(stream_result) => {
-            let (stream_tx, stream_rx) = flume::unbounded::<Result<Bytes, std::io::Error>>();
-            let body = Body::wrap_stream(stream_rx.into_stream());
+        for chunk in ["Hello", " wor", "ld!"] {
+            tx.send_async(RunResult::Stream(StreamResult::Data(
+                chunk.as_bytes().to_vec(),
+            )))
+            .await
+            .unwrap();
+        }
 
-            let (response_tx, response_rx) = flume::bounded(1);
-            let mut total_bytes = 0;
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
 
-            match stream_result {
-                StreamResult::Start(response) => {
-                    response_tx.send_async(response).await.unwrap_or(());
-                }
-                StreamResult::Data(bytes) => {
-                    total_bytes += bytes.len();
+        handle_response_with_options(
+            rx,
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().range(range),
+        )
+        .await
+        .unwrap()
+    }
 
-                    let bytes = Bytes::from(bytes);
-                    stream_tx.send_async(Ok(bytes)).await.unwrap_or(());
-                }
-                StreamResult::Done(_) => {
-                    on_event(ResponseEvent::StreamDoneNoDataError, data.clone()).await?;
+    #[tokio::test]
+    async fn stream_range() {
+        let mut response = stream_with_range(true, ByteRange::FromTo(3, Some(7))).await;
 
-                    // Close the stream by sending empty bytes
-                    stream_tx.send_async(Ok(Bytes::new())).await.unwrap_or(());
-                }
-            }
+        assert_eq!(response.status(), 206);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "bytes 3-7/*"
+        );
+        assert_eq!(response.headers().get("content-length").unwrap(), "5");
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("lo wo")
+        );
+    }
 
-            tokio::spawn(async move {
-                while let Ok(result) = rx.recv_async().await {
-                    match result {
-                        RunResult::Stream(StreamResult::Start(response)) => {
-                            response_tx.send_async(response).await.unwrap_or(());
-                        }
-                        RunResult::Stream(StreamResult::Data(bytes)) => {
-                            total_bytes += bytes.len();
+    #[tokio::test]
+    async fn stream_range_shorter_body() {
+        let mut response = stream_with_range(true, ByteRange::FromTo(8, Some(20))).await;
 
-                            let bytes = Bytes::from(bytes);
-                            stream_tx.send_async(Ok(bytes)).await.unwrap_or(());
-                        }
-                        RunResult::Stream(StreamResult::Done(elapsed)) => {
-                            on_event(
-                                ResponseEvent::Bytes(total_bytes, Some(elapsed.as_micros())),
-                                data.clone(),
-                            )
-                            .await
-                            .expect("Failed to send event");
+        assert_eq!(response.status(), 206);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "bytes 8-11/12"
+        );
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("rld!")
+        );
 
-                            // Close the stream by sending empty bytes
-                            stream_tx.send_async(Ok(Bytes::new())).await.unwrap_or(());
-                        }
-                        _ => {
-                            on_event(ResponseEvent::UnexpectedStreamResult(result), data.clone())
-                                .await
-                                .expect("Failed to send event");
+        let response = stream_with_range(true, ByteRange::FromTo(20, Some(30))).await;
 
-                            // Close the stream by sending empty bytes
-                            stream_tx.send_async(Ok(Bytes::new())).await.unwrap_or(());
-                            break;
-                        }
+        assert_eq!(response.status(), 416);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            "bytes */12"
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_range_not_accepted() {
+        let mut response = stream_with_range(false, ByteRange::FromTo(3, Some(7))).await;
+
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get("content-range").is_none());
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello world!")
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_range_unknown_length() {
+        // The last bytes can't be found without a Content-Length
+        let mut response = stream_with_range(true, ByteRange::Suffix(3)).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello world!")
+        );
+    }
+
+    fn invalid_utf8_response(content_type: &str) -> RunResult {
+        let mut response = Response::from("");
+        response.headers = Some(HashMap::from([(
+            "content-type".into(),
+            vec![content_type.into()],
+        )]));
+        response.body = Bytes::from_static(b"Hello \xff world");
+
+        RunResult::Response(response, None)
+    }
+
+    #[tokio::test]
+    async fn utf8_replace() {
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([invalid_utf8_response("text/plain")])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().utf8_policy(Utf8Policy::Replace),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello \u{FFFD} world")
+        );
+    }
+
+    #[tokio::test]
+    async fn utf8_reject() {
+        let (events_tx, events_rx) = flume::unbounded::<String>();
+
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([invalid_utf8_response("Text/HTML")])),
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    if let ResponseEvent::Error(RunResult::Error(error)) = event {
+                        events_tx.send(error).unwrap();
                     }
-                }
-            });
 
-            let response = response_rx.recv_async().await?;
-            let hyper_response = Builder::try_from(&response)?.body(body)?;
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().utf8_policy(Utf8Policy::Reject),
+        )
+        .await
+        .unwrap();
 
-            Ok(hyper_response)
-        }
-        RunResult::Response(response, elapsed) => {
-            on_event(
-                ResponseEvent::Bytes(response.len(), elapsed.map(|duration| duration.as_micros())),
-                data,
+        assert_eq!(response.status(), 500);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from(PAGE_500)
+        );
+        assert_eq!(
+            events_rx.recv_async().await.unwrap(),
+            "Invalid UTF-8 in the body of a text response"
+        );
+    }
+
+    #[tokio::test]
+    async fn utf8_charset() {
+        for (content_type, skipped) in [
+            ("text/plain; charset=UTF-8", false),
+            ("text/plain;charset=\"utf-8\"", false),
+            ("text/plain; charset=iso-8859-1", true),
+            ("textual/plain", true),
+            ("text/", true),
+        ] {
+            let response = handle_response_with_options(
+                forward_results(stream::iter([invalid_utf8_response(content_type)])),
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().utf8_policy(Utf8Policy::Reject),
             )
-            .await?;
+            .await
+            .unwrap();
 
-            Ok(Builder::try_from(&response)?.body(response.body.into())?)
+            assert_eq!(response.status(), if skipped { 200 } else { 500 });
         }
-        RunResult::Timeout | RunResult::MemoryLimit => {
-            on_event(ResponseEvent::LimitsReached(result), data).await?;
+    }
 
-            Ok(HyperResponse::builder().status(502).body(PAGE_502.into())?)
-        }
-        RunResult::Error(_) => {
-            on_event(ResponseEvent::Error(result), data).await?;
+    #[tokio::test]
+    async fn utf8_binary_skipped() {
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([invalid_utf8_response(
+                "application/octet-stream",
+            )])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().utf8_policy(Utf8Policy::Reject),
+        )
+        .await
+        .unwrap();
 
-            Ok(HyperResponse::builder().status(500).body(PAGE_500.into())?)
-        }
-        RunResult::NotFound => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from_static(b"Hello \xff world")
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    fn header_filter_response() -> RunResult {
+        let mut response = Response::from("Hello World");
+        response.headers = Some(HashMap::from([
+            ("x-custom".into(), vec!["custom".into()]),
+            ("Set-Cookie".into(), vec!["a=b".into()]),
+        ]));
 
-    use hyper::body::to_bytes;
-    use lagon_runtime_http::Response;
+        RunResult::Response(response, None)
+    }
 
-    use super::*;
+    #[tokio::test]
+    async fn header_filter_deny() {
+        let (events_tx, events_rx) = flume::unbounded::<Vec<String>>();
+
+        let response = handle_response_with_options(
+            forward_results(stream::iter([header_filter_response()])),
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    if let ResponseEvent::HeadersStripped(names) = event {
+                        events_tx.send(names).unwrap();
+                    }
+
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().header_filter(HeaderFilter {
+                mode: HeaderFilterMode::Deny,
+                names: HashSet::from(["set-cookie".into()]),
+                log_violations: true,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get("x-custom").unwrap(), "custom");
+        assert!(response.headers().get("set-cookie").is_none());
+        assert_eq!(
+            events_rx.recv_async().await.unwrap(),
+            vec!["Set-Cookie".to_string()]
+        );
+    }
 
     #[tokio::test]
-    async fn sequential() {
+    async fn header_filter_allow() {
+        let (events_tx, events_rx) = flume::unbounded::<Vec<String>>();
+
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([header_filter_response()])),
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    if let ResponseEvent::HeadersStripped(names) = event {
+                        events_tx.send(names).unwrap();
+                    }
+
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default()
+                .header_filter(HeaderFilter {
+                    mode: HeaderFilterMode::Allow,
+                    names: HashSet::from(["x-custom".into()]),
+                    log_violations: false,
+                })
+                .response_headers(vec![ResponseHeader {
+                    name: "x-platform".into(),
+                    value: "lagon".into(),
+                    policy: HeaderPolicy::Append,
+                    path: None,
+                }]),
+        )
+        .await
+        .unwrap();
+
+        // The headers of the deployment and Content-Length aren't filtered
+        assert_eq!(response.headers().get("x-custom").unwrap(), "custom");
+        assert_eq!(response.headers().get("x-platform").unwrap(), "lagon");
+        assert!(response.headers().get("set-cookie").is_none());
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello World")
+        );
+        assert!(events_rx.is_empty());
+    }
+
+    async fn stream_with_chunk_transform(
+        chunk_transform: ChunkTransform,
+    ) -> (HyperResponse<Body>, Receiver<ResponseEvent>) {
         let (tx, rx) = flume::unbounded::<RunResult>();
+        let (events_tx, events_rx) = flume::unbounded::<ResponseEvent>();
 
-        let handle = tokio::spawn(async move {
-            let mut response =
-                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
-                    .await
-                    .unwrap();
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response {
+            headers: Some(HashMap::from([(
+                "content-length".into(),
+                vec!["11".into()],
+            )])),
+            ..Response::from("")
+        })))
+        .await
+        .unwrap();
 
-            assert_eq!(response.status(), 200);
-            assert_eq!(
-                to_bytes(response.body_mut()).await.unwrap(),
-                Bytes::from("Hello World")
-            );
-        });
+        for chunk in ["Hello", " world"] {
+            tx.send_async(RunResult::Stream(StreamResult::Data(chunk.into())))
+                .await
+                .unwrap();
+        }
 
-        tx.send_async(RunResult::Response(Response::from("Hello World"), None))
-            .await
-            .unwrap();
+        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
+            0,
+        ))))
+        .await
+        .unwrap();
 
-        handle.await.unwrap();
+        let response = handle_response_with_options(
+            rx,
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    events_tx.send_async(event).await.unwrap();
+
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().chunk_transform(chunk_transform),
+        )
+        .await
+        .unwrap();
+
+        (response, events_rx)
     }
 
     #[tokio::test]
-    async fn stream() {
-        let (tx, rx) = flume::unbounded::<RunResult>();
+    async fn stream_chunk_transform() {
+        let (mut response, events_rx) = stream_with_chunk_transform(ChunkTransform::new(|bytes| {
+            Ok(Bytes::from(bytes.to_ascii_uppercase()))
+        }))
+        .await;
 
-        let handle = tokio::spawn(async move {
-            let mut response =
-                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
-                    .await
-                    .unwrap();
+        assert!(response.headers().get(CONTENT_LENGTH).is_none());
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("HELLO WORLD")
+        );
+        assert!(matches!(
+            events_rx.recv_async().await.unwrap(),
+            ResponseEvent::Bytes(11, _, 200)
+        ));
+    }
 
-            assert_eq!(response.status(), 200);
-            assert_eq!(
-                to_bytes(response.body_mut()).await.unwrap(),
-                Bytes::from("Hello world")
-            );
-        });
+    #[tokio::test]
+    async fn stream_chunk_transform_error() {
+        let mut chunks = 0;
+        let (mut response, events_rx) =
+            stream_with_chunk_transform(ChunkTransform::new(move |bytes| {
+                chunks += 1;
 
-        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
-            .await
-            .unwrap();
+                match chunks {
+                    1 => Ok(bytes),
+                    _ => Err(anyhow::anyhow!("Failed to transform the chunk")),
+                }
+            }))
+            .await;
 
-        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
-            .await
-            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(to_bytes(response.body_mut()).await.is_err());
+        assert!(matches!(
+            events_rx.recv_async().await.unwrap(),
+            ResponseEvent::Error(RunResult::Error(error)) if error == "Failed to transform the chunk"
+        ));
+    }
 
-        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+    #[tokio::test]
+    async fn stream_max_chunks() {
+        let (tx, rx) = flume::unbounded::<RunResult>();
+        let (events_tx, events_rx) = flume::unbounded::<ResponseEvent>();
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
             .await
             .unwrap();
 
+        for _ in 0..5 {
+            tx.send_async(RunResult::Stream(StreamResult::Data(b"a".to_vec())))
+                .await
+                .unwrap();
+        }
+
         tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
             0,
         ))))
         .await
         .unwrap();
 
-        drop(tx);
+        let mut response = handle_response_with_options(
+            rx,
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    events_tx.send_async(event).await.unwrap();
 
-        handle.await.unwrap();
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().max_stream_chunks(3),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(to_bytes(response.body_mut()).await.is_err());
+        assert!(matches!(
+            events_rx.recv_async().await.unwrap(),
+            ResponseEvent::Error(RunResult::Error(error)) if error == "Response stream exceeded the limit of 3 chunks"
+        ));
+        // The stream was terminated before completing
+        assert!(events_rx.try_recv().is_err());
     }
 
     #[tokio::test]
-    async fn stream_data_before_response() {
-        let (tx, rx) = flume::unbounded::<RunResult>();
+    async fn stream_max_chunks_not_exceeded() {
+        let mut response = handle_response_with_options(
+            forward_results(stream::iter([
+                RunResult::Stream(StreamResult::Start(Response::from(""))),
+                RunResult::Stream(StreamResult::Data(b"Hello".to_vec())),
+                RunResult::Stream(StreamResult::Data(b" world".to_vec())),
+                RunResult::Stream(StreamResult::Done(Duration::from_secs(0))),
+            ])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            // Split chunks aren't counted
+            ResponseOptions::default()
+                .max_chunk_size(1)
+                .max_stream_chunks(2),
+        )
+        .await
+        .unwrap();
 
-        let handle = tokio::spawn(async move {
-            let mut response =
-                handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
-                    .await
-                    .unwrap();
+        assert_eq!(
+            to_bytes(response.body_mut()).await.unwrap(),
+            Bytes::from("Hello world")
+        );
+    }
 
-            assert_eq!(response.status(), 200);
-            assert_eq!(
-                to_bytes(response.body_mut()).await.unwrap(),
-                Bytes::from("Hello world")
-            );
-        });
+    fn response_with_status(status: u16) -> RunResult {
+        let mut response = Response::from("Hello World");
+        response.status = status;
+        response.headers = Some(HashMap::from([(
+            "Content-Length".into(),
+            vec!["11".into()],
+        )]));
 
-        tx.send_async(RunResult::Stream(StreamResult::Data(b"Hello".to_vec())))
-            .await
-            .unwrap();
+        RunResult::Response(response, None)
+    }
 
-        tx.send_async(RunResult::Stream(StreamResult::Start(Response::from(""))))
+    #[tokio::test]
+    async fn no_content_without_body() {
+        for status in [204, 304] {
+            let mut response = handle_response_with_options(
+                forward_results(stream::iter([response_with_status(status)])),
+                (),
+                Box::new(|_, _| Box::pin(async move { Ok(()) })),
+                ResponseOptions::default().content_digest(true),
+            )
             .await
             .unwrap();
 
-        tx.send_async(RunResult::Stream(StreamResult::Data(b" world".to_vec())))
+            assert_eq!(response.status(), status);
+            assert!(response.headers().get(CONTENT_LENGTH).is_none());
+            assert!(response.headers().get(CONTENT_DIGEST).is_none());
+            assert!(to_bytes(response.body_mut()).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn no_content_stream_without_body() {
+        for status in [204, 304] {
+            let mut start = Response::from("");
+            start.status = status;
+            let (events_tx, events_rx) = flume::unbounded::<ResponseEvent>();
+
+            let mut response = handle_response_with_options(
+                forward_results(stream::iter([
+                    RunResult::Stream(StreamResult::Start(start)),
+                    RunResult::Stream(StreamResult::Data(b"Hello World".to_vec())),
+                    RunResult::Stream(StreamResult::Done(Duration::from_secs(0))),
+                ])),
+                events_tx,
+                Box::new(|event, events_tx| {
+                    Box::pin(async move {
+                        events_tx.send_async(event).await.unwrap();
+                        Ok(())
+                    })
+                }),
+                ResponseOptions::default(),
+            )
             .await
             .unwrap();
 
-        tx.send_async(RunResult::Stream(StreamResult::Done(Duration::from_secs(
-            0,
-        ))))
+            assert_eq!(response.status(), status);
+            assert!(to_bytes(response.body_mut()).await.unwrap().is_empty());
+
+            // Sent once, without the dropped bytes
+            let events = events_rx.into_stream().collect::<Vec<_>>().await;
+            assert_eq!(events.len(), 1);
+            assert!(matches!(events[0], ResponseEvent::Bytes(0, _, s) if s == status));
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_not_modified() {
+        let (events_tx, events_rx) = flume::unbounded::<ResponseEvent>();
+
+        let response = handle_response_with_options(
+            forward_results(stream::iter([
+                RunResult::Stream(StreamResult::Start(Response {
+                    headers: Some(HashMap::from([("etag".into(), vec!["\"abc\"".into()])])),
+                    ..Response::from("")
+                })),
+                RunResult::Stream(StreamResult::Data(b"Hello World".to_vec())),
+                RunResult::Stream(StreamResult::Done(Duration::from_secs(0))),
+            ])),
+            events_tx,
+            Box::new(|event, events_tx| {
+                Box::pin(async move {
+                    events_tx.send_async(event).await.unwrap();
+                    Ok(())
+                })
+            }),
+            ResponseOptions::default().conditional_headers(ConditionalHeaders {
+                if_none_match: Some("\"abc\"".into()),
+                if_modified_since: None,
+            }),
+        )
         .await
         .unwrap();
 
-        drop(tx);
+        assert_eq!(response.status(), 304);
 
-        handle.await.unwrap();
+        let events = events_rx.into_stream().collect::<Vec<_>>().await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ResponseEvent::Bytes(0, _, 304)));
+    }
+
+    #[tokio::test]
+    async fn empty_response_policy() {
+        let empty_response = || RunResult::Response(Response::from(""), None);
+
+        let response = handle_response_with_options(
+            forward_results(stream::iter([empty_response()])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 200);
+
+        let response = handle_response_with_options(
+            forward_results(stream::iter([empty_response()])),
+            (),
+            Box::new(|_, _| Box::pin(async move { Ok(()) })),
+            ResponseOptions::default().empty_response_policy(EmptyResponsePolicy::NoContent),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), 204);
     }
 }
@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Result};
+use flate2::{write::DeflateEncoder, Compression};
+use flume::Sender;
+use futures::{Stream, StreamExt};
+use lagon_runtime_http::{
+    encode_zip_central_directory, encode_zip_data_descriptor, encode_zip_local_header, to_zip_size,
+    Response, RunResult, StreamResult, ZipEntryRecord,
+};
+use std::{collections::HashMap, io::Write, time::Instant};
+
+pub use lagon_runtime_http::{ZipCompression, ZIP_CONTENT_TYPE};
+
+pub struct ZipEntry<S> {
+    pub name: String,
+    pub compression: ZipCompression,
+    pub body: S,
+}
+
+// Streams a ZIP archive as stream results, one local header, data and data
+// descriptor per entry followed by the central directory. Only the
+// compressor state is kept in memory. ZIP64 isn't supported, so archives
+// and entries are limited to 4 GiB and 65535 entries
+pub struct ZipStream {
+    tx: Sender<RunResult>,
+    started_at: Instant,
+    offset: u64,
+    entries: Vec<ZipEntryRecord>,
+}
+
+impl ZipStream {
+    pub async fn start(
+        tx: Sender<RunResult>,
+        mut headers: HashMap<String, Vec<String>>,
+    ) -> Result<Self> {
+        if !headers
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case("content-type"))
+        {
+            headers.insert("content-type".into(), vec![ZIP_CONTENT_TYPE.into()]);
+        }
+
+        tx.send_async(RunResult::Stream(StreamResult::Start(Response {
+            headers: Some(headers),
+            body: Default::default(),
+            status: 200,
+            status_text: None,
+        })))
+        .await?;
+
+        Ok(Self {
+            tx,
+            started_at: Instant::now(),
+            offset: 0,
+            entries: Vec::new(),
+        })
+    }
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.offset += data.len() as u64;
+        self.tx
+            .send_async(RunResult::Stream(StreamResult::Data(data)))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn add_entry<S, B>(
+        &mut self,
+        name: &str,
+        compression: ZipCompression,
+        body: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        if self.entries.len() == u16::MAX as usize {
+            return Err(anyhow!("ZIP archive can't have more than 65535 entries"));
+        }
+
+        let offset = to_zip_size(self.offset, "archive")?;
+        let header = encode_zip_local_header(name, compression)?;
+        self.send(header).await?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut encoder = match compression {
+            ZipCompression::Store => None,
+            ZipCompression::Deflate => {
+                Some(DeflateEncoder::new(Vec::new(), Compression::default()))
+            }
+        };
+        let mut compressed_size = 0u64;
+        let mut uncompressed_size = 0u64;
+
+        let mut body = Box::pin(body);
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.as_ref();
+
+            hasher.update(chunk);
+            uncompressed_size += chunk.len() as u64;
+
+            let data = match &mut encoder {
+                Some(encoder) => {
+                    encoder.write_all(chunk)?;
+                    std::mem::take(encoder.get_mut())
+                }
+                None => chunk.to_vec(),
+            };
+
+            compressed_size += data.len() as u64;
+            self.send(data).await?;
+        }
+
+        if let Some(encoder) = encoder {
+            let data = encoder.finish()?;
+
+            compressed_size += data.len() as u64;
+            self.send(data).await?;
+        }
+
+        let entry = ZipEntryRecord {
+            name: name.into(),
+            compression,
+            crc: hasher.finalize(),
+            compressed_size: to_zip_size(compressed_size, "entry")?,
+            uncompressed_size: to_zip_size(uncompressed_size, "entry")?,
+            offset,
+        };
+
+        self.send(encode_zip_data_descriptor(&entry)).await?;
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<()> {
+        let central_directory = encode_zip_central_directory(&self.entries, self.offset)?;
+        self.send(central_directory).await?;
+
+        self.tx
+            .send_async(RunResult::Stream(StreamResult::Done(
+                self.started_at.elapsed(),
+            )))
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Stream all the entries as a single ZIP archive
+pub async fn stream_zip<S, B>(
+    tx: Sender<RunResult>,
+    headers: HashMap<String, Vec<String>>,
+    entries: Vec<ZipEntry<S>>,
+) -> Result<()>
+where
+    S: Stream<Item = B>,
+    B: AsRef<[u8]>,
+{
+    let mut zip = ZipStream::start(tx, headers).await?;
+
+    for entry in entries {
+        zip.add_entry(&entry.name, entry.compression, entry.body)
+            .await?;
+    }
+
+    zip.finish().await
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use hyper::body::to_bytes;
+    use std::process::Command;
+
+    use crate::response::handle_response;
+
+    use super::*;
+
+    // The archives are checked with the system `unzip`
+    fn has_unzip() -> bool {
+        Command::new("unzip")
+            .arg("-v")
+            .output()
+            .map_or(false, |output| output.status.success())
+    }
+
+    fn unzip(archive: &[u8], args: &[&str]) -> String {
+        let path = std::env::temp_dir().join(format!("lagon-zip-{}.zip", std::process::id()));
+        std::fs::write(&path, archive).unwrap();
+
+        let output = Command::new("unzip")
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[tokio::test]
+    async fn zip_extractable() {
+        if !has_unzip() {
+            eprintln!("Skipping zip_extractable: `unzip` isn't installed");
+            return;
+        }
+
+        let (tx, rx) = flume::unbounded::<RunResult>();
+
+        tokio::spawn(stream_zip(
+            tx,
+            HashMap::new(),
+            vec![
+                ZipEntry {
+                    name: "hello.txt".into(),
+                    compression: ZipCompression::Store,
+                    body: stream::iter(vec![b"Hello ".to_vec(), b"world".to_vec()]),
+                },
+                ZipEntry {
+                    name: "assets/lorem.txt".into(),
+                    compression: ZipCompression::Deflate,
+                    body: stream::iter(vec![b"Lorem ipsum ".repeat(100), b"dolor".to_vec()]),
+                },
+            ],
+        ));
+
+        let mut response =
+            handle_response(rx, (), Box::new(|_, _| Box::pin(async move { Ok(()) })))
+                .await
+                .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            ZIP_CONTENT_TYPE
+        );
+
+        let archive = to_bytes(response.body_mut()).await.unwrap();
+
+        assert!(unzip(&archive, &["-t"]).contains("No errors detected"));
+        assert_eq!(unzip(&archive, &["-p"]), {
+            let mut content = "Hello world".to_owned();
+            content.push_str(&"Lorem ipsum ".repeat(100));
+            content.push_str("dolor");
+            content
+        });
+    }
+
+    #[tokio::test]
+    async fn empty_name() {
+        let (tx, _rx) = flume::unbounded::<RunResult>();
+        let mut zip = ZipStream::start(tx, HashMap::new()).await.unwrap();
+
+        assert!(zip
+            .add_entry("", ZipCompression::Store, stream::iter(vec![b"a"]))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+
+// V8 flags are process-global, and most of them can only be set once before
+// initializing V8. The few that have a per-isolate equivalent are translated
+// to the isolate's settings instead of being passed to V8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum V8FlagScope {
+    Isolate,
+    Global,
+}
+
+// Flags not listed here are rejected, since they could disable the
+// sandboxing (e.g `--allow-natives-syntax`) or the isolates limits
+const ALLOWED_V8_FLAGS: &[(&str, V8FlagScope)] = &[
+    // Initial size of the heap, in MB
+    ("initial-heap-size", V8FlagScope::Isolate),
+    ("stack-trace-limit", V8FlagScope::Isolate),
+    ("max-semi-space-size", V8FlagScope::Global),
+    ("min-semi-space-size", V8FlagScope::Global),
+    ("jitless", V8FlagScope::Global),
+    ("lite-mode", V8FlagScope::Global),
+    ("max-lazy", V8FlagScope::Global),
+    ("no-opt", V8FlagScope::Global),
+    ("single-threaded-gc", V8FlagScope::Global),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct V8Flag {
+    // Using dashes, e.g `max-semi-space-size`
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl V8Flag {
+    pub fn parse(flag: &str, scope: V8FlagScope) -> Result<Self> {
+        let (name, value) = match flag.strip_prefix("--") {
+            Some(flag) => match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (flag, None),
+            },
+            None => return Err(anyhow!("Invalid V8 flag {flag}, expected --name[=value]")),
+        };
+
+        // Values are passed to V8 as a single string split on
+        // whitespaces, which could be used to inject other flags
+        if let Some(value) = value {
+            if value.is_empty()
+                || !value
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '.')
+            {
+                return Err(anyhow!("Invalid value for V8 flag {flag}"));
+            }
+        }
+
+        let name = name.replace('_', "-");
+
+        match ALLOWED_V8_FLAGS
+            .iter()
+            .find(|(allowed_name, _)| *allowed_name == name)
+        {
+            Some((_, allowed_scope)) if *allowed_scope == scope => Ok(Self {
+                name,
+                value: value.map(|value| value.to_string()),
+            }),
+            Some((_, V8FlagScope::Global)) => Err(anyhow!(
+                "V8 flag {flag} is process-global and can't be set per isolate"
+            )),
+            Some((_, V8FlagScope::Isolate)) => {
+                Err(anyhow!("V8 flag {flag} can only be set per isolate"))
+            }
+            None => Err(anyhow!("V8 flag {flag} is not allowed")),
+        }
+    }
+
+    // The value of flags expecting a number, e.g `--stack-trace-limit=20`
+    pub fn number_value(&self) -> Result<usize> {
+        self.value
+            .as_deref()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| anyhow!("V8 flag --{} expects a number", self.name))
+    }
+}
+
+impl std::fmt::Display for V8Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "--{}={}", self.name, value),
+            None => write!(f, "--{}", self.name),
+        }
+    }
+}
+
+pub fn parse_v8_flags(flags: &[String], scope: V8FlagScope) -> Result<Vec<V8Flag>> {
+    flags
+        .iter()
+        .map(|flag| V8Flag::parse(flag.trim(), scope))
+        .collect()
+}
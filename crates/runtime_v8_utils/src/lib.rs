@@ -2,6 +2,10 @@ use std::collections::HashMap;
 
 use anyhow::{anyhow, Result};
 
+mod flags;
+
+pub use flags::*;
+
 pub fn extract_v8_string(
     value: v8::Local<v8::Value>,
     scope: &mut v8::HandleScope,
@@ -2,7 +2,7 @@ use lagon_runtime::{options::RuntimeOptions, Runtime};
 use lagon_runtime_isolate::{options::IsolateOptions, Isolate};
 
 fn main() {
-    let runtime = Runtime::new(RuntimeOptions::default());
+    let runtime = Runtime::new(RuntimeOptions::default()).unwrap();
     let (_, rx) = flume::unbounded();
     let mut isolate = Isolate::new(IsolateOptions::new("".into()).snapshot(true), rx);
 
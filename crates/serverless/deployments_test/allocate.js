@@ -0,0 +1,9 @@
+export async function handler() {
+  // 32MB, stored outside of the V8 heap
+  const blobs = [];
+  for (let i = 0; i < 32; i++) {
+    blobs.push(new Blob([new Uint8Array(1024 * 1024).fill(1)]));
+  }
+  await new Promise(resolve => setTimeout(resolve, 100));
+  return new Response(String(blobs.length));
+}
@@ -0,0 +1,19 @@
+let count = 0;
+
+export function handler(request) {
+  count += 1;
+  const url = new URL(request.url);
+  const headers = {
+    'cache-control': url.searchParams.get('cache-control') ?? 'public, max-age=60',
+  };
+
+  if (url.searchParams.has('vary')) {
+    headers['vary'] = url.searchParams.get('vary');
+  }
+
+  if (url.searchParams.has('etag')) {
+    headers['etag'] = url.searchParams.get('etag');
+  }
+
+  return new Response(count.toString(), { headers });
+}
@@ -0,0 +1,8 @@
+export async function handler(request) {
+  if (new URL(request.url).pathname === '/never') {
+    await new Promise(() => {});
+  }
+
+  await new Promise(resolve => setTimeout(resolve, 200));
+  return new Response('Hello world');
+}
@@ -0,0 +1,12 @@
+// Evaluated once per isolate
+const id = crypto.randomUUID();
+
+export async function handler(request) {
+  const sleep = new URL(request.url).searchParams.get('sleep');
+
+  if (sleep) {
+    await new Promise(resolve => setTimeout(resolve, Number(sleep)));
+  }
+
+  return new Response(id);
+}
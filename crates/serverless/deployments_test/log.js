@@ -0,0 +1,6 @@
+export function handler() {
+  console.log('Hello world');
+  console.error('Something failed');
+
+  return new Response('Hello world');
+}
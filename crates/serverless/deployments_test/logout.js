@@ -0,0 +1,7 @@
+export function handler() {
+  return new Response('Logged out', {
+    headers: {
+      'Clear-Site-Data': '"cookies", "storage"',
+    },
+  });
+}
@@ -0,0 +1,8 @@
+export async function handler(request) {
+  // Give the tests time to check the spilled body
+  await new Promise(resolve => setTimeout(resolve, 200));
+
+  const body = await request.text();
+
+  return new Response(`${body.length} ${body.slice(0, 5)}`);
+}
@@ -0,0 +1,11 @@
+export async function handler(request) {
+  const body = await request.text();
+  console.log(`${request.method} ${request.url}`);
+
+  return new Response(`${request.headers.get('x-custom')}: ${body}`, {
+    status: 201,
+    headers: {
+      'x-replayed': 'true',
+    },
+  });
+}
@@ -0,0 +1,12 @@
+export function handler(request, event) {
+  event.addServerTiming('cache', 1.5, 'Cache "read"');
+  event.addServerTiming('miss');
+
+  try {
+    event.addServerTiming('invalid name', 1);
+  } catch (error) {
+    return new Response(`${error.name}: ${error.message}`);
+  }
+
+  return new Response('Hello world');
+}
@@ -0,0 +1,7 @@
+export function handler() {
+  return new Response('Hello world', {
+    headers: {
+      'server-timing': 'db;dur=5',
+    },
+  });
+}
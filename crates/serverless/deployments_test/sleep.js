@@ -0,0 +1,4 @@
+export async function handler() {
+  await new Promise(resolve => setTimeout(resolve, 500));
+  return new Response('Hello world');
+}
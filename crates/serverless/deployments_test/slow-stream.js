@@ -0,0 +1,12 @@
+export function handler() {
+  const { readable, writable } = new TransformStream();
+  const writer = writable.getWriter();
+  writer.write(new TextEncoder().encode('Hello'));
+
+  setTimeout(() => {
+    writer.write(new TextEncoder().encode(' world'));
+    writer.close();
+  }, 500);
+
+  return new Response(readable);
+}
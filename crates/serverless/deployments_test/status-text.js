@@ -0,0 +1,3 @@
+export function handler() {
+  return new Response("I'm a teapot", { status: 418, statusText: 'Short and stout' });
+}
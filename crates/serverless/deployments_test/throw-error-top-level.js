@@ -0,0 +1,5 @@
+throw new Error('hello');
+
+export function handler() {
+  return new Response('Hello world');
+}
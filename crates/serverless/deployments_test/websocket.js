@@ -0,0 +1,13 @@
+export function handler(request) {
+  if (request.headers.get('upgrade') !== 'websocket') {
+    return new Response('Expected a WebSocket', { status: 426 });
+  }
+
+  const { socket, response } = upgradeWebSocket(request);
+
+  socket.onmessage = event => {
+    socket.send(event.data);
+  };
+
+  return response;
+}
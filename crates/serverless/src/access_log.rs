@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::{
+    collections::{
+        hash_map::{DefaultHasher, RandomState},
+        HashMap,
+    },
+    hash::{BuildHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+static SAMPLING_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogEntry {
+    pub timestamp: SystemTime,
+    pub deployment_id: String,
+    pub request_id: String,
+    pub client_ip: String,
+    pub method: String,
+    // Path and query string
+    pub path: String,
+    // e.g `HTTP/1.1`
+    pub version: String,
+    pub status: u16,
+    pub bytes: usize,
+    // From receiving the request to sending the last byte of the response
+    pub duration: Duration,
+}
+
+// Format the access log line written after each response. Formatters are
+// registered by name, and selected per deployment
+pub trait AccessLogFormatter: Send + Sync {
+    fn format(&self, entry: &AccessLogEntry) -> String;
+}
+
+pub struct JsonFormatter;
+
+impl AccessLogFormatter for JsonFormatter {
+    fn format(&self, entry: &AccessLogEntry) -> String {
+        json!({
+            "timestamp": DateTime::<Utc>::from(entry.timestamp)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            "deployment": entry.deployment_id,
+            "request": entry.request_id,
+            "ip": entry.client_ip,
+            "method": entry.method,
+            "path": entry.path,
+            "version": entry.version,
+            "status": entry.status,
+            "bytes": entry.bytes,
+            "duration_ms": entry.duration.as_micros() as f64 / 1000.0,
+        })
+        .to_string()
+    }
+}
+
+// Common Log Format, which has no field for the request id and duration
+pub struct CommonLogFormatter;
+
+impl AccessLogFormatter for CommonLogFormatter {
+    fn format(&self, entry: &AccessLogEntry) -> String {
+        let bytes = match entry.bytes {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            entry.client_ip,
+            DateTime::<Utc>::from(entry.timestamp).format("%d/%b/%Y:%H:%M:%S %z"),
+            entry.method,
+            entry.path,
+            entry.version,
+            entry.status,
+            bytes
+        )
+    }
+}
+
+pub type AccessLogFormatters = HashMap<String, Arc<dyn AccessLogFormatter>>;
+
+pub fn default_access_log_formatters() -> AccessLogFormatters {
+    let mut formatters: AccessLogFormatters = HashMap::new();
+    formatters.insert("json".into(), Arc::new(JsonFormatter));
+    formatters.insert("clf".into(), Arc::new(CommonLogFormatter));
+
+    formatters
+}
+
+// Parse a sample rate written as a ratio (`0.1`), a percentage (`10%`) or 1-in-N (`1/10`)
+pub fn parse_sample_rate(value: &str) -> Result<f64> {
+    let value = value.trim();
+
+    let rate = if let Some(percentage) = value.strip_suffix('%') {
+        percentage.trim().parse::<f64>()? / 100.0
+    } else if let Some((numerator, denominator)) = value.split_once('/') {
+        numerator.trim().parse::<f64>()? / denominator.trim().parse::<f64>()?
+    } else {
+        value.parse::<f64>()?
+    };
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(anyhow!("Invalid access log sample rate: {}", value));
+    }
+
+    Ok(rate)
+}
+
+// Only a fraction of the successful requests are logged, while failed
+// requests (5xx status) are always logged regardless of the sample rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccessLogSampling {
+    // From 0 (only failed requests) to 1 (all requests)
+    pub rate: f64,
+    // Take the same decision for a given request id, e.g. across
+    // nodes. Requests without an id are sampled randomly
+    pub by_request_id: bool,
+}
+
+impl Default for AccessLogSampling {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            by_request_id: false,
+        }
+    }
+}
+
+impl AccessLogSampling {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    pub fn by_request_id(mut self, by_request_id: bool) -> Self {
+        self.by_request_id = by_request_id;
+        self
+    }
+
+    pub fn should_log(&self, request_id: &str, status: u16) -> bool {
+        if status >= 500 || self.rate >= 1.0 {
+            return true;
+        }
+
+        if self.rate <= 0.0 {
+            return false;
+        }
+
+        let hash = match self.by_request_id && !request_id.is_empty() {
+            true => {
+                let mut hasher = DefaultHasher::new();
+                request_id.hash(&mut hasher);
+                hasher.finish()
+            }
+            false => {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_u64(SAMPLING_SEQUENCE.fetch_add(1, Ordering::Relaxed));
+                hasher.finish()
+            }
+        };
+
+        (hash as f64 / u64::MAX as f64) < self.rate
+    }
+}
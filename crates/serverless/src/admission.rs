@@ -0,0 +1,234 @@
+use hyper::{Body, Request as HyperRequest};
+use metrics::{gauge, increment_counter};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::oneshot;
+
+pub const PRIORITY: &str = "priority";
+// https://www.rfc-editor.org/rfc/rfc9218#section-4.1
+const DEFAULT_URGENCY: u8 = 3;
+const MAX_URGENCY: u8 = 7;
+
+// The urgency goes from 0 (highest priority) to 7 (lowest priority)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+    pub urgency: u8,
+    pub incremental: bool,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+// Parse a `Priority` header value like `u=1, i`, ignoring unknown or invalid parameters
+pub fn parse_priority(value: &str) -> Priority {
+    let mut priority = Priority::default();
+
+    for parameter in value.split(',') {
+        let (key, value) = match parameter.trim().split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (parameter.trim(), None),
+        };
+
+        match (key, value) {
+            ("u", Some(urgency)) => {
+                if let Some(urgency) = urgency
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|urgency| *urgency <= MAX_URGENCY)
+                {
+                    priority.urgency = urgency;
+                }
+            }
+            ("i", None | Some("?1")) => priority.incremental = true,
+            ("i", Some("?0")) => priority.incremental = false,
+            _ => {}
+        }
+    }
+
+    priority
+}
+
+pub fn get_priority(req: &HyperRequest<Body>) -> Priority {
+    req.headers()
+        .get(PRIORITY)
+        .and_then(|priority| priority.to_str().ok())
+        .map_or_else(Priority::default, parse_priority)
+}
+
+struct Waiter {
+    urgency: u8,
+    sequence: u64,
+    sender: oneshot::Sender<()>,
+}
+
+// The most urgent waiter comes first, then the oldest one
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .urgency
+            .cmp(&self.urgency)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.urgency == other.urgency && self.sequence == other.sequence
+    }
+}
+
+impl Eq for Waiter {}
+
+#[derive(Default)]
+struct AdmissionState {
+    running: usize,
+    sequence: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+impl AdmissionState {
+    fn record_metrics(&self) {
+        gauge!("lagon_admission_running", self.running as f64);
+        gauge!("lagon_admission_queued", self.waiting.len() as f64);
+    }
+}
+
+// Limit the number of requests processed concurrently. When saturated,
+// requests wait in a queue ordered by their `Priority` urgency, and are
+// shed once the queue holds `max_queued` requests
+pub struct AdmissionQueue {
+    max_concurrent: usize,
+    max_queued: Option<usize>,
+    state: Mutex<AdmissionState>,
+}
+
+impl AdmissionQueue {
+    pub fn new(max_concurrent: usize, max_queued: Option<usize>) -> Self {
+        Self {
+            max_concurrent,
+            max_queued,
+            state: Mutex::new(AdmissionState::default()),
+        }
+    }
+
+    // Returns `None` when the request has been shed
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Option<AdmissionPermit> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+
+            if state.running < self.max_concurrent {
+                state.running += 1;
+                state.record_metrics();
+
+                return Some(AdmissionPermit {
+                    queue: Arc::clone(self),
+                });
+            }
+
+            if let Some(max_queued) = self.max_queued {
+                // Waiters whose request has been cancelled don't count
+                if state.waiting.len() >= max_queued {
+                    state.waiting = std::mem::take(&mut state.waiting)
+                        .into_iter()
+                        .filter(|waiter| !waiter.sender.is_closed())
+                        .collect();
+                }
+
+                if state.waiting.len() >= max_queued {
+                    state.record_metrics();
+                    increment_counter!("lagon_admission_shed");
+
+                    return None;
+                }
+            }
+
+            let (sender, receiver) = oneshot::channel();
+
+            state.sequence += 1;
+            let sequence = state.sequence;
+
+            state.waiting.push(Waiter {
+                urgency: priority.urgency,
+                sequence,
+                sender,
+            });
+            state.record_metrics();
+
+            receiver
+        };
+
+        let mut pending = PendingAdmission {
+            queue: Arc::clone(self),
+            receiver: Some(receiver),
+        };
+
+        // The slot is handed over by the permit released before
+        if let Some(receiver) = pending.receiver.as_mut() {
+            receiver.await.unwrap_or(());
+        }
+
+        pending.receiver.take();
+
+        Some(AdmissionPermit {
+            queue: Arc::clone(self),
+        })
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        // Waiters whose request has been cancelled are skipped
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.sender.send(()).is_ok() {
+                state.record_metrics();
+                return;
+            }
+        }
+
+        state.running -= 1;
+        state.record_metrics();
+    }
+}
+
+// Release the slot if it has been handed over while the request was cancelled
+struct PendingAdmission {
+    queue: Arc<AdmissionQueue>,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for PendingAdmission {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+
+            if receiver.try_recv().is_ok() {
+                self.queue.release();
+            }
+        }
+    }
+}
+
+pub struct AdmissionPermit {
+    queue: Arc<AdmissionQueue>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
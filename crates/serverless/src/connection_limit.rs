@@ -0,0 +1,240 @@
+use dashmap::DashMap;
+use hyper::server::{
+    accept::Accept,
+    conn::{AddrIncoming, AddrStream},
+};
+use ipnet::IpNet;
+use metrics::{decrement_gauge, increment_counter, increment_gauge};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{
+    forwarded::is_trusted_proxy,
+    idle_connections::{ConnectionActivity, IdleConnections, IdleGuard},
+};
+
+// Caps the concurrent connections of each client IP. Trusted proxies forward
+// the connections of many clients, so they can be exempted from the limit
+pub struct ConnectionLimiter {
+    max_connections: usize,
+    exempted: Vec<IpNet>,
+    connections: DashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize, exempted: Vec<IpNet>) -> Self {
+        Self {
+            max_connections,
+            exempted,
+            connections: DashMap::new(),
+        }
+    }
+
+    // Returns None when the IP already has the maximum number of connections
+    pub fn acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionGuard> {
+        if is_trusted_proxy(&ip, &self.exempted) {
+            return Some(ConnectionGuard { limiter: None });
+        }
+
+        let mut connections = self.connections.entry(ip).or_insert(0);
+
+        if *connections >= self.max_connections {
+            return None;
+        }
+
+        *connections += 1;
+
+        Some(ConnectionGuard {
+            limiter: Some((Arc::clone(self), ip)),
+        })
+    }
+}
+
+// Releases the connection once dropped
+pub struct ConnectionGuard {
+    limiter: Option<(Arc<ConnectionLimiter>, IpAddr)>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some((limiter, ip)) = self.limiter.take() {
+            if let Some(mut connections) = limiter.connections.get_mut(&ip) {
+                *connections -= 1;
+            }
+
+            limiter
+                .connections
+                .remove_if(&ip, |_, connections| *connections == 0);
+        }
+    }
+}
+
+// Refuses the connections over the limit as soon as they are
+// accepted, before reading anything from them
+pub struct LimitedIncoming {
+    incoming: AddrIncoming,
+    limiter: Option<Arc<ConnectionLimiter>>,
+    idle_connections: Option<Arc<IdleConnections>>,
+}
+
+impl LimitedIncoming {
+    pub fn new(incoming: AddrIncoming, limiter: Option<Arc<ConnectionLimiter>>) -> Self {
+        Self {
+            incoming,
+            limiter,
+            idle_connections: None,
+        }
+    }
+
+    // Track the activity of the accepted connections to reap the idle ones
+    pub fn idle_connections(mut self, idle_connections: Arc<IdleConnections>) -> Self {
+        self.idle_connections = Some(idle_connections);
+        self
+    }
+}
+
+impl Accept for LimitedIncoming {
+    type Conn = LimitedStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        loop {
+            let stream = match Pin::new(&mut self.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(stream))) => stream,
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let guard = match &self.limiter {
+                Some(limiter) => match limiter.acquire(stream.remote_addr().ip()) {
+                    Some(guard) => Some(guard),
+                    None => {
+                        increment_counter!("lagon_connections_refused");
+
+                        // Dropping the stream closes the connection
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            increment_gauge!("lagon_connections_open", 1.0);
+
+            return Poll::Ready(Some(Ok(LimitedStream {
+                stream,
+                _guard: guard,
+                idle: self
+                    .idle_connections
+                    .as_ref()
+                    .map(|idle_connections| idle_connections.track()),
+            })));
+        }
+    }
+}
+
+pub struct LimitedStream {
+    stream: AddrStream,
+    _guard: Option<ConnectionGuard>,
+    idle: Option<IdleGuard>,
+}
+
+impl LimitedStream {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.stream.remote_addr()
+    }
+
+    pub fn activity(&self) -> Option<Arc<ConnectionActivity>> {
+        self.idle.as_ref().map(|idle| Arc::clone(idle.activity()))
+    }
+
+    fn touch(&self, written: &Poll<io::Result<usize>>) {
+        if let (Some(idle), Poll::Ready(Ok(written))) = (&self.idle, written) {
+            if *written > 0 {
+                idle.activity().touch();
+            }
+        }
+    }
+}
+
+impl Drop for LimitedStream {
+    fn drop(&mut self) {
+        decrement_gauge!("lagon_connections_open", 1.0);
+    }
+}
+
+impl AsyncRead for LimitedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let activity = match &self.idle {
+            Some(idle) => Arc::clone(idle.activity()),
+            None => return Pin::new(&mut self.stream).poll_read(cx, buf),
+        };
+
+        // Reaped connections are closed as if the client did
+        if !activity.poll_active(cx.waker(), false) {
+            return Poll::Ready(Ok(()));
+        }
+
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.stream).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = result {
+            // The connection may have been reaped while reading, in
+            // which case what has been read is discarded
+            if buf.filled().len() > filled && !activity.poll_active(cx.waker(), true) {
+                buf.set_filled(filled);
+            }
+        }
+
+        result
+    }
+}
+
+impl AsyncWrite for LimitedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.stream).poll_write(cx, buf);
+        self.touch(&result);
+
+        result
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.stream).poll_write_vectored(cx, bufs);
+        self.touch(&result);
+
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
@@ -148,6 +148,15 @@ impl Cronjob {
                                     error,
                                 )
                             }
+                            RunResult::LoadError(error) => {
+                                error!(
+                                    source = CONSOLE_SOURCE,
+                                    deployment = deployment.id,
+                                    function = deployment.function_id;
+                                    "Cron deployment could not be loaded: {}",
+                                    error,
+                                )
+                            }
                             RunResult::NotFound => {}
                         }
                     })
@@ -1,23 +1,24 @@
 use std::{
-    env,
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use super::pubsub::clear_deployment_cache;
-use crate::serverless::Workers;
+use crate::{
+    management::{get_in_flight_requests, DeploymentsStats},
+    serverless::Workers,
+};
 use dashmap::DashMap;
 
 const CACHE_TASK_INTERVAL: Duration = Duration::from_secs(1);
 
-pub fn run_cache_clear_task(last_requests: Arc<DashMap<String, Instant>>, workers: Workers) {
-    let isolates_cache_seconds = Duration::from_secs(
-        env::var("LAGON_ISOLATES_CACHE_SECONDS")
-            .expect("LAGON_ISOLATES_CACHE_SECONDS is not set")
-            .parse()
-            .expect("LAGON_ISOLATES_CACHE_SECONDS is not a valid number"),
-    );
-
+pub fn run_cache_clear_task(
+    last_requests: Arc<DashMap<String, Instant>>,
+    workers: Workers,
+    stats: DeploymentsStats,
+    isolates_idle_ttl: Duration,
+    min_warm_isolates: usize,
+) {
     tokio::spawn(async move {
         let mut deployments_to_clear = Vec::new();
 
@@ -29,7 +30,11 @@ pub fn run_cache_clear_task(last_requests: Arc<DashMap<String, Instant>>, worker
             for last_request in last_requests.iter() {
                 let (deployment_id, last_request) = last_request.pair();
 
-                if now.duration_since(*last_request) > isolates_cache_seconds {
+                // Requests that are still running (e.g streaming a response)
+                // keep their isolate active
+                if now.duration_since(*last_request) > isolates_idle_ttl
+                    && get_in_flight_requests(&stats, deployment_id) == 0
+                {
                     deployments_to_clear.push(deployment_id.clone());
                 }
             }
@@ -39,14 +44,34 @@ pub fn run_cache_clear_task(last_requests: Arc<DashMap<String, Instant>>, worker
             }
 
             for deployment_id in &deployments_to_clear {
-                last_requests.remove(deployment_id);
-
-                clear_deployment_cache(
-                    deployment_id.clone(),
-                    Arc::clone(&workers),
-                    String::from("expiration"),
-                )
-                .await;
+                let pool = workers.get(deployment_id).map(|pool| Arc::clone(&pool));
+                let isolates = pool.as_ref().map_or(0, |pool| pool.len());
+
+                // Keep the minimum amount of warm isolates for this deployment
+                if isolates <= min_warm_isolates {
+                    continue;
+                }
+
+                // The last isolate is evicted with its pool, so the next
+                // requests start a new pool instead of failing
+                if isolates == 1 {
+                    last_requests.remove(deployment_id);
+
+                    clear_deployment_cache(
+                        deployment_id.clone(),
+                        Arc::clone(&workers),
+                        String::from("expiration"),
+                    )
+                    .await;
+
+                    continue;
+                }
+
+                // Surplus isolates are evicted one at a time, the next
+                // ones by the next runs while the deployment stays idle
+                if let Some(pool) = pool {
+                    pool.terminate_idle(String::from("expiration")).await;
+                }
             }
 
             deployments_to_clear.clear();
@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use hyper::{body, client::HttpConnector, Body, Client, StatusCode};
+use hyper_tls::HttpsConnector;
+use lagon_runtime_utils::DEPLOYMENTS_DIR;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// What's needed to create the isolates of a deployment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    pub code: String,
+    // Not all deployments have a sourcemap
+    pub source_map: Option<Vec<u8>>,
+}
+
+// Loads the bundle of a deployment when its first isolate is created.
+// Loaded bundles are cached in `Bundles` until the deployment is
+// deployed again or undeployed
+#[async_trait]
+pub trait DeploymentLoader: Send + Sync {
+    async fn load(&self, deployment_id: &str) -> Result<Bundle>;
+}
+
+// Reads the bundles written to the deployments folder by the downloader
+pub struct FilesystemLoader {
+    dir: PathBuf,
+}
+
+impl FilesystemLoader {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl Default for FilesystemLoader {
+    fn default() -> Self {
+        Self::new(PathBuf::from(DEPLOYMENTS_DIR))
+    }
+}
+
+#[async_trait]
+impl DeploymentLoader for FilesystemLoader {
+    async fn load(&self, deployment_id: &str) -> Result<Bundle> {
+        let code = fs::read_to_string(self.dir.join(deployment_id.to_owned() + ".js"))?;
+        let source_map = fs::read(self.dir.join(deployment_id.to_owned() + ".js.map")).ok();
+
+        Ok(Bundle { code, source_map })
+    }
+}
+
+// Fetches the bundles from `<base_url>/<deployment_id>.js` (and `.js.map`),
+// e.g from a registry or a bucket served over HTTP
+pub struct HttpLoader {
+    base_url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl HttpLoader {
+    pub fn new(base_url: String) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: Client::builder().build::<_, Body>(HttpsConnector::new()),
+        }
+    }
+
+    // Returns None when the file doesn't exist
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let uri = format!("{}/{}", self.base_url, path).parse()?;
+        let response = self.client.get(uri).await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                Ok(Some(body::to_bytes(response.into_body()).await?.to_vec()))
+            }
+            status => Err(anyhow!("Unexpected status {} for {}", status, path)),
+        }
+    }
+}
+
+#[async_trait]
+impl DeploymentLoader for HttpLoader {
+    async fn load(&self, deployment_id: &str) -> Result<Bundle> {
+        let code = self
+            .get(&(deployment_id.to_owned() + ".js"))
+            .await?
+            .ok_or_else(|| anyhow!("Bundle of deployment {} not found", deployment_id))?;
+        let source_map = self
+            .get(&(deployment_id.to_owned() + ".js.map"))
+            .await
+            .ok()
+            .flatten();
+
+        Ok(Bundle {
+            code: String::from_utf8(code)?,
+            source_map,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct BundleCache {
+    bundles: DashMap<String, Arc<Bundle>>,
+    // Bundles that couldn't be loaded or compiled, with the error and when it
+    // happened. Requests are rejected without retrying until the error expires
+    failures: DashMap<String, (String, Instant)>,
+}
+
+impl BundleCache {
+    pub fn get_failure(&self, deployment_id: &str, ttl: Duration) -> Option<String> {
+        let failure = self.failures.get(deployment_id)?;
+        let (error, failed_at) = failure.value();
+
+        match failed_at.elapsed() < ttl {
+            true => Some(error.clone()),
+            false => None,
+        }
+    }
+
+    pub fn set_failure(&self, deployment_id: &str, error: String) {
+        self.failures
+            .insert(deployment_id.to_string(), (error, Instant::now()));
+    }
+
+    // Called when the bundle of a deployment changes
+    pub fn invalidate(&self, deployment_id: &str) {
+        self.bundles.remove(deployment_id);
+        self.failures.remove(deployment_id);
+    }
+}
+
+pub type Bundles = Arc<BundleCache>;
+
+// Failed loads are cached for `failure_ttl`, after which they are
+// retried by the next isolate
+pub async fn load_bundle(
+    loader: &dyn DeploymentLoader,
+    bundles: &Bundles,
+    deployment_id: &str,
+    failure_ttl: Duration,
+) -> Result<Arc<Bundle>> {
+    if let Some(bundle) = bundles.bundles.get(deployment_id) {
+        return Ok(Arc::clone(&bundle));
+    }
+
+    if let Some(error) = bundles.get_failure(deployment_id, failure_ttl) {
+        return Err(anyhow!(error));
+    }
+
+    match loader.load(deployment_id).await {
+        Ok(bundle) => {
+            let bundle = Arc::new(bundle);
+            bundles
+                .bundles
+                .insert(deployment_id.to_string(), Arc::clone(&bundle));
+
+            Ok(bundle)
+        }
+        Err(error) => {
+            bundles.set_failure(deployment_id, error.to_string());
+
+            Err(error)
+        }
+    }
+}
@@ -2,24 +2,65 @@ use crate::REGION;
 use anyhow::{anyhow, Result};
 use dashmap::DashMap;
 use futures::{stream::FuturesUnordered, StreamExt};
-use lagon_runtime_utils::{Deployment, DEPLOYMENTS_DIR};
+use lagon_runtime_isolate::SourceMap;
+use lagon_runtime_utils::{
+    error_page::ErrorSchema, BalancingPolicy, Deployment, Fallback, HeaderFilter, HeaderFilterMode,
+    HeaderPolicy, IsolatePool, RateLimit, ResponseHeader, DEPLOYMENTS_DIR,
+};
 use lagon_serverless_downloader::Downloader;
 use log::{error, info, warn};
-use mysql::{prelude::Queryable, PooledConn};
+use mysql::{
+    prelude::{FromValue, Queryable},
+    PooledConn, Row,
+};
 use std::{
     collections::{HashMap, HashSet},
     fs,
     path::Path,
     sync::Arc,
+    time::Duration,
 };
 
 use self::filesystem::{create_deployments_folder, rm_deployment};
 
 pub mod cache;
 pub mod filesystem;
+pub mod loader;
 pub mod pubsub;
 
 pub type Deployments = Arc<DashMap<String, Arc<Deployment>>>;
+// Whether the sourcemap of each deployment is valid. The raw sourcemaps are
+// cached with the bundles, since a parsed SourceMap can't be shared across threads
+pub type SourceMaps = Arc<DashMap<String, bool>>;
+
+// Parse the sourcemap of a deployment on the thread of the isolate using it.
+// Invalid sourcemaps are only reported and parsed once
+pub fn get_source_map(
+    source_maps: &SourceMaps,
+    deployment_id: &str,
+    source_map: Option<&[u8]>,
+) -> Option<Arc<SourceMap>> {
+    let source_map = source_map?;
+
+    if source_maps
+        .get(deployment_id)
+        .map_or(false, |valid| !*valid)
+    {
+        return None;
+    }
+
+    match SourceMap::from_slice(source_map) {
+        Ok(source_map) => {
+            source_maps.insert(deployment_id.to_string(), true);
+            Some(Arc::new(source_map))
+        }
+        Err(error) => {
+            warn!(deployment = deployment_id; "Failed to parse deployment sourcemap: {}", error);
+            source_maps.insert(deployment_id.to_string(), false);
+            None
+        }
+    }
+}
 
 pub async fn download_deployment<D>(deployment: &Deployment, downloader: Arc<D>) -> Result<()>
 where
@@ -30,6 +71,11 @@ where
             deployment.write_code(&object)?;
             info!(deployment = deployment.id; "Wrote deployment");
 
+            // Not all deployments have a sourcemap
+            if let Ok(source_map) = downloader.download(deployment.id.clone() + ".js.map").await {
+                deployment.write_source_map(&source_map)?;
+            }
+
             if !deployment.assets.is_empty() {
                 let mut futures = FuturesUnordered::new();
 
@@ -65,13 +111,256 @@ type QueryResult = (
     String,
     String,
     usize,
+    Option<usize>,
     usize,
     usize,
+    Option<usize>,
+    Option<String>,
+    Option<u32>,
+    Option<u32>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
     Option<String>,
     Option<String>,
+    bool,
+    Option<String>,
     Option<String>,
 );
 
+fn take_column<T: FromValue>(row: &mut Row, index: usize) -> Result<T> {
+    match row.take_opt(index) {
+        Some(Ok(value)) => Ok(value),
+        Some(Err(error)) => Err(anyhow!("Invalid value for column {}: {}", index, error)),
+        None => Err(anyhow!("Missing column {}", index)),
+    }
+}
+
+// mysql can only convert rows to tuples of up to 12 columns
+fn from_row(mut row: Row) -> Result<QueryResult> {
+    Ok((
+        take_column(&mut row, 0)?,
+        take_column(&mut row, 1)?,
+        take_column(&mut row, 2)?,
+        take_column(&mut row, 3)?,
+        take_column(&mut row, 4)?,
+        take_column(&mut row, 5)?,
+        take_column(&mut row, 6)?,
+        take_column(&mut row, 7)?,
+        take_column(&mut row, 8)?,
+        take_column(&mut row, 9)?,
+        take_column(&mut row, 10)?,
+        take_column(&mut row, 11)?,
+        take_column(&mut row, 12)?,
+        take_column(&mut row, 13)?,
+        take_column(&mut row, 14)?,
+        take_column(&mut row, 15)?,
+        take_column(&mut row, 16)?,
+        take_column(&mut row, 17)?,
+        take_column(&mut row, 18)?,
+        take_column(&mut row, 19)?,
+        take_column(&mut row, 20)?,
+        take_column(&mut row, 21)?,
+        take_column(&mut row, 22)?,
+    ))
+}
+
+// Stored as a comma-separated list
+fn get_comma_separated(value: Option<&str>) -> Option<Vec<String>> {
+    value
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|items| !items.is_empty())
+}
+
+pub fn get_accepted_content_types(accepted_content_types: Option<&str>) -> Option<Vec<String>> {
+    get_comma_separated(accepted_content_types)
+}
+
+pub fn get_supported_locales(supported_locales: Option<&str>) -> Option<Vec<String>> {
+    get_comma_separated(supported_locales)
+}
+
+// Stored as a JSON array of `{ name, value, policy, path }` objects, where
+// the policy is either "override" (the default) or "append"
+pub fn get_response_headers(response_headers: Option<&str>) -> Option<Vec<ResponseHeader>> {
+    let response_headers = response_headers?;
+
+    let value = match serde_json::from_str::<serde_json::Value>(response_headers) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to parse response headers: {}", error);
+            return None;
+        }
+    };
+
+    let response_headers = value
+        .as_array()?
+        .iter()
+        .filter_map(|header| {
+            let name = header["name"].as_str()?;
+            let value = header["value"].as_str()?;
+            let policy = match header["policy"].as_str() {
+                Some("append") => HeaderPolicy::Append,
+                Some("override") | None => HeaderPolicy::Override,
+                Some(policy) => {
+                    warn!("Unknown response header policy: {}", policy);
+                    return None;
+                }
+            };
+
+            Some(ResponseHeader {
+                name: name.to_string(),
+                value: value.to_string(),
+                policy,
+                path: header["path"].as_str().map(|path| path.to_string()),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if response_headers.is_empty() {
+        None
+    } else {
+        Some(response_headers)
+    }
+}
+
+// Stored as a JSON object `{ deadline, cache, status, contentType, body }`, where
+// the deadline is in milliseconds. Ignored without a cached or static response
+pub fn get_fallback(fallback: Option<&str>) -> Option<Fallback> {
+    let value = match serde_json::from_str::<serde_json::Value>(fallback?) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to parse fallback: {}", error);
+            return None;
+        }
+    };
+
+    let deadline = Duration::from_millis(value["deadline"].as_u64()?);
+    let from_cache = value["cache"].as_bool().unwrap_or(false);
+    let body = value["body"].as_str().map(|body| body.to_string());
+
+    if !from_cache && body.is_none() {
+        return None;
+    }
+
+    Some(Fallback {
+        deadline,
+        from_cache,
+        status: value["status"]
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok())
+            .unwrap_or(200),
+        content_type: value["contentType"]
+            .as_str()
+            .map(|content_type| content_type.to_string()),
+        body,
+    })
+}
+
+// Stored as a JSON object of templates by status, e.g `{ "404": { "code": "not_found",
+// "requestId": "{{requestId}}" }, "default": { ... } }`. Invalid templates are ignored
+pub fn get_error_schema(error_schema: Option<&str>) -> Option<ErrorSchema> {
+    let value = match serde_json::from_str::<serde_json::Value>(error_schema?) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to parse error schema: {}", error);
+            return None;
+        }
+    };
+
+    let mut schema = ErrorSchema::default();
+
+    for (key, template) in value.as_object()? {
+        let template = template.to_string();
+
+        match key.as_str() {
+            "default" => schema.default = Some(template),
+            status => match status.parse::<u16>() {
+                Ok(status) => {
+                    schema.templates.insert(status, template);
+                }
+                Err(_) => warn!("Invalid error schema status: {}", status),
+            },
+        }
+    }
+
+    Some(schema)
+}
+
+// Either `{"allow": [...]}` or `{"deny": [...]}`, with an optional `"log": true`
+pub fn get_header_filter(header_filter: Option<&str>) -> Option<HeaderFilter> {
+    let value = match serde_json::from_str::<serde_json::Value>(header_filter?) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to parse header filter: {}", error);
+            return None;
+        }
+    };
+
+    let (mode, names) = match (value["allow"].as_array(), value["deny"].as_array()) {
+        (Some(names), None) => (HeaderFilterMode::Allow, names),
+        (None, Some(names)) => (HeaderFilterMode::Deny, names),
+        _ => {
+            warn!("Invalid header filter, expected either an allow or a deny list");
+            return None;
+        }
+    };
+
+    Some(HeaderFilter {
+        mode,
+        names: names
+            .iter()
+            .filter_map(|name| Some(name.as_str()?.to_ascii_lowercase()))
+            .collect(),
+        log_violations: value["log"].as_bool().unwrap_or(false),
+    })
+}
+
+// Stored as a JSON object `{ size, policy }`, where the policy is `round-robin`
+// (the default) or `least-in-flight`. Unknown policies are ignored
+pub fn get_isolate_pool(isolate_pool: Option<&str>) -> Option<IsolatePool> {
+    let value = match serde_json::from_str::<serde_json::Value>(isolate_pool?) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to parse isolate pool: {}", error);
+            return None;
+        }
+    };
+
+    let policy = match value["policy"].as_str() {
+        Some("least-in-flight") => BalancingPolicy::LeastInFlight,
+        Some("round-robin") | None => BalancingPolicy::RoundRobin,
+        Some(policy) => {
+            warn!("Invalid isolate pool policy: {}", policy);
+            BalancingPolicy::RoundRobin
+        }
+    };
+
+    Some(IsolatePool {
+        size: value["size"]
+            .as_u64()
+            .map_or(1, |size| size.max(1) as usize),
+        policy,
+    })
+}
+
+// The burst defaults to the rate when not set
+pub fn get_rate_limit(rate: Option<u32>, burst: Option<u32>) -> Option<RateLimit> {
+    rate.map(|rate| RateLimit {
+        rate,
+        burst: burst.unwrap_or(rate),
+    })
+}
+
 pub async fn get_deployments<D>(
     mut conn: PooledConn,
     downloader: Arc<D>,
@@ -93,9 +382,22 @@ SELECT
     Function.id,
     Function.name,
     Function.memory,
+    Function.maxMemory,
     Function.tickTimeout,
     Function.totalTimeout,
+    Function.fetchTimeout,
     Function.cron,
+    Function.rateLimit,
+    Function.rateLimitBurst,
+    Function.acceptedContentTypes,
+    Function.supportedLocales,
+    Function.accessLogFormat,
+    Function.responseHeaders,
+    Function.headerFilter,
+    Function.fallback,
+    Function.errorSchema,
+    Function.isolatePool,
+    Function.serverTiming,
     Domain.domain,
     Asset.name
 FROM
@@ -113,18 +415,33 @@ OR
 ",
             REGION.as_str()
         ),
-        |(
-            id,
-            is_production,
-            function_id,
-            function_name,
-            memory,
-            tick_timeout,
-            total_timeout,
-            cron,
-            domain,
-            asset,
-        ): QueryResult| {
+        |row: Row| -> Result<()> {
+            let (
+                id,
+                is_production,
+                function_id,
+                function_name,
+                memory,
+                max_memory,
+                tick_timeout,
+                total_timeout,
+                fetch_timeout,
+                cron,
+                rate_limit,
+                rate_limit_burst,
+                accepted_content_types,
+                supported_locales,
+                access_log_format,
+                response_headers,
+                header_filter,
+                fallback,
+                error_schema,
+                isolate_pool,
+                server_timing,
+                domain,
+                asset,
+            ) = from_row(row)?;
+
             deployments_list
                 .entry(id.clone())
                 .and_modify(|deployment| {
@@ -156,13 +473,31 @@ OR
                         .unwrap_or_default(),
                     environment_variables: HashMap::new(),
                     memory,
+                    max_memory,
+                    fetch_timeout,
                     tick_timeout,
                     total_timeout,
                     is_production,
                     cron,
+                    rate_limit: get_rate_limit(rate_limit, rate_limit_burst),
+                    accepted_content_types: get_accepted_content_types(
+                        accepted_content_types.as_deref(),
+                    ),
+                    supported_locales: get_supported_locales(supported_locales.as_deref()),
+                    access_log_format,
+                    response_headers: get_response_headers(response_headers.as_deref()),
+                    header_filter: get_header_filter(header_filter.as_deref()),
+                    fallback: get_fallback(fallback.as_deref()),
+                    error_schema: get_error_schema(error_schema.as_deref()),
+                    isolate_pool: get_isolate_pool(isolate_pool.as_deref()),
+                    server_timing,
                 });
+
+            Ok(())
         },
-    )?;
+    )?
+    .into_iter()
+    .collect::<Result<()>>()?;
 
     let deployments_list: Vec<Deployment> = deployments_list.values().cloned().collect();
 
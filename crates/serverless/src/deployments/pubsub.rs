@@ -1,8 +1,11 @@
-use super::{download_deployment, filesystem::rm_deployment, Deployment, Deployments};
-use crate::{serverless::Workers, REGION};
+use super::{
+    download_deployment, filesystem::rm_deployment, get_accepted_content_types, get_error_schema,
+    get_fallback, get_header_filter, get_isolate_pool, get_rate_limit, get_response_headers,
+    get_supported_locales, loader::Bundles, Deployment, Deployments, SourceMaps,
+};
+use crate::{management::Maintenances, serverless::Workers, REGION};
 use anyhow::Result;
 use futures::StreamExt;
-use lagon_runtime_isolate::IsolateEvent;
 use lagon_serverless_downloader::Downloader;
 use lagon_serverless_pubsub::{PubSubListener, PubSubMessage, PubSubMessageKind};
 use log::{error, warn};
@@ -12,10 +15,8 @@ use std::{collections::HashMap, sync::Arc};
 use tokio::{runtime::Handle, sync::Mutex};
 
 pub async fn clear_deployment_cache(deployment_id: String, workers: Workers, reason: String) {
-    if let Some((_, tx)) = workers.remove(&deployment_id) {
-        tx.send_async(IsolateEvent::Terminate(reason))
-            .await
-            .unwrap_or(());
+    if let Some((_, pool)) = workers.remove(&deployment_id) {
+        pool.terminate(reason).await;
     }
 }
 
@@ -23,6 +24,9 @@ async fn run<D, P>(
     downloader: Arc<D>,
     deployments: Deployments,
     workers: Workers,
+    source_maps: SourceMaps,
+    bundles: Bundles,
+    maintenances: Maintenances,
     // cronjob: Arc<Mutex<Cronjob>>,
     pubsub: Arc<Mutex<P>>,
 ) -> Result<()>
@@ -72,10 +76,33 @@ where
                 .map(|(k, v)| (k.to_owned(), v.as_str().unwrap().to_string()))
                 .collect::<HashMap<_, _>>(),
             memory: value["memory"].as_u64().unwrap() as usize,
+            max_memory: value["maxMemory"]
+                .as_u64()
+                .map(|max_memory| max_memory as usize),
+            fetch_timeout: value["fetchTimeout"]
+                .as_u64()
+                .map(|fetch_timeout| fetch_timeout as usize),
             tick_timeout: value["tickTimeout"].as_u64().unwrap() as usize,
             total_timeout: value["totalTimeout"].as_u64().unwrap() as usize,
             is_production: value["isProduction"].as_bool().unwrap(),
             cron,
+            rate_limit: get_rate_limit(
+                value["rateLimit"].as_u64().map(|rate| rate as u32),
+                value["rateLimitBurst"].as_u64().map(|burst| burst as u32),
+            ),
+            accepted_content_types: get_accepted_content_types(
+                value["acceptedContentTypes"].as_str(),
+            ),
+            supported_locales: get_supported_locales(value["supportedLocales"].as_str()),
+            access_log_format: value["accessLogFormat"]
+                .as_str()
+                .map(|access_log_format| access_log_format.to_string()),
+            response_headers: get_response_headers(value["responseHeaders"].as_str()),
+            header_filter: get_header_filter(value["headerFilter"].as_str()),
+            fallback: get_fallback(value["fallback"].as_str()),
+            error_schema: get_error_schema(value["errorSchema"].as_str()),
+            isolate_pool: get_isolate_pool(value["isolatePool"].as_str()),
+            server_timing: value["serverTiming"].as_bool().unwrap_or(false),
         };
 
         let workers = Arc::clone(&workers);
@@ -92,6 +119,9 @@ where
                             "region" => REGION.clone(),
                         );
 
+                        // The bundle has been downloaded again
+                        bundles.invalidate(&deployment.id);
+
                         let domains = deployment.get_domains();
                         let deployment = Arc::new(deployment);
 
@@ -140,6 +170,10 @@ where
                             deployments.remove(domain);
                         }
 
+                        source_maps.remove(&deployment.id);
+                        bundles.invalidate(&deployment.id);
+                        maintenances.remove(&deployment.id);
+
                         clear_deployment_cache(
                             deployment.id.clone(),
                             workers,
@@ -222,6 +256,9 @@ pub fn listen_pub_sub<D, P>(
     downloader: Arc<D>,
     deployments: Deployments,
     workers: Workers,
+    source_maps: SourceMaps,
+    bundles: Bundles,
+    maintenances: Maintenances,
     // cronjob: Arc<Mutex<Cronjob>>,
     pubsub: Arc<Mutex<P>>,
 ) where
@@ -236,6 +273,9 @@ pub fn listen_pub_sub<D, P>(
                     Arc::clone(&downloader),
                     Arc::clone(&deployments),
                     Arc::clone(&workers),
+                    Arc::clone(&source_maps),
+                    Arc::clone(&bundles),
+                    Arc::clone(&maintenances),
                     // Arc::clone(&cronjob),
                     Arc::clone(&pubsub),
                 )
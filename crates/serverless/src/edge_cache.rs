@@ -0,0 +1,466 @@
+use dashmap::DashMap;
+use hyper::{
+    header::{AUTHORIZATION, CACHE_CONTROL, SET_COOKIE, UPGRADE, VARY},
+    Body, HeaderMap, Method, Request as HyperRequest,
+};
+use lagon_runtime_http::{Response, RunResult};
+use lagon_runtime_utils::conditional::ConditionalHeaders;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+// Bigger responses are served without being stored
+pub const EDGE_CACHE_MAX_BODY_SIZE: usize = 1024 * 1024;
+// Stale responses are served without being refreshed above this limit
+pub const EDGE_CACHE_MAX_REVALIDATIONS: usize = 32;
+// How long expired responses are kept to be served as fallbacks
+pub const EDGE_CACHE_MAX_FALLBACK_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const EDGE_CACHE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freshness {
+    pub max_age: Duration,
+    // How long a stale response can be served while it's refreshed
+    pub stale_while_revalidate: Duration,
+}
+
+struct CacheEntry {
+    response: Response,
+    stored_at: Instant,
+    freshness: Freshness,
+    revalidating: bool,
+}
+
+impl CacheEntry {
+    fn age(&self) -> Duration {
+        self.stored_at.elapsed()
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.age() < self.freshness.max_age
+    }
+
+    fn is_usable(&self) -> bool {
+        self.age() < self.freshness.max_age + self.freshness.stale_while_revalidate
+    }
+
+    fn is_usable_as_fallback(&self) -> bool {
+        self.age()
+            < self.freshness.max_age
+                + self.freshness.stale_while_revalidate
+                + EDGE_CACHE_MAX_FALLBACK_AGE
+    }
+}
+
+pub enum CacheLookup {
+    Fresh(Response),
+    // The guard is set when the caller has to refresh the entry
+    Stale(Response, Option<Revalidation>),
+    // The conditional headers of the request match the entry. The
+    // response has no body, and is stale when a guard is returned
+    NotModified(Response, Option<Revalidation>),
+    Miss,
+}
+
+// Held while an entry is refreshed, so a single request refreshes an entry at a time
+pub struct Revalidation {
+    cache: Arc<EdgeCache>,
+    key: String,
+}
+
+impl Drop for Revalidation {
+    fn drop(&mut self) {
+        if let Some(mut entry) = self.cache.entries.get_mut(&self.key) {
+            entry.revalidating = false;
+        }
+
+        self.cache.revalidations.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn has_directive(cache_control: &str, name: &str) -> bool {
+    cache_control
+        .split(',')
+        .any(|directive| directive.trim().eq_ignore_ascii_case(name))
+}
+
+fn get_directive(cache_control: &str, name: &str) -> Option<u64> {
+    cache_control.split(',').find_map(|directive| {
+        let (key, value) = directive.trim().split_once('=')?;
+
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => value.trim().trim_matches('"').parse().ok(),
+            false => None,
+        }
+    })
+}
+
+// The freshness lifetime of a response for a shared cache, using `s-maxage`
+// over `max-age`. `None` when the response can't be stored
+pub fn get_freshness(response: &Response) -> Option<Freshness> {
+    if response.status != 200
+        || response.is_streamed()
+        || response.len() > EDGE_CACHE_MAX_BODY_SIZE
+        || response.get_header(SET_COOKIE.as_str()).is_some()
+    {
+        return None;
+    }
+
+    let cache_control = response.get_header(CACHE_CONTROL.as_str())?;
+
+    if ["no-store", "no-cache", "private"]
+        .iter()
+        .any(|name| has_directive(cache_control, name))
+    {
+        return None;
+    }
+
+    let max_age = get_directive(cache_control, "s-maxage")
+        .or_else(|| get_directive(cache_control, "max-age"))
+        .unwrap_or(0);
+    let stale_while_revalidate =
+        get_directive(cache_control, "stale-while-revalidate").unwrap_or(0);
+
+    if max_age == 0 && stale_while_revalidate == 0 {
+        return None;
+    }
+
+    Some(Freshness {
+        max_age: Duration::from_secs(max_age),
+        stale_while_revalidate: Duration::from_secs(stale_while_revalidate),
+    })
+}
+
+// The lowercased names of the request headers a response varies on. `None`
+// when the response can't be stored, because it varies on everything
+pub fn get_vary(response: &Response) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+
+    for name in response
+        .get_header(VARY.as_str())
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+    {
+        if name == "*" {
+            return None;
+        }
+
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names.sort();
+
+    Some(names)
+}
+
+// The primary key a variant has been computed from
+fn get_primary(variant: &str) -> &str {
+    variant.split('\n').next().unwrap_or(variant)
+}
+
+#[derive(Clone)]
+pub struct CacheKey {
+    // The deployment id and the request path and query
+    primary: String,
+    // Used to select the variant of the response
+    headers: HeaderMap,
+}
+
+impl CacheKey {
+    // The values of the request headers named by the Vary header of the response
+    fn variant(&self, vary: &[String]) -> String {
+        let mut key = self.primary.clone();
+
+        for name in vary {
+            let values = self
+                .headers
+                .get_all(name.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            key.push_str(&format!("\n{name}: {values}"));
+        }
+
+        key
+    }
+}
+
+// The key of a request that can be served from the cache. Requests that aren't
+// GET, are authenticated, or explicitly ask to bypass caches, have no key
+pub fn get_cache_key(deployment_id: &str, req: &HyperRequest<Body>) -> Option<CacheKey> {
+    let headers = req.headers();
+
+    if req.method() != Method::GET
+        || headers.contains_key(AUTHORIZATION)
+        || headers.contains_key(UPGRADE)
+    {
+        return None;
+    }
+
+    if let Some(cache_control) = headers
+        .get(CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+    {
+        if has_directive(cache_control, "no-cache") || has_directive(cache_control, "no-store") {
+            return None;
+        }
+    }
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+
+    Some(CacheKey {
+        primary: format!("{deployment_id} {path}"),
+        headers: headers.clone(),
+    })
+}
+
+// Full responses of the deployments, served on fresh hits without invoking
+// the isolate. Keyed by the deployment id, so a new deployment starts empty
+pub struct EdgeCache {
+    // Keyed by variant, see `CacheKey::variant`
+    entries: DashMap<String, CacheEntry>,
+    // The Vary header of the last response stored for a primary key
+    vary: DashMap<String, Vec<String>>,
+    max_entries: usize,
+    revalidations: AtomicUsize,
+}
+
+impl EdgeCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: DashMap::new(),
+            vary: DashMap::new(),
+            max_entries,
+            revalidations: AtomicUsize::new(0),
+        }
+    }
+
+    fn variant(&self, key: &CacheKey) -> String {
+        match self.vary.get(&key.primary) {
+            Some(vary) => key.variant(&vary),
+            None => key.variant(&[]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Return a fresh or stale response, with its `Age` header. Stale responses
+    // are returned within their `stale-while-revalidate` window
+    pub fn get(
+        self: &Arc<Self>,
+        key: &CacheKey,
+        conditional_headers: Option<&ConditionalHeaders>,
+    ) -> CacheLookup {
+        let variant = self.variant(key);
+        let key = variant.as_str();
+        let mut entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return CacheLookup::Miss,
+        };
+
+        // Expired entries are kept to be served as fallbacks, until they are
+        // replaced, pruned, or removed when the cache is full
+        if !entry.is_usable() {
+            return CacheLookup::Miss;
+        }
+
+        // The body isn't needed to compare the validators
+        let mut response = Response {
+            headers: entry.response.headers.clone(),
+            body: Default::default(),
+            status: entry.response.status,
+            status_text: entry.response.status_text.clone(),
+        };
+        let age = entry.age().as_secs().to_string();
+
+        response
+            .headers
+            .get_or_insert_with(Default::default)
+            .insert("age".into(), vec![age]);
+
+        let is_not_modified = conditional_headers.map_or(false, |conditional_headers| {
+            conditional_headers.is_not_modified(&response)
+        });
+
+        let is_fresh = entry.is_fresh();
+        let revalidation = match is_fresh || entry.revalidating {
+            true => None,
+            false => self.start_revalidation(key).map(|revalidation| {
+                entry.revalidating = true;
+                revalidation
+            }),
+        };
+
+        if is_not_modified {
+            return CacheLookup::NotModified(response, revalidation);
+        }
+
+        response.body = entry.response.body.clone();
+
+        match is_fresh {
+            true => CacheLookup::Fresh(response),
+            false => CacheLookup::Stale(response, revalidation),
+        }
+    }
+
+    // Return the last response stored, even if it has expired
+    // less than `EDGE_CACHE_MAX_FALLBACK_AGE` ago
+    pub fn get_fallback(&self, key: &CacheKey) -> Option<Response> {
+        let variant = self.variant(key);
+        let entry = self.entries.get(&variant)?;
+
+        if !entry.is_usable_as_fallback() {
+            return None;
+        }
+
+        let mut response = entry.response.clone();
+        response
+            .headers
+            .get_or_insert_with(Default::default)
+            .insert("age".into(), vec![entry.age().as_secs().to_string()]);
+
+        Some(response)
+    }
+
+    // Remove the entries that can't be served anymore, even as fallbacks,
+    // and the Vary headers of the URLs without any entry left
+    pub fn prune(&self) {
+        self.entries
+            .retain(|_, entry| entry.is_usable_as_fallback());
+
+        let primaries = self
+            .entries
+            .iter()
+            .map(|entry| get_primary(entry.key()).to_string())
+            .collect::<HashSet<_>>();
+
+        self.vary.retain(|primary, _| primaries.contains(primary));
+    }
+
+    fn start_revalidation(self: &Arc<Self>, key: &str) -> Option<Revalidation> {
+        if self.revalidations.fetch_add(1, Ordering::SeqCst) >= EDGE_CACHE_MAX_REVALIDATIONS {
+            self.revalidations.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(Revalidation {
+            cache: Arc::clone(self),
+            key: key.to_string(),
+        })
+    }
+
+    // Store the response if its `Cache-Control` allows it, and it isn't `Vary: *`. When
+    // the cache is full, expired entries are removed and the response is skipped if needed
+    pub fn insert(&self, key: &CacheKey, response: &Response) -> bool {
+        let (freshness, vary) = match (get_freshness(response), get_vary(response)) {
+            (Some(freshness), Some(vary)) => (freshness, vary),
+            _ => return false,
+        };
+
+        // Only grows with the number of cached URLs. Keep the Vary headers
+        // still needed to find the stored entries
+        if self.vary.len() >= self.max_entries && !self.vary.contains_key(&key.primary) {
+            let primaries = self
+                .entries
+                .iter()
+                .map(|entry| get_primary(entry.key()).to_string())
+                .collect::<HashSet<_>>();
+
+            self.vary.retain(|primary, _| primaries.contains(primary));
+
+            if self.vary.len() >= self.max_entries {
+                return false;
+            }
+        }
+
+        let variant = key.variant(&vary);
+
+        // The variants stored with the previous Vary header can't be found anymore
+        if let Some(previous_vary) = self.vary.insert(key.primary.clone(), vary.clone()) {
+            if previous_vary != vary {
+                self.entries
+                    .retain(|variant, _| get_primary(variant) != key.primary);
+            }
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&variant) {
+            self.entries.retain(|_, entry| entry.is_usable());
+
+            if self.entries.len() >= self.max_entries {
+                return false;
+            }
+        }
+
+        self.entries.insert(
+            variant,
+            CacheEntry {
+                response: response.clone(),
+                stored_at: Instant::now(),
+                freshness,
+                revalidating: false,
+            },
+        );
+
+        true
+    }
+
+    // Forward the results of a request to the returned receiver,
+    // storing its response on the way when it can be cached
+    pub fn store(
+        self: &Arc<Self>,
+        key: CacheKey,
+        receiver: flume::Receiver<RunResult>,
+        revalidation: Option<Revalidation>,
+    ) -> flume::Receiver<RunResult> {
+        let (sender, tapped_receiver) = flume::unbounded();
+        let cache = Arc::clone(self);
+
+        tokio::spawn(async move {
+            // Released once the entry has been refreshed
+            let _revalidation = revalidation;
+
+            while let Ok(result) = receiver.recv_async().await {
+                if let RunResult::Response(response, _) = &result {
+                    cache.insert(&key, response);
+                }
+
+                if sender.send_async(result).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        tapped_receiver
+    }
+}
+
+pub fn run_edge_cache_prune_task(edge_cache: Arc<EdgeCache>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(EDGE_CACHE_PRUNE_INTERVAL).await;
+
+            edge_cache.prune();
+        }
+    });
+}
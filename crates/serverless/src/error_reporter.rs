@@ -0,0 +1,90 @@
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use log::error;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    pub message: String,
+    // The JS stack trace, when the error is an exception
+    pub stack: Option<String>,
+    pub deployment_id: String,
+    // Empty when the error isn't tied to a request, e.g an isolate panic
+    pub request_id: String,
+}
+
+impl ErrorReport {
+    pub fn new(error: &str, deployment_id: String, request_id: String) -> Self {
+        // Exceptions messages are followed by their stack frames
+        let (message, stack) = match error.split_once("\n  at ") {
+            Some((message, stack)) => (message.to_string(), Some(format!("  at {stack}"))),
+            None => (error.to_string(), None),
+        };
+
+        Self {
+            message,
+            stack,
+            deployment_id,
+            request_id,
+        }
+    }
+}
+
+// Forward functions errors to an external service
+pub trait ErrorReporter: Send + Sync {
+    fn report(&self, report: ErrorReport);
+}
+
+pub struct NoopErrorReporter;
+
+impl ErrorReporter for NoopErrorReporter {
+    fn report(&self, _report: ErrorReport) {}
+}
+
+// POST each report as JSON to the given URL
+pub struct WebhookErrorReporter {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl WebhookErrorReporter {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: Client::builder().build::<_, Body>(HttpsConnector::new()),
+        }
+    }
+}
+
+impl ErrorReporter for WebhookErrorReporter {
+    fn report(&self, report: ErrorReport) {
+        let body = match serde_json::to_string(&report) {
+            Ok(body) => body,
+            Err(error) => {
+                error!("Error while serializing error report: {}", error);
+                return;
+            }
+        };
+
+        let request = match Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+        {
+            Ok(request) => request,
+            Err(error) => {
+                error!("Error while creating error report request: {}", error);
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = client.request(request).await {
+                error!("Error while sending error report: {}", error);
+            }
+        });
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use hyper::{header::HeaderValue, Body, HeaderMap, Request as HyperRequest};
+use ipnet::IpNet;
+use lagon_runtime_http::{
+    FORWARDED, X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO, X_LAGON_MAX_BODY_SIZE,
+    X_LAGON_MEMORY, X_REAL_IP,
+};
+use std::net::IpAddr;
+
+// Formatted as `10.0.0.0/8,192.168.1.1`, where single IPs are allowed
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpNet>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| match value.parse::<IpAddr>() {
+            Ok(ip) => Ok(IpNet::from(ip)),
+            Err(_) => Ok(value.parse::<IpNet>()?),
+        })
+        .collect()
+}
+
+pub fn is_trusted_proxy(ip: &IpAddr, trusted_proxies: &[IpNet]) -> bool {
+    trusted_proxies.iter().any(|proxy| proxy.contains(ip))
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value.trim().parse().ok()
+}
+
+// A hop of the Forwarded header (RFC 7239), e.g `for=192.0.2.60;proto=https`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    // `None` for the obfuscated and unknown nodes
+    pub for_ip: Option<IpAddr>,
+    pub by: Option<String>,
+    pub host: Option<String>,
+    pub proto: Option<String>,
+}
+
+// Nodes can have a port, and IPv6 addresses are within brackets,
+// e.g `192.0.2.60:8080` or `"[2001:db8:cafe::17]:4711"`
+fn parse_node(value: &str) -> Option<IpAddr> {
+    if let Some(ipv6) = value.strip_prefix('[') {
+        return ipv6.split(']').next()?.parse().ok();
+    }
+
+    parse_ip(value).or_else(|| parse_ip(value.split_once(':')?.0))
+}
+
+// Quoted values can't contain commas or semicolons, which are always
+// treated as separators. Unknown parameters are ignored
+pub fn parse_forwarded(value: &str) -> Vec<ForwardedElement> {
+    value
+        .split(',')
+        .map(|element| {
+            let mut forwarded = ForwardedElement::default();
+
+            for pair in element.split(';') {
+                let (name, value) = match pair.split_once('=') {
+                    Some((name, value)) => (name.trim(), value.trim().trim_matches('"')),
+                    None => continue,
+                };
+
+                match name.to_ascii_lowercase().as_str() {
+                    "for" => forwarded.for_ip = parse_node(value),
+                    "by" => forwarded.by = Some(value.to_string()),
+                    "host" => forwarded.host = Some(value.to_string()),
+                    "proto" => forwarded.proto = Some(value.to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+
+            forwarded
+        })
+        .collect()
+}
+
+// Like X-Forwarded-For, the client is the last hop that hasn't been added by a
+// trusted proxy. Its protocol and host replace the X-Forwarded-* headers
+fn get_forwarded_client_ip(headers: &mut HeaderMap, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    let elements = parse_forwarded(headers.get(FORWARDED)?.to_str().ok()?);
+    let mut client = None;
+
+    for element in elements.iter().rev() {
+        match element.for_ip {
+            Some(ip) => {
+                client = Some((ip, element));
+
+                if !is_trusted_proxy(&ip, trusted_proxies) {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+
+    let (client_ip, element) = client?;
+
+    for (name, value) in [
+        (X_FORWARDED_PROTO, &element.proto),
+        (X_FORWARDED_HOST, &element.host),
+    ] {
+        if let Some(value) = value
+            .as_deref()
+            .and_then(|value| HeaderValue::from_str(value).ok())
+        {
+            headers.insert(name, value);
+        }
+    }
+
+    Some(client_ip)
+}
+
+// Forwarded headers are only honored when the peer is a trusted proxy. Otherwise,
+// they are removed from the request since they could have been spoofed by the client.
+// The Forwarded header takes precedence over X-Real-Ip and X-Forwarded-*
+pub fn get_client_ip(
+    req: &mut HyperRequest<Body>,
+    peer_ip: IpAddr,
+    trusted_proxies: &[IpNet],
+) -> IpAddr {
+    let headers = req.headers_mut();
+
+    if !is_trusted_proxy(&peer_ip, trusted_proxies) {
+        headers.remove(X_REAL_IP);
+        headers.remove(X_FORWARDED_PROTO);
+        headers.remove(X_FORWARDED_HOST);
+        headers.remove(FORWARDED);
+
+        return peer_ip;
+    }
+
+    if let Some(client_ip) = get_forwarded_client_ip(headers, trusted_proxies) {
+        return client_ip;
+    }
+
+    if let Some(x_real_ip) = headers
+        .get(X_REAL_IP)
+        .and_then(|x_real_ip| x_real_ip.to_str().ok())
+        .and_then(parse_ip)
+    {
+        return x_real_ip;
+    }
+
+    let mut client_ip = peer_ip;
+
+    // Each proxy appends the IP it received the request from, so the client IP
+    // is the last one that hasn't been added by a trusted proxy
+    if let Some(x_forwarded_for) = headers
+        .get(X_FORWARDED_FOR)
+        .and_then(|x_forwarded_for| x_forwarded_for.to_str().ok())
+    {
+        for ip in x_forwarded_for.rsplit(',') {
+            match parse_ip(ip) {
+                Some(ip) => {
+                    client_ip = ip;
+
+                    if !is_trusted_proxy(&ip, trusted_proxies) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    client_ip
+}
+
+// The memory limit (in MB) requested by a trusted proxy, e.g for routes needing more
+// memory than the others. The header is always removed from the request, and ignored
+// when the deployment doesn't allow overriding its memory limit
+pub fn get_memory_override(
+    req: &mut HyperRequest<Body>,
+    peer_ip: IpAddr,
+    trusted_proxies: &[IpNet],
+    max_memory: Option<usize>,
+) -> Option<usize> {
+    let memory = req.headers_mut().remove(X_LAGON_MEMORY)?;
+
+    if max_memory.is_none() || !is_trusted_proxy(&peer_ip, trusted_proxies) {
+        return None;
+    }
+
+    memory.to_str().ok()?.trim().parse().ok()
+}
+
+// The body size limit (in bytes) of the request. Trusted proxies can raise it with
+// a header, e.g for internal uploads, up to `max_body_size_override`. The header
+// is always removed from the request, and only raises the default limit
+pub fn get_max_body_size(
+    req: &mut HyperRequest<Body>,
+    peer_ip: IpAddr,
+    trusted_proxies: &[IpNet],
+    max_body_size: Option<usize>,
+    max_body_size_override: Option<usize>,
+) -> Option<usize> {
+    let requested = req.headers_mut().remove(X_LAGON_MAX_BODY_SIZE);
+    let max_body_size = max_body_size?;
+
+    let requested = requested
+        .filter(|_| is_trusted_proxy(&peer_ip, trusted_proxies))
+        .and_then(|requested| requested.to_str().ok()?.trim().parse::<usize>().ok());
+
+    match (requested, max_body_size_override) {
+        (Some(requested), Some(max_body_size_override)) => {
+            Some(max_body_size.max(requested.min(max_body_size_override)))
+        }
+        _ => Some(max_body_size),
+    }
+}
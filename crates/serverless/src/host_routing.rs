@@ -0,0 +1,86 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+// Routes the hostnames without a deployment of their own to the hostname of
+// another deployment, e.g to serve all the tenants of an app from a single
+// deployment. Hostnames are matched case-insensitively and without their port
+#[derive(Debug, Clone, Default)]
+pub struct HostRoutes {
+    exact: HashMap<String, String>,
+    // `*.example.com` is stored as `.example.com`, and matches all its
+    // subdomains but not `example.com`. The longest suffix matches first
+    wildcards: Vec<(String, String)>,
+    // Unmatched hostnames are answered with a 404 when unset
+    default: Option<String>,
+}
+
+fn normalize_hostname(hostname: &str) -> String {
+    let hostname = match hostname.rsplit_once(':') {
+        // IPv6 addresses without a port, e.g `[::1]`
+        Some((hostname, port)) if !port.contains(']') => hostname,
+        _ => hostname,
+    };
+
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl HostRoutes {
+    pub fn route(mut self, pattern: &str, target: String) -> Self {
+        let pattern = normalize_hostname(pattern);
+
+        match pattern.strip_prefix('*') {
+            Some(suffix) => {
+                self.wildcards.push((suffix.to_string(), target));
+                self.wildcards
+                    .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+            }
+            None => {
+                self.exact.insert(pattern, target);
+            }
+        }
+
+        self
+    }
+
+    pub fn default_route(mut self, target: String) -> Self {
+        self.default = Some(target);
+        self
+    }
+
+    // The hostname of the deployment serving this hostname, if any
+    pub fn resolve(&self, hostname: &str) -> Option<&str> {
+        let hostname = normalize_hostname(hostname);
+
+        if let Some(target) = self.exact.get(&hostname) {
+            return Some(target);
+        }
+
+        self.wildcards
+            .iter()
+            .find(|(suffix, _)| hostname.len() > suffix.len() && hostname.ends_with(suffix))
+            .map(|(_, target)| target.as_str())
+            .or(self.default.as_deref())
+    }
+}
+
+// Formatted as `pattern=hostname;pattern=hostname`, e.g `*.example.com=tenants.lagon.app`
+pub fn parse_host_routes(value: &str) -> Result<HostRoutes> {
+    let mut host_routes = HostRoutes::default();
+
+    for route in value.split(';').filter(|route| !route.trim().is_empty()) {
+        let (pattern, target) = route
+            .split_once('=')
+            .map(|(pattern, target)| (pattern.trim(), target.trim()))
+            .filter(|(pattern, target)| {
+                !pattern.is_empty()
+                    && !target.is_empty()
+                    && (!pattern.contains('*') || pattern.starts_with("*."))
+                    && pattern.matches('*').count() <= 1
+            })
+            .ok_or_else(|| anyhow!("Invalid host route: {}", route))?;
+
+        host_routes = host_routes.route(pattern, target.into());
+    }
+
+    Ok(host_routes)
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use bytes::{Buf, Bytes};
+use futures::Future;
+use h3::{error::ErrorLevel, server::RequestStream};
+use hyper::{
+    body::HttpBody,
+    header::{HeaderValue, CONNECTION, HOST, TRANSFER_ENCODING, UPGRADE},
+    http, Body, Request as HyperRequest,
+};
+use log::{debug, error};
+use quinn::Endpoint;
+use rustls::ServerConfig;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{serverless::RequestHandler, tls::CertificateStore};
+
+// How long the clients can cache that HTTP/3 is available, in seconds
+const ALT_SVC_MAX_AGE: u64 = 24 * 60 * 60;
+
+pub fn alt_svc_header(port: u16) -> HeaderValue {
+    HeaderValue::from_str(&format!("h3=\":{port}\"; ma={ALT_SVC_MAX_AGE}")).unwrap()
+}
+
+// Connection-specific headers aren't allowed in HTTP/3 (RFC 9114)
+fn remove_connection_headers(headers: &mut http::HeaderMap) {
+    for name in [CONNECTION, TRANSFER_ENCODING, UPGRADE] {
+        headers.remove(name);
+    }
+
+    headers.remove("keep-alive");
+    headers.remove("proxy-connection");
+}
+
+async fn handle_http3_request(
+    req: http::Request<()>,
+    stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    handler: RequestHandler,
+    peer_ip: IpAddr,
+) -> Result<()> {
+    let (mut send_stream, mut recv_stream) = stream.split();
+    let (mut body_tx, body) = Body::channel();
+
+    // The body is read while the request is handled, like with hyper
+    tokio::spawn(async move {
+        loop {
+            match recv_stream.recv_data().await {
+                Ok(Some(mut data)) => {
+                    let data = data.copy_to_bytes(data.remaining());
+
+                    if body_tx.send_data(data).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    body_tx.abort();
+                    break;
+                }
+            }
+        }
+    });
+
+    let (parts, ()) = req.into_parts();
+    let mut req = HyperRequest::from_parts(parts, body);
+
+    // Deployments are found using the Host header, which is replaced by
+    // the :authority pseudo-header in HTTP/3
+    if !req.headers().contains_key(HOST) {
+        if let Some(host) = req
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        {
+            req.headers_mut().insert(HOST, host);
+        }
+    }
+
+    let (mut parts, mut body) = handler.handle(req, peer_ip).await?.into_parts();
+    remove_connection_headers(&mut parts.headers);
+
+    send_stream
+        .send_response(http::Response::from_parts(parts, ()))
+        .await?;
+
+    // Streamed responses are sent chunk by chunk
+    while let Some(chunk) = body.data().await {
+        send_stream.send_data(chunk?).await?;
+    }
+
+    send_stream.finish().await?;
+
+    Ok(())
+}
+
+async fn handle_http3_connection(connecting: quinn::Connecting, handler: RequestHandler) {
+    let connection = match connecting.await {
+        Ok(connection) => connection,
+        Err(error) => {
+            debug!("QUIC handshake failed: {}", error);
+            return;
+        }
+    };
+
+    let peer_ip = connection.remote_address().ip();
+    let mut connection =
+        match h3::server::Connection::new(h3_quinn::Connection::new(connection)).await {
+            Ok(connection) => connection,
+            Err(error) => {
+                debug!("Error while establishing HTTP/3 connection: {}", error);
+                return;
+            }
+        };
+
+    loop {
+        match connection.accept().await {
+            Ok(Some((req, stream))) => {
+                let handler = handler.clone();
+
+                tokio::spawn(async move {
+                    if let Err(error) = handle_http3_request(req, stream, handler, peer_ip).await {
+                        debug!("Error while handling HTTP/3 request: {}", error);
+                    }
+                });
+            }
+            // Closed by the client
+            Ok(None) => break,
+            Err(error) => match error.get_error_level() {
+                ErrorLevel::ConnectionError => break,
+                ErrorLevel::StreamError => continue,
+            },
+        }
+    }
+}
+
+// Serves HTTP/3 over QUIC, using the same certificates and request handling as
+// the HTTP/1.1 server. The connection limits and idle connections reaping only
+// apply to the TCP connections
+pub fn http3_server(
+    addr: SocketAddr,
+    store: Arc<CertificateStore>,
+    handler: RequestHandler,
+    shutdown: CancellationToken,
+) -> Result<impl Future<Output = ()> + Send> {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(store);
+    config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let endpoint = Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(config)), addr)?;
+
+    Ok(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                connecting = endpoint.accept() => match connecting {
+                    Some(connecting) => {
+                        tokio::spawn(handle_http3_connection(connecting, handler.clone()));
+                    }
+                    None => {
+                        error!("HTTP/3 endpoint closed");
+                        break;
+                    }
+                },
+            }
+        }
+
+        // Stop accepting new connections, and wait for the in-flight
+        // requests to complete (bounded by the shutdown grace period)
+        endpoint.set_server_config(None);
+        endpoint.wait_idle().await;
+    })
+}
@@ -0,0 +1,238 @@
+use dashmap::DashMap;
+use hyper::{
+    body::{Bytes, HttpBody, SizeHint},
+    Body, HeaderMap,
+};
+use log::debug;
+use metrics::increment_counter;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+const MAX_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+// Tracks the activity of the inbound connections, to close the ones that didn't
+// read or write anything for longer than the timeout, while not handling a request
+pub struct IdleConnections {
+    timeout: Duration,
+    next_id: AtomicU64,
+    connections: DashMap<u64, Arc<ConnectionActivity>>,
+}
+
+impl IdleConnections {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            next_id: AtomicU64::new(0),
+            connections: DashMap::new(),
+        }
+    }
+
+    pub fn track(self: &Arc<Self>) -> IdleGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let activity = Arc::new(ConnectionActivity::default());
+
+        self.connections.insert(id, Arc::clone(&activity));
+
+        IdleGuard {
+            connections: Arc::clone(self),
+            id,
+            activity,
+        }
+    }
+
+    // Close the connections idle for longer than the timeout,
+    // returning how many have been closed
+    pub fn reap(&self) -> usize {
+        let mut reaped = 0;
+
+        for connection in self.connections.iter() {
+            if connection.try_reap(self.timeout) {
+                increment_counter!("lagon_connections_reaped");
+                reaped += 1;
+            }
+        }
+
+        reaped
+    }
+}
+
+pub fn run_idle_connections_reaper(idle_connections: Arc<IdleConnections>) {
+    let reap_interval = idle_connections.timeout.min(MAX_REAP_INTERVAL);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(reap_interval).await;
+
+            let reaped = idle_connections.reap();
+
+            if reaped > 0 {
+                debug!("Closed {} idle connection(s)", reaped);
+            }
+        }
+    });
+}
+
+struct ActivityState {
+    last_active: Instant,
+    requests: usize,
+    reaped: bool,
+    // Woken when the connection is reaped, so it is polled and closed
+    waker: Option<Waker>,
+}
+
+// All the state is behind the same lock, so a connection can't be
+// reaped while it starts reading a request
+pub struct ConnectionActivity {
+    state: Mutex<ActivityState>,
+}
+
+impl Default for ConnectionActivity {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ActivityState {
+                last_active: Instant::now(),
+                requests: 0,
+                reaped: false,
+                waker: None,
+            }),
+        }
+    }
+}
+
+impl ConnectionActivity {
+    // Returns false once the connection has been reaped. Otherwise, record
+    // some activity if `active` is true, or wait to be reaped
+    pub fn poll_active(&self, waker: &Waker, active: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if state.reaped {
+            return false;
+        }
+
+        if active {
+            state.last_active = Instant::now();
+        } else if !state
+            .waker
+            .as_ref()
+            .map_or(false, |current| current.will_wake(waker))
+        {
+            state.waker = Some(waker.clone());
+        }
+
+        true
+    }
+
+    pub fn touch(&self) {
+        self.state.lock().unwrap().last_active = Instant::now();
+    }
+
+    // The connection isn't idle while a request is being handled,
+    // even if nothing is read or written
+    pub fn start_request(self: &Arc<Self>) -> RequestGuard {
+        self.state.lock().unwrap().requests += 1;
+
+        RequestGuard(Arc::clone(self))
+    }
+
+    fn try_reap(&self, timeout: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if state.reaped || state.requests > 0 || state.last_active.elapsed() < timeout {
+            return false;
+        }
+
+        state.reaped = true;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+
+        true
+    }
+}
+
+pub struct RequestGuard(Arc<ConnectionActivity>);
+
+// A response body keeping its request guard until the whole body has been
+// sent, so the connection isn't reaped while waiting for the next chunks
+pub struct GuardedBody {
+    body: Body,
+    request_guard: Option<RequestGuard>,
+}
+
+impl GuardedBody {
+    pub fn new(body: Body, request_guard: Option<RequestGuard>) -> Self {
+        Self {
+            body,
+            request_guard,
+        }
+    }
+}
+
+impl HttpBody for GuardedBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = Pin::new(&mut self.body).poll_data(cx);
+
+        if let Poll::Ready(None) = data {
+            self.request_guard.take();
+        }
+
+        data
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+
+        state.requests -= 1;
+        state.last_active = Instant::now();
+    }
+}
+
+// Stops tracking the connection once dropped
+pub struct IdleGuard {
+    connections: Arc<IdleConnections>,
+    id: u64,
+    activity: Arc<ConnectionActivity>,
+}
+
+impl IdleGuard {
+    pub fn activity(&self) -> &Arc<ConnectionActivity> {
+        &self.activity
+    }
+}
+
+impl Drop for IdleGuard {
+    fn drop(&mut self) {
+        self.connections.connections.remove(&self.id);
+    }
+}
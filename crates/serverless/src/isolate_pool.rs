@@ -0,0 +1,143 @@
+use lagon_runtime_isolate::IsolateEvent;
+use lagon_runtime_utils::BalancingPolicy;
+use once_cell::sync::OnceCell;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct StartupTimings {
+    pub cold_start: Duration,
+    pub compilation_time: Duration,
+    // When the isolate started handling its requests
+    pub ready_at: Instant,
+}
+
+// Set by the isolate once it has been created and evaluated
+pub type IsolateStartup = Arc<OnceCell<StartupTimings>>;
+
+struct Worker {
+    sender: flume::Sender<IsolateEvent>,
+    startup: IsolateStartup,
+    in_flight_requests: Arc<AtomicUsize>,
+    // Set when evicted, before its event loop has completed
+    terminated: AtomicBool,
+}
+
+impl Worker {
+    fn is_running(&self) -> bool {
+        !self.terminated.load(Ordering::SeqCst) && !self.sender.is_disconnected()
+    }
+}
+
+// Counted in the in-flight requests of the isolate until dropped
+pub struct WorkerRequest(Arc<AtomicUsize>);
+
+impl Drop for WorkerRequest {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// The warm isolates of a deployment. Isolates whose event loop has completed
+// are skipped, and the pool is replaced once none of them is running
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+    policy: BalancingPolicy,
+    next: AtomicUsize,
+}
+
+impl WorkerPool {
+    pub fn new(
+        senders: Vec<(flume::Sender<IsolateEvent>, IsolateStartup)>,
+        policy: BalancingPolicy,
+    ) -> Self {
+        Self {
+            workers: senders
+                .into_iter()
+                .map(|(sender, startup)| Worker {
+                    sender,
+                    startup,
+                    in_flight_requests: Arc::new(AtomicUsize::new(0)),
+                    terminated: AtomicBool::new(false),
+                })
+                .collect(),
+            policy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // The number of isolates still running
+    pub fn len(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|worker| worker.is_running())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Pick the isolate of the next request, starting after the last isolate picked so
+    // ties are broken in turn. `None` when all the isolates have been terminated
+    pub fn select(&self) -> Option<(flume::Sender<IsolateEvent>, WorkerRequest, IsolateStartup)> {
+        let start = self.next.fetch_add(1, Ordering::SeqCst);
+        let mut workers = (0..self.workers.len())
+            .map(|index| &self.workers[(start + index) % self.workers.len()])
+            .filter(|worker| worker.is_running());
+
+        let worker = match self.policy {
+            BalancingPolicy::RoundRobin => workers.next(),
+            BalancingPolicy::LeastInFlight => {
+                workers.min_by_key(|worker| worker.in_flight_requests.load(Ordering::SeqCst))
+            }
+        }?;
+
+        worker.in_flight_requests.fetch_add(1, Ordering::SeqCst);
+
+        Some((
+            worker.sender.clone(),
+            WorkerRequest(Arc::clone(&worker.in_flight_requests)),
+            Arc::clone(&worker.startup),
+        ))
+    }
+
+    // Terminate one of the isolates not handling any request, which stops
+    // receiving requests right away. Returns whether one was terminated
+    pub async fn terminate_idle(&self, reason: String) -> bool {
+        for worker in self.workers.iter().filter(|worker| worker.is_running()) {
+            worker.terminated.store(true, Ordering::SeqCst);
+
+            // A request may have been sent to it in the meantime
+            if worker.in_flight_requests.load(Ordering::SeqCst) > 0 {
+                worker.terminated.store(false, Ordering::SeqCst);
+                continue;
+            }
+
+            worker
+                .sender
+                .send_async(IsolateEvent::Terminate(reason))
+                .await
+                .unwrap_or(());
+
+            return true;
+        }
+
+        false
+    }
+
+    pub async fn terminate(&self, reason: String) {
+        for worker in &self.workers {
+            worker
+                .sender
+                .send_async(IsolateEvent::Terminate(reason.clone()))
+                .await
+                .unwrap_or(());
+        }
+    }
+}
@@ -3,9 +3,26 @@ use std::env;
 
 // TODO add back cron jobs
 // pub mod cronjob;
+pub mod access_log;
+pub mod admission;
 pub mod clickhouse;
+pub mod connection_limit;
 pub mod deployments;
+pub mod edge_cache;
+pub mod error_reporter;
+pub mod forwarded;
+pub mod host_routing;
+pub mod http3;
+pub mod idle_connections;
+pub mod isolate_pool;
+pub mod management;
+pub mod options;
+pub mod rate_limit;
+pub mod replay;
+pub mod server_timing;
 pub mod serverless;
+pub mod tls;
+pub mod websocket;
 
 pub static REGION: Lazy<String> =
     Lazy::new(|| env::var("LAGON_REGION").expect("LAGON_REGION must be set"));
@@ -2,6 +2,7 @@ use anyhow::Result;
 use lagon_runtime::{options::RuntimeOptions, Runtime};
 use lagon_serverless::clickhouse::{create_client, run_migrations};
 use lagon_serverless::deployments::get_deployments;
+use lagon_serverless::options::ServerlessOptions;
 use lagon_serverless::serverless::start;
 use lagon_serverless::REGION;
 use lagon_serverless_downloader::{get_bucket, S3BucketDownloader};
@@ -19,6 +20,7 @@ use std::net::SocketAddr;
 #[cfg(not(debug_assertions))]
 use std::path::Path;
 use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -28,7 +30,11 @@ async fn main() -> Result<()> {
 
     let _flush_guard = init_logger(REGION.clone()).expect("Failed to init logger");
 
-    let runtime = Runtime::new(RuntimeOptions::default());
+    // Process-global V8 flags, separated by spaces
+    let v8_flags = env::var("LAGON_V8_FLAGS")
+        .map(|v8_flags| v8_flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+    let runtime = Runtime::new(RuntimeOptions::default().v8_flags(v8_flags))?;
     let addr: SocketAddr = env::var("LAGON_LISTEN_ADDR")
         .expect("LAGON_LISTEN_ADDR must be set")
         .parse()?;
@@ -73,12 +79,27 @@ async fn main() -> Result<()> {
         Arc::clone(&downloader), /*, Arc::clone(&cronjob)*/
     )
     .await?;
+
+    let options = ServerlessOptions::from_env()?;
+    let shutdown = options.shutdown.clone();
+
+    tokio::spawn(async move {
+        let mut sigterm =
+            signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM signals");
+
+        sigterm.recv().await;
+        info!("Received SIGTERM, draining in-flight requests");
+
+        shutdown.cancel();
+    });
+
     let serverless = start(
         deployments,
         addr,
         downloader,
         pubsub,
-        client, /*, cronjob*/
+        client,
+        options, /*, cronjob*/
     )
     .await?;
     tokio::spawn(serverless).await?;
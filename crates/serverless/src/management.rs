@@ -0,0 +1,773 @@
+use crate::{
+    deployments::{loader::Bundles, Deployments, SourceMaps},
+    isolate_pool::WorkerRequest,
+    options::ServerlessOptions,
+    replay::{replay_request, ReplayedRequest},
+    serverless::Workers,
+    tls::CertificateStore,
+    websocket::accept_host_websocket,
+};
+use anyhow::Result;
+use dashmap::DashMap;
+use futures::{SinkExt, StreamExt};
+use hyper::{
+    body::{self, Bytes},
+    header::{HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
+    Body, Method, Request as HyperRequest, Response as HyperResponse,
+};
+use lagon_runtime_http::{read_body, BodyTooLarge};
+use lagon_runtime_isolate::BundleMetadata;
+use lagon_runtime_utils::{
+    error_page::error_page,
+    response::{PAGE_404, PAGE_413, PAGE_503},
+};
+use log::error;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::{
+    tungstenite::{protocol::Role, Message},
+    WebSocketStream,
+};
+use tokio_util::sync::CancellationToken;
+
+// Routes under this prefix are only handled when a management
+// token is configured, and require it as a Bearer token
+pub const MANAGEMENT_PREFIX: &str = "/__lagon/";
+// Always handled, without requiring the management token
+pub const HEALTH_PATH: &str = "/__lagon/health";
+// The replayed requests are read in memory, with their body
+const REPLAY_MAX_PAYLOAD_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct DeploymentStats {
+    in_flight_requests: usize,
+    memory_usage: usize,
+    // Only set while the deployment has an isolate
+    bundle: Option<BundleMetadata>,
+}
+
+pub type DeploymentsStats = Arc<DashMap<String, DeploymentStats>>;
+
+pub fn set_memory_usage(stats: &DeploymentsStats, deployment_id: &str, memory_usage: usize) {
+    stats
+        .entry(deployment_id.to_string())
+        .or_default()
+        .memory_usage = memory_usage;
+}
+
+pub fn set_bundle_metadata(
+    stats: &DeploymentsStats,
+    deployment_id: &str,
+    bundle: Option<BundleMetadata>,
+) {
+    stats.entry(deployment_id.to_string()).or_default().bundle = bundle;
+}
+
+pub fn get_in_flight_requests(stats: &DeploymentsStats, deployment_id: &str) -> usize {
+    stats
+        .get(deployment_id)
+        .map_or(0, |stats| stats.in_flight_requests)
+}
+
+// How many logs a slow tailing client can be late, before missing the oldest ones
+pub const LOG_TAIL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TailedLog {
+    pub deployment: String,
+    pub function: String,
+    pub level: String,
+    pub message: String,
+    // Milliseconds since the Unix epoch
+    pub timestamp: u128,
+}
+
+// Logs of the isolates, sent to the clients tailing them. Sending
+// never blocks the isolates, even without any client
+pub type LogTail = broadcast::Sender<TailedLog>;
+
+pub fn new_log_tail() -> LogTail {
+    broadcast::channel(LOG_TAIL_CAPACITY).0
+}
+
+// In-flight requests which can be cancelled, by request id. Tokens are
+// tagged with a unique id, since clients may send the same request id twice
+pub type CancellationTokens = Arc<DashMap<String, (u64, CancellationToken)>>;
+
+static CANCELLATION_TOKENS_COUNT: AtomicU64 = AtomicU64::new(0);
+
+// Deployments in maintenance are answered with a 503 without
+// invoking their isolates, until the maintenance is disabled
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    // The 503 error page is sent when empty
+    body: Bytes,
+    content_type: Option<HeaderValue>,
+    // In seconds
+    retry_after: Option<u64>,
+}
+
+impl Maintenance {
+    pub fn to_response(&self) -> Result<HyperResponse<Body>> {
+        let mut response = if self.body.is_empty() {
+            error_page(503, PAGE_503)?
+        } else {
+            let mut builder = HyperResponse::builder().status(503);
+
+            if let Some(content_type) = &self.content_type {
+                builder = builder.header(CONTENT_TYPE, content_type);
+            }
+
+            builder.body(self.body.clone().into())?
+        };
+
+        if let Some(retry_after) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.into());
+        }
+
+        Ok(response)
+    }
+}
+
+// By deployment id
+pub type Maintenances = Arc<DashMap<String, Maintenance>>;
+
+// Requests of paused deployments wait for them to be resumed, instead of
+// failing fast like with a maintenance. Requests still waiting at the
+// deadline of the pause, or arriving after it, are answered with a 503
+#[derive(Debug, Clone)]
+pub struct Pause {
+    resumed: CancellationToken,
+    // Fixed when pausing the deployment, and not when each request arrives
+    deadline: Instant,
+    waiting: Arc<AtomicUsize>,
+}
+
+// Decrements the waiting requests, even if the request is dropped while waiting
+struct WaitingRequest<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingRequest<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Pause {
+    // Whether the deployment has been resumed in time. Requests above
+    // `max_waiting` are rejected right away instead of waiting
+    pub async fn wait(&self, shutdown: &CancellationToken, max_waiting: usize) -> bool {
+        if self.resumed.is_cancelled() {
+            return true;
+        }
+
+        if Instant::now() >= self.deadline {
+            return false;
+        }
+
+        let _waiting = WaitingRequest(&self.waiting);
+
+        if self.waiting.fetch_add(1, Ordering::Relaxed) >= max_waiting {
+            return false;
+        }
+
+        tokio::select! {
+            _ = self.resumed.cancelled() => true,
+            _ = shutdown.cancelled() => false,
+            _ = tokio::time::sleep_until(self.deadline.into()) => false,
+        }
+    }
+}
+
+// By deployment id
+pub type Pauses = Arc<DashMap<String, Pause>>;
+
+// Count a request as in-flight until this guard is dropped, which
+// happens when the response (or its stream) has been fully sent
+pub struct InFlightRequest {
+    deployment_id: String,
+    stats: DeploymentsStats,
+    cancellation: Option<(String, u64, CancellationTokens)>,
+    worker_request: Option<WorkerRequest>,
+}
+
+impl InFlightRequest {
+    pub fn new(deployment_id: String, stats: DeploymentsStats) -> Self {
+        stats
+            .entry(deployment_id.clone())
+            .or_default()
+            .in_flight_requests += 1;
+
+        Self {
+            deployment_id,
+            stats,
+            cancellation: None,
+            worker_request: None,
+        }
+    }
+
+    // Counted in the in-flight requests of its isolate too
+    pub fn worker_request(&mut self, worker_request: WorkerRequest) {
+        self.worker_request = Some(worker_request);
+    }
+
+    // Allow cancelling the request using its id until it has completed. When
+    // another in-flight request already uses the same id, it stays the one cancelled
+    pub fn cancellable(
+        &mut self,
+        request_id: String,
+        cancellation_tokens: CancellationTokens,
+    ) -> CancellationToken {
+        let cancellation_token = CancellationToken::new();
+        let id = CANCELLATION_TOKENS_COUNT.fetch_add(1, Ordering::Relaxed);
+
+        cancellation_tokens
+            .entry(request_id.clone())
+            .or_insert_with(|| (id, cancellation_token.clone()));
+        self.cancellation = Some((request_id, id, cancellation_tokens));
+
+        cancellation_token
+    }
+}
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        if let Some(mut stats) = self.stats.get_mut(&self.deployment_id) {
+            stats.in_flight_requests = stats.in_flight_requests.saturating_sub(1);
+        }
+
+        // Only remove the token of this request, not one of another request using the same id
+        if let Some((request_id, id, cancellation_tokens)) = self.cancellation.take() {
+            cancellation_tokens.remove_if(&request_id, |_, (token_id, _)| *token_id == id);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeploymentIsolates {
+    isolates: usize,
+    warm_isolates: usize,
+    in_flight_requests: usize,
+    memory_usage: usize,
+}
+
+#[derive(Serialize)]
+struct Isolates {
+    isolates: usize,
+    in_flight_requests: usize,
+    memory_usage: usize,
+    deployments: HashMap<String, DeploymentIsolates>,
+}
+
+#[derive(Serialize)]
+struct DeploymentBundle {
+    hash: String,
+    size: usize,
+    compilation_time_ms: f64,
+    evaluation_time_ms: f64,
+    code_cache: bool,
+    modules: usize,
+    source_map: bool,
+}
+
+impl From<&BundleMetadata> for DeploymentBundle {
+    fn from(bundle: &BundleMetadata) -> Self {
+        Self {
+            hash: bundle.hash.clone(),
+            size: bundle.size,
+            compilation_time_ms: bundle.compilation_time.as_secs_f64() * 1000.0,
+            evaluation_time_ms: bundle.evaluation_time.as_secs_f64() * 1000.0,
+            code_cache: bundle.code_cache,
+            modules: bundle.modules,
+            source_map: bundle.has_source_map,
+        }
+    }
+}
+
+fn get_bundles(stats: &DeploymentsStats) -> HashMap<String, DeploymentBundle> {
+    stats
+        .iter()
+        .filter_map(|entry| {
+            let (deployment_id, stats) = entry.pair();
+
+            stats
+                .bundle
+                .as_ref()
+                .map(|bundle| (deployment_id.clone(), DeploymentBundle::from(bundle)))
+        })
+        .collect()
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<HyperResponse<Body>> {
+    Ok(HyperResponse::builder()
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(value)?.into())?)
+}
+
+fn get_isolates(workers: &Workers, stats: &DeploymentsStats) -> Isolates {
+    let mut deployments = HashMap::new();
+
+    for entry in stats.iter() {
+        let (deployment_id, stats) = entry.pair();
+        let isolates = workers.get(deployment_id).map_or(0, |pool| pool.len());
+
+        if isolates == 0 && stats.in_flight_requests == 0 {
+            continue;
+        }
+
+        deployments.insert(
+            deployment_id.clone(),
+            DeploymentIsolates {
+                isolates,
+                // Isolates are warm when they are ready but not processing any request
+                warm_isolates: if stats.in_flight_requests == 0 {
+                    isolates
+                } else {
+                    0
+                },
+                in_flight_requests: stats.in_flight_requests,
+                memory_usage: if isolates > 0 { stats.memory_usage } else { 0 },
+            },
+        );
+    }
+
+    Isolates {
+        isolates: deployments
+            .values()
+            .map(|deployment| deployment.isolates)
+            .sum(),
+        in_flight_requests: deployments
+            .values()
+            .map(|deployment| deployment.in_flight_requests)
+            .sum(),
+        memory_usage: deployments
+            .values()
+            .map(|deployment| deployment.memory_usage)
+            .sum(),
+        deployments,
+    }
+}
+
+pub fn is_management_request(req: &HyperRequest<Body>, options: &ServerlessOptions) -> bool {
+    options.management_token.is_some() && req.uri().path().starts_with(MANAGEMENT_PREFIX)
+}
+
+pub fn is_health_request(req: &HyperRequest<Body>) -> bool {
+    req.uri().path() == HEALTH_PATH
+}
+
+// Load balancers should stop routing requests to this instance as soon as it starts draining
+pub fn handle_health_request(options: &ServerlessOptions) -> Result<HyperResponse<Body>> {
+    if options.shutdown.is_cancelled() {
+        return Ok(HyperResponse::builder()
+            .status(503)
+            .body("Shutting down".into())?);
+    }
+
+    Ok(HyperResponse::new("OK".into()))
+}
+
+// The isolate stops handling the request, and the client
+// receives an error or the end of the stream
+fn cancel_request(
+    cancellation_tokens: &CancellationTokens,
+    request_id: &str,
+) -> Result<HyperResponse<Body>> {
+    match cancellation_tokens.get(request_id) {
+        Some(entry) => {
+            let (_, cancellation_token) = entry.value();
+            cancellation_token.cancel();
+
+            Ok(HyperResponse::builder().status(204).body(Body::empty())?)
+        }
+        None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+    }
+}
+
+fn get_query_parameter<'a>(req: &'a HyperRequest<Body>, name: &str) -> Option<&'a str> {
+    req.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    })
+}
+
+// The body and content type of the request are sent as the 503 response,
+// with an optional `Retry-After` header (`?retry_after=<seconds>`)
+async fn enable_maintenance(
+    req: HyperRequest<Body>,
+    deployments: &Deployments,
+    maintenances: &Maintenances,
+    deployment_id: &str,
+) -> Result<HyperResponse<Body>> {
+    if !deployments
+        .iter()
+        .any(|deployment| deployment.id == deployment_id)
+    {
+        return Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?);
+    }
+
+    let retry_after = match get_query_parameter(&req, "retry_after").map(str::parse::<u64>) {
+        Some(Ok(retry_after)) => Some(retry_after),
+        Some(Err(_)) => {
+            return Ok(HyperResponse::builder()
+                .status(400)
+                .body("Invalid retry_after".into())?)
+        }
+        None => None,
+    };
+    let content_type = req.headers().get(CONTENT_TYPE).cloned();
+    let body = body::to_bytes(req.into_body()).await?;
+
+    maintenances.insert(
+        deployment_id.to_string(),
+        Maintenance {
+            body,
+            content_type,
+            retry_after,
+        },
+    );
+
+    Ok(HyperResponse::builder().status(204).body(Body::empty())?)
+}
+
+fn disable_maintenance(
+    maintenances: &Maintenances,
+    deployment_id: &str,
+) -> Result<HyperResponse<Body>> {
+    match maintenances.remove(deployment_id) {
+        Some(_) => Ok(HyperResponse::builder().status(204).body(Body::empty())?),
+        None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+    }
+}
+
+// Requests wait for `?timeout=<seconds>`, or the default pause timeout. Pausing
+// an already paused deployment keeps its waiting requests paused, and moves
+// the deadline of the requests arriving next
+fn pause_deployment(
+    req: &HyperRequest<Body>,
+    options: &ServerlessOptions,
+    deployments: &Deployments,
+    pauses: &Pauses,
+    deployment_id: &str,
+) -> Result<HyperResponse<Body>> {
+    if !deployments
+        .iter()
+        .any(|deployment| deployment.id == deployment_id)
+    {
+        return Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?);
+    }
+
+    let timeout = match get_query_parameter(req, "timeout").map(str::parse::<u64>) {
+        Some(Ok(timeout)) => Duration::from_secs(timeout),
+        Some(Err(_)) => {
+            return Ok(HyperResponse::builder()
+                .status(400)
+                .body("Invalid timeout".into())?)
+        }
+        None => options.pause_timeout,
+    };
+
+    let deadline = Instant::now() + timeout;
+
+    pauses
+        .entry(deployment_id.to_string())
+        .and_modify(|pause| pause.deadline = deadline)
+        .or_insert_with(|| Pause {
+            resumed: CancellationToken::new(),
+            deadline,
+            waiting: Arc::new(AtomicUsize::new(0)),
+        });
+
+    Ok(HyperResponse::builder().status(204).body(Body::empty())?)
+}
+
+// The waiting requests are all handled right away
+fn resume_deployment(pauses: &Pauses, deployment_id: &str) -> Result<HyperResponse<Body>> {
+    match pauses.remove(deployment_id) {
+        Some((_, pause)) => {
+            pause.resumed.cancel();
+
+            Ok(HyperResponse::builder().status(204).body(Body::empty())?)
+        }
+        None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+    }
+}
+
+// Stream the logs of a deployment as JSON text frames, optionally
+// filtered using a comma-separated list of levels (`?level=warn,error`)
+fn tail_logs(
+    req: &mut HyperRequest<Body>,
+    options: &ServerlessOptions,
+    log_tail: &LogTail,
+    deployment_id: &str,
+) -> Result<HyperResponse<Body>> {
+    let levels = get_query_parameter(req, "level").map(|levels| {
+        levels
+            .split(',')
+            .map(|level| level.to_string())
+            .collect::<Vec<_>>()
+    });
+
+    let (response, on_upgrade) = match accept_host_websocket(req) {
+        Some(websocket) => websocket,
+        None => {
+            return Ok(HyperResponse::builder()
+                .status(400)
+                .body("Expected a WebSocket upgrade".into())?)
+        }
+    };
+
+    // Subscribe before the upgrade, to not miss the logs sent in between
+    let mut logs = log_tail.subscribe();
+    let deployment_id = deployment_id.to_string();
+    let shutdown = options.shutdown.clone();
+
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(error) => {
+                error!("Error while upgrading logs WebSocket: {}", error);
+                return;
+            }
+        };
+
+        let mut stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                log = logs.recv() => {
+                    let log = match log {
+                        Ok(log) => log,
+                        // The oldest logs have been dropped
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    };
+
+                    if log.deployment != deployment_id
+                        || levels.as_ref().map_or(false, |levels| !levels.contains(&log.level))
+                    {
+                        continue;
+                    }
+
+                    let message = match serde_json::to_string(&log) {
+                        Ok(message) => message,
+                        Err(_) => continue,
+                    };
+
+                    if stream.send(Message::Text(message)).await.is_err() {
+                        break;
+                    }
+                }
+                message = stream.next() => match message {
+                    // Closed by the client, or errored
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    // Pings are answered by tungstenite
+                    Some(Ok(_)) => continue,
+                },
+            }
+        }
+
+        stream.close(None).await.unwrap_or(());
+    });
+
+    Ok(response)
+}
+
+// The current certificates are kept when any of the new ones is invalid
+fn reload_certificates(tls: &CertificateStore) -> Result<HyperResponse<Body>> {
+    match tls.reload() {
+        Ok(()) => Ok(HyperResponse::builder().status(204).body(Body::empty())?),
+        Err(error) => {
+            error!("Error while reloading TLS certificates: {}", error);
+
+            Ok(HyperResponse::builder()
+                .status(500)
+                .body(error.to_string().into())?)
+        }
+    }
+}
+
+// Replay a request serialized as JSON in a throwaway isolate, responding
+// with its response, logs and timings
+async fn replay(
+    req: HyperRequest<Body>,
+    options: &Arc<ServerlessOptions>,
+    deployments: &Deployments,
+    source_maps: &SourceMaps,
+    bundles: &Bundles,
+    deployment_id: &str,
+) -> Result<HyperResponse<Body>> {
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|content_length| content_length.to_str().ok())
+        .and_then(|content_length| content_length.parse::<usize>().ok());
+
+    if content_length.is_some_and(|content_length| content_length > REPLAY_MAX_PAYLOAD_SIZE) {
+        return error_page(413, PAGE_413);
+    }
+
+    let body = match read_body(
+        req.into_body(),
+        None,
+        Some(REPLAY_MAX_PAYLOAD_SIZE),
+        None,
+        None,
+    )
+    .await
+    {
+        Ok((body, _)) => body,
+        Err(error) if error.is::<BodyTooLarge>() => return error_page(413, PAGE_413),
+        Err(error) => return Err(error),
+    };
+    let request = match serde_json::from_slice::<ReplayedRequest>(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(ReplayedRequest::into_request)
+    {
+        Ok(request) => request,
+        Err(error) => {
+            return Ok(HyperResponse::builder()
+                .status(400)
+                .body(format!("Invalid request: {error}").into())?)
+        }
+    };
+
+    match replay_request(
+        options,
+        deployments,
+        source_maps,
+        bundles,
+        deployment_id,
+        request,
+    )
+    .await?
+    {
+        Some(result) => json_response(&result),
+        None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_management_request(
+    mut req: HyperRequest<Body>,
+    options: &Arc<ServerlessOptions>,
+    deployments: &Deployments,
+    source_maps: &SourceMaps,
+    bundles: &Bundles,
+    workers: &Workers,
+    stats: &DeploymentsStats,
+    cancellation_tokens: &CancellationTokens,
+    maintenances: &Maintenances,
+    pauses: &Pauses,
+    log_tail: &LogTail,
+) -> Result<HyperResponse<Body>> {
+    let authorized = match (&options.management_token, req.headers().get(AUTHORIZATION)) {
+        (Some(token), Some(authorization)) => {
+            // Compared in constant time, to not leak the token through the response times
+            authorization
+                .as_bytes()
+                .ct_eq(format!("Bearer {token}").as_bytes())
+                .into()
+        }
+        _ => false,
+    };
+
+    if !authorized {
+        return Ok(HyperResponse::builder().status(401).body(Body::empty())?);
+    }
+
+    let path = req.uri().path()[MANAGEMENT_PREFIX.len()..].to_string();
+    let path = path.as_str();
+
+    if let Some(deployment_id) = path
+        .strip_prefix("deployments/")
+        .and_then(|path| path.strip_suffix("/maintenance"))
+    {
+        return match *req.method() {
+            Method::PUT => enable_maintenance(req, deployments, maintenances, deployment_id).await,
+            Method::DELETE => disable_maintenance(maintenances, deployment_id),
+            _ => Ok(HyperResponse::builder().status(405).body(Body::empty())?),
+        };
+    }
+
+    if let Some(deployment_id) = path
+        .strip_prefix("deployments/")
+        .and_then(|path| path.strip_suffix("/pause"))
+    {
+        return match *req.method() {
+            Method::PUT => pause_deployment(&req, options, deployments, pauses, deployment_id),
+            Method::DELETE => resume_deployment(pauses, deployment_id),
+            _ => Ok(HyperResponse::builder().status(405).body(Body::empty())?),
+        };
+    }
+
+    if let Some(deployment_id) = path
+        .strip_prefix("deployments/")
+        .and_then(|path| path.strip_suffix("/replay"))
+    {
+        return match *req.method() {
+            Method::POST => {
+                replay(
+                    req,
+                    options,
+                    deployments,
+                    source_maps,
+                    bundles,
+                    deployment_id,
+                )
+                .await
+            }
+            _ => Ok(HyperResponse::builder().status(405).body(Body::empty())?),
+        };
+    }
+
+    if path == "certificates/reload" {
+        return match (req.method(), &options.tls) {
+            (&Method::POST, Some(tls)) => reload_certificates(tls),
+            (&Method::POST, None) => {
+                Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?)
+            }
+            _ => Ok(HyperResponse::builder().status(405).body(Body::empty())?),
+        };
+    }
+
+    if req.method() == Method::DELETE {
+        return match path.strip_prefix("requests/") {
+            Some(request_id) => cancel_request(cancellation_tokens, request_id),
+            None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+        };
+    }
+
+    if let Some(deployment_id) = path
+        .strip_prefix("deployments/")
+        .and_then(|path| path.strip_suffix("/logs"))
+    {
+        return tail_logs(&mut req, options, log_tail, deployment_id);
+    }
+
+    match path {
+        "isolates" => json_response(&get_isolates(workers, stats)),
+        "deployments" => json_response(&get_bundles(stats)),
+        path => match path
+            .strip_prefix("deployments/")
+            .and_then(|deployment_id| get_bundles(stats).remove(deployment_id))
+        {
+            Some(bundle) => json_response(&bundle),
+            None => Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?),
+        },
+    }
+}
@@ -0,0 +1,995 @@
+use crate::{
+    access_log::{
+        default_access_log_formatters, parse_sample_rate, AccessLogFormatter, AccessLogFormatters,
+        AccessLogSampling,
+    },
+    deployments::{
+        get_error_schema,
+        loader::{DeploymentLoader, FilesystemLoader, HttpLoader},
+    },
+    error_reporter::{ErrorReporter, NoopErrorReporter, WebhookErrorReporter},
+    forwarded::parse_trusted_proxies,
+    host_routing::{parse_host_routes, HostRoutes},
+    tls::CertificateStore,
+};
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use lagon_runtime_http::BodySpilling;
+use lagon_runtime_isolate::CodeCache;
+use lagon_runtime_utils::{
+    assets::AssetsCacheControl,
+    error_page::{ErrorSchema, TimeoutResponse},
+    response::{EmptyResponsePolicy, StreamBuffering, Utf8Policy},
+};
+use lagon_runtime_v8_utils::{parse_v8_flags, V8FlagScope};
+use std::{collections::HashMap, env, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+// Path + query string, in bytes
+const DEFAULT_MAX_URL_LENGTH: usize = 8192;
+const DEFAULT_ISOLATES_IDLE_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_WEBSOCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_WEBSOCKET_MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_STREAM_BUFFER_DURATION: Duration = Duration::from_millis(10);
+const DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS: u32 = 100;
+const DEFAULT_HTTP2_MAX_PENDING_RESET_STREAMS: usize = 20;
+const DEFAULT_STREAM_MAX_CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_WAIT_UNTIL_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_PAUSE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_PAUSED_REQUESTS: usize = 1000;
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+const DEFAULT_BUNDLE_LOAD_FAILURE_TTL: Duration = Duration::from_secs(5);
+
+pub struct ServerlessOptions {
+    pub max_url_length: usize,
+    // Required to access the management endpoints, which are disabled when unset
+    pub management_token: Option<String>,
+    // Isolates idle for longer than this are evicted, while
+    // keeping at least `min_warm_isolates` per deployment
+    pub isolates_idle_ttl: Duration,
+    pub min_warm_isolates: usize,
+    // Upgraded WebSockets are closed when no message has been sent
+    // or received for `websocket_idle_timeout`, or after `websocket_max_duration`
+    pub websocket_idle_timeout: Duration,
+    pub websocket_max_duration: Duration,
+    // Small streams completing quickly are sent with a Content-Length
+    // instead of chunked transfer encoding. Disabled when unset
+    pub stream_buffering: Option<StreamBuffering>,
+    // Larger chunks streamed by functions are split, to flush
+    // them to the client incrementally. Disabled when unset
+    pub stream_max_chunk_size: Option<usize>,
+    // Streams sending more chunks are aborted, so functions can't stream
+    // a huge number of tiny chunks. Unlimited when unset
+    pub stream_max_chunks: Option<usize>,
+    // Request bodies larger than the threshold are written to a temporary
+    // file instead of being buffered in memory. Disabled when unset
+    pub body_spilling: Option<BodySpilling>,
+    // Requests with a larger body are rejected with a 413, in bytes. Also enforced
+    // while functions read bodies at once, e.g fetch() responses. Unlimited when unset
+    pub max_body_size: Option<usize>,
+    // Trusted proxies can raise the body size limit of a request up to this
+    // limit, with the `X-Lagon-Max-Body-Size` header. Disabled when unset
+    pub max_body_size_override: Option<usize>,
+    // Requests whose body stalls for longer than this, waiting for the next chunk,
+    // are rejected with a 408. Unlike the timeouts of the deployments, it doesn't
+    // include the execution of the function. Disabled when unset
+    pub body_read_timeout: Option<Duration>,
+    // Requests whose whole body isn't received within this are rejected with a 408
+    // too, even when it never stalls (e.g a byte sent at a time). Disabled when unset
+    pub body_total_timeout: Option<Duration>,
+    // Functions errors and isolates panics are forwarded to this reporter
+    pub error_reporter: Arc<dyn ErrorReporter>,
+    // Where the bundles of the deployments are loaded from when creating their
+    // isolates. Defaults to the deployments folder written by the downloader
+    pub deployment_loader: Arc<dyn DeploymentLoader>,
+    // Bundles failing to load or compile are not retried for this long,
+    // requests being rejected with a 503 in the meantime
+    pub bundle_load_failure_ttl: Duration,
+    // How long waitUntil() promises can keep running after the response has been
+    // sent. Should be lower than `isolates_idle_ttl` to not evict busy isolates
+    pub wait_until_timeout: Duration,
+    // How long the requests of paused deployments wait for them to be
+    // resumed, unless another timeout is set when pausing them
+    pub pause_timeout: Duration,
+    // Requests of a paused deployment above this count are answered
+    // with a 503 right away, instead of waiting for it to be resumed
+    pub max_paused_requests: usize,
+    // Requests waiting for async operations (fetch(), timers, etc) for longer
+    // than this in total time out, even when below the total timeout of their
+    // deployment. Disabled when unset
+    pub async_timeout: Option<Duration>,
+    // Cache-Control headers sent with the deployments assets
+    pub assets_cache_control: AssetsCacheControl,
+    // Once cancelled, the health requests fail right away. After `shutdown_drain_delay`,
+    // new connections are refused and in-flight requests have `shutdown_grace_period`
+    // to complete before the server exits
+    pub shutdown: CancellationToken,
+    pub shutdown_drain_delay: Duration,
+    pub shutdown_grace_period: Duration,
+    // Forwarded, X-Real-Ip, X-Forwarded-For and X-Forwarded-Proto are
+    // only honored when the peer is one of these proxies
+    pub trusted_proxies: Vec<IpNet>,
+    // New connections of a client IP over this limit are refused. Disabled when unset
+    pub max_connections_per_ip: Option<usize>,
+    // Trusted proxies forward the connections of many clients,
+    // so they are not limited unless this is enabled
+    pub limit_trusted_proxies_connections: bool,
+    // Connections not handling a request are closed after not reading or
+    // writing anything for this long. Disabled when unset
+    pub idle_connection_timeout: Option<Duration>,
+    // Limits of request.json(), using the isolate's defaults when unset
+    pub json_max_size: Option<usize>,
+    pub json_max_depth: Option<usize>,
+    // Async operations a single isolate can have pending at the same time,
+    // after which new ones are rejected. Unlimited when unset
+    pub max_pending_ops: Option<usize>,
+    // Hosts whose fetch() calls are sent over a Unix socket (e.g to reach
+    // sidecar services), by function id. Other functions can't use them
+    pub fetch_unix_sockets: HashMap<String, HashMap<String, PathBuf>>,
+    // Hostnames without a deployment are served by the deployment of the
+    // hostname they are routed to, e.g to serve wildcard subdomains
+    pub host_routes: HostRoutes,
+    // Connections are accepted over TLS when set, serving the certificate
+    // of the SNI of each handshake
+    pub tls: Option<Arc<CertificateStore>>,
+    // UDP address of the HTTP/3 server, advertised using the Alt-Svc
+    // header. Requires `tls`, and disabled when unset
+    pub http3_listen_addr: Option<SocketAddr>,
+    // Streams opened by an HTTP/2 client over this limit wait for
+    // the previous ones to complete
+    pub http2_max_concurrent_streams: u32,
+    // HTTP/2 connections resetting more streams before they are handled are
+    // closed (a GOAWAY is sent), to mitigate the Rapid Reset attack (CVE-2023-44487)
+    pub http2_max_pending_reset_streams: usize,
+    // Limits of logged objects, using the isolate's defaults when unset
+    pub console_max_depth: Option<usize>,
+    pub console_max_length: Option<usize>,
+    pub console_max_string_length: Option<usize>,
+    pub console_max_output_size: Option<usize>,
+    // Per-isolate V8 flags of all the isolates
+    pub isolate_v8_flags: Vec<String>,
+    // Stores the V8 code caches of the bundles, to speed up
+    // the next cold starts. Disabled when unset
+    pub code_cache: Option<Arc<CodeCache>>,
+    // Requests over this limit are queued by their Priority
+    // header urgency. Disabled when unset
+    pub max_concurrent_requests: Option<usize>,
+    // Requests are shed with a 503 once this many are
+    // queued. The queue is unbounded when unset
+    pub max_queued_requests: Option<usize>,
+    // Cacheable responses of the functions are served from memory, up
+    // to this many responses. Disabled when unset
+    pub edge_cache_max_entries: Option<usize>,
+    // Responses of the fetch() calls are cached per deployment, up
+    // to this many responses. Disabled when unset
+    pub fetch_cache_max_entries: Option<usize>,
+    // Send a Content-Digest of the responses bodies, as a trailer when streamed
+    pub content_digest: bool,
+    // Formatters available to the deployments, by name. Includes `json` and `clf`
+    pub access_log_formatters: AccessLogFormatters,
+    // Used by the deployments without an access log format. Disabled when unset
+    pub access_log_format: Option<String>,
+    // Log all requests by default
+    pub access_log_sampling: AccessLogSampling,
+    // Used by the deployments without an error schema, when
+    // sending the error pages as JSON
+    pub error_schema: Option<ErrorSchema>,
+    // Served instead of the 502 error page when a function times out
+    pub timeout_response: Option<TimeoutResponse>,
+    // Check that the bodies of `text/*` responses are valid UTF-8. Disabled when unset
+    pub utf8_policy: Option<Utf8Policy>,
+    // Whether functions returning an empty response get a 200 or a 204
+    pub empty_response_policy: EmptyResponsePolicy,
+}
+
+impl Default for ServerlessOptions {
+    fn default() -> Self {
+        Self {
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            management_token: None,
+            isolates_idle_ttl: DEFAULT_ISOLATES_IDLE_TTL,
+            min_warm_isolates: 0,
+            websocket_idle_timeout: DEFAULT_WEBSOCKET_IDLE_TIMEOUT,
+            websocket_max_duration: DEFAULT_WEBSOCKET_MAX_DURATION,
+            stream_buffering: None,
+            stream_max_chunk_size: Some(DEFAULT_STREAM_MAX_CHUNK_SIZE),
+            stream_max_chunks: None,
+            body_spilling: None,
+            max_body_size: None,
+            max_body_size_override: None,
+            body_read_timeout: None,
+            body_total_timeout: None,
+            error_reporter: Arc::new(NoopErrorReporter),
+            deployment_loader: Arc::new(FilesystemLoader::default()),
+            bundle_load_failure_ttl: DEFAULT_BUNDLE_LOAD_FAILURE_TTL,
+            wait_until_timeout: DEFAULT_WAIT_UNTIL_TIMEOUT,
+            pause_timeout: DEFAULT_PAUSE_TIMEOUT,
+            max_paused_requests: DEFAULT_MAX_PAUSED_REQUESTS,
+            async_timeout: None,
+            assets_cache_control: AssetsCacheControl::default(),
+            shutdown: CancellationToken::new(),
+            shutdown_drain_delay: Duration::ZERO,
+            shutdown_grace_period: DEFAULT_SHUTDOWN_GRACE_PERIOD,
+            trusted_proxies: Vec::new(),
+            max_connections_per_ip: None,
+            limit_trusted_proxies_connections: false,
+            idle_connection_timeout: None,
+            json_max_size: None,
+            json_max_depth: None,
+            max_pending_ops: None,
+            fetch_unix_sockets: HashMap::new(),
+            host_routes: HostRoutes::default(),
+            tls: None,
+            http3_listen_addr: None,
+            http2_max_concurrent_streams: DEFAULT_HTTP2_MAX_CONCURRENT_STREAMS,
+            http2_max_pending_reset_streams: DEFAULT_HTTP2_MAX_PENDING_RESET_STREAMS,
+            console_max_depth: None,
+            console_max_length: None,
+            console_max_string_length: None,
+            console_max_output_size: None,
+            isolate_v8_flags: Vec::new(),
+            code_cache: None,
+            max_concurrent_requests: None,
+            max_queued_requests: None,
+            edge_cache_max_entries: None,
+            fetch_cache_max_entries: None,
+            content_digest: false,
+            access_log_formatters: default_access_log_formatters(),
+            access_log_format: None,
+            access_log_sampling: AccessLogSampling::default(),
+            error_schema: None,
+            timeout_response: None,
+            utf8_policy: None,
+            empty_response_policy: EmptyResponsePolicy::default(),
+        }
+    }
+}
+
+impl ServerlessOptions {
+    pub fn from_env() -> Result<Self> {
+        let mut options = Self::default();
+
+        if let Ok(max_url_length) = env::var("LAGON_MAX_URL_LENGTH") {
+            options = options.max_url_length(max_url_length.parse()?);
+        }
+
+        if let Ok(management_token) = env::var("LAGON_MANAGEMENT_TOKEN") {
+            if !management_token.is_empty() {
+                options = options.management_token(management_token);
+            }
+        }
+
+        if let Ok(isolates_cache_seconds) = env::var("LAGON_ISOLATES_CACHE_SECONDS") {
+            options =
+                options.isolates_idle_ttl(Duration::from_secs(isolates_cache_seconds.parse()?));
+        }
+
+        if let Ok(min_warm_isolates) = env::var("LAGON_MIN_WARM_ISOLATES") {
+            options = options.min_warm_isolates(min_warm_isolates.parse()?);
+        }
+
+        if let Ok(websocket_idle_seconds) = env::var("LAGON_WEBSOCKET_IDLE_SECONDS") {
+            options = options
+                .websocket_idle_timeout(Duration::from_secs(websocket_idle_seconds.parse()?));
+        }
+
+        if let Ok(websocket_max_seconds) = env::var("LAGON_WEBSOCKET_MAX_SECONDS") {
+            options =
+                options.websocket_max_duration(Duration::from_secs(websocket_max_seconds.parse()?));
+        }
+
+        if let Ok(stream_buffer_bytes) = env::var("LAGON_STREAM_BUFFER_BYTES") {
+            let stream_buffer_bytes = stream_buffer_bytes.parse()?;
+            let stream_buffer_duration = match env::var("LAGON_STREAM_BUFFER_MS") {
+                Ok(stream_buffer_ms) => Duration::from_millis(stream_buffer_ms.parse()?),
+                Err(_) => DEFAULT_STREAM_BUFFER_DURATION,
+            };
+
+            if stream_buffer_bytes > 0 {
+                options = options.stream_buffering(stream_buffer_bytes, stream_buffer_duration);
+            }
+        }
+
+        if let Ok(stream_max_chunk_bytes) = env::var("LAGON_STREAM_MAX_CHUNK_BYTES") {
+            options = options.stream_max_chunk_size(match stream_max_chunk_bytes.parse()? {
+                0 => None,
+                stream_max_chunk_bytes => Some(stream_max_chunk_bytes),
+            });
+        }
+
+        if let Ok(stream_max_chunks) = env::var("LAGON_STREAM_MAX_CHUNKS") {
+            options = options.stream_max_chunks(match stream_max_chunks.parse()? {
+                0 => None,
+                stream_max_chunks => Some(stream_max_chunks),
+            });
+        }
+
+        if let Ok(body_spill_bytes) = env::var("LAGON_BODY_SPILL_BYTES") {
+            let body_spill_bytes = body_spill_bytes.parse()?;
+
+            if body_spill_bytes > 0 {
+                let mut body_spilling = BodySpilling::new(body_spill_bytes);
+
+                if let Ok(body_spill_dir) = env::var("LAGON_BODY_SPILL_DIR") {
+                    if !body_spill_dir.is_empty() {
+                        body_spilling = body_spilling.dir(PathBuf::from(body_spill_dir));
+                    }
+                }
+
+                options = options.body_spilling(body_spilling);
+            }
+        }
+
+        if let Ok(max_body_bytes) = env::var("LAGON_MAX_BODY_BYTES") {
+            let max_body_bytes = max_body_bytes.parse()?;
+
+            if max_body_bytes > 0 {
+                options = options.max_body_size(max_body_bytes);
+            }
+        }
+
+        if let Ok(max_body_override_bytes) = env::var("LAGON_MAX_BODY_OVERRIDE_BYTES") {
+            let max_body_override_bytes = max_body_override_bytes.parse()?;
+
+            if max_body_override_bytes > 0 {
+                options = options.max_body_size_override(max_body_override_bytes);
+            }
+        }
+
+        if let Ok(body_read_timeout_ms) = env::var("LAGON_BODY_READ_TIMEOUT_MS") {
+            let body_read_timeout_ms = body_read_timeout_ms.parse()?;
+
+            if body_read_timeout_ms > 0 {
+                options = options.body_read_timeout(Duration::from_millis(body_read_timeout_ms));
+            }
+        }
+
+        if let Ok(body_total_timeout_ms) = env::var("LAGON_BODY_TOTAL_TIMEOUT_MS") {
+            let body_total_timeout_ms = body_total_timeout_ms.parse()?;
+
+            if body_total_timeout_ms > 0 {
+                options = options.body_total_timeout(Duration::from_millis(body_total_timeout_ms));
+            }
+        }
+
+        if let Ok(error_webhook_url) = env::var("LAGON_ERROR_WEBHOOK_URL") {
+            if !error_webhook_url.is_empty() {
+                options =
+                    options.error_reporter(Arc::new(WebhookErrorReporter::new(error_webhook_url)));
+            }
+        }
+
+        if let Ok(deployment_loader_url) = env::var("LAGON_DEPLOYMENT_LOADER_URL") {
+            if !deployment_loader_url.is_empty() {
+                options =
+                    options.deployment_loader(Arc::new(HttpLoader::new(deployment_loader_url)));
+            }
+        }
+
+        if let Ok(bundle_load_failure_seconds) = env::var("LAGON_BUNDLE_LOAD_FAILURE_SECONDS") {
+            options = options
+                .bundle_load_failure_ttl(Duration::from_secs(bundle_load_failure_seconds.parse()?));
+        }
+
+        if let Ok(wait_until_seconds) = env::var("LAGON_WAIT_UNTIL_SECONDS") {
+            options = options.wait_until_timeout(Duration::from_secs(wait_until_seconds.parse()?));
+        }
+
+        if let Ok(pause_seconds) = env::var("LAGON_PAUSE_SECONDS") {
+            options = options.pause_timeout(Duration::from_secs(pause_seconds.parse()?));
+        }
+
+        if let Ok(max_paused_requests) = env::var("LAGON_MAX_PAUSED_REQUESTS") {
+            options = options.max_paused_requests(max_paused_requests.parse()?);
+        }
+
+        if let Ok(async_timeout_ms) = env::var("LAGON_ASYNC_TIMEOUT_MS") {
+            let async_timeout_ms = async_timeout_ms.parse()?;
+
+            if async_timeout_ms > 0 {
+                options = options.async_timeout(Duration::from_millis(async_timeout_ms));
+            }
+        }
+
+        // An empty value disables the header
+        let cache_control = |value: String| Some(value).filter(|value| !value.is_empty());
+        let mut assets_cache_control = options.assets_cache_control.clone();
+
+        if let Ok(hashed) = env::var("LAGON_ASSETS_CACHE_CONTROL_HASHED") {
+            assets_cache_control = assets_cache_control.hashed(cache_control(hashed));
+        }
+
+        if let Ok(html) = env::var("LAGON_ASSETS_CACHE_CONTROL_HTML") {
+            assets_cache_control = assets_cache_control.html(cache_control(html));
+        }
+
+        if let Ok(default) = env::var("LAGON_ASSETS_CACHE_CONTROL") {
+            assets_cache_control =
+                assets_cache_control.default_cache_control(cache_control(default));
+        }
+
+        // Formatted as `pattern=value;pattern=value`
+        if let Ok(overrides) = env::var("LAGON_ASSETS_CACHE_CONTROL_OVERRIDES") {
+            for value in overrides.split(';').filter(|value| !value.is_empty()) {
+                let (pattern, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Invalid assets Cache-Control override: {}", value))?;
+
+                assets_cache_control = assets_cache_control
+                    .override_pattern(pattern.trim().into(), value.trim().into());
+            }
+        }
+
+        options = options.assets_cache_control(assets_cache_control);
+
+        if let Ok(shutdown_drain_delay_seconds) = env::var("LAGON_SHUTDOWN_DRAIN_DELAY_SECONDS") {
+            options = options
+                .shutdown_drain_delay(Duration::from_secs(shutdown_drain_delay_seconds.parse()?));
+        }
+
+        if let Ok(shutdown_grace_seconds) = env::var("LAGON_SHUTDOWN_GRACE_SECONDS") {
+            options =
+                options.shutdown_grace_period(Duration::from_secs(shutdown_grace_seconds.parse()?));
+        }
+
+        if let Ok(trusted_proxies) = env::var("LAGON_TRUSTED_PROXIES") {
+            options = options.trusted_proxies(parse_trusted_proxies(&trusted_proxies)?);
+        }
+
+        if let Ok(max_connections_per_ip) = env::var("LAGON_MAX_CONNECTIONS_PER_IP") {
+            let max_connections_per_ip = max_connections_per_ip.parse()?;
+
+            if max_connections_per_ip > 0 {
+                options = options.max_connections_per_ip(max_connections_per_ip);
+            }
+        }
+
+        if let Ok(limit_trusted_proxies_connections) =
+            env::var("LAGON_LIMIT_TRUSTED_PROXIES_CONNECTIONS")
+        {
+            options = options
+                .limit_trusted_proxies_connections(limit_trusted_proxies_connections.parse()?);
+        }
+
+        if let Ok(idle_connection_seconds) = env::var("LAGON_IDLE_CONNECTION_SECONDS") {
+            let idle_connection_seconds = idle_connection_seconds.parse()?;
+
+            if idle_connection_seconds > 0 {
+                options =
+                    options.idle_connection_timeout(Duration::from_secs(idle_connection_seconds));
+            }
+        }
+
+        if let Ok(json_max_bytes) = env::var("LAGON_JSON_MAX_BYTES") {
+            options = options.json_max_size(json_max_bytes.parse()?);
+        }
+
+        if let Ok(json_max_depth) = env::var("LAGON_JSON_MAX_DEPTH") {
+            options = options.json_max_depth(json_max_depth.parse()?);
+        }
+
+        if let Ok(max_pending_ops) = env::var("LAGON_MAX_PENDING_OPS") {
+            let max_pending_ops = max_pending_ops.parse()?;
+
+            if max_pending_ops > 0 {
+                options = options.max_pending_ops(max_pending_ops);
+            }
+        }
+
+        // e.g function_id:sidecar=/run/sidecar.sock;...
+        if let Ok(unix_sockets) = env::var("LAGON_FETCH_UNIX_SOCKETS") {
+            for value in unix_sockets.split(';').filter(|value| !value.is_empty()) {
+                let (function_id, host, path) = value
+                    .split_once(':')
+                    .and_then(|(function_id, mapping)| {
+                        let (host, path) = mapping.split_once('=')?;
+                        Some((function_id.trim(), host.trim(), path.trim()))
+                    })
+                    .ok_or_else(|| anyhow!("Invalid fetch Unix socket: {}", value))?;
+
+                options = options.fetch_unix_socket(function_id.into(), host.into(), path.into());
+            }
+        }
+
+        let mut host_routes = match env::var("LAGON_HOST_ROUTES") {
+            Ok(host_routes) => parse_host_routes(&host_routes)?,
+            Err(_) => HostRoutes::default(),
+        };
+
+        // e.g a landing page, instead of the 404 page
+        if let Ok(default_host_route) = env::var("LAGON_DEFAULT_HOST_ROUTE") {
+            if !default_host_route.is_empty() {
+                host_routes = host_routes.default_route(default_host_route);
+            }
+        }
+
+        options = options.host_routes(host_routes);
+
+        if let Ok(tls_certificates_dir) = env::var("LAGON_TLS_CERTIFICATES_DIR") {
+            if !tls_certificates_dir.is_empty() {
+                options = options.tls(Arc::new(CertificateStore::from_dir(
+                    tls_certificates_dir.into(),
+                )?));
+            }
+        }
+
+        if let Ok(http2_max_concurrent_streams) = env::var("LAGON_HTTP2_MAX_CONCURRENT_STREAMS") {
+            options = options.http2_max_concurrent_streams(http2_max_concurrent_streams.parse()?);
+        }
+
+        if let Ok(http2_max_pending_reset_streams) =
+            env::var("LAGON_HTTP2_MAX_PENDING_RESET_STREAMS")
+        {
+            options =
+                options.http2_max_pending_reset_streams(http2_max_pending_reset_streams.parse()?);
+        }
+
+        if let Ok(http3_listen_addr) = env::var("LAGON_HTTP3_LISTEN_ADDR") {
+            if !http3_listen_addr.is_empty() {
+                options = options.http3_listen_addr(http3_listen_addr.parse()?);
+            }
+        }
+
+        if let Ok(console_max_depth) = env::var("LAGON_CONSOLE_MAX_DEPTH") {
+            options = options.console_max_depth(console_max_depth.parse()?);
+        }
+
+        if let Ok(console_max_length) = env::var("LAGON_CONSOLE_MAX_LENGTH") {
+            options = options.console_max_length(console_max_length.parse()?);
+        }
+
+        if let Ok(console_max_string_length) = env::var("LAGON_CONSOLE_MAX_STRING_LENGTH") {
+            options = options.console_max_string_length(console_max_string_length.parse()?);
+        }
+
+        if let Ok(console_max_output_bytes) = env::var("LAGON_CONSOLE_MAX_OUTPUT_BYTES") {
+            options = options.console_max_output_size(console_max_output_bytes.parse()?);
+        }
+
+        // Separated by spaces, e.g `--stack-trace-limit=20`
+        if let Ok(isolate_v8_flags) = env::var("LAGON_ISOLATE_V8_FLAGS") {
+            let isolate_v8_flags = isolate_v8_flags
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>();
+
+            // Fail on startup instead of when creating the first isolate
+            parse_v8_flags(&isolate_v8_flags, V8FlagScope::Isolate)?;
+
+            options = options.isolate_v8_flags(isolate_v8_flags);
+        }
+
+        if let Ok(code_cache_dir) = env::var("LAGON_CODE_CACHE_DIR") {
+            if !code_cache_dir.is_empty() {
+                let mut code_cache = CodeCache::new(code_cache_dir);
+
+                if let Ok(code_cache_max_bytes) = env::var("LAGON_CODE_CACHE_MAX_BYTES") {
+                    let code_cache_max_bytes = code_cache_max_bytes.parse()?;
+
+                    if code_cache_max_bytes > 0 {
+                        code_cache = code_cache.max_size(code_cache_max_bytes);
+                    }
+                }
+
+                options = options.code_cache(Arc::new(code_cache));
+            }
+        }
+
+        if let Ok(max_concurrent_requests) = env::var("LAGON_MAX_CONCURRENT_REQUESTS") {
+            let max_concurrent_requests = max_concurrent_requests.parse()?;
+
+            if max_concurrent_requests > 0 {
+                options = options.max_concurrent_requests(max_concurrent_requests);
+            }
+        }
+
+        if let Ok(max_queued_requests) = env::var("LAGON_MAX_QUEUED_REQUESTS") {
+            if !max_queued_requests.is_empty() {
+                options = options.max_queued_requests(max_queued_requests.parse()?);
+            }
+        }
+
+        if let Ok(edge_cache_max_entries) = env::var("LAGON_EDGE_CACHE_MAX_ENTRIES") {
+            let edge_cache_max_entries = edge_cache_max_entries.parse()?;
+
+            if edge_cache_max_entries > 0 {
+                options = options.edge_cache_max_entries(edge_cache_max_entries);
+            }
+        }
+
+        if let Ok(fetch_cache_max_entries) = env::var("LAGON_FETCH_CACHE_MAX_ENTRIES") {
+            let fetch_cache_max_entries = fetch_cache_max_entries.parse()?;
+
+            if fetch_cache_max_entries > 0 {
+                options = options.fetch_cache_max_entries(fetch_cache_max_entries);
+            }
+        }
+
+        if let Ok(content_digest) = env::var("LAGON_CONTENT_DIGEST") {
+            options = options.content_digest(content_digest.parse()?);
+        }
+
+        if let Ok(access_log_format) = env::var("LAGON_ACCESS_LOG_FORMAT") {
+            if !access_log_format.is_empty() {
+                options = options.access_log_format(access_log_format);
+            }
+        }
+
+        if let Ok(access_log_sample_rate) = env::var("LAGON_ACCESS_LOG_SAMPLE_RATE") {
+            let mut access_log_sampling =
+                AccessLogSampling::new(parse_sample_rate(&access_log_sample_rate)?);
+
+            if let Ok(by_request_id) = env::var("LAGON_ACCESS_LOG_SAMPLE_BY_REQUEST_ID") {
+                access_log_sampling = access_log_sampling.by_request_id(by_request_id.parse()?);
+            }
+
+            options = options.access_log_sampling(access_log_sampling);
+        }
+
+        // Using the same format as the deployments' error schemas
+        if let Ok(error_schema) = env::var("LAGON_ERROR_SCHEMA") {
+            if !error_schema.is_empty() {
+                let error_schema = get_error_schema(Some(&error_schema))
+                    .ok_or_else(|| anyhow!("Invalid error schema: {}", error_schema))?;
+
+                options = options.error_schema(error_schema);
+            }
+        }
+
+        let mut timeout_response = None;
+
+        if let Ok(timeout_status) = env::var("LAGON_TIMEOUT_STATUS") {
+            if !timeout_status.is_empty() {
+                timeout_response
+                    .get_or_insert_with(TimeoutResponse::default)
+                    .status = match timeout_status.parse()? {
+                    status @ 100..=599 => status,
+                    status => return Err(anyhow!("Invalid timeout status: {}", status)),
+                };
+            }
+        }
+
+        if let Ok(timeout_content_type) = env::var("LAGON_TIMEOUT_CONTENT_TYPE") {
+            if !timeout_content_type.is_empty() {
+                timeout_response
+                    .get_or_insert_with(TimeoutResponse::default)
+                    .content_type = Some(timeout_content_type);
+            }
+        }
+
+        if let Ok(timeout_body) = env::var("LAGON_TIMEOUT_BODY") {
+            if !timeout_body.is_empty() {
+                timeout_response
+                    .get_or_insert_with(TimeoutResponse::default)
+                    .body = timeout_body;
+            }
+        }
+
+        if let Some(timeout_response) = timeout_response {
+            options = options.timeout_response(timeout_response);
+        }
+
+        if let Ok(utf8_policy) = env::var("LAGON_UTF8_POLICY") {
+            match utf8_policy.as_str() {
+                "replace" => options = options.utf8_policy(Utf8Policy::Replace),
+                "reject" => options = options.utf8_policy(Utf8Policy::Reject),
+                "" => {}
+                utf8_policy => return Err(anyhow!("Invalid UTF-8 policy: {}", utf8_policy)),
+            }
+        }
+
+        if let Ok(empty_response_policy) = env::var("LAGON_EMPTY_RESPONSE_POLICY") {
+            match empty_response_policy.as_str() {
+                "ok" | "" => {}
+                "no-content" => {
+                    options = options.empty_response_policy(EmptyResponsePolicy::NoContent)
+                }
+                empty_response_policy => {
+                    return Err(anyhow!(
+                        "Invalid empty response policy: {}",
+                        empty_response_policy
+                    ))
+                }
+            }
+        }
+
+        Ok(options)
+    }
+
+    pub fn max_url_length(mut self, max_url_length: usize) -> Self {
+        self.max_url_length = max_url_length;
+        self
+    }
+
+    pub fn management_token(mut self, management_token: String) -> Self {
+        self.management_token = Some(management_token);
+        self
+    }
+
+    pub fn isolates_idle_ttl(mut self, isolates_idle_ttl: Duration) -> Self {
+        self.isolates_idle_ttl = isolates_idle_ttl;
+        self
+    }
+
+    pub fn min_warm_isolates(mut self, min_warm_isolates: usize) -> Self {
+        self.min_warm_isolates = min_warm_isolates;
+        self
+    }
+
+    pub fn websocket_idle_timeout(mut self, websocket_idle_timeout: Duration) -> Self {
+        self.websocket_idle_timeout = websocket_idle_timeout;
+        self
+    }
+
+    pub fn websocket_max_duration(mut self, websocket_max_duration: Duration) -> Self {
+        self.websocket_max_duration = websocket_max_duration;
+        self
+    }
+
+    pub fn stream_buffering(mut self, max_bytes: usize, max_duration: Duration) -> Self {
+        self.stream_buffering = Some(StreamBuffering {
+            max_bytes,
+            max_duration,
+        });
+        self
+    }
+
+    pub fn stream_max_chunk_size(mut self, stream_max_chunk_size: Option<usize>) -> Self {
+        self.stream_max_chunk_size = stream_max_chunk_size;
+        self
+    }
+
+    pub fn stream_max_chunks(mut self, stream_max_chunks: Option<usize>) -> Self {
+        self.stream_max_chunks = stream_max_chunks;
+        self
+    }
+
+    pub fn body_spilling(mut self, body_spilling: BodySpilling) -> Self {
+        self.body_spilling = Some(body_spilling);
+        self
+    }
+
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
+    pub fn max_body_size_override(mut self, max_body_size_override: usize) -> Self {
+        self.max_body_size_override = Some(max_body_size_override);
+        self
+    }
+
+    pub fn body_read_timeout(mut self, body_read_timeout: Duration) -> Self {
+        self.body_read_timeout = Some(body_read_timeout);
+        self
+    }
+
+    pub fn body_total_timeout(mut self, body_total_timeout: Duration) -> Self {
+        self.body_total_timeout = Some(body_total_timeout);
+        self
+    }
+
+    pub fn error_reporter(mut self, error_reporter: Arc<dyn ErrorReporter>) -> Self {
+        self.error_reporter = error_reporter;
+        self
+    }
+
+    pub fn deployment_loader(mut self, deployment_loader: Arc<dyn DeploymentLoader>) -> Self {
+        self.deployment_loader = deployment_loader;
+        self
+    }
+
+    pub fn assets_cache_control(mut self, assets_cache_control: AssetsCacheControl) -> Self {
+        self.assets_cache_control = assets_cache_control;
+        self
+    }
+
+    pub fn bundle_load_failure_ttl(mut self, bundle_load_failure_ttl: Duration) -> Self {
+        self.bundle_load_failure_ttl = bundle_load_failure_ttl;
+        self
+    }
+
+    pub fn wait_until_timeout(mut self, wait_until_timeout: Duration) -> Self {
+        self.wait_until_timeout = wait_until_timeout;
+        self
+    }
+
+    pub fn pause_timeout(mut self, pause_timeout: Duration) -> Self {
+        self.pause_timeout = pause_timeout;
+        self
+    }
+
+    pub fn max_paused_requests(mut self, max_paused_requests: usize) -> Self {
+        self.max_paused_requests = max_paused_requests;
+        self
+    }
+
+    pub fn async_timeout(mut self, async_timeout: Duration) -> Self {
+        self.async_timeout = Some(async_timeout);
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: CancellationToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn shutdown_drain_delay(mut self, shutdown_drain_delay: Duration) -> Self {
+        self.shutdown_drain_delay = shutdown_drain_delay;
+        self
+    }
+
+    pub fn shutdown_grace_period(mut self, shutdown_grace_period: Duration) -> Self {
+        self.shutdown_grace_period = shutdown_grace_period;
+        self
+    }
+
+    pub fn trusted_proxies(mut self, trusted_proxies: Vec<IpNet>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    pub fn max_connections_per_ip(mut self, max_connections_per_ip: usize) -> Self {
+        self.max_connections_per_ip = Some(max_connections_per_ip);
+        self
+    }
+
+    pub fn limit_trusted_proxies_connections(
+        mut self,
+        limit_trusted_proxies_connections: bool,
+    ) -> Self {
+        self.limit_trusted_proxies_connections = limit_trusted_proxies_connections;
+        self
+    }
+
+    pub fn idle_connection_timeout(mut self, idle_connection_timeout: Duration) -> Self {
+        self.idle_connection_timeout = Some(idle_connection_timeout);
+        self
+    }
+
+    pub fn json_max_size(mut self, json_max_size: usize) -> Self {
+        self.json_max_size = Some(json_max_size);
+        self
+    }
+
+    pub fn json_max_depth(mut self, json_max_depth: usize) -> Self {
+        self.json_max_depth = Some(json_max_depth);
+        self
+    }
+
+    pub fn max_pending_ops(mut self, max_pending_ops: usize) -> Self {
+        self.max_pending_ops = Some(max_pending_ops);
+        self
+    }
+
+    pub fn host_routes(mut self, host_routes: HostRoutes) -> Self {
+        self.host_routes = host_routes;
+        self
+    }
+
+    pub fn tls(mut self, tls: Arc<CertificateStore>) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn http3_listen_addr(mut self, http3_listen_addr: SocketAddr) -> Self {
+        self.http3_listen_addr = Some(http3_listen_addr);
+        self
+    }
+
+    pub fn http2_max_concurrent_streams(mut self, http2_max_concurrent_streams: u32) -> Self {
+        self.http2_max_concurrent_streams = http2_max_concurrent_streams;
+        self
+    }
+
+    pub fn http2_max_pending_reset_streams(
+        mut self,
+        http2_max_pending_reset_streams: usize,
+    ) -> Self {
+        self.http2_max_pending_reset_streams = http2_max_pending_reset_streams;
+        self
+    }
+
+    pub fn fetch_unix_socket(mut self, function_id: String, host: String, path: PathBuf) -> Self {
+        self.fetch_unix_sockets
+            .entry(function_id)
+            .or_default()
+            .insert(host.to_lowercase(), path);
+        self
+    }
+
+    pub fn console_max_depth(mut self, console_max_depth: usize) -> Self {
+        self.console_max_depth = Some(console_max_depth);
+        self
+    }
+
+    pub fn console_max_length(mut self, console_max_length: usize) -> Self {
+        self.console_max_length = Some(console_max_length);
+        self
+    }
+
+    pub fn console_max_string_length(mut self, console_max_string_length: usize) -> Self {
+        self.console_max_string_length = Some(console_max_string_length);
+        self
+    }
+
+    pub fn console_max_output_size(mut self, console_max_output_size: usize) -> Self {
+        self.console_max_output_size = Some(console_max_output_size);
+        self
+    }
+
+    pub fn isolate_v8_flags(mut self, isolate_v8_flags: Vec<String>) -> Self {
+        self.isolate_v8_flags = isolate_v8_flags;
+        self
+    }
+
+    pub fn code_cache(mut self, code_cache: Arc<CodeCache>) -> Self {
+        self.code_cache = Some(code_cache);
+        self
+    }
+
+    pub fn max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = Some(max_concurrent_requests);
+        self
+    }
+
+    pub fn max_queued_requests(mut self, max_queued_requests: usize) -> Self {
+        self.max_queued_requests = Some(max_queued_requests);
+        self
+    }
+
+    pub fn edge_cache_max_entries(mut self, edge_cache_max_entries: usize) -> Self {
+        self.edge_cache_max_entries = Some(edge_cache_max_entries);
+        self
+    }
+
+    pub fn fetch_cache_max_entries(mut self, fetch_cache_max_entries: usize) -> Self {
+        self.fetch_cache_max_entries = Some(fetch_cache_max_entries);
+        self
+    }
+
+    pub fn content_digest(mut self, content_digest: bool) -> Self {
+        self.content_digest = content_digest;
+        self
+    }
+
+    pub fn access_log_formatter(
+        mut self,
+        name: String,
+        access_log_formatter: Arc<dyn AccessLogFormatter>,
+    ) -> Self {
+        self.access_log_formatters
+            .insert(name, access_log_formatter);
+        self
+    }
+
+    pub fn access_log_format(mut self, access_log_format: String) -> Self {
+        self.access_log_format = Some(access_log_format);
+        self
+    }
+
+    pub fn access_log_sampling(mut self, access_log_sampling: AccessLogSampling) -> Self {
+        self.access_log_sampling = access_log_sampling;
+        self
+    }
+
+    pub fn error_schema(mut self, error_schema: ErrorSchema) -> Self {
+        self.error_schema = Some(error_schema);
+        self
+    }
+
+    pub fn timeout_response(mut self, timeout_response: TimeoutResponse) -> Self {
+        self.timeout_response = Some(timeout_response);
+        self
+    }
+
+    pub fn utf8_policy(mut self, utf8_policy: Utf8Policy) -> Self {
+        self.utf8_policy = Some(utf8_policy);
+        self
+    }
+
+    pub fn empty_response_policy(mut self, empty_response_policy: EmptyResponsePolicy) -> Self {
+        self.empty_response_policy = empty_response_policy;
+        self
+    }
+}
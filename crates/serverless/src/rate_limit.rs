@@ -0,0 +1,83 @@
+use dashmap::DashMap;
+use lagon_runtime_utils::RateLimit;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    // When the bucket will be refilled to its burst capacity
+    full_at: Instant,
+}
+
+fn time_to_refill(tokens: f64, rate: u32) -> Duration {
+    if rate == 0 {
+        return CLEANUP_INTERVAL;
+    }
+
+    Duration::from_secs_f64(tokens / f64::from(rate))
+}
+
+// Token buckets keyed by (deployment id, client IP)
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: DashMap<(String, String), TokenBucket>,
+}
+
+impl RateLimiter {
+    // Consume a token, or return how long to wait before a token is available
+    pub fn check(
+        &self,
+        deployment_id: &str,
+        ip: &str,
+        rate_limit: &RateLimit,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let burst = f64::from(rate_limit.burst.max(1));
+
+        let mut bucket = self
+            .buckets
+            .entry((deployment_id.to_string(), ip.to_string()))
+            .or_insert_with(|| TokenBucket {
+                tokens: burst,
+                last_refill: now,
+                full_at: now,
+            });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * f64::from(rate_limit.rate)).min(burst);
+        bucket.last_refill = now;
+
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(time_to_refill(1.0 - bucket.tokens, rate_limit.rate))
+        };
+
+        bucket.full_at = now + time_to_refill(burst - bucket.tokens, rate_limit.rate);
+
+        result
+    }
+
+    // Full buckets behave the same as missing ones, so we can drop them
+    fn cleanup(&self) {
+        let now = Instant::now();
+
+        self.buckets.retain(|_, bucket| bucket.full_at > now);
+    }
+}
+
+pub fn run_rate_limiter_cleanup_task(rate_limiter: Arc<RateLimiter>) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+
+            rate_limiter.cleanup();
+        }
+    });
+}
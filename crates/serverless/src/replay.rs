@@ -0,0 +1,211 @@
+use crate::{
+    deployments::{
+        get_source_map,
+        loader::{load_bundle, Bundles},
+        Deployments, SourceMaps,
+    },
+    options::ServerlessOptions,
+    serverless::isolate_options,
+};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::body::Bytes;
+use lagon_runtime_http::{Method, Request, RunResult, StreamResult};
+use lagon_runtime_isolate::{Isolate, IsolateEvent, IsolateRequest};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use tokio::runtime::Handle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+// A request sent to a deployment again, e.g copied from the access logs
+#[derive(Debug, Deserialize)]
+pub struct ReplayedRequest {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub body_encoding: BodyEncoding,
+}
+
+fn default_method() -> String {
+    "GET".into()
+}
+
+impl ReplayedRequest {
+    pub fn into_request(self) -> Result<Request> {
+        let body = match self.body_encoding {
+            BodyEncoding::Utf8 => Bytes::from(self.body),
+            BodyEncoding::Base64 => Bytes::from(STANDARD.decode(self.body)?),
+        };
+
+        Ok(Request {
+            headers: Some(self.headers),
+            method: Method::from(self.method.as_str()),
+            body,
+            url: self.url,
+            spilled_body: None,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayedLog {
+    pub level: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayResult {
+    // Not set when the isolate didn't respond
+    pub status: Option<u16>,
+    pub headers: HashMap<String, Vec<String>>,
+    pub body: String,
+    pub body_encoding: BodyEncoding,
+    // Set when the isolate errored, timed out or reached its memory limit
+    pub error: Option<String>,
+    pub logs: Vec<ReplayedLog>,
+    // CPU time of the handler, only known for complete responses
+    pub cpu_time_ms: Option<f64>,
+    pub wall_time_ms: f64,
+}
+
+// Run a request in a new isolate created only for it, which doesn't count in the
+// stats, metrics and logs of the deployment. The isolate gets the same code, env
+// variables and limits as the isolates serving the production requests
+pub async fn replay_request(
+    options: &Arc<ServerlessOptions>,
+    deployments: &Deployments,
+    source_maps: &SourceMaps,
+    bundles: &Bundles,
+    deployment_id: &str,
+    request: Request,
+) -> Result<Option<ReplayResult>> {
+    let deployment = match deployments
+        .iter()
+        .find(|deployment| deployment.id == deployment_id)
+    {
+        Some(deployment) => Arc::clone(deployment.value()),
+        None => return Ok(None),
+    };
+
+    let bundle = load_bundle(
+        options.deployment_loader.as_ref(),
+        bundles,
+        &deployment.id,
+        options.bundle_load_failure_ttl,
+    )
+    .await?;
+
+    let (log_sender, log_receiver) = flume::unbounded();
+    let (isolate_sender, isolate_receiver) = flume::unbounded();
+    let (sender, receiver) = flume::unbounded();
+    let handle = Handle::current();
+    let serverless_options = Arc::clone(options);
+    let source_maps = Arc::clone(source_maps);
+    let started_at = Instant::now();
+
+    std::thread::Builder::new()
+        .name(String::from("replay-") + deployment.id.as_str())
+        .spawn(move || {
+            handle.block_on(async move {
+                let source_map =
+                    get_source_map(&source_maps, &deployment.id, bundle.source_map.as_deref());
+                let options =
+                    isolate_options(&deployment, &serverless_options, bundle.code.clone())
+                        .source_map(source_map)
+                        .log_sender(log_sender);
+
+                let mut isolate = Isolate::new(options, isolate_receiver);
+                isolate.evaluate();
+                isolate.run_event_loop().await;
+            })
+        })?;
+
+    isolate_sender
+        .send_async(IsolateEvent::Request(IsolateRequest {
+            request,
+            sender,
+            cancellation_token: None,
+            memory: None,
+            max_body_size: None,
+        }))
+        .await?;
+
+    let mut result = ReplayResult::default();
+    let mut body = Vec::new();
+
+    loop {
+        match receiver.recv_async().await {
+            Ok(RunResult::Response(response, elapsed)) => {
+                result.status = Some(response.status);
+                result.headers = response.headers.unwrap_or_default();
+                result.cpu_time_ms = elapsed.map(|elapsed| elapsed.as_secs_f64() * 1000.0);
+                body.extend_from_slice(&response.body);
+                break;
+            }
+            Ok(RunResult::Stream(StreamResult::Start(response))) => {
+                result.status = Some(response.status);
+                result.headers = response.headers.unwrap_or_default();
+            }
+            Ok(RunResult::Stream(StreamResult::Data(data))) => body.extend_from_slice(&data),
+            Ok(RunResult::Stream(StreamResult::Done(elapsed))) => {
+                result.cpu_time_ms = Some(elapsed.as_secs_f64() * 1000.0);
+                break;
+            }
+            Ok(RunResult::Timeout) => {
+                result.error = Some("Timeout".into());
+                break;
+            }
+            Ok(RunResult::MemoryLimit) => {
+                result.error = Some("Memory limit".into());
+                break;
+            }
+            Ok(RunResult::Error(error) | RunResult::LoadError(error)) => {
+                result.error = Some(error);
+                break;
+            }
+            Ok(RunResult::NotFound) => {
+                result.status = Some(404);
+                break;
+            }
+            Err(_) => {
+                result.error = Some("The isolate was dropped".into());
+                break;
+            }
+        }
+    }
+
+    result.wall_time_ms = started_at.elapsed().as_secs_f64() * 1000.0;
+
+    // The event loop never completes on its own
+    isolate_sender
+        .send_async(IsolateEvent::Terminate("Replayed".into()))
+        .await
+        .unwrap_or(());
+
+    result.logs = log_receiver
+        .try_iter()
+        .map(|(level, message, _)| ReplayedLog { level, message })
+        .collect();
+
+    match String::from_utf8(body) {
+        Ok(body) => result.body = body,
+        Err(error) => {
+            result.body = STANDARD.encode(error.into_bytes());
+            result.body_encoding = BodyEncoding::Base64;
+        }
+    }
+
+    Ok(Some(result))
+}
@@ -0,0 +1,36 @@
+use hyper::{header::HeaderValue, HeaderMap};
+use std::time::Duration;
+
+pub const SERVER_TIMING: &str = "server-timing";
+
+// Timings measured by the platform, sent with the `Server-Timing` header of
+// the deployments opting in so they show up in the browsers' devtools
+#[derive(Debug, Default)]
+pub struct ServerTiming {
+    entries: Vec<(&'static str, Duration)>,
+}
+
+impl ServerTiming {
+    pub fn add(&mut self, name: &'static str, duration: Duration) {
+        self.entries.push((name, duration));
+    }
+
+    // e.g `cold-start;dur=12.5, exec;dur=1.2`, in milliseconds
+    pub fn to_header_value(&self) -> HeaderValue {
+        let value = self
+            .entries
+            .iter()
+            .map(|(name, duration)| format!("{name};dur={:.3}", duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        HeaderValue::from_str(&value).unwrap()
+    }
+
+    // Sent as another header, so the entries set by the function are kept
+    pub fn append_to(&self, headers: &mut HeaderMap) {
+        if !self.entries.is_empty() {
+            headers.append(SERVER_TIMING, self.to_header_value());
+        }
+    }
+}
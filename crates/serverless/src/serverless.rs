@@ -1,53 +1,101 @@
 use crate::{
+    access_log::{AccessLogEntry, AccessLogFormatter, AccessLogSampling},
+    admission::{get_priority, AdmissionQueue},
     clickhouse::{LogRow, RequestRow},
-    deployments::{cache::run_cache_clear_task, pubsub::listen_pub_sub, Deployments},
+    connection_limit::{ConnectionLimiter, LimitedIncoming},
+    deployments::{
+        cache::run_cache_clear_task,
+        get_source_map,
+        loader::{load_bundle, BundleCache, Bundles},
+        pubsub::listen_pub_sub,
+        Deployments, SourceMaps,
+    },
+    edge_cache::{get_cache_key, run_edge_cache_prune_task, CacheKey, CacheLookup, EdgeCache},
+    error_reporter::{ErrorReport, ErrorReporter},
+    forwarded::{get_client_ip, get_max_body_size, get_memory_override},
+    http3::{alt_svc_header, http3_server},
+    idle_connections::{run_idle_connections_reaper, GuardedBody, IdleConnections},
+    isolate_pool::{IsolateStartup, StartupTimings, WorkerPool},
+    management::{
+        handle_health_request, handle_management_request, is_health_request, is_management_request,
+        new_log_tail, set_bundle_metadata, set_memory_usage, CancellationTokens, DeploymentsStats,
+        InFlightRequest, LogTail, Maintenances, Pauses, TailedLog,
+    },
+    options::ServerlessOptions,
+    rate_limit::{run_rate_limiter_cleanup_task, RateLimiter},
+    server_timing::ServerTiming,
+    tls::{tls_acceptor, ServerIncoming, ServerStream},
+    websocket::get_websocket_upgrade,
     REGION, SNAPSHOT_BLOB,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clickhouse::{inserter::Inserter, Client};
 use dashmap::DashMap;
 use hyper::{
-    header::HOST,
+    header::{ACCEPT, ALT_SVC, CONTENT_LENGTH, CONTENT_TYPE, HOST, RETRY_AFTER, TRANSFER_ENCODING},
     http::response::Builder,
-    server::conn::AddrStream,
+    server::conn::AddrIncoming,
     service::{make_service_fn, service_fn},
     Body, Request as HyperRequest, Response as HyperResponse, Server,
 };
 use lagon_runtime_http::{
-    Request, Response, RunResult, X_FORWARDED_FOR, X_LAGON_ID, X_LAGON_REGION, X_REAL_IP,
+    BodyReadTimeout, BodyTooLarge, Request, Response, RunResult, X_FORWARDED_FOR, X_LAGON_ID,
+    X_LAGON_REGION,
 };
 use lagon_runtime_isolate::{
     options::{IsolateOptions, Metadata},
-    Isolate, IsolateEvent, IsolateRequest,
+    FetchCache, Isolate, IsolateEvent, IsolateLifecycleEvent, IsolateRequest,
 };
 use lagon_runtime_utils::{
     assets::{find_asset, handle_asset},
-    response::{handle_response, ResponseEvent, FAVICON_URL, PAGE_403, PAGE_404},
-    DEPLOYMENTS_DIR,
+    conditional::{not_modified, ConditionalHeaders},
+    error_page::{error_page, json_error_response, prefers_json, ErrorSchema},
+    range::ByteRange,
+    request_context::RequestContext,
+    response::{
+        handle_response_with_options, insert_response_headers, ResponseEvent, ResponseOptions,
+        FAVICON_URL, PAGE_403, PAGE_404, PAGE_408, PAGE_413, PAGE_414, PAGE_415, PAGE_429,
+        PAGE_503,
+    },
+    BalancingPolicy, Deployment, Fallback, ResponseHeader, DEPLOYMENTS_DIR,
 };
 use lagon_serverless_downloader::Downloader;
 use lagon_serverless_pubsub::PubSubListener;
 use log::{as_debug, error, info, warn};
 use metrics::{decrement_gauge, histogram, increment_counter, increment_gauge};
 use std::{
+    collections::HashMap,
     convert::Infallible,
     env,
     future::Future,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
+    panic::AssertUnwindSafe,
     path::Path,
     sync::Arc,
-    time::{Duration, Instant, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{runtime::Handle, sync::Mutex};
+use tokio_util::sync::CancellationToken;
 
-pub type Workers = Arc<DashMap<String, flume::Sender<IsolateEvent>>>;
+pub type Workers = Arc<DashMap<String, Arc<WorkerPool>>>;
+
+// Passed to the response events, along with the ids of the request
+struct ResponseEventData {
+    bytes_in: u32,
+    labels: [(&'static str, String); 3],
+    inserters: Arc<Mutex<(Inserter<RequestRow>, Inserter<LogRow>)>>,
+    // Only dropped once the last event has been handled, e.g when the stream ends
+    _in_flight_request: Option<Arc<InFlightRequest>>,
+    error_reporter: Arc<dyn ErrorReporter>,
+    access_log: Option<AccessLog>,
+    timeout_status: u16,
+}
+
+fn handle_error(result: RunResult, context: &RequestContext<ResponseEventData>) {
+    let deployment_id = context.deployment_id();
+    let request_id = context.request_id();
+    let labels = &context.labels;
 
-fn handle_error(
-    result: RunResult,
-    deployment_id: &String,
-    request_id: &String,
-    labels: &[(&'static str, String); 3],
-) {
     match result {
         RunResult::Timeout => {
             increment_counter!("lagon_isolate_timeouts", labels);
@@ -60,25 +108,391 @@ fn handle_error(
         RunResult::Error(error) => {
             increment_counter!("lagon_isolate_errors", labels);
             error!(deployment = deployment_id, request = request_id; "Function execution error: {}", error);
+
+            context.error_reporter.report(ErrorReport::new(
+                &error,
+                deployment_id.to_string(),
+                request_id.to_string(),
+            ));
+        }
+        RunResult::LoadError(error) => {
+            increment_counter!("lagon_bundle_load_errors", labels);
+            error!(deployment = deployment_id, request = request_id; "Deployment could not be loaded: {}", error);
+
+            context.error_reporter.report(ErrorReport::new(
+                &error,
+                deployment_id.to_string(),
+                request_id.to_string(),
+            ));
         }
         _ => {}
     };
 }
 
+fn reject_body_too_large(hostname: &str, request_id: &str) -> Result<HyperResponse<Body>> {
+    increment_counter!(
+        "lagon_ignored_requests",
+        "reason" => "Body too large",
+        "hostname" => hostname.to_string(),
+        "region" => REGION.clone(),
+    );
+    warn!(hostname = hostname, request = request_id; "Request body exceeds the maximum size");
+
+    error_page(413, PAGE_413)
+}
+
+// The options shared by the isolates serving the requests of a deployment and
+// the ones replaying requests, which must run the code with the same limits
+pub(crate) fn isolate_options(
+    deployment: &Deployment,
+    options: &ServerlessOptions,
+    code: String,
+) -> IsolateOptions {
+    let mut isolate_options = IsolateOptions::new(code)
+        .environment_variables(deployment.environment_variables.clone())
+        .memory(deployment.memory)
+        .tick_timeout(Duration::from_millis(deployment.tick_timeout as u64))
+        .total_timeout(Duration::from_millis(deployment.total_timeout as u64))
+        .metadata(Some((
+            deployment.id.clone(),
+            deployment.function_id.clone(),
+        )))
+        .wait_until_timeout(options.wait_until_timeout)
+        .v8_flags(options.isolate_v8_flags.clone())
+        .snapshot_blob(SNAPSHOT_BLOB);
+
+    if let Some(json_max_size) = options.json_max_size {
+        isolate_options = isolate_options.json_max_size(json_max_size);
+    }
+
+    if let Some(json_max_depth) = options.json_max_depth {
+        isolate_options = isolate_options.json_max_depth(json_max_depth);
+    }
+
+    if let Some(max_pending_ops) = options.max_pending_ops {
+        isolate_options = isolate_options.max_pending_ops(max_pending_ops);
+    }
+
+    // Trusted uploads raise this limit for their own request only
+    if let Some(max_body_size) = options.max_body_size {
+        isolate_options = isolate_options.max_body_size(max_body_size);
+    }
+
+    if let Some(unix_sockets) = options.fetch_unix_sockets.get(&deployment.function_id) {
+        isolate_options = isolate_options.unix_sockets(unix_sockets.clone());
+    }
+
+    if let Some(body_spilling) = options.body_spilling.clone() {
+        isolate_options = isolate_options.body_spilling(body_spilling);
+    }
+
+    if let Some(console_max_depth) = options.console_max_depth {
+        isolate_options = isolate_options.inspect_max_depth(console_max_depth);
+    }
+
+    if let Some(console_max_length) = options.console_max_length {
+        isolate_options = isolate_options.inspect_max_length(console_max_length);
+    }
+
+    if let Some(console_max_string_length) = options.console_max_string_length {
+        isolate_options = isolate_options.inspect_max_string_length(console_max_string_length);
+    }
+
+    if let Some(console_max_output_size) = options.console_max_output_size {
+        isolate_options = isolate_options.inspect_max_output_size(console_max_output_size);
+    }
+
+    if let Some(supported_locales) = deployment.supported_locales.clone() {
+        isolate_options = isolate_options.supported_locales(supported_locales);
+    }
+
+    if let Some(max_memory) = deployment.max_memory {
+        isolate_options = isolate_options.max_memory(max_memory);
+    }
+
+    if let Some(fetch_timeout) = deployment.fetch_timeout {
+        isolate_options =
+            isolate_options.fetch_timeout(Duration::from_millis(fetch_timeout as u64));
+    }
+
+    if let Some(async_timeout) = options.async_timeout {
+        isolate_options = isolate_options.async_timeout(async_timeout);
+    }
+
+    if let Some(code_cache) = options.code_cache.clone() {
+        isolate_options = isolate_options.code_cache(code_cache);
+    }
+
+    isolate_options
+}
+
+// Requests sent to an isolate that couldn't be created, which are
+// rejected instead of being left without a response
+fn reject_isolate_events(receiver: &flume::Receiver<IsolateEvent>, error: &str) {
+    while let Ok(event) = receiver.try_recv() {
+        if let IsolateEvent::Request(IsolateRequest { sender, .. })
+        | IsolateEvent::WebSocket(IsolateRequest { sender, .. }, _) = event
+        {
+            sender
+                .send(RunResult::LoadError(error.to_string()))
+                .unwrap_or(());
+        }
+    }
+}
+
+type AccessLog = (
+    Arc<dyn AccessLogFormatter>,
+    AccessLogEntry,
+    Instant,
+    AccessLogSampling,
+);
+
+fn write_access_log(access_log: Option<AccessLog>, status: u16, bytes: usize) {
+    if let Some((formatter, mut entry, received_at, sampling)) = access_log {
+        if !sampling.should_log(&entry.request_id, status) {
+            return;
+        }
+
+        entry.status = status;
+        entry.bytes = bytes;
+        entry.duration = received_at.elapsed();
+
+        info!(target: "access", "{}", formatter.format(&entry));
+    }
+}
+
+// The cache stores the responses of the function, so the headers of
+// the deployment are added the same way as the non-cached responses
+fn serve_cached_response(
+    response: Response,
+    conditional_headers: &Option<ConditionalHeaders>,
+    response_headers: &[ResponseHeader],
+    access_log: Option<AccessLog>,
+) -> Result<HyperResponse<Body>> {
+    if conditional_headers
+        .as_ref()
+        .map_or(false, |conditional_headers| {
+            conditional_headers.is_not_modified(&response)
+        })
+    {
+        return serve_not_modified(&response, response_headers, access_log);
+    }
+
+    write_access_log(access_log, response.status, response.len());
+
+    let content_length = response.len();
+    let mut hyper_response = Builder::try_from(&response)?.body(response.body.into())?;
+    hyper_response
+        .headers_mut()
+        .insert(CONTENT_LENGTH, content_length.into());
+    insert_response_headers(hyper_response.headers_mut(), response_headers);
+
+    Ok(hyper_response)
+}
+
+fn serve_not_modified(
+    response: &Response,
+    response_headers: &[ResponseHeader],
+    access_log: Option<AccessLog>,
+) -> Result<HyperResponse<Body>> {
+    write_access_log(access_log, 304, 0);
+
+    let mut hyper_response = not_modified(response)?;
+    insert_response_headers(hyper_response.headers_mut(), response_headers);
+
+    Ok(hyper_response)
+}
+
+// The last cached response is preferred over the static response
+fn get_fallback_response(
+    fallback: &Fallback,
+    edge_cache: &Option<Arc<EdgeCache>>,
+    cache_key: &Option<CacheKey>,
+) -> Option<(Response, &'static str)> {
+    if fallback.from_cache {
+        if let (Some(edge_cache), Some(cache_key)) = (edge_cache, cache_key) {
+            if let Some(response) = edge_cache.get_fallback(cache_key) {
+                return Some((response, "cache"));
+            }
+        }
+    }
+
+    fallback.body.as_ref().map(|body| {
+        let content_type = fallback
+            .content_type
+            .clone()
+            .unwrap_or_else(|| "text/plain;charset=UTF-8".into());
+
+        (
+            Response {
+                headers: Some(HashMap::from([("content-type".into(), vec![content_type])])),
+                body: body.clone().into(),
+                status: fallback.status,
+                status_text: None,
+            },
+            "static",
+        )
+    })
+}
+
+// The error pages are sent as JSON to the clients preferring it, using the error
+// schema of the deployment (or the default one) and including the request id
+fn get_json_errors(
+    req: &HyperRequest<Body>,
+    deployments: &Deployments,
+    options: &ServerlessOptions,
+) -> Option<(Option<ErrorSchema>, String)> {
+    let accept = req.headers().get(ACCEPT)?.to_str().ok()?;
+
+    if !prefers_json(accept) {
+        return None;
+    }
+
+    let request_id = req
+        .headers()
+        .get(X_LAGON_ID)
+        .and_then(|request_id| request_id.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let error_schema = req
+        .headers()
+        .get(HOST)
+        .and_then(|hostname| hostname.to_str().ok())
+        .and_then(|hostname| deployments.get(hostname))
+        .and_then(|deployment| deployment.error_schema.clone())
+        .or_else(|| options.error_schema.clone());
+
+    Some((error_schema, request_id))
+}
+
+// Everything needed to handle the requests, shared by the HTTP/1.1 and HTTP/3 servers
+#[derive(Clone)]
+pub struct RequestHandler {
+    options: Arc<ServerlessOptions>,
+    deployments: Deployments,
+    last_requests: Arc<DashMap<String, Instant>>,
+    workers: Workers,
+    source_maps: SourceMaps,
+    bundles: Bundles,
+    stats: DeploymentsStats,
+    rate_limiter: Arc<RateLimiter>,
+    admission_queue: Option<Arc<AdmissionQueue>>,
+    edge_cache: Option<Arc<EdgeCache>>,
+    cancellation_tokens: CancellationTokens,
+    maintenances: Maintenances,
+    pauses: Pauses,
+    inserters: Arc<Mutex<(Inserter<RequestRow>, Inserter<LogRow>)>>,
+    log_sender: flume::Sender<(String, String, Metadata)>,
+    lifecycle_sender: flume::Sender<(IsolateLifecycleEvent, Metadata)>,
+    log_tail: LogTail,
+}
+
+impl RequestHandler {
+    pub async fn handle(
+        self,
+        req: HyperRequest<Body>,
+        peer_ip: IpAddr,
+    ) -> Result<HyperResponse<Body>> {
+        let json_errors = get_json_errors(&req, &self.deployments, &self.options);
+
+        let response = handle_request(
+            req,
+            peer_ip,
+            self.options,
+            self.deployments,
+            self.last_requests,
+            self.workers,
+            self.source_maps,
+            self.bundles,
+            self.stats,
+            self.rate_limiter,
+            self.admission_queue,
+            self.edge_cache,
+            self.cancellation_tokens,
+            self.maintenances,
+            self.pauses,
+            self.inserters,
+            self.log_sender,
+            self.lifecycle_sender,
+            self.log_tail,
+        )
+        .await;
+
+        match (response, json_errors) {
+            (Ok(response), Some((error_schema, request_id))) => {
+                json_error_response(response, error_schema.as_ref(), &request_id)
+            }
+            (response, _) => response,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_request(
-    req: HyperRequest<Body>,
-    ip: String,
+    mut req: HyperRequest<Body>,
+    peer_ip: IpAddr,
+    options: Arc<ServerlessOptions>,
     deployments: Deployments,
     last_requests: Arc<DashMap<String, Instant>>,
     workers: Workers,
+    source_maps: SourceMaps,
+    bundles: Bundles,
+    stats: DeploymentsStats,
+    rate_limiter: Arc<RateLimiter>,
+    admission_queue: Option<Arc<AdmissionQueue>>,
+    edge_cache: Option<Arc<EdgeCache>>,
+    cancellation_tokens: CancellationTokens,
+    maintenances: Maintenances,
+    pauses: Pauses,
     inserters: Arc<Mutex<(Inserter<RequestRow>, Inserter<LogRow>)>>,
     log_sender: flume::Sender<(String, String, Metadata)>,
+    lifecycle_sender: flume::Sender<(IsolateLifecycleEvent, Metadata)>,
+    log_tail: LogTail,
 ) -> Result<HyperResponse<Body>> {
+    let received_at = Instant::now();
+    let ip = peer_ip.to_string();
     let request_id = match req.headers().get(X_LAGON_ID) {
         Some(x_lagon_id) => x_lagon_id.to_str().unwrap_or("").to_string(),
         None => String::new(),
     };
 
+    let url_length = req
+        .uri()
+        .path_and_query()
+        .map_or(0, |path_and_query| path_and_query.as_str().len());
+
+    if url_length > options.max_url_length {
+        increment_counter!(
+            "lagon_ignored_requests",
+            "reason" => "URL too long",
+            "region" => REGION.clone(),
+        );
+        warn!(ip = ip, request = request_id, length = url_length; "URL exceeds the maximum length");
+
+        return error_page(414, PAGE_414);
+    }
+
+    if is_health_request(&req) {
+        return handle_health_request(&options);
+    }
+
+    if is_management_request(&req, &options) {
+        return handle_management_request(
+            req,
+            &options,
+            &deployments,
+            &source_maps,
+            &bundles,
+            &workers,
+            &stats,
+            &cancellation_tokens,
+            &maintenances,
+            &pauses,
+            &log_tail,
+        )
+        .await;
+    }
+
     let hostname = match req.headers().get(HOST) {
         Some(hostname) => hostname.to_str()?.to_string(),
         None => {
@@ -89,11 +503,16 @@ async fn handle_request(
             );
             warn!(req = as_debug!(req), ip = ip, request = request_id; "No Host header found in request");
 
-            return Ok(Builder::new().status(404).body(PAGE_404.into())?);
+            return error_page(404, PAGE_404);
         }
     };
 
-    let deployment = match deployments.get(&hostname) {
+    let deployment = match deployments.get(&hostname).or_else(|| {
+        options
+            .host_routes
+            .resolve(&hostname)
+            .and_then(|target| deployments.get(target))
+    }) {
         Some(entry) => Arc::clone(entry.value()),
         None => {
             increment_counter!(
@@ -104,7 +523,7 @@ async fn handle_request(
             );
             warn!(req = as_debug!(req), ip = ip, hostname = hostname, request = request_id; "No deployment found for hostname");
 
-            return Ok(HyperResponse::builder().status(404).body(PAGE_404.into())?);
+            return error_page(404, PAGE_404);
         }
     };
 
@@ -117,16 +536,278 @@ async fn handle_request(
         );
         warn!(req = as_debug!(req), ip = ip, hostname = hostname, request = request_id; "Cron deployment cannot be called directly");
 
-        return Ok(HyperResponse::builder().status(403).body(PAGE_403.into())?);
+        return error_page(403, PAGE_403);
+    }
+
+    if let Some(maintenance) = maintenances.get(&deployment.id) {
+        increment_counter!(
+            "lagon_ignored_requests",
+            "reason" => "Maintenance",
+            "hostname" => hostname.clone(),
+            "region" => REGION.clone(),
+        );
+
+        return maintenance.to_response();
+    }
+
+    // Cloned to not hold the lock while waiting
+    let pause = pauses.get(&deployment.id).map(|pause| pause.clone());
+
+    if let Some(pause) = pause {
+        if !pause
+            .wait(&options.shutdown, options.max_paused_requests)
+            .await
+        {
+            increment_counter!(
+                "lagon_ignored_requests",
+                "reason" => "Paused",
+                "hostname" => hostname.clone(),
+                "region" => REGION.clone(),
+            );
+
+            return error_page(503, PAGE_503);
+        }
+    }
+
+    let client_ip = get_client_ip(&mut req, peer_ip, &options.trusted_proxies).to_string();
+    let memory = get_memory_override(
+        &mut req,
+        peer_ip,
+        &options.trusted_proxies,
+        deployment.max_memory,
+    );
+    let max_body_size = get_max_body_size(
+        &mut req,
+        peer_ip,
+        &options.trusted_proxies,
+        options.max_body_size,
+        options.max_body_size_override,
+    );
+
+    // Bodies without a length are checked while they are read
+    if let (Some(max_body_size), Some(content_length)) = (
+        max_body_size,
+        req.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok()),
+    ) {
+        if content_length > max_body_size {
+            return reject_body_too_large(&hostname, &request_id);
+        }
+    }
+
+    // The status, bytes and duration are set once the response has been sent
+    let mut access_log = deployment
+        .access_log_format
+        .as_ref()
+        .or(options.access_log_format.as_ref())
+        .and_then(|access_log_format| {
+            let formatter = options.access_log_formatters.get(access_log_format);
+
+            if formatter.is_none() {
+                warn!(deployment = deployment.id, format = access_log_format, request = request_id; "Unknown access log format");
+            }
+
+            formatter.cloned()
+        })
+        .map(|formatter| {
+            let entry = AccessLogEntry {
+                timestamp: SystemTime::now(),
+                deployment_id: deployment.id.clone(),
+                request_id: request_id.clone(),
+                client_ip: client_ip.clone(),
+                method: req.method().to_string(),
+                path: req
+                    .uri()
+                    .path_and_query()
+                    .map_or_else(|| "/".into(), |path_and_query| path_and_query.to_string()),
+                version: format!("{:?}", req.version()),
+                status: 0,
+                bytes: 0,
+                duration: Duration::ZERO,
+            };
+
+            (formatter, entry, received_at, options.access_log_sampling)
+        });
+
+    if let Some(rate_limit) = &deployment.rate_limit {
+        if let Err(retry_after) = rate_limiter.check(&deployment.id, &client_ip, rate_limit) {
+            increment_counter!(
+                "lagon_ignored_requests",
+                "reason" => "Rate limited",
+                "hostname" => hostname.clone(),
+                "region" => REGION.clone(),
+            );
+            warn!(ip = client_ip, hostname = hostname, request = request_id; "Rate limit exceeded");
+
+            let mut response = error_page(429, PAGE_429)?;
+            response.headers_mut().insert(
+                RETRY_AFTER,
+                (retry_after.as_secs_f64().ceil() as u64).into(),
+            );
+
+            return Ok(response);
+        }
+    }
+
+    // Only requests with a body are checked
+    let has_body = req.headers().contains_key(TRANSFER_ENCODING)
+        || req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|content_length| content_length.to_str().ok())
+            .and_then(|content_length| content_length.parse::<u64>().ok())
+            .map_or(false, |content_length| content_length > 0);
+
+    if has_body {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("");
+
+        if !deployment.accepts_content_type(content_type) {
+            increment_counter!(
+                "lagon_ignored_requests",
+                "reason" => "Unsupported media type",
+                "hostname" => hostname.clone(),
+                "region" => REGION.clone(),
+            );
+            warn!(content_type = content_type, hostname = hostname, request = request_id; "Unsupported request content type");
+
+            return error_page(415, PAGE_415);
+        }
     }
 
+    let conditional_headers = ConditionalHeaders::from_request(req.method(), req.headers());
+    let range = ByteRange::from_request(req.method(), req.headers());
+    let response_headers =
+        deployment
+            .response_headers
+            .as_ref()
+            .map_or_else(Vec::new, |response_headers| {
+                response_headers
+                    .iter()
+                    .filter(|response_header| response_header.matches_path(req.uri().path()))
+                    .cloned()
+                    .collect()
+            });
+    let cache_key = edge_cache
+        .as_ref()
+        .and_then(|_| get_cache_key(&deployment.id, &req));
+
+    // Fresh responses are served without invoking the isolate. Stale responses
+    // are served too, while the request refreshes the entry in the background
+    let mut stale_response = None;
+    let mut revalidation = None;
+
+    if let (Some(edge_cache), Some(cache_key)) = (&edge_cache, &cache_key) {
+        match edge_cache.get(cache_key, conditional_headers.as_ref()) {
+            CacheLookup::Fresh(response) | CacheLookup::Stale(response, None) => {
+                increment_counter!(
+                    "lagon_edge_cache_hits",
+                    "deployment" => deployment.id.clone(),
+                    "region" => REGION.clone(),
+                );
+
+                return serve_cached_response(
+                    response,
+                    &conditional_headers,
+                    &response_headers,
+                    access_log,
+                );
+            }
+            CacheLookup::Stale(response, Some(stale_revalidation)) => {
+                increment_counter!(
+                    "lagon_edge_cache_hits",
+                    "deployment" => deployment.id.clone(),
+                    "region" => REGION.clone(),
+                );
+
+                // Logged now as the refresh isn't seen by the client
+                stale_response = Some(serve_cached_response(
+                    response,
+                    &conditional_headers,
+                    &response_headers,
+                    access_log.take(),
+                )?);
+                revalidation = Some(stale_revalidation);
+            }
+            // The cached body is never read for conditional hits
+            CacheLookup::NotModified(response, None) => {
+                increment_counter!(
+                    "lagon_edge_cache_hits",
+                    "deployment" => deployment.id.clone(),
+                    "region" => REGION.clone(),
+                );
+
+                return serve_not_modified(&response, &response_headers, access_log);
+            }
+            CacheLookup::NotModified(response, Some(stale_revalidation)) => {
+                increment_counter!(
+                    "lagon_edge_cache_hits",
+                    "deployment" => deployment.id.clone(),
+                    "region" => REGION.clone(),
+                );
+
+                stale_response = Some(serve_not_modified(
+                    &response,
+                    &response_headers,
+                    access_log.take(),
+                )?);
+                revalidation = Some(stale_revalidation);
+            }
+            CacheLookup::Miss => increment_counter!(
+                "lagon_edge_cache_misses",
+                "deployment" => deployment.id.clone(),
+                "region" => REGION.clone(),
+            ),
+        }
+    }
+
+    // Held until the response has started, serving the most
+    // urgent requests first when the node is saturated
+    let _admission_permit = match &admission_queue {
+        // Refreshes are bounded by the edge cache instead, so they don't delay the client
+        Some(_) if stale_response.is_some() => None,
+        Some(admission_queue) => match admission_queue.acquire(get_priority(&req)).await {
+            Some(admission_permit) => Some(admission_permit),
+            None => {
+                increment_counter!(
+                    "lagon_ignored_requests",
+                    "reason" => "Node overloaded",
+                    "hostname" => hostname.clone(),
+                    "region" => REGION.clone(),
+                );
+                warn!(hostname = hostname, request = request_id; "Request shed as the admission queue is full");
+
+                return error_page(503, PAGE_503);
+            }
+        },
+        None => None,
+    };
+
     let function_id = deployment.function_id.clone();
     let deployment_id = deployment.id.clone();
     let mut bytes_in = 0;
+    let mut in_flight_request = None;
+    let mut websocket_upgrade = None;
+    let mut dispatched_at = None;
+    // Set when the request waits for its isolate to be created
+    let mut isolate_startup = None;
+    // The deployment and request id are moved to the isolate thread below
+    let fallback = deployment.fallback.clone();
+    let fallback_request_id = request_id.clone();
+    // Cancelled to abandon the invocation when serving the fallback response
+    let mut fallback_cancellation_token = None;
+    let fallback_cache_key = fallback
+        .as_ref()
+        .filter(|fallback| fallback.from_cache)
+        .and_then(|_| cache_key.clone());
 
     let request_id_handle = request_id.clone();
 
-    let (sender, receiver) = flume::unbounded();
+    let (sender, mut receiver) = flume::unbounded();
 
     let labels = [
         ("deployment", deployment.id.clone()),
@@ -142,7 +823,7 @@ async fn handle_request(
             .join(DEPLOYMENTS_DIR)
             .join(&deployment.id);
 
-        let run_result = match handle_asset(root, asset) {
+        let run_result = match handle_asset(root, asset, options.assets_cache_control.get(asset)) {
             Ok(response) => RunResult::Response(response, None),
             Err(error) => {
                 error!(deployment = &deployment.id, asset = asset, request = request_id; "Error while handing asset: {}", error);
@@ -163,102 +844,268 @@ async fn handle_request(
             ))
             .await
             .unwrap_or(());
+    } else if let Some(error) = bundles.get_failure(&deployment_id, options.bundle_load_failure_ttl)
+    {
+        sender
+            .send_async(RunResult::LoadError(error))
+            .await
+            .unwrap_or(());
     } else {
         last_requests.insert(deployment_id.clone(), Instant::now());
 
-        match Request::from_hyper_with_capacity(req, 2).await {
+        let websocket = match get_websocket_upgrade(&mut req) {
+            Some((upgrade, websocket)) => {
+                websocket_upgrade = Some(upgrade);
+                Some(websocket)
+            }
+            None => None,
+        };
+
+        match Request::from_hyper_with_limits(
+            req,
+            2,
+            options.body_spilling.as_ref(),
+            max_body_size,
+            options.body_read_timeout,
+            options.body_total_timeout,
+        )
+        .await
+        {
             Ok(mut request) => {
                 bytes_in = request.len() as u32;
 
-                // Try to Extract the X-Real-Ip header or fallback to remote addr IP
-                let ip = request
-                    .headers
-                    .as_ref()
-                    .map_or(&ip, |headers| {
-                        headers
-                            .get(X_REAL_IP)
-                            .map_or(&ip, |x_real_ip| x_real_ip.get(0).unwrap_or(&ip))
-                    })
-                    .to_string();
-
-                request.set_header(X_FORWARDED_FOR.to_string(), ip);
+                request.set_header(X_FORWARDED_FOR.to_string(), client_ip);
                 request.set_header(X_LAGON_REGION.to_string(), REGION.to_string());
 
+                let mut in_flight = InFlightRequest::new(deployment_id.clone(), Arc::clone(&stats));
+                let cancellation_token =
+                    match request_id_handle.is_empty() {
+                        false => Some(in_flight.cancellable(
+                            request_id_handle.clone(),
+                            Arc::clone(&cancellation_tokens),
+                        )),
+                        true => fallback.as_ref().map(|_| CancellationToken::new()),
+                    };
+                fallback_cancellation_token = cancellation_token.clone();
+
                 let isolate_workers = Arc::clone(&workers);
-                let isolate_sender = workers.entry(deployment_id.clone()).or_insert_with(|| {
-                    let handle = Handle::current();
-                    let (sender, receiver) = flume::unbounded();
-                    let labels = labels.clone();
-
-                    std::thread::Builder::new().name(String::from("isolate-") + deployment.id.as_str()).spawn(move || {
-                        handle.block_on(async move {
-                            increment_gauge!("lagon_isolates", 1.0, &labels);
-                            info!(deployment = deployment.id, function = deployment.function_id, request = request_id; "Creating new isolate");
-
-                            let code = deployment.get_code().unwrap_or_else(|error| {
-                                error!(deployment = deployment.id, request = request_id; "Error while getting deployment code: {}", error);
-
-                                "".into()
-                            });
-                            let options = IsolateOptions::new(code)
-                                .environment_variables(deployment.environment_variables.clone())
-                                .memory(deployment.memory)
-                                .tick_timeout(Duration::from_millis(deployment.tick_timeout as u64))
-                                .total_timeout(Duration::from_millis(
-                                    deployment.total_timeout as u64,
-                                ))
-                                .metadata(Some((
-                                    deployment.id.clone(),
-                                    deployment.function_id.clone(),
-                                )))
-                                .on_drop_callback(Box::new(|metadata| {
-                                    if let Some(metadata) = metadata.as_ref().as_ref() {
-                                        let labels = [
-                                            ("deployment", metadata.0.clone()),
-                                            ("function", metadata.1.clone()),
-                                            ("region", REGION.clone()),
-                                        ];
-
-                                        decrement_gauge!("lagon_isolates", 1.0, &labels);
-                                        info!(deployment = metadata.0, function = metadata.1; "Dropping isolate");
-                                    }
-                                }))
-                                .on_statistics_callback(Box::new(|metadata, statistics| {
-                                    if let Some(metadata) = metadata.as_ref().as_ref() {
-                                        let labels = [
-                                            ("deployment", metadata.0.clone()),
-                                            ("function", metadata.1.clone()),
-                                            ("region", REGION.clone()),
-                                        ];
-
-                                        histogram!(
-                                            "lagon_isolate_memory_usage",
-                                            statistics as f64,
-                                            &labels
-                                        );
-                                    }
-                                }))
-                                .log_sender(log_sender)
-                                .snapshot_blob(SNAPSHOT_BLOB);
-
-                            let mut isolate = Isolate::new(options, receiver);
-                            isolate.evaluate();
-                            isolate.run_event_loop().await;
-
-                            // When the event loop is completed, that means a) the isolate was terminate due to limits
-                            // or b) the isolate was dropped because of cache expiration. In the first case, the isolate
-                            // isn't removed from the workers map
-                            isolate_workers.remove(&deployment.id);
+                let worker_pool = Arc::clone(&workers.entry(deployment_id.clone()).or_insert_with(|| {
+                    let (size, policy) = deployment
+                        .isolate_pool
+                        .map_or((1, BalancingPolicy::default()), |isolate_pool| {
+                            (isolate_pool.size, isolate_pool.policy)
                         });
-                    }).unwrap();
+                    // Dropped with the isolates, so cached responses are never shared between deployments
+                    let fetch_cache = options
+                        .fetch_cache_max_entries
+                        .map(|max_entries| Arc::new(FetchCache::new(max_entries)));
 
-                    sender
-                });
+                    let senders = (0..size).map(|_| {
+                        let deployment = Arc::clone(&deployment);
+                        let request_id = request_id.clone();
+                        let log_sender = log_sender.clone();
+                        let lifecycle_sender = lifecycle_sender.clone();
+                        let handle = Handle::current();
+                        let (sender, receiver) = flume::unbounded();
+                        let labels = labels.clone();
+                        let isolate_stats = Arc::clone(&stats);
+                        let statistics_stats = Arc::clone(&stats);
+                        let bundle_stats = Arc::clone(&stats);
+                        let panic_workers = Arc::clone(&isolate_workers);
+                        let isolate_workers = Arc::clone(&isolate_workers);
+                        let panic_deployment_id = deployment.id.clone();
+                        let error_reporter = Arc::clone(&options.error_reporter);
+                        let source_maps = Arc::clone(&source_maps);
+                        let bundles = Arc::clone(&bundles);
+                        let serverless_options = Arc::clone(&options);
+                        let fetch_cache = fetch_cache.clone();
+                        let startup = IsolateStartup::default();
+                        let startup_timings = Arc::clone(&startup);
 
-                isolate_sender
-                    .send_async(IsolateEvent::Request(IsolateRequest { request, sender }))
-                    .await
-                    .unwrap_or(());
+                        std::thread::Builder::new().name(String::from("isolate-") + deployment.id.as_str()).spawn(move || {
+                            let result = std::panic::catch_unwind(AssertUnwindSafe(|| handle.block_on(async move {
+                                let started_at = Instant::now();
+                                info!(deployment = deployment.id, function = deployment.function_id, request = request_id; "Creating new isolate");
+
+                                let (code, source_map) = match load_bundle(serverless_options.deployment_loader.as_ref(), &bundles, &deployment.id, serverless_options.bundle_load_failure_ttl).await {
+                                    Ok(bundle) => (
+                                        bundle.code.clone(),
+                                        get_source_map(&source_maps, &deployment.id, bundle.source_map.as_deref()),
+                                    ),
+                                    Err(error) => {
+                                        error!(deployment = deployment.id, request = request_id; "Error while loading deployment bundle: {}", error);
+
+                                        isolate_workers.remove(&deployment.id);
+                                        reject_isolate_events(&receiver, &error.to_string());
+                                        return;
+                                    }
+                                };
+
+                                increment_gauge!("lagon_isolates", 1.0, &labels);
+                                let mut options = isolate_options(&deployment, &serverless_options, code)
+                                    .source_map(source_map)
+                                    .on_drop_callback(Box::new(move |metadata| {
+                                        if let Some(metadata) = metadata.as_ref().as_ref() {
+                                            set_memory_usage(&isolate_stats, &metadata.0, 0);
+                                            set_bundle_metadata(&isolate_stats, &metadata.0, None);
+
+                                            let labels = [
+                                                ("deployment", metadata.0.clone()),
+                                                ("function", metadata.1.clone()),
+                                                ("region", REGION.clone()),
+                                            ];
+
+                                            decrement_gauge!("lagon_isolates", 1.0, &labels);
+                                            info!(deployment = metadata.0, function = metadata.1; "Dropping isolate");
+                                        }
+                                    }))
+                                    .on_statistics_callback(Box::new(move |metadata, statistics| {
+                                        if let Some(metadata) = metadata.as_ref().as_ref() {
+                                            set_memory_usage(&statistics_stats, &metadata.0, statistics.memory_usage);
+
+                                            let labels = [
+                                                ("deployment", metadata.0.clone()),
+                                                ("function", metadata.1.clone()),
+                                                ("region", REGION.clone()),
+                                            ];
+
+                                            histogram!(
+                                                "lagon_isolate_memory_usage",
+                                                statistics.memory_usage as f64,
+                                                &labels
+                                            );
+                                        }
+                                    }))
+                                    .log_sender(log_sender)
+                                    .lifecycle_sender(lifecycle_sender);
+
+                                if let Some(fetch_cache) = fetch_cache {
+                                    options = options.fetch_cache(fetch_cache);
+                                }
+
+                                let mut isolate = Isolate::new(options, receiver.clone());
+                                isolate.evaluate();
+
+                                // The bundle can't be used until it's deployed again, or the failure expires. Errors
+                                // thrown by the top-level code are answered by the isolate like the other errors
+                                if let (Some(error), true) = (isolate.get_compilation_error(), isolate.has_compilation_failed()) {
+                                    error!(deployment = deployment.id, request = request_id; "Error while compiling deployment bundle: {}", error);
+
+                                    bundles.set_failure(&deployment.id, error.to_string());
+                                    isolate_workers.remove(&deployment.id);
+                                    reject_isolate_events(&receiver, error);
+                                    return;
+                                }
+
+                                if let Some(bundle) = isolate.get_bundle_metadata() {
+                                    histogram!(
+                                        "lagon_isolate_compilation_time",
+                                        bundle.compilation_time.as_secs_f64(),
+                                        &labels
+                                    );
+                                    histogram!(
+                                        "lagon_isolate_evaluation_time",
+                                        bundle.evaluation_time.as_secs_f64(),
+                                        &labels
+                                    );
+                                }
+
+                                set_bundle_metadata(
+                                    &bundle_stats,
+                                    &deployment.id,
+                                    isolate.get_bundle_metadata().cloned(),
+                                );
+                                startup_timings.set(StartupTimings {
+                                    cold_start: started_at.elapsed(),
+                                    compilation_time: isolate.get_bundle_metadata().map_or(Duration::ZERO, |bundle| bundle.compilation_time),
+                                    ready_at: Instant::now(),
+                                }).unwrap_or(());
+                                isolate.run_event_loop().await;
+                                drop(isolate);
+                                drop(receiver);
+
+                                // When the event loop is completed, that means a) the isolate was terminate due to limits
+                                // or b) the isolate was dropped because of cache expiration. In the first case, the pool
+                                // isn't removed from the workers map, which is done once none of its isolates is running
+                                isolate_workers.remove_if(&deployment.id, |_, pool| pool.is_empty());
+                            })));
+
+                            if let Err(panic) = result {
+                                let message = panic
+                                    .downcast_ref::<&str>()
+                                    .map(|message| message.to_string())
+                                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                                    .unwrap_or_else(|| "Unknown panic".into());
+
+                                error!(deployment = panic_deployment_id; "Isolate panicked: {}", message);
+
+                                error_reporter.report(ErrorReport::new(
+                                    &format!("Isolate panicked: {message}"),
+                                    panic_deployment_id.clone(),
+                                    String::new(),
+                                ));
+
+                                panic_workers.remove_if(&panic_deployment_id, |_, pool| pool.is_empty());
+                            }
+                        }).unwrap();
+
+                        (sender, startup)
+                    }).collect();
+
+                    Arc::new(WorkerPool::new(senders, policy))
+                }));
+
+                // None when all the isolates of the pool have just completed, in which
+                // case the request fails like when its isolate is terminated
+                let isolate_sender = match worker_pool.select() {
+                    Some((isolate_sender, worker_request, startup)) => {
+                        in_flight.worker_request(worker_request);
+
+                        if startup.get().is_none() {
+                            isolate_startup = Some(startup);
+                        }
+
+                        Some(isolate_sender)
+                    }
+                    None => None,
+                };
+                in_flight_request = Some(Arc::new(in_flight));
+
+                let request = IsolateRequest {
+                    request,
+                    sender,
+                    cancellation_token,
+                    memory,
+                    max_body_size,
+                };
+                let event = match websocket {
+                    Some(websocket) => IsolateEvent::WebSocket(request, websocket),
+                    None => IsolateEvent::Request(request),
+                };
+
+                // Only the responses of the isolate are stored
+                if let (Some(edge_cache), Some(cache_key)) = (&edge_cache, cache_key) {
+                    receiver = edge_cache.store(cache_key, receiver, revalidation);
+                }
+
+                if let Some(isolate_sender) = isolate_sender {
+                    dispatched_at = Some(Instant::now());
+                    isolate_sender.send_async(event).await.unwrap_or(());
+                }
+            }
+            Err(error) if error.is::<BodyTooLarge>() => {
+                return reject_body_too_large(&hostname, &request_id);
+            }
+            Err(error) if error.is::<BodyReadTimeout>() => {
+                increment_counter!(
+                    "lagon_ignored_requests",
+                    "reason" => "Body read timeout",
+                    "hostname" => hostname.clone(),
+                    "region" => REGION.clone(),
+                );
+                warn!(hostname = hostname, request = request_id; "Request body timed out while being read");
+
+                return error_page(408, PAGE_408);
             }
             Err(error) => {
                 error!(deployment = &deployment.id, request = request_id; "Error while parsing request: {}", error);
@@ -271,59 +1118,181 @@ async fn handle_request(
         }
     }
 
-    handle_response(
+    let websocket_in_flight_request = in_flight_request.clone();
+    // Only the fallback response is logged if it is served
+    let fallback_access_log = fallback.as_ref().and(access_log.clone());
+
+    let response_options = ResponseOptions {
+        stream_buffering: options.stream_buffering,
+        max_chunk_size: options.stream_max_chunk_size,
+        max_stream_chunks: options.stream_max_chunks,
+        content_digest: options.content_digest,
+        conditional_headers: conditional_headers.clone(),
+        response_headers: response_headers.clone(),
+        range,
+        timeout_response: options.timeout_response.clone(),
+        utf8_policy: options.utf8_policy,
+        header_filter: deployment.header_filter.clone(),
+        chunk_transform: None,
+        empty_response_policy: options.empty_response_policy,
+    };
+
+    let response = handle_response_with_options(
         receiver,
-        (
-            function_id,
+        RequestContext::with_data(
             deployment_id,
-            bytes_in,
+            function_id,
             request_id_handle,
-            labels,
-            inserters,
+            ResponseEventData {
+                bytes_in,
+                labels,
+                inserters,
+                _in_flight_request: in_flight_request,
+                error_reporter: Arc::clone(&options.error_reporter),
+                access_log,
+                timeout_status: options
+                    .timeout_response
+                    .as_ref()
+                    .map_or(502, |timeout_response| timeout_response.status),
+            },
         ),
-        Box::new(
-            |event, (function_id, deployment_id, bytes_in, request_id, labels, inserters)| {
-                Box::pin(async move {
-                    match event {
-                        ResponseEvent::Bytes(bytes, cpu_time_micros) => {
-                            inserters
-                                .lock()
-                                .await
-                                .0
-                                .write(&RequestRow {
-                                    function_id,
-                                    deployment_id,
-                                    region: REGION.clone(),
-                                    bytes_in,
-                                    bytes_out: bytes as u32,
-                                    cpu_time_micros,
-                                    timestamp: UNIX_EPOCH.elapsed().unwrap().as_secs() as u32,
-                                })
-                                .await?;
-                        }
-                        ResponseEvent::StreamDoneNoDataError => {
-                            handle_error(
-                                RunResult::Error(
-                                    "The stream was done before sending a response/data".into(),
-                                ),
-                                &deployment_id,
-                                &request_id,
-                                &labels,
-                            );
-                        }
-                        ResponseEvent::UnexpectedStreamResult(result)
-                        | ResponseEvent::LimitsReached(result)
-                        | ResponseEvent::Error(result) => {
-                            handle_error(result, &deployment_id, &request_id, &labels);
-                        }
+        Box::new(|event, context| {
+            Box::pin(async move {
+                match event {
+                    ResponseEvent::Bytes(bytes, cpu_time_micros, status) => {
+                        write_access_log(context.access_log.clone(), status, bytes);
+
+                        context
+                            .inserters
+                            .lock()
+                            .await
+                            .0
+                            .write(&RequestRow {
+                                function_id: context.function_id().to_string(),
+                                deployment_id: context.deployment_id().to_string(),
+                                region: REGION.clone(),
+                                bytes_in: context.bytes_in,
+                                bytes_out: bytes as u32,
+                                cpu_time_micros,
+                                timestamp: UNIX_EPOCH.elapsed().unwrap().as_secs() as u32,
+                            })
+                            .await?;
+                    }
+                    ResponseEvent::StreamDoneNoDataError => {
+                        handle_error(
+                            RunResult::Error(
+                                "The stream was done before sending a response/data".into(),
+                            ),
+                            &context,
+                        );
+                    }
+                    ResponseEvent::UnexpectedStreamResult(result) => {
+                        handle_error(result, &context);
                     }
+                    ResponseEvent::LimitsReached(result) | ResponseEvent::Error(result) => {
+                        // Same status as the error pages
+                        let status = match result {
+                            RunResult::Error(_) => 500,
+                            RunResult::LoadError(_) => 503,
+                            RunResult::Timeout => context.timeout_status,
+                            _ => 502,
+                        };
 
-                    Ok(())
-                })
-            },
-        ),
-    )
-    .await
+                        write_access_log(context.access_log.clone(), status, 0);
+                        handle_error(result, &context);
+                    }
+                    ResponseEvent::HeadersStripped(names) => {
+                        warn!(
+                            deployment = context.deployment_id(),
+                            request = context.request_id();
+                            "Stripped response headers not allowed by the header filter: {}",
+                            names.join(", ")
+                        );
+                    }
+                }
+
+                Ok(())
+            })
+        }),
+        response_options,
+    );
+
+    // The client doesn't wait for the refresh of a stale response
+    if let Some(stale_response) = stale_response {
+        tokio::spawn(async move {
+            response.await.ok();
+        });
+
+        return Ok(stale_response);
+    }
+
+    let mut response = match (&fallback, &fallback_cancellation_token) {
+        // The handshake response of a WebSocket can't be replaced
+        (Some(fallback), Some(cancellation_token)) if websocket_upgrade.is_none() => {
+            tokio::pin!(response);
+
+            match tokio::time::timeout(fallback.deadline, &mut response).await {
+                Ok(response) => response?,
+                Err(_) => match get_fallback_response(fallback, &edge_cache, &fallback_cache_key) {
+                    Some((fallback_response, source)) => {
+                        cancellation_token.cancel();
+
+                        increment_counter!(
+                            "lagon_fallback_responses",
+                            "hostname" => hostname.clone(),
+                            "source" => source,
+                            "region" => REGION.clone(),
+                        );
+                        warn!(hostname = hostname, request = fallback_request_id; "Function exceeded its deadline, serving the fallback response");
+
+                        return serve_cached_response(
+                            fallback_response,
+                            &conditional_headers,
+                            &response_headers,
+                            fallback_access_log,
+                        );
+                    }
+                    None => response.await?,
+                },
+            }
+        }
+        _ => response.await?,
+    };
+
+    if deployment.server_timing {
+        let mut server_timing = ServerTiming::default();
+        let startup = isolate_startup
+            .as_ref()
+            .and_then(|isolate_startup| isolate_startup.get());
+
+        if let Some(startup) = startup {
+            server_timing.add("cold-start", startup.cold_start);
+            server_timing.add("compile", startup.compilation_time);
+        }
+
+        // Requests waiting for their isolate only run once it's ready
+        if let Some(dispatched_at) = dispatched_at {
+            let started_at = startup.map_or(dispatched_at, |startup| {
+                startup.ready_at.max(dispatched_at)
+            });
+
+            server_timing.add("exec", started_at.elapsed());
+        }
+
+        server_timing.add("total", received_at.elapsed());
+        server_timing.append_to(response.headers_mut());
+    }
+
+    if let Some(websocket_upgrade) = websocket_upgrade {
+        websocket_upgrade.accept(
+            &mut response,
+            options.websocket_idle_timeout,
+            options.websocket_max_duration,
+            websocket_in_flight_request,
+        );
+    }
+
+    Ok(response)
 }
 
 pub async fn start<D, P>(
@@ -332,25 +1301,72 @@ pub async fn start<D, P>(
     downloader: Arc<D>,
     pubsub: P,
     client: Client,
+    options: ServerlessOptions,
     // cronjob: Arc<Mutex<Cronjob>>,
 ) -> Result<impl Future<Output = ()> + Send>
 where
     D: Downloader + Send + Sync + 'static,
     P: PubSubListener + Unpin + 'static,
 {
+    let shutdown = options.shutdown.clone();
+    let shutdown_grace_period = options.shutdown_grace_period;
+
+    // New connections are still accepted during the drain delay while the health
+    // requests already fail, giving the load balancers time to stop routing here
+    let stop_accepting = CancellationToken::new();
+    let shutdown_drain_delay = options.shutdown_drain_delay;
+    let stop_accepting_handle = stop_accepting.clone();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        tokio::time::sleep(shutdown_drain_delay).await;
+        stop_accepting_handle.cancel();
+    });
+    let options = Arc::new(options);
     let last_requests = Arc::new(DashMap::new());
 
     let workers = Arc::new(DashMap::new());
+    let source_maps = Arc::new(DashMap::new());
+    let bundles = Arc::new(BundleCache::default());
+    let stats = Arc::new(DashMap::new());
+    let rate_limiter = Arc::new(RateLimiter::default());
+    let admission_queue = options
+        .max_concurrent_requests
+        .map(|max_concurrent_requests| {
+            Arc::new(AdmissionQueue::new(
+                max_concurrent_requests,
+                options.max_queued_requests,
+            ))
+        });
+    let edge_cache = options
+        .edge_cache_max_entries
+        .map(|max_entries| Arc::new(EdgeCache::new(max_entries)));
+    let cancellation_tokens = Arc::new(DashMap::new());
+    let maintenances = Arc::new(DashMap::new());
+    let pauses = Arc::new(DashMap::new());
     let pubsub = Arc::new(Mutex::new(pubsub));
 
     listen_pub_sub(
         Arc::clone(&downloader),
         Arc::clone(&deployments),
         Arc::clone(&workers),
+        Arc::clone(&source_maps),
+        Arc::clone(&bundles),
+        Arc::clone(&maintenances),
         // Arc::clone(&cronjob),
         pubsub,
     );
-    run_cache_clear_task(Arc::clone(&last_requests), Arc::clone(&workers));
+    run_cache_clear_task(
+        Arc::clone(&last_requests),
+        Arc::clone(&workers),
+        Arc::clone(&stats),
+        options.isolates_idle_ttl,
+        options.min_warm_isolates,
+    );
+    run_rate_limiter_cleanup_task(Arc::clone(&rate_limiter));
+
+    if let Some(edge_cache) = &edge_cache {
+        run_edge_cache_prune_task(Arc::clone(edge_cache));
+    }
 
     let insertion_interval = Duration::from_secs(1);
     let inserters = Arc::new(Mutex::new((
@@ -380,9 +1396,24 @@ where
     });
 
     let (log_sender, log_receiver) = flume::unbounded::<(String, String, Metadata)>();
+    let log_tail = new_log_tail();
+    let log_tail_handle = log_tail.clone();
     let inserters_handle = Arc::clone(&inserters);
     tokio::spawn(async move {
         while let Ok(log) = log_receiver.recv_async().await {
+            // Only fails when no client is tailing the logs
+            if let Some(metadata) = log.2.as_ref() {
+                log_tail_handle
+                    .send(TailedLog {
+                        deployment: metadata.0.clone(),
+                        function: metadata.1.clone(),
+                        level: log.0.clone(),
+                        message: log.1.clone(),
+                        timestamp: UNIX_EPOCH.elapsed().unwrap().as_millis(),
+                    })
+                    .ok();
+            }
+
             let mut inserters = inserters_handle.lock().await;
 
             if let Err(error) = inserters
@@ -408,34 +1439,175 @@ where
         }
     });
 
-    let server = Server::bind(&addr).serve(make_service_fn(move |conn: &AddrStream| {
-        let deployments = Arc::clone(&deployments);
-        let last_requests = Arc::clone(&last_requests);
-        let workers = Arc::clone(&workers);
-        let inserters = Arc::clone(&inserters);
-        let log_sender = log_sender.clone();
+    let (lifecycle_sender, lifecycle_receiver) =
+        flume::unbounded::<(IsolateLifecycleEvent, Metadata)>();
+    tokio::spawn(async move {
+        while let Ok((event, metadata)) = lifecycle_receiver.recv_async().await {
+            // Isolates are always created with their deployment as metadata
+            if let Some((deployment, function)) = metadata {
+                let labels = [
+                    ("deployment", deployment),
+                    ("function", function),
+                    ("region", REGION.clone()),
+                ];
+
+                match event {
+                    IsolateLifecycleEvent::Created { cold_start_us } => {
+                        histogram!(
+                            "lagon_isolate_cold_start",
+                            cold_start_us as f64 / 1_000_000.0,
+                            &labels
+                        );
+                    }
+                    IsolateLifecycleEvent::Warmed => {
+                        increment_counter!("lagon_isolate_warmed", &labels);
+                    }
+                    IsolateLifecycleEvent::RequestStarted => {
+                        increment_gauge!("lagon_isolate_requests", 1.0, &labels);
+                    }
+                    IsolateLifecycleEvent::RequestFinished { timings } => {
+                        decrement_gauge!("lagon_isolate_requests", 1.0, &labels);
+                        histogram!(
+                            "lagon_isolate_handler_time",
+                            timings.handler_time.as_secs_f64(),
+                            &labels
+                        );
+                    }
+                    IsolateLifecycleEvent::Disposed { reason } => {
+                        let [deployment, function, region] = labels;
+
+                        increment_counter!(
+                            "lagon_isolate_disposals",
+                            &[
+                                deployment,
+                                function,
+                                region,
+                                ("reason", reason.as_str().to_string()),
+                            ]
+                        );
+                    }
+                }
+            }
+        }
+    });
+
+    let connection_limiter = options.max_connections_per_ip.map(|max_connections| {
+        let exempted = match options.limit_trusted_proxies_connections {
+            true => Vec::new(),
+            false => options.trusted_proxies.clone(),
+        };
+
+        Arc::new(ConnectionLimiter::new(max_connections, exempted))
+    });
+    let mut incoming = LimitedIncoming::new(AddrIncoming::bind(&addr)?, connection_limiter);
+
+    if let Some(idle_connection_timeout) = options.idle_connection_timeout {
+        let idle_connections = Arc::new(IdleConnections::new(idle_connection_timeout));
+        incoming = incoming.idle_connections(Arc::clone(&idle_connections));
+
+        run_idle_connections_reaper(idle_connections);
+    }
+
+    let incoming = ServerIncoming::new(incoming, options.tls.clone().map(tls_acceptor));
+
+    let handler = RequestHandler {
+        options: Arc::clone(&options),
+        deployments,
+        last_requests,
+        workers,
+        source_maps,
+        bundles,
+        stats,
+        rate_limiter,
+        admission_queue,
+        edge_cache,
+        cancellation_tokens,
+        maintenances,
+        pauses,
+        inserters,
+        log_sender,
+        lifecycle_sender,
+        log_tail,
+    };
+
+    let http3_server = match (options.http3_listen_addr, &options.tls) {
+        (Some(http3_listen_addr), Some(tls)) => Some(http3_server(
+            http3_listen_addr,
+            Arc::clone(tls),
+            handler.clone(),
+            stop_accepting.clone(),
+        )?),
+        (Some(_), None) => return Err(anyhow!("HTTP/3 requires TLS to be configured")),
+        (None, _) => None,
+    };
+    // Tells the clients they can switch to HTTP/3 for the next requests
+    let alt_svc = options
+        .http3_listen_addr
+        .map(|http3_listen_addr| alt_svc_header(http3_listen_addr.port()));
+
+    // HTTP/1.1 connections handle a single request at a time, so the responses
+    // of pipelined requests are always sent in the same order as the requests.
+    // HTTP/2 is used with prior knowledge, or when negotiated over TLS
+    let builder = Server::builder(incoming)
+        .http2_max_concurrent_streams(options.http2_max_concurrent_streams)
+        .http2_max_pending_accept_reset_streams(options.http2_max_pending_reset_streams);
+
+    let server = builder.serve(make_service_fn(move |conn: &ServerStream| {
+        let handler = handler.clone();
+        let alt_svc = alt_svc.clone();
 
-        let addr = conn.remote_addr();
-        let ip = addr.ip().to_string();
+        let peer_ip = conn.remote_addr().ip();
+        let activity = conn.activity();
 
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
-                handle_request(
-                    req,
-                    ip.clone(),
-                    Arc::clone(&deployments),
-                    Arc::clone(&last_requests),
-                    Arc::clone(&workers),
-                    Arc::clone(&inserters),
-                    log_sender.clone(),
-                )
+                let request_guard = activity.as_ref().map(|activity| activity.start_request());
+                let response = handler.clone().handle(req, peer_ip);
+                let alt_svc = alt_svc.clone();
+
+                async move {
+                    let mut response = response.await;
+
+                    if let (Ok(response), Some(alt_svc)) = (&mut response, alt_svc) {
+                        response.headers_mut().entry(ALT_SVC).or_insert(alt_svc);
+                    }
+
+                    // The request is over once the whole body has been sent
+                    response.map(|response| {
+                        response.map(|body| GuardedBody::new(body, request_guard))
+                    })
+                }
             }))
         }
     }));
 
-    Ok(async move {
-        if let Err(error) = server.await {
+    // Stop accepting new connections once the drain delay elapsed, and
+    // wait for the in-flight requests (including streams) to complete
+    let stop_accepting_handle = stop_accepting.clone();
+    let server =
+        server.with_graceful_shutdown(async move { stop_accepting_handle.cancelled().await });
+
+    let servers = async move {
+        let http3 = async move {
+            if let Some(http3_server) = http3_server {
+                http3_server.await;
+            }
+        };
+
+        if let (Err(error), ()) = tokio::join!(server, http3) {
             error!("Server error: {}", error);
         }
+    };
+
+    Ok(async move {
+        tokio::select! {
+            _ = servers => {}
+            _ = async {
+                stop_accepting.cancelled().await;
+                tokio::time::sleep(shutdown_grace_period).await;
+            } => {
+                warn!("Shutdown grace period elapsed, dropping remaining connections");
+            }
+        }
     })
 }
@@ -0,0 +1,332 @@
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
+use futures::{stream::FuturesUnordered, Future, StreamExt};
+use hyper::server::accept::Accept;
+use log::debug;
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{any_supported_type, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig,
+};
+use rustls_pemfile::Item;
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+use crate::{
+    connection_limit::{LimitedIncoming, LimitedStream},
+    idle_connections::ConnectionActivity,
+};
+
+// Connections not completing their handshake in time are closed
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// The certificate served when the SNI doesn't match any domain
+const DEFAULT_CERTIFICATE: &str = "default";
+
+// Parse a PEM file containing a certificate chain and its private key
+pub fn load_certified_key(pem: &[u8]) -> Result<Arc<CertifiedKey>> {
+    let mut certificates = Vec::new();
+    let mut private_key = None;
+
+    for item in rustls_pemfile::read_all(&mut &pem[..])? {
+        match item {
+            Item::X509Certificate(certificate) => certificates.push(Certificate(certificate)),
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                private_key.get_or_insert(PrivateKey(key));
+            }
+            _ => {}
+        }
+    }
+
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificate found"));
+    }
+
+    let private_key = private_key.ok_or_else(|| anyhow!("No private key found"))?;
+    let signing_key =
+        any_supported_type(&private_key).map_err(|_| anyhow!("Unsupported private key"))?;
+
+    Ok(Arc::new(CertifiedKey::new(certificates, signing_key)))
+}
+
+// Certificates of the domains served over TLS, selected using the SNI of the
+// handshakes. They can be replaced at any time, e.g when a custom domain is
+// added or renewed, without restarting the server
+#[derive(Default)]
+pub struct CertificateStore {
+    // Wildcard certificates are stored as `*.example.com`
+    certificates: DashMap<String, Arc<CertifiedKey>>,
+    default: RwLock<Option<Arc<CertifiedKey>>>,
+    // Loaded from `<domain>.pem` files, and `default.pem` for the default certificate
+    dir: Option<PathBuf>,
+}
+
+impl CertificateStore {
+    pub fn from_dir(dir: PathBuf) -> Result<Self> {
+        let store = Self {
+            dir: Some(dir),
+            ..Default::default()
+        };
+
+        store.reload()?;
+
+        Ok(store)
+    }
+
+    pub fn insert(&self, domain: &str, certified_key: Arc<CertifiedKey>) {
+        self.certificates
+            .insert(domain.to_ascii_lowercase(), certified_key);
+    }
+
+    pub fn remove(&self, domain: &str) -> bool {
+        self.certificates
+            .remove(&domain.to_ascii_lowercase())
+            .is_some()
+    }
+
+    pub fn set_default(&self, certified_key: Option<Arc<CertifiedKey>>) {
+        *self.default.write().unwrap() = certified_key;
+    }
+
+    // Exact domains are preferred over wildcards, which only match a single label
+    pub fn get(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        if let Some(server_name) = server_name.map(str::to_ascii_lowercase) {
+            if let Some(certified_key) = self.certificates.get(&server_name) {
+                return Some(Arc::clone(&certified_key));
+            }
+
+            if let Some((_, parent)) = server_name.split_once('.') {
+                if let Some(certified_key) = self.certificates.get(&format!("*.{parent}")) {
+                    return Some(Arc::clone(&certified_key));
+                }
+            }
+        }
+
+        self.default.read().unwrap().clone()
+    }
+
+    // Replace all the certificates with the ones of the directory. Nothing is
+    // replaced if any of them is invalid. A no-op without a directory
+    pub fn reload(&self) -> Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        let mut certificates = read_certificates_dir(dir)?;
+
+        self.set_default(certificates.remove(DEFAULT_CERTIFICATE));
+        self.certificates
+            .retain(|domain, _| certificates.contains_key(domain));
+
+        for (domain, certified_key) in certificates {
+            self.certificates.insert(domain, certified_key);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_certificates_dir(dir: &Path) -> Result<HashMap<String, Arc<CertifiedKey>>> {
+    let mut certificates = HashMap::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "pem")
+        {
+            continue;
+        }
+
+        let domain = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(domain) => domain.to_ascii_lowercase(),
+            None => continue,
+        };
+
+        let certified_key = load_certified_key(&fs::read(&path)?)
+            .map_err(|error| anyhow!("Invalid certificate {}: {}", path.display(), error))?;
+
+        certificates.insert(domain, certified_key);
+    }
+
+    Ok(certificates)
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.get(client_hello.server_name())
+    }
+}
+
+pub fn tls_acceptor(store: Arc<CertificateStore>) -> TlsAcceptor {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(store);
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    TlsAcceptor::from(Arc::new(config))
+}
+
+type Handshake = Pin<Box<dyn Future<Output = io::Result<TlsStream<LimitedStream>>> + Send>>;
+
+// Accepts the connections over TLS when an acceptor is set. The handshakes
+// run concurrently, so a slow client doesn't delay the other connections
+pub struct ServerIncoming {
+    incoming: LimitedIncoming,
+    acceptor: Option<TlsAcceptor>,
+    handshakes: FuturesUnordered<Handshake>,
+    closed: bool,
+}
+
+impl ServerIncoming {
+    pub fn new(incoming: LimitedIncoming, acceptor: Option<TlsAcceptor>) -> Self {
+        Self {
+            incoming,
+            acceptor,
+            handshakes: FuturesUnordered::new(),
+            closed: false,
+        }
+    }
+}
+
+impl Accept for ServerIncoming {
+    type Conn = ServerStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        while !self.closed {
+            let stream = match Pin::new(&mut self.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(stream))) => stream,
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error))),
+                Poll::Ready(None) => {
+                    self.closed = true;
+                    break;
+                }
+                Poll::Pending => break,
+            };
+
+            let acceptor = match &self.acceptor {
+                Some(acceptor) => acceptor.clone(),
+                None => return Poll::Ready(Some(Ok(ServerStream::Plain(stream)))),
+            };
+
+            self.handshakes.push(Box::pin(async move {
+                tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                    .await
+                    .map_err(|_| {
+                        io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")
+                    })?
+            }));
+        }
+
+        loop {
+            match self.handshakes.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(stream))) => {
+                    return Poll::Ready(Some(Ok(ServerStream::Tls(Box::new(stream)))))
+                }
+                // Dropping the stream closes the connection
+                Poll::Ready(Some(Err(error))) => {
+                    debug!("TLS handshake failed: {}", error);
+                }
+                Poll::Ready(None) if self.closed => return Poll::Ready(None),
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+pub enum ServerStream {
+    Plain(LimitedStream),
+    Tls(Box<TlsStream<LimitedStream>>),
+}
+
+impl ServerStream {
+    fn stream(&self) -> &LimitedStream {
+        match self {
+            ServerStream::Plain(stream) => stream,
+            ServerStream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.stream().remote_addr()
+    }
+
+    pub fn activity(&self) -> Option<Arc<ConnectionActivity>> {
+        self.stream().activity()
+    }
+}
+
+impl AsyncRead for ServerStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            ServerStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ServerStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ServerStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            ServerStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ServerStream::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            ServerStream::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            ServerStream::Plain(stream) => stream.is_write_vectored(),
+            ServerStream::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            ServerStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ServerStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            ServerStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use futures::{SinkExt, StreamExt};
+use hyper::{
+    header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE},
+    upgrade::OnUpgrade,
+    Body, Request as HyperRequest, Response as HyperResponse,
+};
+use lagon_runtime_http::WebSocketMessage;
+use lagon_runtime_isolate::IsolateWebSocket;
+use log::error;
+use std::time::Duration;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+
+// The host side of a WebSocket connection, before it's upgraded
+pub struct WebSocketUpgrade {
+    key: String,
+    on_upgrade: OnUpgrade,
+    incoming: flume::Sender<WebSocketMessage>,
+    outgoing: flume::Receiver<WebSocketMessage>,
+}
+
+fn has_header_token(
+    req: &HyperRequest<Body>,
+    name: hyper::header::HeaderName,
+    token: &str,
+) -> bool {
+    req.headers().get_all(name).iter().any(|value| {
+        value.to_str().map_or(false, |value| {
+            value
+                .split(',')
+                .any(|value| value.trim().eq_ignore_ascii_case(token))
+        })
+    })
+}
+
+// Accept a WebSocket handled by the host, returning the 101 response. The
+// connection can be used once the response has been sent
+pub fn accept_host_websocket(
+    req: &mut HyperRequest<Body>,
+) -> Option<(HyperResponse<Body>, OnUpgrade)> {
+    if !has_header_token(req, UPGRADE, "websocket") || !has_header_token(req, CONNECTION, "upgrade")
+    {
+        return None;
+    }
+
+    let accept = derive_accept_key(req.headers().get(SEC_WEBSOCKET_KEY)?.as_bytes());
+    let response = HyperResponse::builder()
+        .status(101)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "Upgrade")
+        .header(SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .ok()?;
+
+    Some((response, hyper::upgrade::on(req)))
+}
+
+// Take the upgrade from a request asking for a WebSocket, and
+// create the channels used to talk with the isolate
+pub fn get_websocket_upgrade(
+    req: &mut HyperRequest<Body>,
+) -> Option<(WebSocketUpgrade, IsolateWebSocket)> {
+    if !has_header_token(req, UPGRADE, "websocket") || !has_header_token(req, CONNECTION, "upgrade")
+    {
+        return None;
+    }
+
+    let key = req
+        .headers()
+        .get(SEC_WEBSOCKET_KEY)?
+        .to_str()
+        .ok()?
+        .to_string();
+    let on_upgrade = hyper::upgrade::on(req);
+
+    let (incoming_sender, incoming_receiver) = flume::unbounded();
+    let (outgoing_sender, outgoing_receiver) = flume::unbounded();
+
+    Some((
+        WebSocketUpgrade {
+            key,
+            on_upgrade,
+            incoming: incoming_sender,
+            outgoing: outgoing_receiver,
+        },
+        IsolateWebSocket {
+            incoming: incoming_receiver,
+            outgoing: outgoing_sender,
+        },
+    ))
+}
+
+impl WebSocketUpgrade {
+    // Complete the handshake if the function accepted the upgrade by returning
+    // a 101 response, and forward messages until the connection is closed
+    pub fn accept<T>(
+        self,
+        response: &mut HyperResponse<Body>,
+        idle_timeout: Duration,
+        max_duration: Duration,
+        guard: T,
+    ) where
+        T: Send + 'static,
+    {
+        if response.status() != 101 {
+            return;
+        }
+
+        let headers = response.headers_mut();
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        headers.insert(CONNECTION, "Upgrade".parse().unwrap());
+        headers.insert(
+            SEC_WEBSOCKET_ACCEPT,
+            derive_accept_key(self.key.as_bytes()).parse().unwrap(),
+        );
+
+        tokio::spawn(async move {
+            let upgraded = match self.on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(error) => {
+                    error!("Error while upgrading WebSocket: {}", error);
+                    return;
+                }
+            };
+
+            let mut stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+            let max_duration = tokio::time::sleep(max_duration);
+            tokio::pin!(max_duration);
+
+            loop {
+                tokio::select! {
+                    _ = &mut max_duration => break,
+                    message = tokio::time::timeout(idle_timeout, stream.next()) => {
+                        let message = match message {
+                            Ok(Some(Ok(Message::Text(text)))) => WebSocketMessage::Text(text),
+                            Ok(Some(Ok(Message::Binary(bytes)))) => WebSocketMessage::Binary(bytes),
+                            // Pings are answered by tungstenite
+                            Ok(Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)))) => continue,
+                            // Closed by the client, errored, or idle for too long
+                            _ => break,
+                        };
+
+                        if self.incoming.send_async(message).await.is_err() {
+                            break;
+                        }
+                    }
+                    message = self.outgoing.recv_async() => {
+                        let message = match message {
+                            Ok(WebSocketMessage::Text(text)) => Message::Text(text),
+                            Ok(WebSocketMessage::Binary(bytes)) => Message::Binary(bytes),
+                            // Closed by the function, or the isolate was dropped
+                            Ok(WebSocketMessage::Close) | Err(_) => break,
+                        };
+
+                        if stream.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            stream.close(None).await.unwrap_or(());
+
+            // Keep the deployment's request in-flight until the connection is closed
+            drop(guard);
+        });
+    }
+}
@@ -0,0 +1,123 @@
+use lagon_serverless::access_log::{
+    default_access_log_formatters, parse_sample_rate, AccessLogEntry, AccessLogFormatter,
+    AccessLogSampling, CommonLogFormatter, JsonFormatter,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+fn entry() -> AccessLogEntry {
+    AccessLogEntry {
+        // 2023-04-22T10:30:00.123Z
+        timestamp: UNIX_EPOCH + Duration::from_millis(1682159400123),
+        deployment_id: "deployment".into(),
+        request_id: "request".into(),
+        client_ip: "1.2.3.4".into(),
+        method: "GET".into(),
+        path: "/hello?world=true".into(),
+        version: "HTTP/1.1".into(),
+        status: 200,
+        bytes: 11,
+        duration: Duration::from_micros(1500),
+    }
+}
+
+#[test]
+fn json_format() {
+    let line = JsonFormatter.format(&entry());
+
+    assert!(!line.contains('\n'));
+    assert_eq!(
+        serde_json::from_str::<Value>(&line).unwrap(),
+        json!({
+            "timestamp": "2023-04-22T10:30:00.123Z",
+            "deployment": "deployment",
+            "request": "request",
+            "ip": "1.2.3.4",
+            "method": "GET",
+            "path": "/hello?world=true",
+            "version": "HTTP/1.1",
+            "status": 200,
+            "bytes": 11,
+            "duration_ms": 1.5,
+        })
+    );
+}
+
+#[test]
+fn common_log_format() {
+    assert_eq!(
+        CommonLogFormatter.format(&entry()),
+        "1.2.3.4 - - [22/Apr/2023:10:30:00 +0000] \"GET /hello?world=true HTTP/1.1\" 200 11"
+    );
+
+    // Empty bodies are logged as `-`
+    let mut entry = entry();
+    entry.status = 304;
+    entry.bytes = 0;
+
+    assert_eq!(
+        CommonLogFormatter.format(&entry),
+        "1.2.3.4 - - [22/Apr/2023:10:30:00 +0000] \"GET /hello?world=true HTTP/1.1\" 304 -"
+    );
+}
+
+#[test]
+fn custom_formatter() {
+    struct StatusFormatter;
+
+    impl AccessLogFormatter for StatusFormatter {
+        fn format(&self, entry: &AccessLogEntry) -> String {
+            format!("status={}", entry.status)
+        }
+    }
+
+    let mut formatters = default_access_log_formatters();
+    formatters.insert("status".into(), Arc::new(StatusFormatter));
+
+    assert!(formatters.contains_key("json"));
+    assert!(formatters.contains_key("clf"));
+    assert_eq!(formatters["status"].format(&entry()), "status=200");
+}
+
+#[test]
+fn sample_rate() {
+    assert_eq!(parse_sample_rate("0.1").unwrap(), 0.1);
+    assert_eq!(parse_sample_rate("10%").unwrap(), 0.1);
+    assert_eq!(parse_sample_rate("1/10").unwrap(), 0.1);
+    assert!(parse_sample_rate("150%").is_err());
+    assert!(parse_sample_rate("abc").is_err());
+}
+
+#[test]
+fn sampling() {
+    let sampling = AccessLogSampling::new(0.1);
+    let logged = (0..10_000)
+        .filter(|_| sampling.should_log("request", 200))
+        .count();
+
+    assert!((800..1200).contains(&logged), "logged {} requests", logged);
+
+    // Failed requests are always logged
+    assert!((0..1_000).all(|_| sampling.should_log("request", 500)));
+    assert!((0..1_000).all(|_| AccessLogSampling::new(0.0).should_log("request", 502)));
+    assert!(!AccessLogSampling::new(0.0).should_log("request", 200));
+}
+
+#[test]
+fn sampling_by_request_id() {
+    let sampling = AccessLogSampling::new(0.1).by_request_id(true);
+    let logged = (0..10_000)
+        .filter(|id| sampling.should_log(&id.to_string(), 200))
+        .count();
+
+    assert!((800..1200).contains(&logged), "logged {} requests", logged);
+
+    // The decision is the same for a given request id
+    for id in 0..100 {
+        let id = id.to_string();
+        assert_eq!(sampling.should_log(&id, 200), sampling.should_log(&id, 200));
+    }
+}
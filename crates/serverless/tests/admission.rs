@@ -0,0 +1,105 @@
+use anyhow::Result;
+use lagon_serverless::{
+    admission::{parse_priority, Priority},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+use std::time::Duration;
+
+mod utils;
+
+fn get(name: &'static str, priority: &'static str, sender: flume::Sender<&'static str>) {
+    tokio::spawn(async move {
+        let response = reqwest::Client::new()
+            .get("http://127.0.0.1:4000")
+            .header("priority", priority)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "Hello world");
+
+        sender.send_async(name).await.unwrap();
+    });
+}
+
+#[test]
+fn parse_priority_header() {
+    assert_eq!(parse_priority(""), Priority::default());
+    assert_eq!(
+        parse_priority("u=0, i"),
+        Priority {
+            urgency: 0,
+            incremental: true,
+        }
+    );
+    assert_eq!(
+        parse_priority("i=?0,u=7"),
+        Priority {
+            urgency: 7,
+            incremental: false,
+        }
+    );
+    assert_eq!(parse_priority("u=8, foo=bar"), Priority::default());
+}
+
+#[tokio::test]
+#[serial]
+async fn urgent_requests_first() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("sleep"),
+        ServerlessOptions::default().max_concurrent_requests(1),
+    )
+    .await?;
+
+    let (sender, receiver) = flume::unbounded();
+
+    // Saturate the node, then queue a low and a high urgency request
+    get("running", "u=3", sender.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    get("low", "u=7", sender.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    get("high", "u=0", sender);
+
+    assert_eq!(receiver.recv_async().await?, "running");
+    assert_eq!(receiver.recv_async().await?, "high");
+    assert_eq!(receiver.recv_async().await?, "low");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn excess_requests_queued_then_shed() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("sleep"),
+        ServerlessOptions::default()
+            .max_concurrent_requests(1)
+            .max_queued_requests(1),
+    )
+    .await?;
+
+    let (sender, receiver) = flume::unbounded();
+
+    get("running", "u=3", sender.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    get("queued", "u=3", sender);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Both the concurrency limit and the queue are full
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+
+    assert_eq!(receiver.recv_async().await?, "running");
+
+    // The queued request only starts once the running one is done
+    let started = std::time::Instant::now();
+    assert_eq!(receiver.recv_async().await?, "queued");
+    assert!(started.elapsed() >= Duration::from_millis(400));
+
+    Ok(())
+}
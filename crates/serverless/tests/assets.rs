@@ -1,48 +1,22 @@
 use anyhow::Result;
-use dashmap::DashMap;
-use lagon_runtime_utils::Deployment;
-use lagon_serverless::serverless::start;
-use lagon_serverless_downloader::FakeDownloader;
-use lagon_serverless_pubsub::FakePubSub;
+use lagon_runtime_utils::{assets::AssetsCacheControl, Deployment};
+use lagon_serverless::options::ServerlessOptions;
 use serial_test::serial;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use std::collections::HashSet;
 
 mod utils;
 
 #[tokio::test]
 #[serial]
 async fn html_assets() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "assets".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
+    utils::start_serverless(
+        Deployment {
             assets: HashSet::from(["hello.html".into(), "world/index.html".into()]),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("assets")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -71,34 +45,14 @@ async fn html_assets() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn assets_nested() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "assets".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
+    utils::start_serverless(
+        Deployment {
             assets: HashSet::from(["index.css".into(), "static/app.js".into()]),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("assets")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000/index.css").await?;
     assert_eq!(response.status(), 200);
@@ -120,38 +74,18 @@ async fn assets_nested() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn set_content_type() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "assets".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
+    utils::start_serverless(
+        Deployment {
             assets: HashSet::from([
                 "hello.html".into(),
                 "index.css".into(),
                 "static/app.js".into(),
             ]),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("assets")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     // TODO: set default content type?
     // let response = reqwest::get("http://127.0.0.1:4000").await?;
@@ -172,3 +106,49 @@ async fn set_content_type() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn assets_cache_control() -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            assets: HashSet::from([
+                "world/index.html".into(),
+                "static/app.js".into(),
+                "static/app.3f9a1c2e.js".into(),
+                "index.css".into(),
+            ]),
+            ..utils::deployment("assets")
+        },
+        ServerlessOptions::default().assets_cache_control(
+            AssetsCacheControl::default()
+                .override_pattern("*.css".into(), "public, max-age=60".into()),
+        ),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000/static/app.3f9a1c2e.js").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("cache-control").unwrap(),
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(response.text().await?, "console.log('hashed');\n");
+
+    let response = reqwest::get("http://127.0.0.1:4000/world").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-cache");
+
+    let response = reqwest::get("http://127.0.0.1:4000/static/app.js").await?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("cache-control").is_none());
+
+    let response = reqwest::get("http://127.0.0.1:4000/index.css").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("cache-control").unwrap(),
+        "public, max-age=60"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use lagon_runtime_utils::response::PAGE_413;
+use lagon_serverless::{forwarded::parse_trusted_proxies, options::ServerlessOptions};
+use serial_test::serial;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+async fn start_with_body_limit(trusted_proxies: &str) -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("read-body"),
+        ServerlessOptions::default()
+            .max_body_size(16)
+            .max_body_size_override(64)
+            .trusted_proxies(parse_trusted_proxies(trusted_proxies)?),
+    )
+    .await
+}
+
+async fn post(body: &str, max_body_size: Option<&str>) -> Result<(u16, String)> {
+    let client = reqwest::Client::new();
+    let mut request = client.post("http://127.0.0.1:4000").body(body.to_string());
+
+    if let Some(max_body_size) = max_body_size {
+        request = request.header("x-lagon-max-body-size", max_body_size);
+    }
+
+    let response = request.send().await?;
+
+    Ok((response.status().as_u16(), response.text().await?))
+}
+
+#[tokio::test]
+#[serial]
+async fn body_limit() -> Result<()> {
+    start_with_body_limit("").await?;
+
+    assert_eq!(post("Hello world", None).await?, (200, "11 Hello".into()));
+    assert_eq!(post(&"a".repeat(32), None).await?, (413, PAGE_413.into()));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn chunked_body_limit() -> Result<()> {
+    start_with_body_limit("").await?;
+
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+    stream
+        .write_all(
+            format!(
+                "POST / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nTransfer-Encoding: chunked\r\n\r\n20\r\n{}\r\n0\r\n\r\n",
+                "a".repeat(32)
+            )
+            .as_bytes(),
+        )
+        .await?;
+
+    let mut buf = [0; 1024];
+    let read = stream.read(&mut buf).await?;
+
+    assert!(String::from_utf8_lossy(&buf[..read]).starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn trusted_body_limit_override() -> Result<()> {
+    start_with_body_limit("127.0.0.1").await?;
+
+    let body = "a".repeat(32);
+    assert_eq!(post(&body, Some("64")).await?, (200, "32 aaaaa".into()));
+
+    // The override is capped, and can't lower the default limit
+    assert_eq!(
+        post(&"a".repeat(128), Some("1024")).await?,
+        (413, PAGE_413.into())
+    );
+    assert_eq!(
+        post("Hello world", Some("1")).await?,
+        (200, "11 Hello".into())
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn untrusted_body_limit_override() -> Result<()> {
+    start_with_body_limit("10.0.0.1").await?;
+
+    assert_eq!(
+        post(&"a".repeat(32), Some("64")).await?,
+        (413, PAGE_413.into())
+    );
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use lagon_runtime_http::BodySpilling;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+mod utils;
+
+async fn start_with_spilling(threshold: usize) -> Result<PathBuf> {
+    let dir = env::temp_dir().join("lagon-body-spilling-test");
+    fs::remove_dir_all(&dir).unwrap_or(());
+    fs::create_dir_all(&dir)?;
+
+    utils::start_serverless(
+        utils::deployment("read-body"),
+        ServerlessOptions::default().body_spilling(BodySpilling::new(threshold).dir(dir.clone())),
+    )
+    .await?;
+
+    Ok(dir)
+}
+
+fn spilled_files(dir: &Path) -> Result<usize> {
+    Ok(fs::read_dir(dir)?.count())
+}
+
+#[tokio::test]
+#[serial]
+async fn large_body_spilled() -> Result<()> {
+    let dir = start_with_spilling(1024).await?;
+
+    let request = tokio::spawn(
+        reqwest::Client::new()
+            .post("http://127.0.0.1:4000")
+            .body(vec![b'a'; 64 * 1024])
+            .send(),
+    );
+
+    // The function waits before reading the body
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(spilled_files(&dir)?, 1);
+
+    let response = request.await??;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "65536 aaaaa");
+
+    // The file is removed once the request is finished
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(spilled_files(&dir)?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn small_body_buffered() -> Result<()> {
+    let dir = start_with_spilling(1024).await?;
+
+    let request = tokio::spawn(
+        reqwest::Client::new()
+            .post("http://127.0.0.1:4000")
+            .body(vec![b'a'; 512])
+            .send(),
+    );
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(spilled_files(&dir)?, 0);
+
+    let response = request.await??;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "512 aaaaa");
+
+    Ok(())
+}
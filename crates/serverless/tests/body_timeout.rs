@@ -0,0 +1,115 @@
+use anyhow::Result;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::time::{Duration, Instant};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+async fn start_with_body_read_timeout() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("read-body"),
+        ServerlessOptions::default().body_read_timeout(Duration::from_millis(200)),
+    )
+    .await
+}
+
+async fn read_response(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = [0; 4096];
+    let read = stream.read(&mut buf).await?;
+
+    Ok(String::from_utf8_lossy(&buf[..read]).to_string())
+}
+
+#[tokio::test]
+#[serial]
+async fn stalled_body() -> Result<()> {
+    start_with_body_read_timeout().await?;
+
+    let start = Instant::now();
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+
+    // Only half of the body is ever sent
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nContent-Length: 10\r\n\r\nHello")
+        .await?;
+
+    let response = read_response(&mut stream).await?;
+
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(start.elapsed() < Duration::from_millis(1000));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn slow_body() -> Result<()> {
+    start_with_body_read_timeout().await?;
+
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nTransfer-Encoding: chunked\r\n\r\n")
+        .await?;
+
+    // The whole upload takes longer than the timeout, but never stalls
+    for _ in 0..4 {
+        stream.write_all(b"5\r\nHello\r\n").await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    stream.write_all(b"0\r\n\r\n").await?;
+
+    let response = read_response(&mut stream).await?;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("20 Hello"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn body_total_timeout() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("read-body"),
+        ServerlessOptions::default()
+            .body_read_timeout(Duration::from_millis(200))
+            .body_total_timeout(Duration::from_millis(300)),
+    )
+    .await?;
+
+    let start = Instant::now();
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nTransfer-Encoding: chunked\r\n\r\n")
+        .await?;
+
+    let (mut reader, mut writer) = stream.into_split();
+
+    // The body never stalls, but takes longer than the total timeout. The
+    // response is read meanwhile, since the server closes the connection
+    tokio::spawn(async move {
+        for _ in 0..10 {
+            if writer.write_all(b"5\r\nHello\r\n").await.is_err() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+
+    let mut buf = [0; 4096];
+    let read = reader.read(&mut buf).await?;
+    let response = String::from_utf8_lossy(&buf[..read]);
+
+    assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_millis(1000));
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_runtime_utils::Deployment;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::{sync::Arc, time::Duration};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn cancel_request() -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            total_timeout: 10000,
+            ..utils::deployment("cancel")
+        },
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+
+    let stuck = tokio::spawn(
+        client
+            .get("http://127.0.0.1:4000/never")
+            .header("x-lagon-id", "stuck")
+            .send(),
+    );
+    let other = tokio::spawn(
+        client
+            .get("http://127.0.0.1:4000")
+            .header("x-lagon-id", "other")
+            .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/requests/stuck")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = tokio::time::timeout(Duration::from_millis(500), stuck).await???;
+    assert_eq!(response.status(), 500);
+
+    // Other requests handled by the same isolate complete normally
+    let response = other.await??;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    // Completed requests can't be cancelled
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/requests/stuck")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn cancel_request_with_duplicated_id() -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            total_timeout: 10000,
+            ..utils::deployment("cancel")
+        },
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+
+    let stuck = tokio::spawn(
+        client
+            .get("http://127.0.0.1:4000/never")
+            .header("x-lagon-id", "stuck")
+            .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Completing another request with the same id doesn't unregister the stuck one
+    let response = client
+        .get("http://127.0.0.1:4000")
+        .header("x-lagon-id", "stuck")
+        .send()
+        .await?;
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/requests/stuck")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = tokio::time::timeout(Duration::from_millis(500), stuck).await???;
+    assert_eq!(response.status(), 500);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn cancel_request_requires_token() -> Result<()> {
+    utils::start_with_deployments(
+        Arc::new(DashMap::new()),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let response = reqwest::Client::new()
+        .delete("http://127.0.0.1:4000/__lagon/requests/stuck")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 401);
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_serverless::{forwarded::parse_trusted_proxies, options::ServerlessOptions};
+use serial_test::serial;
+use std::{sync::Arc, time::Duration};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+mod utils;
+
+async fn start_with_options(options: ServerlessOptions) -> Result<()> {
+    utils::start_with_deployments(Arc::new(DashMap::new()), options).await
+}
+
+async fn is_refused(mut stream: TcpStream) -> bool {
+    let mut buf = [0; 1];
+
+    // Refused connections are closed right after being accepted
+    matches!(
+        tokio::time::timeout(Duration::from_millis(200), stream.read(&mut buf)).await,
+        Ok(Ok(0)) | Ok(Err(_))
+    )
+}
+
+#[tokio::test]
+#[serial]
+async fn excess_connections_refused() -> Result<()> {
+    start_with_options(ServerlessOptions::default().max_connections_per_ip(2)).await?;
+
+    let first = TcpStream::connect("127.0.0.1:4000").await?;
+    let second = TcpStream::connect("127.0.0.1:4000").await?;
+
+    let third = TcpStream::connect("127.0.0.1:4000").await?;
+    assert!(is_refused(third).await);
+    assert!(reqwest::get("http://127.0.0.1:4000/__lagon/health")
+        .await
+        .is_err());
+
+    // The connections are released once closed
+    drop(first);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let response = reqwest::get("http://127.0.0.1:4000/__lagon/health").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "OK");
+
+    drop(second);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn trusted_proxies_not_limited() -> Result<()> {
+    start_with_options(
+        ServerlessOptions::default()
+            .max_connections_per_ip(1)
+            .trusted_proxies(parse_trusted_proxies("127.0.0.1")?),
+    )
+    .await?;
+
+    let _first = TcpStream::connect("127.0.0.1:4000").await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000/__lagon/health").await?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn trusted_proxies_limited() -> Result<()> {
+    start_with_options(
+        ServerlessOptions::default()
+            .max_connections_per_ip(1)
+            .trusted_proxies(parse_trusted_proxies("127.0.0.1")?)
+            .limit_trusted_proxies_connections(true),
+    )
+    .await?;
+
+    let _first = TcpStream::connect("127.0.0.1:4000").await?;
+
+    let second = TcpStream::connect("127.0.0.1:4000").await?;
+    assert!(is_refused(second).await);
+
+    Ok(())
+}
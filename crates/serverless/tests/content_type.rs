@@ -0,0 +1,77 @@
+use anyhow::Result;
+use lagon_runtime_utils::{response::PAGE_415, Deployment};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+
+mod utils;
+
+async fn start_with_content_types(accepted_content_types: Vec<String>) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            accepted_content_types: Some(accepted_content_types),
+            ..utils::deployment("simple")
+        },
+        ServerlessOptions::default(),
+    )
+    .await
+}
+
+async fn post(content_type: &str) -> Result<reqwest::Response> {
+    Ok(reqwest::Client::new()
+        .post("http://127.0.0.1:4000")
+        .header("content-type", content_type)
+        .body("{}")
+        .send()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn accepted_content_type() -> Result<()> {
+    start_with_content_types(vec!["application/json".into()]).await?;
+
+    let response = post("application/json; charset=utf-8").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    // Requests without a body are always accepted
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn rejected_content_type() -> Result<()> {
+    start_with_content_types(vec!["application/json".into()]).await?;
+
+    let response = post("text/plain").await?;
+    assert_eq!(response.status(), 415);
+    assert_eq!(response.text().await?, PAGE_415);
+
+    let response = reqwest::Client::new()
+        .post("http://127.0.0.1:4000")
+        .body("{}")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 415);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn wildcard_content_type() -> Result<()> {
+    start_with_content_types(vec!["application/*".into()]).await?;
+
+    let response = post("application/xml").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = post("text/xml").await?;
+    assert_eq!(response.status(), 415);
+
+    Ok(())
+}
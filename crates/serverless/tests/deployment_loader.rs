@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lagon_runtime_utils::response::PAGE_503_LOAD_ERROR;
+use lagon_serverless::{
+    deployments::loader::{Bundle, DeploymentLoader},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+mod utils;
+
+#[derive(Default)]
+struct MockLoader {
+    loads: AtomicUsize,
+}
+
+#[async_trait]
+impl DeploymentLoader for MockLoader {
+    async fn load(&self, deployment_id: &str) -> Result<Bundle> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+
+        Ok(Bundle {
+            code: format!(
+                "export function handler() {{
+    return new Response('Loaded {deployment_id}');
+}}"
+            ),
+            source_map: None,
+        })
+    }
+}
+
+#[derive(Default)]
+struct FailingLoader {
+    loads: AtomicUsize,
+}
+
+#[async_trait]
+impl DeploymentLoader for FailingLoader {
+    async fn load(&self, deployment_id: &str) -> Result<Bundle> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+
+        Err(anyhow!(
+            "Bundle of deployment {} is corrupted",
+            deployment_id
+        ))
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn bundle_loaded_once() -> Result<()> {
+    let loader = Arc::new(MockLoader::default());
+    utils::start_serverless(
+        utils::deployment("remote"),
+        ServerlessOptions::default()
+            .deployment_loader(Arc::clone(&loader) as Arc<dyn DeploymentLoader>)
+            .isolates_idle_ttl(Duration::from_secs(1)),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Loaded remote");
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    // Wait for the isolate to be evicted, so the next request creates a new one
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Loaded remote");
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn bundle_load_failure_cached() -> Result<()> {
+    let loader = Arc::new(FailingLoader::default());
+    utils::start_serverless(
+        utils::deployment("remote"),
+        ServerlessOptions::default()
+            .deployment_loader(Arc::clone(&loader) as Arc<dyn DeploymentLoader>)
+            .bundle_load_failure_ttl(Duration::from_secs(1)),
+    )
+    .await?;
+
+    for _ in 0..3 {
+        let response = reqwest::get("http://127.0.0.1:4000").await?;
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.text().await?, PAGE_503_LOAD_ERROR);
+    }
+
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    // Retried once the failure expired
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
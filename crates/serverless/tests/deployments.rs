@@ -1,48 +1,23 @@
 use anyhow::Result;
 use dashmap::DashMap;
 use lagon_runtime_utils::Deployment;
-use lagon_serverless::serverless::start;
-use lagon_serverless_downloader::FakeDownloader;
-use lagon_serverless_pubsub::FakePubSub;
+use lagon_serverless::options::ServerlessOptions;
 use serial_test::serial;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use std::{collections::HashSet, sync::Arc};
 
 mod utils;
 
 #[tokio::test]
 #[serial]
 async fn simple() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "simple".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
+    utils::start_serverless(
+        Deployment {
             domains: HashSet::from(["127.0.0.1:4000".into()]),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("simple")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -54,33 +29,14 @@ async fn simple() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn custom_domains() -> Result<()> {
-    let client = utils::setup();
     let deployments = Arc::new(DashMap::new());
     let deployment = Arc::new(Deployment {
-        id: "simple".into(),
-        function_id: "function_id".into(),
-        function_name: "function_name".into(),
         domains: HashSet::from(["127.0.0.1:4000".into(), "custom.domain".into()]),
-        assets: HashSet::new(),
-        environment_variables: HashMap::new(),
-        memory: 128,
-        tick_timeout: 1000,
-        total_timeout: 1000,
-        is_production: true,
-        cron: None,
+        ..utils::deployment("simple")
     });
     deployments.insert("127.0.0.1:4000".into(), Arc::clone(&deployment));
     deployments.insert("custom.domain".into(), Arc::clone(&deployment));
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_with_deployments(deployments, ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -101,34 +57,14 @@ async fn custom_domains() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn reuse_isolate() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "counter".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
+    utils::start_serverless(
+        Deployment {
             domains: HashSet::from(["127.0.0.1:4000".into()]),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("counter")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -144,33 +80,14 @@ async fn reuse_isolate() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn reuse_isolate_across_domains() -> Result<()> {
-    let client = utils::setup();
     let deployments = Arc::new(DashMap::new());
     let deployment = Arc::new(Deployment {
-        id: "counter".into(),
-        function_id: "function_id".into(),
-        function_name: "function_name".into(),
         domains: HashSet::from(["127.0.0.1:4000".into(), "another.domain".into()]),
-        assets: HashSet::new(),
-        environment_variables: HashMap::new(),
-        memory: 128,
-        tick_timeout: 1000,
-        total_timeout: 1000,
-        is_production: true,
-        cron: None,
+        ..utils::deployment("counter")
     });
     deployments.insert("127.0.0.1:4000".into(), Arc::clone(&deployment));
     deployments.insert("another.domain".into(), deployment);
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_with_deployments(deployments, ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use hyper::{Body, Request as HyperRequest};
+use lagon_runtime_http::Response;
+use lagon_serverless::{
+    edge_cache::{get_cache_key, EdgeCache, EDGE_CACHE_MAX_FALLBACK_AGE},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+use std::{collections::HashMap, time::Duration};
+
+mod utils;
+
+async fn start_with_edge_cache() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("cache"),
+        ServerlessOptions::default().edge_cache_max_entries(16),
+    )
+    .await
+}
+
+#[tokio::test]
+#[serial]
+async fn cache_miss_then_hit() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    // The miss invokes the isolate and populates the cache
+    let response = reqwest::get("http://127.0.0.1:4000/").await?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("age").is_none());
+    assert_eq!(response.text().await?, "1");
+
+    // The hit is served without invoking the isolate
+    let response = reqwest::get("http://127.0.0.1:4000/").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("age").unwrap(), "0");
+    assert_eq!(
+        response.headers().get("cache-control").unwrap(),
+        "public, max-age=60"
+    );
+    assert_eq!(response.text().await?, "1");
+
+    // Other URLs have their own entry
+    let response = reqwest::get("http://127.0.0.1:4000/other").await?;
+    assert_eq!(response.text().await?, "2");
+
+    // Requests can bypass the cache
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:4000/")
+        .header("cache-control", "no-cache")
+        .send()
+        .await?;
+    assert_eq!(response.text().await?, "3");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn uncacheable_responses() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    for (index, cache_control) in ["private,max-age=60", "no-store", "max-age=0"]
+        .iter()
+        .enumerate()
+    {
+        let url = format!("http://127.0.0.1:4000/?cache-control={cache_control}");
+
+        let response = reqwest::get(&url).await?;
+        assert_eq!(response.text().await?, (index * 2 + 1).to_string());
+
+        let response = reqwest::get(&url).await?;
+        assert_eq!(response.text().await?, (index * 2 + 2).to_string());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn stale_while_revalidate() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    let url = "http://127.0.0.1:4000/?cache-control=max-age=1,stale-while-revalidate=60";
+
+    let response = reqwest::get(url).await?;
+    assert_eq!(response.text().await?, "1");
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // The stale response is served while being refreshed in the background
+    let response = reqwest::get(url).await?;
+    assert_eq!(response.headers().get("age").unwrap(), "1");
+    assert_eq!(response.text().await?, "1");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = reqwest::get(url).await?;
+    assert_eq!(response.headers().get("age").unwrap(), "0");
+    assert_eq!(response.text().await?, "2");
+
+    Ok(())
+}
+
+async fn get_with_encoding(url: &str, encoding: &str) -> Result<String> {
+    Ok(reqwest::Client::new()
+        .get(url)
+        .header("accept-encoding", encoding)
+        .send()
+        .await?
+        .text()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn vary() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    let url = "http://127.0.0.1:4000/?vary=Accept-Encoding";
+
+    // Each variant has its own entry
+    assert_eq!(get_with_encoding(url, "gzip").await?, "1");
+    assert_eq!(get_with_encoding(url, "br").await?, "2");
+    assert_eq!(get_with_encoding(url, "gzip").await?, "1");
+    assert_eq!(get_with_encoding(url, "br").await?, "2");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn vary_everything() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    let url = "http://127.0.0.1:4000/?vary=*";
+
+    assert_eq!(get_with_encoding(url, "gzip").await?, "1");
+    assert_eq!(get_with_encoding(url, "gzip").await?, "2");
+
+    Ok(())
+}
+
+async fn get_if_none_match(url: &str, if_none_match: &str) -> Result<reqwest::Response> {
+    Ok(reqwest::Client::new()
+        .get(url)
+        .header("if-none-match", if_none_match)
+        .send()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn cached_not_modified() -> Result<()> {
+    start_with_edge_cache().await?;
+
+    let url = "http://127.0.0.1:4000/?etag=%22abc%22";
+
+    let response = reqwest::get(url).await?;
+    assert_eq!(response.headers().get("etag").unwrap(), "\"abc\"");
+    assert_eq!(response.text().await?, "1");
+
+    // Weak comparison matches the strong ETag of the entry
+    for if_none_match in ["\"abc\"", "W/\"abc\"", "\"xyz\", \"abc\"", "*"] {
+        let response = get_if_none_match(url, if_none_match).await?;
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers().get("etag").unwrap(), "\"abc\"");
+        assert_eq!(response.headers().get("age").unwrap(), "0");
+        assert_eq!(response.text().await?, "");
+    }
+
+    let response = get_if_none_match(url, "\"xyz\"").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "1");
+
+    // The isolate was never invoked again
+    let response = reqwest::get(url).await?;
+    assert_eq!(response.text().await?, "1");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn prune_expired_fallbacks() {
+    tokio::time::pause();
+
+    let edge_cache = EdgeCache::new(16);
+    let request = HyperRequest::builder()
+        .uri("http://127.0.0.1:4000/")
+        .body(Body::empty())
+        .unwrap();
+    let cache_key = get_cache_key("cache", &request).unwrap();
+
+    assert!(edge_cache.insert(
+        &cache_key,
+        &Response {
+            headers: Some(HashMap::from([(
+                "cache-control".into(),
+                vec!["public, max-age=60".into()]
+            )])),
+            ..Response::from("Hello world")
+        }
+    ));
+
+    // Expired responses are still served as fallbacks
+    tokio::time::advance(Duration::from_secs(120)).await;
+    edge_cache.prune();
+    assert_eq!(edge_cache.len(), 1);
+    assert!(edge_cache.get_fallback(&cache_key).is_some());
+
+    tokio::time::advance(EDGE_CACHE_MAX_FALLBACK_AGE).await;
+    assert!(edge_cache.get_fallback(&cache_key).is_none());
+
+    edge_cache.prune();
+    assert!(edge_cache.is_empty());
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use lagon_runtime_utils::response::PAGE_500;
+use lagon_serverless::{
+    error_reporter::{ErrorReport, ErrorReporter},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+use std::sync::{Arc, Mutex};
+
+mod utils;
+
+#[derive(Default)]
+struct TestErrorReporter {
+    reports: Mutex<Vec<ErrorReport>>,
+}
+
+impl ErrorReporter for TestErrorReporter {
+    fn report(&self, report: ErrorReport) {
+        self.reports.lock().unwrap().push(report);
+    }
+}
+
+#[test]
+fn split_message_and_stack() {
+    assert_eq!(
+        ErrorReport::new(
+            "Uncaught Error: hello\n  at handler (2:9)",
+            "deployment".into(),
+            "request".into(),
+        ),
+        ErrorReport {
+            message: "Uncaught Error: hello".into(),
+            stack: Some("  at handler (2:9)".into()),
+            deployment_id: "deployment".into(),
+            request_id: "request".into(),
+        }
+    );
+
+    assert_eq!(
+        ErrorReport::new("Unknown error", "deployment".into(), "request".into()).stack,
+        None
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn report_function_errors() -> Result<()> {
+    let error_reporter = Arc::new(TestErrorReporter::default());
+    utils::start_serverless(
+        utils::deployment("throw-error"),
+        ServerlessOptions::default().error_reporter(Arc::clone(&error_reporter) as _),
+    )
+    .await?;
+
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:4000")
+        .header("x-lagon-id", "request_id")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.text().await?, PAGE_500);
+
+    let reports = error_reporter.reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+
+    let report = &reports[0];
+    assert_eq!(report.message, "Uncaught Error: hello");
+    assert!(report
+        .stack
+        .as_ref()
+        .unwrap()
+        .starts_with("  at handler (2:"));
+    assert_eq!(report.deployment_id, "throw-error");
+    assert_eq!(report.request_id, "request_id");
+
+    Ok(())
+}
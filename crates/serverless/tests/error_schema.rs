@@ -0,0 +1,107 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_runtime_utils::{error_page::ErrorSchema, response::PAGE_404, Deployment};
+use lagon_serverless::{deployments::get_error_schema, options::ServerlessOptions};
+use serial_test::serial;
+use std::sync::Arc;
+
+mod utils;
+
+fn error_schema(error_schema: &str) -> ErrorSchema {
+    get_error_schema(Some(error_schema)).unwrap()
+}
+
+async fn get(accept: &str) -> Result<reqwest::Response> {
+    Ok(reqwest::Client::new()
+        .get("http://127.0.0.1:4000")
+        .header("accept", accept)
+        .header("x-lagon-id", "request-id")
+        .send()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn default_json_schema() -> Result<()> {
+    utils::start_with_deployments(Arc::new(DashMap::new()), ServerlessOptions::default()).await?;
+
+    let response = get("application/json").await?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(
+        response.text().await?,
+        r#"{"status":404,"message":"Not Found","requestId":"request-id"}"#
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn custom_json_schema() -> Result<()> {
+    utils::start_with_deployments(
+        Arc::new(DashMap::new()),
+        ServerlessOptions::default().error_schema(error_schema(
+            r#"{
+    "404": {
+        "error": {
+            "code": "not_found",
+            "status": "{{status}}",
+            "correlationId": "{{requestId}}",
+            "docs": "https://example.com/errors/{{status}}"
+        }
+    }
+}"#,
+        )),
+    )
+    .await?;
+
+    let response = get("application/json").await?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&response.text().await?)?,
+        serde_json::json!({
+            "error": {
+                "code": "not_found",
+                "status": 404,
+                "correlationId": "request-id",
+                "docs": "https://example.com/errors/404",
+            }
+        })
+    );
+
+    // Browsers still get the HTML page
+    let response = get("text/html,application/xhtml+xml,*/*;q=0.8").await?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.text().await?, PAGE_404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn deployment_json_schema() -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            cron: Some("".into()),
+            error_schema: Some(error_schema(
+                r#"{ "default": { "title": "{{message}}", "traceId": "{{requestId}}" } }"#,
+            )),
+            ..utils::deployment("id")
+        },
+        ServerlessOptions::default().error_schema(error_schema(r#"{ "default": {} }"#)),
+    )
+    .await?;
+
+    let response = get("application/problem+json").await?;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&response.text().await?)?,
+        serde_json::json!({ "title": "Forbidden", "traceId": "request-id" })
+    );
+
+    Ok(())
+}
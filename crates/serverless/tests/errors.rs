@@ -1,34 +1,20 @@
 use anyhow::Result;
 use dashmap::DashMap;
 use lagon_runtime_utils::{
-    response::{PAGE_403, PAGE_404, PAGE_500, PAGE_502},
+    error_page::TimeoutResponse,
+    response::{PAGE_403, PAGE_404, PAGE_414, PAGE_500, PAGE_502, PAGE_503_LOAD_ERROR},
     Deployment,
 };
-use lagon_serverless::serverless::start;
-use lagon_serverless_downloader::FakeDownloader;
-use lagon_serverless_pubsub::FakePubSub;
+use lagon_serverless::options::ServerlessOptions;
 use serial_test::serial;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use std::sync::Arc;
 
 mod utils;
 
 #[tokio::test]
 #[serial]
 async fn return_404_no_deployment_found() -> Result<()> {
-    let client = utils::setup();
-    let serverless = start(
-        Arc::new(DashMap::new()),
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_with_deployments(Arc::new(DashMap::new()), ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 404);
@@ -40,34 +26,14 @@ async fn return_404_no_deployment_found() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn return_403_cron_deployment() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "id".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
+    utils::start_serverless(
+        Deployment {
             cron: Some("".into()),
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+            ..utils::deployment("id")
+        },
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 403);
@@ -79,34 +45,7 @@ async fn return_403_cron_deployment() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn return_500_unknown_code() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "unknown".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_serverless(utils::deployment("unknown"), ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 500);
@@ -118,34 +57,11 @@ async fn return_500_unknown_code() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn return_502_timeout_execution() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "timeout-execution".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+    utils::start_serverless(
+        utils::deployment("timeout-execution"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 502);
@@ -156,35 +72,36 @@ async fn return_502_timeout_execution() -> Result<()> {
 
 #[tokio::test]
 #[serial]
-async fn return_502_timeout_init() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "timeout-init".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
+async fn return_custom_timeout_response() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("timeout-execution"),
+        ServerlessOptions::default().timeout_response(TimeoutResponse {
+            status: 504,
+            content_type: Some("application/json".into()),
+            body: r#"{"error":"timeout"}"#.into(),
         }),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 504);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/json"
     );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+    assert_eq!(response.text().await?, r#"{"error":"timeout"}"#);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn return_502_timeout_init() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("timeout-init"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 502);
@@ -196,34 +113,11 @@ async fn return_502_timeout_init() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn return_500_code_invalid() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "code-invalid".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+    utils::start_serverless(
+        utils::deployment("code-invalid"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 500);
@@ -235,34 +129,11 @@ async fn return_500_code_invalid() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn return_500_throw_error() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "throw-error".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+    utils::start_serverless(
+        utils::deployment("throw-error"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 500);
@@ -270,3 +141,73 @@ async fn return_500_throw_error() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn return_414_url_too_long() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().max_url_length(32),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000/?hello=world").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = reqwest::get(format!("http://127.0.0.1:4000/{}", "a".repeat(32))).await?;
+    assert_eq!(response.status(), 414);
+    assert_eq!(response.text().await?, PAGE_414);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn return_503_bundle_invalid() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("syntax-error"),
+        ServerlessOptions::default(),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.text().await?, PAGE_503_LOAD_ERROR);
+
+    // The load failure is cached
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.text().await?, PAGE_503_LOAD_ERROR);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn return_500_throw_error_top_level() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("throw-error-top-level"),
+        ServerlessOptions::default(),
+    )
+    .await?;
+
+    // Not a load failure of the bundle, which compiled successfully
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.text().await?, PAGE_500);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn return_503_bundle_missing() -> Result<()> {
+    utils::start_serverless(utils::deployment("missing"), ServerlessOptions::default()).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.text().await?, PAGE_503_LOAD_ERROR);
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use lagon_runtime_utils::{Deployment, Fallback};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::time::{Duration, Instant};
+
+mod utils;
+
+async fn start_with_fallback(id: &str, fallback: Fallback) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            fallback: Some(fallback),
+            ..utils::deployment(id)
+        },
+        ServerlessOptions::default(),
+    )
+    .await
+}
+
+fn static_fallback() -> Fallback {
+    Fallback {
+        deadline: Duration::from_millis(100),
+        from_cache: false,
+        status: 503,
+        content_type: Some("text/html".into()),
+        body: Some("<h1>Fallback</h1>".into()),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn slow_function_fallback() -> Result<()> {
+    start_with_fallback("sleep", static_fallback()).await?;
+
+    let start = Instant::now();
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+
+    // The function sleeps for 500ms
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
+    assert_eq!(response.text().await?, "<h1>Fallback</h1>");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn fast_function_response() -> Result<()> {
+    start_with_fallback("simple", static_fallback()).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn no_fallback_response_available() -> Result<()> {
+    // The edge cache is disabled, so there is no cached response
+    start_with_fallback(
+        "sleep",
+        Fallback {
+            deadline: Duration::from_millis(100),
+            from_cache: true,
+            status: 200,
+            content_type: None,
+            body: None,
+        },
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    Ok(())
+}
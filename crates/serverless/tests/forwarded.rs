@@ -0,0 +1,136 @@
+use anyhow::Result;
+use ipnet::IpNet;
+use lagon_serverless::{
+    forwarded::{parse_forwarded, parse_trusted_proxies, ForwardedElement},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+
+mod utils;
+
+async fn start_with_trusted_proxies(trusted_proxies: Vec<IpNet>) -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("forwards-headers"),
+        ServerlessOptions::default().trusted_proxies(trusted_proxies),
+    )
+    .await
+}
+
+async fn get(headers: &[(&str, &str)]) -> Result<reqwest::Response> {
+    let mut request = reqwest::Client::new().get("http://127.0.0.1:4000");
+
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    Ok(request.send().await?)
+}
+
+#[test]
+fn parse_proxies() -> Result<()> {
+    assert_eq!(
+        parse_trusted_proxies("10.0.0.0/8, 192.168.1.1,::1")?,
+        vec![
+            "10.0.0.0/8".parse::<IpNet>()?,
+            "192.168.1.1/32".parse()?,
+            "::1/128".parse()?,
+        ]
+    );
+    assert!(parse_trusted_proxies("")?.is_empty());
+    assert!(parse_trusted_proxies("10.0.0.0/33").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn trusted_peer_honors_headers() -> Result<()> {
+    start_with_trusted_proxies(parse_trusted_proxies("127.0.0.0/8")?).await?;
+
+    let response = get(&[("x-real-ip", "1.1.1.1"), ("x-forwarded-proto", "https")]).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "1.1.1.1");
+    assert_eq!(response.headers()["x-forwarded-proto"], "https");
+
+    // The client IP is the last one not added by a trusted proxy
+    let response = get(&[("x-forwarded-for", "1.1.1.1, 2.2.2.2, 127.0.0.2")]).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "2.2.2.2");
+
+    Ok(())
+}
+
+#[test]
+fn parse_forwarded_header() -> Result<()> {
+    assert_eq!(
+        parse_forwarded(
+            "for=192.0.2.60;proto=HTTPS;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\";host=example.com, for=unknown"
+        ),
+        vec![
+            ForwardedElement {
+                for_ip: Some("192.0.2.60".parse()?),
+                by: Some("203.0.113.43".into()),
+                host: None,
+                proto: Some("https".into()),
+            },
+            ForwardedElement {
+                for_ip: Some("2001:db8:cafe::17".parse()?),
+                by: None,
+                host: Some("example.com".into()),
+                proto: None,
+            },
+            ForwardedElement::default(),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn trusted_peer_honors_forwarded() -> Result<()> {
+    start_with_trusted_proxies(parse_trusted_proxies("127.0.0.0/8")?).await?;
+
+    let response = get(&[(
+        "forwarded",
+        "for=1.1.1.1, for=\"2.2.2.2:8080\";proto=https;host=example.com, for=127.0.0.2",
+    )])
+    .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "2.2.2.2");
+    assert_eq!(response.headers()["x-forwarded-proto"], "https");
+    assert_eq!(response.headers()["x-forwarded-host"], "example.com");
+
+    // Forwarded takes precedence over X-Forwarded-For
+    let response = get(&[("forwarded", "for=1.1.1.1"), ("x-forwarded-for", "2.2.2.2")]).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "1.1.1.1");
+
+    // Falling back to X-Forwarded-For when no hop of Forwarded is valid
+    let response = get(&[("forwarded", "for=unknown"), ("x-forwarded-for", "2.2.2.2")]).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "2.2.2.2");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn untrusted_peer_ignores_headers() -> Result<()> {
+    start_with_trusted_proxies(parse_trusted_proxies("10.0.0.0/8")?).await?;
+
+    let response = get(&[
+        ("x-real-ip", "1.1.1.1"),
+        ("x-forwarded-for", "2.2.2.2"),
+        ("x-forwarded-proto", "https"),
+        ("forwarded", "for=3.3.3.3"),
+    ])
+    .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-forwarded-for"], "127.0.0.1");
+    assert!(!response.headers().contains_key("forwarded"));
+    assert!(!response.headers().contains_key("x-real-ip"));
+    assert!(!response.headers().contains_key("x-forwarded-proto"));
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_serverless::{
+    host_routing::{parse_host_routes, HostRoutes},
+    options::ServerlessOptions,
+};
+use serial_test::serial;
+use std::sync::Arc;
+
+mod utils;
+
+async fn start_serverless(host_routes: HostRoutes) -> Result<()> {
+    let deployments = Arc::new(DashMap::new());
+    deployments.insert(
+        "tenants.lagon.app".into(),
+        Arc::new(utils::deployment("simple")),
+    );
+    deployments.insert(
+        "landing.lagon.app".into(),
+        Arc::new(utils::deployment("request")),
+    );
+    utils::start_with_deployments(
+        deployments,
+        ServerlessOptions::default().host_routes(host_routes),
+    )
+    .await
+}
+
+async fn get(host: &str) -> Result<reqwest::Response> {
+    Ok(reqwest::Client::new()
+        .get("http://127.0.0.1:4000")
+        .header("host", host)
+        .send()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn exact_host_route() -> Result<()> {
+    start_serverless(HostRoutes::default().route("app.example.org", "tenants.lagon.app".into()))
+        .await?;
+
+    let response = get("app.example.org").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    // Matched without the port, and case-insensitively
+    let response = get("App.Example.org:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    // Deployments are still served from their own hostnames
+    let response = get("landing.lagon.app").await?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.text().await?, "body");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn wildcard_host_route() -> Result<()> {
+    start_serverless(
+        HostRoutes::default()
+            .route("*.example.com", "tenants.lagon.app".into())
+            .route("*.landing.example.com", "landing.lagon.app".into()),
+    )
+    .await?;
+
+    for host in ["acme.example.com", "eu.acme.example.com"] {
+        let response = get(host).await?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await?, "Hello world");
+    }
+
+    // The most specific wildcard wins
+    let response = get("acme.landing.example.com").await?;
+    assert_eq!(response.status(), 201);
+
+    // Wildcards don't match the domain itself
+    let response = get("example.com").await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn unmatched_host_route() -> Result<()> {
+    start_serverless(HostRoutes::default().route("*.example.com", "tenants.lagon.app".into()))
+        .await?;
+
+    let response = get("unknown.domain").await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn default_host_route() -> Result<()> {
+    start_serverless(
+        HostRoutes::default()
+            .route("*.example.com", "tenants.lagon.app".into())
+            .default_route("landing.lagon.app".into()),
+    )
+    .await?;
+
+    let response = get("acme.example.com").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = get("unknown.domain").await?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.text().await?, "body");
+
+    Ok(())
+}
+
+#[test]
+fn parse_host_routes_format() {
+    let host_routes =
+        parse_host_routes("app.example.org=tenants.lagon.app; *.example.com = tenants.lagon.app;")
+            .unwrap();
+
+    assert_eq!(
+        host_routes.resolve("app.example.org"),
+        Some("tenants.lagon.app")
+    );
+    assert_eq!(
+        host_routes.resolve("acme.example.com"),
+        Some("tenants.lagon.app")
+    );
+    assert_eq!(host_routes.resolve("example.org"), None);
+
+    assert!(parse_host_routes("example.com").is_err());
+    assert!(parse_host_routes("acme.*.example.com=tenants.lagon.app").is_err());
+    assert!(parse_host_routes("*.example.com=").is_err());
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use futures::future::poll_fn;
+use hyper::http;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+
+mod utils;
+
+async fn start_server(id: &str, options: ServerlessOptions) -> Result<()> {
+    utils::start_serverless(utils::deployment(id), options).await
+}
+
+fn request() -> Result<http::Request<()>> {
+    Ok(http::Request::get("http://127.0.0.1:4000/").body(())?)
+}
+
+#[tokio::test]
+#[serial]
+async fn max_concurrent_streams() -> Result<()> {
+    start_server(
+        "sleep",
+        ServerlessOptions::default().http2_max_concurrent_streams(1),
+    )
+    .await?;
+
+    // HTTP/2 with prior knowledge
+    let (send_request, mut connection) =
+        h2::client::handshake(TcpStream::connect("127.0.0.1:4000").await?).await?;
+
+    // Drive the connection until the server settings are received
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        poll_fn(|cx| {
+            let _ = Pin::new(&mut connection).poll(cx);
+
+            match connection.max_concurrent_send_streams() {
+                1 => Poll::Ready(()),
+                _ => Poll::Pending,
+            }
+        }),
+    )
+    .await?;
+    tokio::spawn(connection);
+
+    let mut send_request = send_request.ready().await?;
+
+    let started_at = Instant::now();
+    let (first, _) = send_request.send_request(request()?, true)?;
+    // Queued until the first stream completes
+    let (second, _) = send_request.send_request(request()?, true)?;
+
+    let (first, second) = tokio::join!(first, second);
+    assert_eq!(first?.status(), 200);
+    assert_eq!(second?.status(), 200);
+
+    // Each request sleeps for 500ms
+    assert!(started_at.elapsed() >= Duration::from_millis(1000));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn rapid_reset() -> Result<()> {
+    start_server(
+        "simple",
+        ServerlessOptions::default().http2_max_pending_reset_streams(2),
+    )
+    .await?;
+
+    let (mut send_request, connection) =
+        h2::client::handshake(TcpStream::connect("127.0.0.1:4000").await?).await?;
+    let connection = tokio::spawn(connection);
+
+    // Open streams and cancel them right away, until the server closes the connection
+    for _ in 0..1000 {
+        if futures::future::poll_fn(|cx| send_request.poll_ready(cx))
+            .await
+            .is_err()
+        {
+            break;
+        }
+
+        match send_request.send_request(request()?, true) {
+            Ok((_, mut stream)) => stream.send_reset(h2::Reason::CANCEL),
+            Err(_) => break,
+        }
+    }
+
+    let error = tokio::time::timeout(Duration::from_secs(5), connection)
+        .await??
+        .unwrap_err();
+    assert_eq!(error.reason(), Some(h2::Reason::ENHANCE_YOUR_CALM));
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use bytes::Buf;
+use dashmap::DashMap;
+use hyper::http;
+use lagon_serverless::{
+    options::ServerlessOptions,
+    tls::{load_certified_key, CertificateStore},
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ServerName,
+};
+use serial_test::serial;
+use std::{sync::Arc, time::SystemTime};
+
+mod utils;
+
+// The certificate is self-signed
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn http3_request() -> Result<()> {
+    let certificate = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let pem = certificate.serialize_pem()? + certificate.serialize_private_key_pem().as_str();
+
+    let store = Arc::new(CertificateStore::default());
+    store.set_default(Some(load_certified_key(pem.as_bytes())?));
+
+    let deployments = Arc::new(DashMap::new());
+    let deployment = Arc::new(utils::deployment("simple"));
+    deployments.insert("127.0.0.1:4000".into(), Arc::clone(&deployment));
+    deployments.insert("localhost".into(), deployment);
+    utils::start_with_deployments(
+        deployments,
+        ServerlessOptions::default()
+            .tls(store)
+            .http3_listen_addr("127.0.0.1:4001".parse().unwrap()),
+    )
+    .await?;
+
+    // HTTP/1.1 responses advertise the HTTP/3 server
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()?
+        .get("https://127.0.0.1:4000")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["alt-svc"], "h3=\":4001\"; ma=86400");
+
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h3".to_vec()];
+
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse()?)?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(config)));
+
+    let connection = endpoint
+        .connect("127.0.0.1:4001".parse()?, "localhost")?
+        .await?;
+    let (mut driver, mut send_request) =
+        h3::client::new(h3_quinn::Connection::new(connection)).await?;
+    tokio::spawn(async move { futures::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+    let mut stream = send_request
+        .send_request(http::Request::get("https://localhost/").body(())?)
+        .await?;
+    stream.finish().await?;
+
+    let response = stream.recv_response().await?;
+    assert_eq!(response.status(), 200);
+
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await? {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    assert_eq!(body, b"Hello world");
+
+    Ok(())
+}
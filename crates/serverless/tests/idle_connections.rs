@@ -0,0 +1,88 @@
+use anyhow::Result;
+use lagon_runtime_utils::Deployment;
+use lagon_serverless::options::ServerlessOptions;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use serial_test::serial;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+async fn start_with_idle_timeout(idle_connection_timeout: Duration) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            total_timeout: 10000,
+            ..utils::deployment("slow-stream")
+        },
+        ServerlessOptions::default().idle_connection_timeout(idle_connection_timeout),
+    )
+    .await
+}
+
+async fn is_closed(stream: &mut TcpStream, timeout: Duration) -> bool {
+    let mut buf = [0; 1];
+
+    matches!(
+        tokio::time::timeout(timeout, stream.read(&mut buf)).await,
+        Ok(Ok(0)) | Ok(Err(_))
+    )
+}
+
+#[tokio::test]
+#[serial]
+async fn idle_connection_reaped() -> Result<()> {
+    let metrics = PrometheusBuilder::new().install_recorder()?;
+    start_with_idle_timeout(Duration::from_millis(200)).await?;
+
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+
+    // The connection is still open before the timeout
+    assert!(!is_closed(&mut stream, Duration::from_millis(100)).await);
+    assert!(is_closed(&mut stream, Duration::from_secs(1)).await);
+
+    assert!(metrics.render().contains("lagon_connections_reaped 1\n"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn active_connection_not_reaped() -> Result<()> {
+    start_with_idle_timeout(Duration::from_millis(200)).await?;
+
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+
+    // Each request resets the idle timeout of the connection
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        stream
+            .write_all(b"GET /__lagon/health HTTP/1.1\r\nHost: 127.0.0.1:4000\r\n\r\n")
+            .await?;
+
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await?;
+
+        assert!(String::from_utf8_lossy(&buf[..read]).starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn streaming_connection_not_reaped() -> Result<()> {
+    start_with_idle_timeout(Duration::from_millis(200)).await?;
+
+    // Nothing is written while waiting for the second chunk, for
+    // longer than the timeout, but the request isn't over yet
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    Ok(())
+}
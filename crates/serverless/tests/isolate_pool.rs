@@ -0,0 +1,105 @@
+use anyhow::Result;
+use lagon_runtime_utils::{BalancingPolicy, Deployment, IsolatePool};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::time::Duration;
+
+mod utils;
+
+async fn start_with_isolate_pool(
+    policy: BalancingPolicy,
+    options: ServerlessOptions,
+) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            isolate_pool: Some(IsolatePool { size: 2, policy }),
+            ..utils::deployment("isolate-id")
+        },
+        options,
+    )
+    .await
+}
+
+async fn get_isolate_id(query: &str) -> Result<String> {
+    Ok(reqwest::get(format!("http://127.0.0.1:4000/{query}"))
+        .await?
+        .text()
+        .await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn round_robin() -> Result<()> {
+    start_with_isolate_pool(BalancingPolicy::RoundRobin, ServerlessOptions::default()).await?;
+
+    let mut ids = Vec::new();
+
+    for _ in 0..4 {
+        ids.push(get_isolate_id("").await?);
+    }
+
+    assert_ne!(ids[0], ids[1]);
+    assert_eq!(ids[0], ids[2]);
+    assert_eq!(ids[1], ids[3]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn least_in_flight() -> Result<()> {
+    start_with_isolate_pool(BalancingPolicy::LeastInFlight, ServerlessOptions::default()).await?;
+
+    // Ties are broken in turn
+    let first_id = get_isolate_id("").await?;
+    let second_id = get_isolate_id("").await?;
+    assert_ne!(first_id, second_id);
+
+    let slow_request = tokio::spawn(get_isolate_id("?sleep=500"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The other isolate handles all the requests while the first one is busy
+    let fast_id = get_isolate_id("").await?;
+
+    for _ in 0..3 {
+        assert_eq!(get_isolate_id("").await?, fast_id);
+    }
+
+    assert_ne!(slow_request.await??, fast_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn evict_surplus_idle_isolates() -> Result<()> {
+    start_with_isolate_pool(
+        BalancingPolicy::RoundRobin,
+        ServerlessOptions::default()
+            .management_token("token".into())
+            .isolates_idle_ttl(Duration::from_secs(1))
+            .min_warm_isolates(1),
+    )
+    .await?;
+
+    let first_id = get_isolate_id("").await?;
+    let second_id = get_isolate_id("").await?;
+    assert_ne!(first_id, second_id);
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    let stats: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+    assert_eq!(stats["isolates"], 1);
+
+    // The remaining isolate is kept warm and handles all the requests
+    let id = get_isolate_id("").await?;
+    assert_eq!(get_isolate_id("").await?, id);
+    assert!(id == first_id || id == second_id);
+
+    Ok(())
+}
@@ -0,0 +1,541 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use futures::StreamExt;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn isolates_require_token() -> Result<()> {
+    utils::start_with_deployments(
+        Arc::new(DashMap::new()),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("invalid")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 401);
+
+    // Same length as the token
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("tokem")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 401);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn isolates_disabled_without_token() -> Result<()> {
+    utils::start_with_deployments(Arc::new(DashMap::new()), ServerlessOptions::default()).await?;
+
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn isolates_stats() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("sleep"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let requests = [
+        tokio::spawn(reqwest::get("http://127.0.0.1:4000")),
+        tokio::spawn(reqwest::get("http://127.0.0.1:4000")),
+    ];
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    let stats: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+    assert_eq!(stats["isolates"], 1);
+    assert_eq!(stats["in_flight_requests"], 2);
+    assert_eq!(stats["deployments"]["sleep"]["isolates"], 1);
+    assert_eq!(stats["deployments"]["sleep"]["warm_isolates"], 0);
+    assert_eq!(stats["deployments"]["sleep"]["in_flight_requests"], 2);
+
+    for request in requests {
+        assert_eq!(request.await??.text().await?, "Hello world");
+    }
+
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("token")
+        .send()
+        .await?;
+
+    let stats: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+    assert_eq!(stats["isolates"], 1);
+    assert_eq!(stats["in_flight_requests"], 0);
+    assert_eq!(stats["deployments"]["sleep"]["warm_isolates"], 1);
+
+    Ok(())
+}
+
+async fn get_isolates(client: &reqwest::Client) -> Result<serde_json::Value> {
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/isolates")
+        .bearer_auth("token")
+        .send()
+        .await?;
+
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+#[tokio::test]
+#[serial]
+async fn evict_idle_isolates() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default()
+            .management_token("token".into())
+            .isolates_idle_ttl(Duration::from_secs(1)),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Hello world");
+
+    let client = reqwest::Client::new();
+    let stats = get_isolates(&client).await?;
+    assert_eq!(stats["isolates"], 1);
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let stats = get_isolates(&client).await?;
+    assert_eq!(stats["isolates"], 0);
+    assert_eq!(stats["memory_usage"], 0);
+    assert!(stats["deployments"]["simple"].is_null());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn keep_min_warm_isolates() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default()
+            .management_token("token".into())
+            .isolates_idle_ttl(Duration::from_secs(1))
+            .min_warm_isolates(1),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Hello world");
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let stats = get_isolates(&reqwest::Client::new()).await?;
+    assert_eq!(stats["isolates"], 1);
+    assert_eq!(stats["deployments"]["simple"]["warm_isolates"], 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn deployments_bundles() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/deployments")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "{}");
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/deployments/simple")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    let code = std::fs::read("deployments_test/simple.js")?;
+    let bundle: serde_json::Value = serde_json::from_str(&response.text().await?)?;
+    assert_eq!(bundle["hash"].as_str().unwrap().len(), 64);
+    assert_eq!(bundle["size"], code.len());
+    assert_eq!(bundle["modules"], 1);
+    assert_eq!(bundle["source_map"], false);
+    assert!(bundle["compilation_time_ms"].as_f64().unwrap() > 0.0);
+    assert!(bundle["evaluation_time_ms"].as_f64().unwrap() >= 0.0);
+
+    let response = client
+        .get("http://127.0.0.1:4000/__lagon/deployments/unknown")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn tail_logs() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("log"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let mut request =
+        "ws://127.0.0.1:4000/__lagon/deployments/log/logs?level=error".into_client_request()?;
+    request
+        .headers_mut()
+        .insert("authorization", "Bearer token".parse()?);
+
+    let (mut stream, response) = connect_async(request).await?;
+    assert_eq!(response.status(), 101);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.text().await?, "Hello world");
+
+    // The `log` level is filtered out
+    let message = match stream.next().await.unwrap()? {
+        Message::Text(message) => serde_json::from_str::<serde_json::Value>(&message)?,
+        message => panic!("Unexpected message: {message:?}"),
+    };
+
+    assert_eq!(message["deployment"], "log");
+    assert_eq!(message["function"], "function_id");
+    assert_eq!(message["level"], "error");
+    assert_eq!(message["message"], "Something failed");
+    assert!(message["timestamp"].is_number());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn maintenance_mode() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put("http://127.0.0.1:4000/__lagon/deployments/simple/maintenance?retry_after=120")
+        .bearer_auth("token")
+        .header("content-type", "text/plain")
+        .body("Back soon")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["retry-after"], "120");
+    assert_eq!(response.headers()["content-type"], "text/plain");
+    assert_eq!(response.text().await?, "Back soon");
+
+    // The isolate was never created
+    let stats = get_isolates(&client).await?;
+    assert_eq!(stats["isolates"], 0);
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/maintenance")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/maintenance")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .put("http://127.0.0.1:4000/__lagon/deployments/unknown/maintenance")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn replay_request() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("replay"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let replayed = r#"{
+        "method": "POST",
+        "url": "http://127.0.0.1:4000/hello",
+        "headers": { "x-custom": ["custom"] },
+        "body": "Hello world"
+    }"#;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post("http://127.0.0.1:4000/__lagon/deployments/replay/replay")
+        .body(replayed)
+        .send()
+        .await?;
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .post("http://127.0.0.1:4000/__lagon/deployments/replay/replay")
+        .bearer_auth("token")
+        .body(replayed)
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+
+    let result = serde_json::from_str::<serde_json::Value>(&response.text().await?)?;
+    assert_eq!(result["status"], 201);
+    assert_eq!(result["headers"]["x-replayed"][0], "true");
+    assert_eq!(result["body"], "custom: Hello world");
+    assert_eq!(result["body_encoding"], "utf8");
+    assert_eq!(result["error"], serde_json::Value::Null);
+    assert_eq!(result["logs"][0]["level"], "log");
+    assert_eq!(
+        result["logs"][0]["message"],
+        "POST http://127.0.0.1:4000/hello"
+    );
+    assert!(result["cpu_time_ms"].is_number());
+    assert!(result["wall_time_ms"].is_number());
+
+    // The replayed request wasn't counted in the stats
+    let stats = get_isolates(&client).await?;
+    assert_eq!(stats["isolates"], 0);
+    assert_eq!(stats["in_flight_requests"], 0);
+
+    let response = client
+        .post("http://127.0.0.1:4000/__lagon/deployments/unknown/replay")
+        .bearer_auth("token")
+        .body(replayed)
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .post("http://127.0.0.1:4000/__lagon/deployments/replay/replay")
+        .bearer_auth("token")
+        .body("{}")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 400);
+
+    // The replayed request is read in memory
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+    stream
+        .write_all(
+            b"POST /__lagon/deployments/replay/replay HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nAuthorization: Bearer token\r\nContent-Length: 16777216\r\n\r\n{}",
+        )
+        .await?;
+
+    let mut response = vec![0; 1024];
+    let read = stream.read(&mut response).await?;
+    assert!(String::from_utf8_lossy(&response[..read]).starts_with("HTTP/1.1 413"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn pause_deployment() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let first = tokio::spawn(reqwest::get("http://127.0.0.1:4000"));
+    let second = tokio::spawn(reqwest::get("http://127.0.0.1:4000"));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // The requests are waiting
+    assert!(!first.is_finished());
+    assert!(!second.is_finished());
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    for request in [first, second] {
+        let response = request.await??;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await?, "Hello world");
+    }
+
+    let response = client
+        .put("http://127.0.0.1:4000/__lagon/deployments/simple/pause?timeout=1")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    // Still paused after the timeout
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+
+    // Requests arriving after the deadline don't wait
+    let now = Instant::now();
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+    assert!(now.elapsed() < Duration::from_millis(500));
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn pause_unknown_deployment() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().management_token("token".into()),
+    )
+    .await?;
+
+    let response = reqwest::Client::new()
+        .put("http://127.0.0.1:4000/__lagon/deployments/unknown/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 404);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn max_paused_requests() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default()
+            .management_token("token".into())
+            .max_paused_requests(1),
+    )
+    .await?;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .put("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let first = tokio::spawn(reqwest::get("http://127.0.0.1:4000"));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!first.is_finished());
+
+    // Rejected right away
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 503);
+
+    let response = client
+        .delete("http://127.0.0.1:4000/__lagon/deployments/simple/pause")
+        .bearer_auth("token")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 204);
+
+    let response = first.await??;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
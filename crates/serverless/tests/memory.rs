@@ -0,0 +1,93 @@
+use anyhow::Result;
+use ipnet::IpNet;
+use lagon_runtime_utils::{response::PAGE_502, Deployment};
+use lagon_serverless::{forwarded::parse_trusted_proxies, options::ServerlessOptions};
+use serial_test::serial;
+
+mod utils;
+
+async fn start_with_max_memory(
+    max_memory: Option<usize>,
+    trusted_proxies: Vec<IpNet>,
+) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            memory: 16,
+            max_memory,
+            total_timeout: 2000,
+            ..utils::deployment("allocate")
+        },
+        ServerlessOptions::default().trusted_proxies(trusted_proxies),
+    )
+    .await
+}
+
+async fn get_with_memory(memory: Option<&str>) -> Result<reqwest::Response> {
+    let mut request = reqwest::Client::new().get("http://127.0.0.1:4000");
+
+    if let Some(memory) = memory {
+        request = request.header("x-lagon-memory", memory);
+    }
+
+    Ok(request.send().await?)
+}
+
+#[tokio::test]
+#[serial]
+async fn memory_overridden() -> Result<()> {
+    start_with_max_memory(Some(64), parse_trusted_proxies("127.0.0.1")?).await?;
+
+    let response = get_with_memory(Some("64")).await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "32");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn memory_not_overridden() -> Result<()> {
+    start_with_max_memory(Some(64), parse_trusted_proxies("127.0.0.1")?).await?;
+
+    let response = get_with_memory(None).await?;
+    assert_eq!(response.status(), 502);
+    assert_eq!(response.text().await?, PAGE_502);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn memory_override_capped() -> Result<()> {
+    start_with_max_memory(Some(24), parse_trusted_proxies("127.0.0.1")?).await?;
+
+    let response = get_with_memory(Some("64")).await?;
+    assert_eq!(response.status(), 502);
+    assert_eq!(response.text().await?, PAGE_502);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn memory_override_ignored_from_untrusted_peer() -> Result<()> {
+    start_with_max_memory(Some(64), Vec::new()).await?;
+
+    let response = get_with_memory(Some("64")).await?;
+    assert_eq!(response.status(), 502);
+    assert_eq!(response.text().await?, PAGE_502);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn memory_override_ignored_without_max_memory() -> Result<()> {
+    start_with_max_memory(None, parse_trusted_proxies("127.0.0.1")?).await?;
+
+    let response = get_with_memory(Some("64")).await?;
+    assert_eq!(response.status(), 502);
+    assert_eq!(response.text().await?, PAGE_502);
+
+    Ok(())
+}
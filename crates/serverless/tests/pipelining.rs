@@ -0,0 +1,66 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn pipelined_responses_ordered() -> Result<()> {
+    let deployments = Arc::new(DashMap::new());
+    deployments.insert(
+        "slow.lagon.test".into(),
+        Arc::new(utils::deployment("sleep")),
+    );
+    deployments.insert(
+        "fast.lagon.test".into(),
+        Arc::new(utils::deployment("status-text")),
+    );
+
+    utils::start_with_deployments(deployments, ServerlessOptions::default()).await?;
+
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+
+    // Both requests are sent before reading any response. The
+    // first one sleeps for 500ms, the second one returns directly
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: slow.lagon.test\r\n\r\nGET / HTTP/1.1\r\nHost: fast.lagon.test\r\n\r\n",
+        )
+        .await?;
+
+    let mut responses = Vec::new();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut buf = [0; 1024];
+
+        while !String::from_utf8_lossy(&responses).ends_with("I'm a teapot") {
+            let read = stream.read(&mut buf).await?;
+
+            if read == 0 {
+                break;
+            }
+
+            responses.extend_from_slice(&buf[..read]);
+        }
+
+        Ok::<_, std::io::Error>(())
+    })
+    .await??;
+
+    let responses = String::from_utf8_lossy(&responses);
+    let slow = responses.find("HTTP/1.1 200 OK\r\n").unwrap();
+    let fast = responses.find("HTTP/1.1 418 Short and stout\r\n").unwrap();
+
+    assert_eq!(slow, 0);
+    assert!(slow < fast);
+    assert!(responses[slow..fast].ends_with("Hello world"));
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use dashmap::DashMap;
 use lagon_runtime_utils::response::{PAGE_403, PAGE_404};
-use lagon_serverless::serverless::start;
+use lagon_serverless::{options::ServerlessOptions, serverless::start};
 use lagon_serverless_downloader::FakeDownloader;
 use lagon_serverless_pubsub::{FakePubSub, PubSubMessage, PubSubMessageKind};
 use serial_test::serial;
@@ -21,7 +21,7 @@ async fn deploy_undeploy() -> Result<()> {
         Arc::new(FakeDownloader),
         pubsub,
         client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+        ServerlessOptions::default(),
     )
     .await?;
     tokio::spawn(serverless);
@@ -95,7 +95,7 @@ async fn assign_correct_domains_prod() -> Result<()> {
         Arc::new(FakeDownloader),
         pubsub,
         client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+        ServerlessOptions::default(),
     )
     .await?;
     tokio::spawn(serverless);
@@ -157,7 +157,7 @@ async fn assign_correct_domains_dev() -> Result<()> {
         Arc::new(FakeDownloader),
         pubsub,
         client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+        ServerlessOptions::default(),
     )
     .await?;
     tokio::spawn(serverless);
@@ -219,7 +219,7 @@ async fn skip_cron_not_same_region() -> Result<()> {
         Arc::new(FakeDownloader),
         pubsub,
         client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+        ServerlessOptions::default(),
     )
     .await?;
     tokio::spawn(serverless);
@@ -264,7 +264,7 @@ async fn warn_cron_direct_access() -> Result<()> {
         Arc::new(FakeDownloader),
         pubsub,
         client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+        ServerlessOptions::default(),
     )
     .await?;
     tokio::spawn(serverless);
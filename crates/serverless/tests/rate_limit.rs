@@ -0,0 +1,77 @@
+use anyhow::Result;
+use lagon_runtime_utils::{response::PAGE_429, Deployment, RateLimit};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::time::Duration;
+
+mod utils;
+
+async fn start_rate_limited(rate_limit: RateLimit) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            rate_limit: Some(rate_limit),
+            ..utils::deployment("simple")
+        },
+        ServerlessOptions::default().trusted_proxies(vec!["127.0.0.1/32".parse()?]),
+    )
+    .await
+}
+
+#[tokio::test]
+#[serial]
+async fn within_limit() -> Result<()> {
+    start_rate_limited(RateLimit { rate: 1, burst: 3 }).await?;
+
+    for _ in 0..3 {
+        let response = reqwest::get("http://127.0.0.1:4000").await?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await?, "Hello world");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn return_429_over_limit() -> Result<()> {
+    start_rate_limited(RateLimit { rate: 1, burst: 2 }).await?;
+
+    for _ in 0..2 {
+        let response = reqwest::get("http://127.0.0.1:4000").await?;
+        assert_eq!(response.status(), 200);
+    }
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "1");
+    assert_eq!(response.text().await?, PAGE_429);
+
+    // Buckets are per client IP
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:4000")
+        .header("x-real-ip", "1.1.1.1")
+        .send()
+        .await?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn refill_over_time() -> Result<()> {
+    start_rate_limited(RateLimit { rate: 2, burst: 1 }).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 429);
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
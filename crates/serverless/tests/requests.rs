@@ -1,15 +1,12 @@
 use anyhow::Result;
-use dashmap::DashMap;
 use futures::StreamExt;
 use hyper::body::Bytes;
-use lagon_runtime_utils::Deployment;
-use lagon_serverless::serverless::start;
-use lagon_serverless_downloader::FakeDownloader;
-use lagon_serverless_pubsub::FakePubSub;
+use lagon_serverless::options::ServerlessOptions;
 use serial_test::serial;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
 };
 
 mod utils;
@@ -17,34 +14,7 @@ mod utils;
 #[tokio::test]
 #[serial]
 async fn returns_correct_http() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "request".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_serverless(utils::deployment("request"), ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 201);
@@ -59,34 +29,11 @@ async fn returns_correct_http() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn returns_correct_path() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "path-query".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // Arc::new(Mutex::new(Cronjob::new().await)),
+    utils::start_serverless(
+        utils::deployment("path-query"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -109,34 +56,11 @@ async fn returns_correct_path() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn forwards_headers() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "forwards-headers".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // // Arc::new(Mutex::new(Cronjob::new().await)),
+    utils::start_serverless(
+        utils::deployment("forwards-headers"),
+        ServerlessOptions::default(),
     )
     .await?;
-    tokio::spawn(serverless);
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -150,34 +74,7 @@ async fn forwards_headers() -> Result<()> {
 #[tokio::test]
 #[serial]
 async fn stream_sequentially() -> Result<()> {
-    let client = utils::setup();
-    let deployments = Arc::new(DashMap::new());
-    deployments.insert(
-        "127.0.0.1:4000".into(),
-        Arc::new(Deployment {
-            id: "stream".into(),
-            function_id: "function_id".into(),
-            function_name: "function_name".into(),
-            domains: HashSet::new(),
-            assets: HashSet::new(),
-            environment_variables: HashMap::new(),
-            memory: 128,
-            tick_timeout: 1000,
-            total_timeout: 1000,
-            is_production: true,
-            cron: None,
-        }),
-    );
-    let serverless = start(
-        deployments,
-        "127.0.0.1:4000".parse().unwrap(),
-        Arc::new(FakeDownloader),
-        FakePubSub::default(),
-        client,
-        // // Arc::new(Mutex::new(Cronjob::new().await)),
-    )
-    .await?;
-    tokio::spawn(serverless);
+    utils::start_serverless(utils::deployment("stream"), ServerlessOptions::default()).await?;
 
     let response = reqwest::get("http://127.0.0.1:4000").await?;
     assert_eq!(response.status(), 200);
@@ -190,3 +87,62 @@ async fn stream_sequentially() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn buffer_small_stream() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("stream"),
+        ServerlessOptions::default().stream_buffering(1024, Duration::from_millis(100)),
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-length").unwrap(), "11");
+    assert!(response.headers().get("transfer-encoding").is_none());
+    assert_eq!(response.text().await?, "Hello world");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn stream_max_chunks() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("stream"),
+        ServerlessOptions::default().stream_max_chunks(Some(2)),
+    )
+    .await?;
+
+    // The stream sends 3 chunks, so it's aborted before completing
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert!(response.bytes().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn custom_status_text() -> Result<()> {
+    utils::start_serverless(
+        utils::deployment("status-text"),
+        ServerlessOptions::default(),
+    )
+    .await?;
+
+    // reqwest only exposes the canonical reason, so read the raw status line
+    let mut stream = TcpStream::connect("127.0.0.1:4000").await?;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    assert!(response.starts_with("HTTP/1.1 418 Short and stout\r\n"));
+    assert!(response.ends_with("I'm a teapot"));
+
+    Ok(())
+}
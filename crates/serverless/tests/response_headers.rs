@@ -0,0 +1,72 @@
+use anyhow::Result;
+use lagon_runtime_utils::{Deployment, HeaderPolicy, ResponseHeader};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+
+mod utils;
+
+async fn start_with_response_headers(
+    id: &str,
+    response_headers: Vec<ResponseHeader>,
+) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            response_headers: Some(response_headers),
+            ..utils::deployment(id)
+        },
+        ServerlessOptions::default(),
+    )
+    .await
+}
+
+#[tokio::test]
+#[serial]
+async fn clear_site_data_forwarded() -> Result<()> {
+    start_with_response_headers(
+        "logout",
+        vec![ResponseHeader {
+            name: "x-frame-options".into(),
+            value: "DENY".into(),
+            policy: HeaderPolicy::Override,
+            path: None,
+        }],
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000/logout").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("clear-site-data").unwrap(),
+        "\"cookies\", \"storage\""
+    );
+    assert_eq!(response.headers().get("x-frame-options").unwrap(), "DENY");
+    assert_eq!(response.text().await?, "Logged out");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn response_header_injected_on_path() -> Result<()> {
+    start_with_response_headers(
+        "simple",
+        vec![ResponseHeader {
+            name: "clear-site-data".into(),
+            value: "\"cookies\"".into(),
+            policy: HeaderPolicy::Override,
+            path: Some("/logout".into()),
+        }],
+    )
+    .await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000/logout").await?;
+    assert_eq!(
+        response.headers().get("clear-site-data").unwrap(),
+        "\"cookies\""
+    );
+
+    let response = reqwest::get("http://127.0.0.1:4000/").await?;
+    assert!(response.headers().get("clear-site-data").is_none());
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use lagon_runtime_utils::Deployment;
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+
+mod utils;
+
+async fn start_server(id: &str, server_timing: bool) -> Result<()> {
+    utils::start_serverless(
+        Deployment {
+            server_timing,
+            ..utils::deployment(id)
+        },
+        ServerlessOptions::default(),
+    )
+    .await
+}
+
+fn get_entries(response: &reqwest::Response) -> Vec<String> {
+    response
+        .headers()
+        .get_all("server-timing")
+        .iter()
+        .flat_map(|value| value.to_str().unwrap().split(", "))
+        .map(|entry| entry.split(';').next().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+#[serial]
+async fn server_timing() -> Result<()> {
+    start_server("simple", true).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        get_entries(&response),
+        vec!["cold-start", "compile", "exec", "total"]
+    );
+
+    for entry in response.headers()["server-timing"].to_str()?.split(", ") {
+        let (_, duration) = entry.split_once(";dur=").unwrap();
+        assert!(duration.parse::<f64>()? >= 0.0);
+    }
+
+    assert_eq!(response.text().await?, "Hello world");
+
+    // The isolate is already warm
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(get_entries(&response), vec!["exec", "total"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn server_timing_disabled() -> Result<()> {
+    start_server("simple", false).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("server-timing").is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn server_timing_function_entries() -> Result<()> {
+    start_server("server-timing", true).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        get_entries(&response),
+        vec!["db", "cold-start", "compile", "exec", "total"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn server_timing_function_api() -> Result<()> {
+    start_server("server-timing-api", true).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        get_entries(&response),
+        vec!["cache", "miss", "cold-start", "compile", "exec", "total"]
+    );
+    assert_eq!(
+        response.headers()["server-timing"],
+        "cache;dur=1.5;desc=\"Cache \\\"read\\\"\", miss"
+    );
+    assert_eq!(
+        response.text().await?,
+        "TypeError: Invalid Server-Timing name: invalid name"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use lagon_serverless::{options::ServerlessOptions, serverless::start};
+use lagon_serverless_downloader::FakeDownloader;
+use lagon_serverless_pubsub::FakePubSub;
+use serial_test::serial;
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+mod utils;
+
+#[tokio::test]
+#[serial]
+async fn health() -> Result<()> {
+    let client = utils::setup();
+    let shutdown = CancellationToken::new();
+    let serverless = start(
+        Arc::new(DashMap::new()),
+        "127.0.0.1:4000".parse().unwrap(),
+        Arc::new(FakeDownloader),
+        FakePubSub::default(),
+        client,
+        ServerlessOptions::default().shutdown(shutdown.clone()),
+    )
+    .await?;
+    let serverless = tokio::spawn(serverless);
+
+    let response = reqwest::get("http://127.0.0.1:4000/__lagon/health").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "OK");
+
+    shutdown.cancel();
+    serverless.await?;
+
+    assert!(reqwest::get("http://127.0.0.1:4000/__lagon/health")
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn drain_in_flight_requests() -> Result<()> {
+    let client = utils::setup();
+    let deployments = Arc::new(DashMap::new());
+    deployments.insert(
+        "127.0.0.1:4000".into(),
+        Arc::new(utils::deployment("sleep")),
+    );
+    let shutdown = CancellationToken::new();
+    let serverless = start(
+        deployments,
+        "127.0.0.1:4000".parse().unwrap(),
+        Arc::new(FakeDownloader),
+        FakePubSub::default(),
+        client,
+        ServerlessOptions::default().shutdown(shutdown.clone()),
+    )
+    .await?;
+    let serverless = tokio::spawn(serverless);
+
+    let in_flight = tokio::spawn(reqwest::get("http://127.0.0.1:4000"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // New connections are refused while draining
+    assert!(reqwest::get("http://127.0.0.1:4000").await.is_err());
+
+    let response = in_flight.await??;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    serverless.await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn grace_period_elapsed() -> Result<()> {
+    let client = utils::setup();
+    let deployments = Arc::new(DashMap::new());
+    deployments.insert(
+        "127.0.0.1:4000".into(),
+        Arc::new(utils::deployment("sleep")),
+    );
+    let shutdown = CancellationToken::new();
+    let serverless = start(
+        deployments,
+        "127.0.0.1:4000".parse().unwrap(),
+        Arc::new(FakeDownloader),
+        FakePubSub::default(),
+        client,
+        ServerlessOptions::default()
+            .shutdown(shutdown.clone())
+            .shutdown_grace_period(Duration::from_millis(100)),
+    )
+    .await?;
+    let serverless = tokio::spawn(serverless);
+
+    let in_flight = tokio::spawn(reqwest::get("http://127.0.0.1:4000"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_millis(300), serverless).await??;
+
+    // The server exits without waiting for the request to complete
+    assert!(!in_flight.is_finished());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn drain_delay() -> Result<()> {
+    let shutdown = CancellationToken::new();
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default()
+            .shutdown(shutdown.clone())
+            .shutdown_drain_delay(Duration::from_millis(500)),
+    )
+    .await?;
+
+    shutdown.cancel();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The health requests fail while new requests are still accepted
+    let response = reqwest::get("http://127.0.0.1:4000/__lagon/health").await?;
+    assert_eq!(response.status(), 503);
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await?, "Hello world");
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert!(reqwest::get("http://127.0.0.1:4000").await.is_err());
+
+    Ok(())
+}
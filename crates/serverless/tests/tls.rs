@@ -0,0 +1,164 @@
+use anyhow::Result;
+use lagon_serverless::{
+    options::ServerlessOptions,
+    tls::{load_certified_key, CertificateStore},
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ServerName,
+};
+use serial_test::serial;
+use std::{sync::Arc, time::SystemTime};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+
+mod utils;
+
+// The certificates are self-signed, so the test only checks which one is presented
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+// Returns the PEM of the certificate and its private key, and the DER of the certificate
+fn generate_certificate(domain: &str) -> Result<(String, Vec<u8>)> {
+    let certificate = rcgen::generate_simple_self_signed(vec![domain.into()])?;
+    let pem = certificate.serialize_pem()?;
+    let der = rustls_pemfile::certs(&mut pem.as_bytes())?.remove(0);
+
+    Ok((pem + certificate.serialize_private_key_pem().as_str(), der))
+}
+
+// Returns the certificate presented by the server, and the response to a request
+async fn get(server_name: &str) -> Result<(Vec<u8>, String)> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect("127.0.0.1:4000").await?;
+    let mut stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from(server_name)?, stream)
+        .await?;
+
+    let certificate = stream.get_ref().1.peer_certificates().unwrap()[0].0.clone();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1:4000\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap_or(0);
+
+    Ok((certificate, response))
+}
+
+#[tokio::test]
+#[serial]
+async fn sni_certificates() -> Result<()> {
+    let (first_pem, first_der) = generate_certificate("first.domain")?;
+    let (second_pem, second_der) = generate_certificate("second.domain")?;
+    let (default_pem, default_der) = generate_certificate("default.domain")?;
+
+    let store = Arc::new(CertificateStore::default());
+    store.insert("first.domain", load_certified_key(first_pem.as_bytes())?);
+    store.insert("second.domain", load_certified_key(second_pem.as_bytes())?);
+
+    utils::start_serverless(
+        utils::deployment("simple"),
+        ServerlessOptions::default().tls(Arc::clone(&store)),
+    )
+    .await?;
+
+    let (certificate, response) = get("first.domain").await?;
+    assert_eq!(certificate, first_der);
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.ends_with("Hello world"));
+
+    let (certificate, _) = get("second.domain").await?;
+    assert_eq!(certificate, second_der);
+
+    // Unknown domains fail the handshake without a default certificate
+    assert!(get("unknown.domain").await.is_err());
+
+    // Certificates are replaced without restarting the server
+    store.set_default(Some(load_certified_key(default_pem.as_bytes())?));
+    store.insert("second.domain", load_certified_key(first_pem.as_bytes())?);
+
+    let (certificate, _) = get("unknown.domain").await?;
+    assert_eq!(certificate, default_der);
+
+    let (certificate, _) = get("second.domain").await?;
+    assert_eq!(certificate, first_der);
+
+    Ok(())
+}
+
+#[test]
+fn wildcard_certificates() -> Result<()> {
+    let (wildcard_pem, _) = generate_certificate("*.example.com")?;
+
+    let store = CertificateStore::default();
+    store.insert(
+        "*.example.com",
+        load_certified_key(wildcard_pem.as_bytes())?,
+    );
+
+    assert!(store.get(Some("acme.example.com")).is_some());
+    // Wildcards only match a single label
+    assert!(store.get(Some("eu.acme.example.com")).is_none());
+    assert!(store.get(Some("example.com")).is_none());
+    assert!(store.get(None).is_none());
+
+    assert!(load_certified_key(b"invalid").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn reload_certificates_dir() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("lagon-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let (first_pem, _) = generate_certificate("first.domain")?;
+    let (default_pem, _) = generate_certificate("default.domain")?;
+    std::fs::write(dir.join("first.domain.pem"), first_pem)?;
+
+    let store = CertificateStore::from_dir(dir.clone())?;
+    assert!(store.get(Some("first.domain")).is_some());
+    assert!(store.get(Some("unknown.domain")).is_none());
+
+    std::fs::remove_file(dir.join("first.domain.pem"))?;
+    std::fs::write(dir.join("default.pem"), default_pem)?;
+    store.reload()?;
+
+    // Removed certificates are removed from the store too, so
+    // the domain is now served the default certificate
+    assert_eq!(
+        store.get(Some("first.domain")).map(|key| key.cert.clone()),
+        store.get(None).map(|key| key.cert.clone())
+    );
+
+    // Invalid certificates keep the current ones
+    std::fs::write(dir.join("invalid.pem"), "invalid")?;
+    assert!(store.reload().is_err());
+    assert!(store.get(None).is_some());
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
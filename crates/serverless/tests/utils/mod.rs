@@ -1,7 +1,17 @@
+use anyhow::Result;
 use clickhouse::{test::handlers, Client};
+use dashmap::DashMap;
 use lagon_runtime::{options::RuntimeOptions, Runtime};
-use lagon_serverless::clickhouse::{LogRow, RequestRow};
-use std::sync::Once;
+use lagon_runtime_utils::Deployment;
+use lagon_serverless::{
+    clickhouse::{LogRow, RequestRow},
+    deployments::Deployments,
+    options::ServerlessOptions,
+    serverless::start,
+};
+use lagon_serverless_downloader::FakeDownloader;
+use lagon_serverless_pubsub::FakePubSub;
+use std::sync::{Arc, Once};
 
 use crate::utils::mock::Mock;
 
@@ -13,7 +23,7 @@ pub fn setup() -> Client {
     START.call_once(|| {
         dotenv::dotenv().expect("Failed to load .env file");
 
-        Runtime::new(RuntimeOptions::default());
+        Runtime::new(RuntimeOptions::default()).expect("Failed to start runtime");
     });
 
     let mock = Mock::new();
@@ -21,3 +31,49 @@ pub fn setup() -> Client {
     mock.add(handlers::record::<LogRow>());
     Client::default().with_url(mock.url())
 }
+
+// A production deployment of `deployments_test/{id}.js`. Other fields
+// are set with `Deployment { .., ..utils::deployment(id) }`
+#[allow(dead_code)]
+pub fn deployment(id: &str) -> Deployment {
+    Deployment {
+        id: id.into(),
+        function_id: "function_id".into(),
+        function_name: "function_name".into(),
+        memory: 128,
+        tick_timeout: 1000,
+        total_timeout: 1000,
+        is_production: true,
+        ..Default::default()
+    }
+}
+
+// Serve `deployment` on 127.0.0.1:4000
+#[allow(dead_code)]
+pub async fn start_serverless(deployment: Deployment, options: ServerlessOptions) -> Result<()> {
+    let deployments = Arc::new(DashMap::new());
+    deployments.insert("127.0.0.1:4000".into(), Arc::new(deployment));
+
+    start_with_deployments(deployments, options).await
+}
+
+// Listen on 127.0.0.1:4000, serving the deployments by hostname
+#[allow(dead_code)]
+pub async fn start_with_deployments(
+    deployments: Deployments,
+    options: ServerlessOptions,
+) -> Result<()> {
+    let client = setup();
+    let serverless = start(
+        deployments,
+        "127.0.0.1:4000".parse().unwrap(),
+        Arc::new(FakeDownloader),
+        FakePubSub::default(),
+        client,
+        options,
+    )
+    .await?;
+    tokio::spawn(serverless);
+
+    Ok(())
+}
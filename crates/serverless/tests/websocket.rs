@@ -0,0 +1,105 @@
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use lagon_serverless::options::ServerlessOptions;
+use serial_test::serial;
+use std::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+mod utils;
+
+async fn start_websocket(options: ServerlessOptions) -> Result<()> {
+    utils::start_serverless(utils::deployment("websocket"), options).await
+}
+
+#[tokio::test]
+#[serial]
+async fn echo_messages() -> Result<()> {
+    start_websocket(ServerlessOptions::default()).await?;
+
+    let (mut stream, response) = connect_async("ws://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 101);
+
+    stream.send(Message::Text("Hello world".into())).await?;
+    assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text("Hello world".into())
+    );
+
+    stream.send(Message::Binary(vec![1, 2, 3])).await?;
+    assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Binary(vec![1, 2, 3])
+    );
+
+    stream.close(None).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn ignore_non_upgrade_requests() -> Result<()> {
+    start_websocket(ServerlessOptions::default()).await?;
+
+    let response = reqwest::get("http://127.0.0.1:4000").await?;
+    assert_eq!(response.status(), 426);
+    assert_eq!(response.text().await?, "Expected a WebSocket");
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn close_idle_websocket() -> Result<()> {
+    start_websocket(
+        ServerlessOptions::default().websocket_idle_timeout(Duration::from_millis(200)),
+    )
+    .await?;
+
+    let (mut stream, _) = connect_async("ws://127.0.0.1:4000").await?;
+
+    stream.send(Message::Text("Hello world".into())).await?;
+    assert_eq!(
+        stream.next().await.unwrap()?,
+        Message::Text("Hello world".into())
+    );
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    assert!(matches!(
+        stream.next().await,
+        Some(Ok(Message::Close(_))) | None
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn close_after_max_duration() -> Result<()> {
+    start_websocket(
+        ServerlessOptions::default().websocket_max_duration(Duration::from_millis(300)),
+    )
+    .await?;
+
+    let (mut stream, _) = connect_async("ws://127.0.0.1:4000").await?;
+
+    for _ in 0..3 {
+        stream.send(Message::Text("Hello world".into())).await?;
+        assert_eq!(
+            stream.next().await.unwrap()?,
+            Message::Text("Hello world".into())
+        );
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert!(matches!(
+        stream.next().await,
+        Some(Ok(Message::Close(_))) | None
+    ));
+
+    Ok(())
+}
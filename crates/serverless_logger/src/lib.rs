@@ -49,13 +49,18 @@ impl Log for SimpleLogger {
         if self.enabled(record.metadata()) {
             let metadata = as_map(record.key_values());
 
-            println!(
-                "{} - {} - {} - {}",
-                Local::now(),
-                record.level(),
-                record.args(),
-                as_debug!(metadata),
-            );
+            // Access logs are already formatted
+            if record.target() == "access" {
+                println!("{}", record.args());
+            } else {
+                println!(
+                    "{} - {} - {} - {}",
+                    Local::now(),
+                    record.level(),
+                    record.args(),
+                    as_debug!(metadata),
+                );
+            }
 
             // Axiom is optional, so tx can have no listeners
             let tx = self.tx.read().expect("Tx lock is poisoned");
@@ -143,6 +143,9 @@ export function handler() {{
     tx.send_async(IsolateEvent::Request(IsolateRequest {
         request: Request::default(),
         sender: request_tx,
+        cancellation_token: None,
+        memory: None,
+        max_body_size: None,
     }))
     .await
     .unwrap();
@@ -175,7 +178,8 @@ async fn test_directory(path: &Path) {
 
 #[tokio::main]
 async fn main() {
-    let runtime = Runtime::new(RuntimeOptions::default().expose_gc(true));
+    let runtime =
+        Runtime::new(RuntimeOptions::default().expose_gc(true)).expect("Failed to start runtime");
 
     if let Some(path) = env::args().nth(1) {
         let path = Path::new(&path);
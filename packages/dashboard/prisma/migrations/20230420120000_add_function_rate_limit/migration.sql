@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE `Function` ADD COLUMN `rateLimit` INTEGER NULL,
+    ADD COLUMN `rateLimitBurst` INTEGER NULL;
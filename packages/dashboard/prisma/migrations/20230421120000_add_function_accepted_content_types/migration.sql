@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE `Function` ADD COLUMN `acceptedContentTypes` VARCHAR(191) NULL;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE `Function` ADD COLUMN `accessLogFormat` VARCHAR(191) NULL;
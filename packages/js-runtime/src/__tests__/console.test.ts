@@ -5,6 +5,12 @@ beforeEach(() => {
   globalThis.LagonSync = {
     ...globalThis.LagonSync,
     log: vi.fn(),
+    stackTrace: vi.fn(() => '\n  at handler (1:1)'),
+    inspect: vi.fn(value => JSON.stringify(value)),
+    consoleTime: vi.fn(() => true),
+    consoleTimeEnd: vi.fn(() => 12.3456),
+    consoleCount: vi.fn(() => 1),
+    consoleCountReset: vi.fn(() => true),
   };
 });
 
@@ -19,6 +25,12 @@ describe('Console', () => {
     expect(LagonSync.log).toHaveBeenCalledWith('log', 'Hello World');
   });
 
+  it('should log traces with the stack', () => {
+    console.trace('Hello %s', 'World');
+
+    expect(LagonSync.log).toHaveBeenCalledWith('trace', 'Trace: Hello World\n  at handler (1:1)');
+  });
+
   it('should receive all logs type', () => {
     const types = ['log', 'info', 'debug', 'error', 'warn'] as const;
 
@@ -40,6 +52,19 @@ describe('Console', () => {
     expect(LagonSync.log).toHaveBeenCalledWith('log', '{"hello":"world","nested":{"hello":"world"}}');
   });
 
+  it('should inspect objects with the host', () => {
+    const value = { hello: 'world' };
+    console.log(value);
+    expect(LagonSync.inspect).toHaveBeenLastCalledWith(value);
+
+    console.log('Hello %o', value);
+    expect(LagonSync.inspect).toHaveBeenLastCalledWith(value);
+
+    console.log({});
+    expect(LagonSync.inspect).toHaveBeenLastCalledWith({});
+    expect(LagonSync.log).toHaveBeenLastCalledWith('log', '{}');
+  });
+
   it('should log numbers', () => {
     console.log(42);
     expect(LagonSync.log).toHaveBeenLastCalledWith('log', '42');
@@ -185,4 +210,67 @@ describe('Console', () => {
     console.log('Hello %s, this is the %i test of printing %j');
     expect(LagonSync.log).toHaveBeenLastCalledWith('log', 'Hello %s, this is the %i test of printing %j');
   });
+
+  it('should log timers', () => {
+    console.time('test');
+    expect(LagonSync.consoleTime).toHaveBeenCalledWith('test');
+
+    console.timeLog('test', 'Hello', 'World');
+    expect(LagonSync.consoleTimeEnd).toHaveBeenLastCalledWith('test', false);
+    expect(LagonSync.log).toHaveBeenLastCalledWith('info', 'test: 12.346ms Hello World');
+
+    console.timeEnd('test');
+    expect(LagonSync.consoleTimeEnd).toHaveBeenLastCalledWith('test', true);
+    expect(LagonSync.log).toHaveBeenLastCalledWith('info', 'test: 12.346ms');
+  });
+
+  it('should use the default timer label', () => {
+    console.time();
+    expect(LagonSync.consoleTime).toHaveBeenCalledWith('default');
+
+    console.timeEnd();
+    expect(LagonSync.log).toHaveBeenLastCalledWith('info', 'default: 12.346ms');
+  });
+
+  it('should warn for unknown or existing timers', () => {
+    LagonSync.consoleTime = vi.fn(() => false);
+    LagonSync.consoleTimeEnd = vi.fn(() => undefined);
+
+    console.time('test');
+    expect(LagonSync.log).toHaveBeenLastCalledWith('warn', "Timer 'test' already exists");
+
+    console.timeEnd('unknown');
+    expect(LagonSync.log).toHaveBeenLastCalledWith('warn', "Timer 'unknown' does not exist");
+  });
+
+  it('should log failed assertions only', () => {
+    console.assert(true, 'Not logged');
+    console.assert(1 === 1);
+    expect(LagonSync.log).not.toHaveBeenCalled();
+
+    console.assert(false, 'Hello %s', 'World');
+    expect(LagonSync.log).toHaveBeenLastCalledWith('error', 'Assertion failed: Hello World');
+
+    console.assert(0);
+    expect(LagonSync.log).toHaveBeenLastCalledWith('error', 'Assertion failed');
+  });
+
+  it('should log counters', () => {
+    console.count('test');
+    expect(LagonSync.consoleCount).toHaveBeenLastCalledWith('test');
+    expect(LagonSync.log).toHaveBeenLastCalledWith('info', 'test: 1');
+
+    console.count();
+    expect(LagonSync.log).toHaveBeenLastCalledWith('info', 'default: 1');
+
+    console.countReset('test');
+    expect(LagonSync.consoleCountReset).toHaveBeenLastCalledWith('test');
+  });
+
+  it('should warn when resetting unknown counters', () => {
+    LagonSync.consoleCountReset = vi.fn(() => false);
+
+    console.countReset('unknown');
+    expect(LagonSync.log).toHaveBeenLastCalledWith('warn', "Count for 'unknown' does not exist");
+  });
 });
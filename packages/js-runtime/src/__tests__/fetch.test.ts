@@ -219,4 +219,21 @@ describe('Response', () => {
     expect(response.headers.get('Content-Type')).toEqual('text/plain');
     expect(await response.text()).toEqual('Hello');
   });
+
+  it('should read body as bytes', async () => {
+    const response = new Response('Hello');
+    const body = await response.bytes();
+    expect(body).toBeInstanceOf(Uint8Array);
+    expect(Array.from(body)).toEqual([72, 101, 108, 108, 111]);
+  });
+
+  it('should read request body as bytes', async () => {
+    const request = new Request('https://lagon.app', {
+      method: 'POST',
+      body: new Uint8Array([1, 2, 3]),
+    });
+    const body = await request.bytes();
+    expect(body).toBeInstanceOf(Uint8Array);
+    expect(Array.from(body)).toEqual([1, 2, 3]);
+  });
 });
@@ -20,8 +20,13 @@ import './runtime/http/URLPattern';
 import './runtime/http/Headers';
 import './runtime/http/FormData';
 import './runtime/http/Response';
+import './runtime/http/StreamingResponse';
+import './runtime/http/MultipartResponse';
+import './runtime/http/GrpcWebResponse';
+import './runtime/http/ZipResponse';
 import './runtime/http/Request';
 import './runtime/http/fetch';
+import './runtime/http/WebSocket';
 
 // Declare the global functions and variables available
 // on the runtime, that are injected from the Rust code.
@@ -52,21 +57,144 @@ declare global {
 
   var AsyncLocalStorage: AsyncLocalStorageConstructor;
 
+  interface StreamingResponseConstructor {
+    new (init?: ResponseInit): StreamingResponse;
+  }
+
+  interface StreamingResponse extends Response {
+    write(chunk: string | ArrayBuffer | ArrayBufferView): void;
+    flush(): Promise<void>;
+    end(chunk?: string | ArrayBuffer | ArrayBufferView): void;
+  }
+
+  var StreamingResponse: StreamingResponseConstructor;
+
+  type MultipartBody = string | ArrayBuffer | ArrayBufferView | ReadableStream<Uint8Array>;
+
+  interface MultipartPart {
+    headers?: HeadersInit;
+    body: MultipartBody;
+  }
+
+  interface MultipartResponseInit extends ResponseInit {
+    // e.g `byteranges`, defaults to `mixed`
+    subtype?: string;
+  }
+
+  interface MultipartResponseConstructor {
+    new (parts: MultipartPart[], init?: MultipartResponseInit): MultipartResponse;
+  }
+
+  interface MultipartResponse extends Response {
+    readonly boundary: string;
+  }
+
+  var MultipartResponse: MultipartResponseConstructor;
+
+  interface GrpcWebResponseConstructor {
+    new (init?: ResponseInit): GrpcWebResponse;
+  }
+
+  interface GrpcWebResponse extends StreamingResponse {
+    sendMessage(message: ArrayBuffer | ArrayBufferView): void;
+    // Ends the response with the trailers frame
+    sendStatus(status?: number, message?: string, trailers?: HeadersInit): void;
+  }
+
+  var GrpcWebResponse: GrpcWebResponseConstructor;
+
+  type ZipBody = string | ArrayBuffer | ArrayBufferView | ReadableStream<Uint8Array>;
+
+  interface ZipEntry {
+    name: string;
+    body: ZipBody;
+    // Deflate the entry instead of storing it as-is
+    compress?: boolean;
+  }
+
+  interface ZipResponseConstructor {
+    new (entries: ZipEntry[], init?: ResponseInit): Response;
+  }
+
+  var ZipResponse: ZipResponseConstructor;
+
+  // name, deflate, crc, compressed size, uncompressed size, offset of the local header
+  type ZipEntryRecord = [string, boolean, number, number, number, number];
+
+  interface RequestInit {
+    // Set to `false` to receive compressed response bodies as-is
+    decompress?: boolean;
+  }
+
+  interface Uint8ArrayConstructor {
+    fromBase64(string: string, options?: { alphabet?: 'base64' | 'base64url' }): Uint8Array;
+  }
+
   var LagonSync: {
     log: (level: string, message: string) => void;
+    stackTrace: () => string;
+    inspect: (value: unknown) => string;
+    consoleTime: (label: string) => boolean;
+    consoleTimeEnd: (label: string, end: boolean) => number | undefined;
+    consoleCount: (label: string) => number | undefined;
+    consoleCountReset: (label: string) => boolean;
     pullStream: (id: number, done: boolean, chunk?: Uint8Array) => void;
     uuid: () => `${string}-${string}-${string}-${string}-${string}`;
     randomValues: <T extends ArrayBufferView | null>(array: T) => T;
     getKeyValue: () => ArrayBuffer;
     queueMicrotask: (callback: () => void) => void;
+    pullFetchBody: (id: number, done: boolean, chunk?: Uint8Array, error?: string) => void;
+    upgradeWebSocket: () => number;
+    webSocketSend: (id: number, data: string | Uint8Array) => void;
+    webSocketClose: (id: number) => void;
+    waitUntil: (promise: Promise<unknown>) => void;
+    parseJson: (json: string) => unknown;
+    jsonMaxSize: () => number;
+    parseAcceptLanguage: (header: string) => [string, number][];
+    matchLocale: (header: string) => string | undefined;
+    maxBodySize: () => number | undefined;
+    decodeBase64: (data: string, url: boolean) => Uint8Array;
+    multipartBoundary: () => string;
+    isGrpcWebContentType: (contentType: string) => boolean;
+    grpcWebMessage: (message: Uint8Array) => Uint8Array;
+    grpcWebTrailers: (status: number, message: string | undefined, trailers: Map<string, string>) => Uint8Array;
+    zipLocalHeader: (name: string, deflate: boolean) => Uint8Array;
+    zipCrc32: (crc: number, chunk: Uint8Array) => number;
+    zipDeflate: (chunk: Uint8Array, last: boolean) => Uint8Array;
+    zipDataDescriptor: (...record: ZipEntryRecord) => Uint8Array;
+    zipCentralDirectoryHeader: (...record: ZipEntryRecord) => Uint8Array;
+    zipEndOfCentralDirectory: (entries: number, size: number, offset: number) => Uint8Array;
   };
 
   var LagonAsync: {
-    fetch: ({ h, m, b, u }: { h?: Map<string, string>; m: string; b?: ArrayBuffer; u: string }) => Promise<{
+    fetch: ({
+      h,
+      m,
+      b,
+      u,
+      s,
+      c,
+      d,
+      a,
+    }: {
+      h?: Map<string, string>;
+      m: string;
+      b?: ArrayBuffer;
+      u: string;
+      s?: number;
+      c?: RequestCache;
+      d?: boolean;
+      a?: boolean;
+    }) => Promise<{
       b: ArrayBuffer;
       s: number;
       h?: Record<string, string>;
+      f?: number;
+      t?: FetchTiming;
     }>;
+    readFetchBody: (id: number, all?: boolean) => Promise<Uint8Array | undefined>;
+    readFetchTrailers: (id: number) => Promise<Record<string, string>>;
+    webSocketReceive: (id: number) => Promise<string | Uint8Array | undefined>;
     sign: (
       algorithm: AlgorithmIdentifier | RsaPssParams | EcdsaParams,
       key: CryptoKey,
@@ -96,9 +224,19 @@ declare global {
     parseMultipart: (headers: Headers, body?: string) => FormData;
     TEXT_ENCODER: TextEncoder;
     TEXT_DECODER: TextDecoder;
+    fetchBodies: WeakMap<ReadableStream, number>;
+    readFetchBody: (id: number) => ReadableStream<Uint8Array>;
   };
   var __storage__: Map<AsyncContext, unknown>;
-  var handler: (request: Request) => Promise<Response>;
+  interface HandlerEvent {
+    waitUntil(promise: Promise<unknown>): void;
+    // Non-standard: add an entry to the Server-Timing header of the response,
+    // with a duration in milliseconds. Entries added after the handler returned are ignored
+    addServerTiming(name: string, duration?: number, description?: string): void;
+  }
+
+  var handler: (request: Request, event: HandlerEvent) => Promise<Response>;
+  var upgradeWebSocket: (request: Request) => { socket: WebSocket; response: Response };
   var masterHandler: (
     id: number,
     request: {
@@ -106,15 +244,59 @@ declare global {
       m: RequestInit['method'];
       h: RequestInit['headers'];
       b: RequestInit['body'];
+      // Set when the body has been spilled to disk by the host
+      f?: number;
     },
   ) => Promise<{
     b: ArrayBuffer;
     h: ResponseInit['headers'];
     s: ResponseInit['status'];
+    // Only set when the response has a custom status text
+    st?: string;
+    f?: number;
   }>;
 
+  interface Body {
+    bytes(): Promise<Uint8Array>;
+  }
+
+  interface RequestProgress {
+    loaded: number;
+    // From the content-length header, when present
+    total?: number;
+  }
+
+  interface RequestProgressOptions {
+    // How many bytes are read between each progress report
+    granularity?: number;
+    // Reading the body fails once it is larger than this many bytes
+    maxSize?: number;
+  }
+
+  interface AcceptLanguage {
+    locale: string;
+    quality: number;
+  }
+
+  interface Request {
+    onProgress(callback: (progress: RequestProgress) => void, options?: RequestProgressOptions): void;
+    acceptLanguages(): AcceptLanguage[];
+    readonly locale: string | undefined;
+  }
+
+  // When each phase of a fetch() call ended, in milliseconds since it started
+  interface FetchTiming {
+    dns: number;
+    connect: number;
+    tls: number;
+    ttfb: number;
+    total: number;
+  }
+
   interface Response {
     readonly isStream: boolean;
+    readonly trailers: Promise<Headers>;
+    readonly timing: FetchTiming | undefined;
   }
 
   interface Blob {
@@ -126,6 +308,9 @@ declare global {
   }
 }
 
+// https://httpwg.org/specs/rfc9110.html#tokens
+const SERVER_TIMING_NAME = /^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/;
+
 globalThis.masterHandler = async (id, request) => {
   if (typeof handler !== 'function') {
     throw new Error('Handler function is not defined or is not a function');
@@ -134,11 +319,56 @@ globalThis.masterHandler = async (id, request) => {
   const handlerRequest = new Request(request.i, {
     method: request.m,
     headers: request.h,
-    body: request.b,
+    body: request.f !== undefined ? globalThis.__lagon__.readFetchBody(request.f) : request.b,
   });
 
-  const response = await handler(handlerRequest);
-  let body: ArrayBuffer;
+  const serverTimings: string[] = [];
+
+  const event: HandlerEvent = {
+    waitUntil: promise => {
+      // Rejections are logged instead of being reported
+      // to the requests processed at the same time
+      LagonSync.waitUntil(
+        Promise.resolve(promise).catch(error => {
+          console.error('Uncaught (in waitUntil)', error);
+        }),
+      );
+    },
+    addServerTiming: (name, duration, description) => {
+      if (!SERVER_TIMING_NAME.test(name)) {
+        throw new TypeError(`Invalid Server-Timing name: ${name}`);
+      }
+
+      let entry = name;
+
+      if (duration !== undefined) {
+        entry += `;dur=${Number(duration)}`;
+      }
+
+      if (description !== undefined) {
+        description = String(description);
+
+        // Header values can only contain visible ASCII characters
+        if (!/^[\x20-\x7e]*$/.test(description)) {
+          throw new TypeError(`Invalid Server-Timing description: ${description}`);
+        }
+
+        entry += `;desc=${JSON.stringify(description)}`;
+      }
+
+      serverTimings.push(entry);
+    },
+  };
+
+  const response = await handler(handlerRequest, event);
+  let headers = response.headers;
+  let body: Uint8Array;
+
+  // Sent as another header, so the entries set by the function are kept
+  if (serverTimings.length > 0) {
+    headers = new Headers(headers);
+    headers.append('server-timing', serverTimings.join(', '));
+  }
 
   if (response.isStream) {
     const responseBody = response.body;
@@ -148,6 +378,21 @@ globalThis.masterHandler = async (id, request) => {
     }
 
     body = globalThis.__lagon__.TEXT_ENCODER.encode(responseBody.toString());
+
+    // The body of a fetch() response returned as-is is piped
+    // directly from the host, without going through the isolate
+    const fetchBodyId = globalThis.__lagon__.fetchBodies.get(responseBody);
+
+    if (fetchBodyId !== undefined && !responseBody.locked) {
+      return {
+        b: body,
+        h: headers,
+        s: response.status,
+        st: response.statusText || undefined,
+        f: fetchBodyId,
+      };
+    }
+
     const reader = responseBody.getReader();
 
     const read = () => {
@@ -167,12 +412,13 @@ globalThis.masterHandler = async (id, request) => {
 
     read();
   } else {
-    body = await response.arrayBuffer();
+    body = await response.bytes();
   }
 
   return {
     b: body,
-    h: response.headers,
+    h: headers,
     s: response.status,
+    st: response.statusText || undefined,
   };
 };
@@ -60,6 +60,27 @@
     }
   };
 
+  // Read fetch() response bodies and request bodies spilled to disk lazily from the
+  // host. When the stream isn't touched and is returned from the handler, the host
+  // pipes it directly
+  const readFetchBody = (id: number) => {
+    const stream = new ReadableStream<Uint8Array>({
+      pull: async controller => {
+        const chunk = await LagonAsync.readFetchBody(id);
+
+        if (chunk === undefined) {
+          controller.close();
+        } else {
+          controller.enqueue(chunk);
+        }
+      },
+    });
+
+    globalThis.__lagon__.fetchBodies.set(stream, id);
+
+    return stream;
+  };
+
   const TEXT_ENCODER = new TextEncoder();
   const TEXT_DECODER = new TextDecoder();
 
@@ -72,5 +93,7 @@
     parseMultipart,
     TEXT_ENCODER,
     TEXT_DECODER,
+    fetchBodies: new WeakMap(),
+    readFetchBody,
   };
 })(globalThis);
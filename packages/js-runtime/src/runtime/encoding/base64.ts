@@ -51,4 +51,15 @@
     }
     return output;
   };
+
+  // https://tc39.es/proposal-arraybuffer-base64/, decoded host-side
+  // instead of going through atob() and a binary string
+  if (!('fromBase64' in Uint8Array)) {
+    Object.defineProperty(Uint8Array, 'fromBase64', {
+      value: (string: string, options?: { alphabet?: 'base64' | 'base64url' }) =>
+        LagonSync.decodeBase64(String(string), options?.alphabet === 'base64url'),
+      writable: true,
+      configurable: true,
+    });
+  }
 })(globalThis);
@@ -9,13 +9,18 @@
     } else if (input === undefined || input === null) {
       return input === undefined ? 'undefined' : 'null';
     } else {
-      const result = JSON.stringify(input);
-
-      if (result === '{}' && typeof input === 'object' && 'toString' in input) {
+      // Objects without properties but with a custom toString(), e.g errors
+      if (
+        Object.keys(input).length === 0 &&
+        input.toString !== Object.prototype.toString &&
+        !Array.isArray(input) &&
+        !ArrayBuffer.isView(input)
+      ) {
         return input.toString();
       }
 
-      return result;
+      // Formatted by the host like util.inspect()
+      return LagonSync.inspect(input);
     }
   };
 
@@ -51,9 +56,10 @@
             case '%f':
               return String(parseFloat(arg as string));
             case '%j':
+              return JSON.stringify(arg);
             case '%o':
             case '%O':
-              return JSON.stringify(arg);
+              return inspect(arg);
             case '%%':
               return '%';
             case '%c':
@@ -80,4 +86,63 @@
       LagonSync.log(type, format(input, ...args));
     };
   });
+
+  globalThis.console.trace = (...args) => {
+    const message = args.length > 0 ? `Trace: ${format(args[0], ...args.slice(1))}` : 'Trace';
+
+    LagonSync.log('trace', message + LagonSync.stackTrace());
+  };
+
+  // Timers are tracked by the host, per request
+  globalThis.console.time = (label = 'default') => {
+    if (!LagonSync.consoleTime(String(label))) {
+      LagonSync.log('warn', `Timer '${label}' already exists`);
+    }
+  };
+
+  const timeLog = (label: string, end: boolean, args: unknown[]) => {
+    const elapsed = LagonSync.consoleTimeEnd(String(label), end);
+
+    if (elapsed === undefined) {
+      LagonSync.log('warn', `Timer '${label}' does not exist`);
+      return;
+    }
+
+    const message = `${label}: ${elapsed.toFixed(3)}ms`;
+
+    LagonSync.log('info', args.length > 0 ? `${message} ${format(args[0], ...args.slice(1))}` : message);
+  };
+
+  globalThis.console.timeLog = (label = 'default', ...args) => {
+    timeLog(label, false, args);
+  };
+
+  globalThis.console.timeEnd = (label = 'default') => {
+    timeLog(label, true, []);
+  };
+
+  globalThis.console.assert = (condition, ...args) => {
+    if (condition) {
+      return;
+    }
+
+    const message = args.length > 0 ? `Assertion failed: ${format(args[0], ...args.slice(1))}` : 'Assertion failed';
+
+    LagonSync.log('error', message);
+  };
+
+  // Counters are also tracked by the host, per request
+  globalThis.console.count = (label = 'default') => {
+    const count = LagonSync.consoleCount(String(label));
+
+    if (count !== undefined) {
+      LagonSync.log('info', `${label}: ${count}`);
+    }
+  };
+
+  globalThis.console.countReset = (label = 'default') => {
+    if (!LagonSync.consoleCountReset(String(label))) {
+      LagonSync.log('warn', `Count for '${label}' does not exist`);
+    }
+  };
 })(globalThis);
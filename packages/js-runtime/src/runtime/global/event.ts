@@ -182,4 +182,31 @@
       this.total = eventInitDict?.total ?? 0;
     }
   };
+
+  // @ts-expect-error we don't implement all the properties
+  globalThis.MessageEvent = class<T> extends Event {
+    readonly data: T;
+    readonly origin: string;
+
+    constructor(type: string, eventInitDict?: MessageEventInit<T>) {
+      super(type, eventInitDict);
+
+      this.data = eventInitDict?.data ?? (null as T);
+      this.origin = eventInitDict?.origin ?? '';
+    }
+  };
+
+  globalThis.CloseEvent = class extends Event {
+    readonly code: number;
+    readonly reason: string;
+    readonly wasClean: boolean;
+
+    constructor(type: string, eventInitDict?: CloseEventInit) {
+      super(type, eventInitDict);
+
+      this.code = eventInitDict?.code ?? 0;
+      this.reason = eventInitDict?.reason ?? '';
+      this.wasClean = eventInitDict?.wasClean ?? false;
+    }
+  };
 })(globalThis);
@@ -0,0 +1,34 @@
+(globalThis => {
+  const GRPC_WEB_CONTENT_TYPE = 'application/grpc-web+proto';
+
+  // Non-standard StreamingResponse sending gRPC-Web framed messages, followed
+  // by the status of the call in the trailers frame. gRPC always responds with
+  // a 200, the status of the call being sent with the trailers
+  globalThis.GrpcWebResponse = class extends StreamingResponse {
+    constructor(init?: ResponseInit) {
+      const headers = new Headers(init?.headers);
+      const contentType = headers.get('content-type');
+
+      if (contentType === null) {
+        headers.set('content-type', GRPC_WEB_CONTENT_TYPE);
+      } else if (!LagonSync.isGrpcWebContentType(contentType)) {
+        throw new TypeError(`Invalid gRPC-Web content type: ${contentType}`);
+      }
+
+      super({ ...init, status: 200, headers });
+    }
+
+    sendMessage(message: ArrayBuffer | ArrayBufferView) {
+      const bytes =
+        message instanceof ArrayBuffer
+          ? new Uint8Array(message)
+          : new Uint8Array(message.buffer, message.byteOffset, message.byteLength);
+
+      this.write(LagonSync.grpcWebMessage(bytes));
+    }
+
+    sendStatus(status = 0, message?: string, trailers?: HeadersInit) {
+      this.end(LagonSync.grpcWebTrailers(status, message, new Map(new Headers(trailers))));
+    }
+  };
+})(globalThis);
@@ -0,0 +1,137 @@
+(globalThis => {
+  const CRLF = '\r\n';
+
+  const toBytes = (chunk: string | ArrayBuffer | ArrayBufferView): Uint8Array => {
+    if (typeof chunk === 'string') {
+      return globalThis.__lagon__.TEXT_ENCODER.encode(chunk);
+    }
+
+    if (chunk instanceof ArrayBuffer) {
+      return new Uint8Array(chunk);
+    }
+
+    return new Uint8Array(chunk.buffer, chunk.byteOffset, chunk.byteLength);
+  };
+
+  // Detect the delimiter in the content of a part, even when it is split
+  // across multiple chunks
+  const createDelimiterCheck = (delimiter: Uint8Array) => {
+    let tail = new Uint8Array(0);
+
+    return (chunk: Uint8Array): boolean => {
+      const window = new Uint8Array(tail.length + chunk.length);
+      window.set(tail);
+      window.set(chunk, tail.length);
+
+      let found = false;
+
+      search: for (let start = 0; start + delimiter.length <= window.length; start++) {
+        for (let index = 0; index < delimiter.length; index++) {
+          if (window[start + index] !== delimiter[index]) {
+            continue search;
+          }
+        }
+
+        found = true;
+        break;
+      }
+
+      tail = window.slice(Math.max(0, window.length - delimiter.length + 1));
+
+      return found;
+    };
+  };
+
+  async function* readPart(body: MultipartBody): AsyncGenerator<Uint8Array> {
+    if (!(body instanceof ReadableStream)) {
+      yield toBytes(body);
+      return;
+    }
+
+    const reader = body.getReader();
+
+    while (true) {
+      const { done, value } = await reader.read();
+
+      if (done) {
+        return;
+      }
+
+      yield value;
+    }
+  }
+
+  // Frame the parts with the delimiter (RFC 2046), reading each part only
+  // when the previous ones have been sent
+  async function* frameParts(parts: MultipartPart[], boundary: string): AsyncGenerator<Uint8Array> {
+    const delimiter = `--${boundary}`;
+    const delimiterBytes = toBytes(delimiter);
+
+    for (const [index, part] of parts.entries()) {
+      // The CRLF before a delimiter belongs to the delimiter
+      let head = index > 0 ? CRLF : '';
+      head += delimiter + CRLF;
+
+      new Headers(part.headers).forEach((value, name) => {
+        head += `${name}: ${value}${CRLF}`;
+      });
+
+      yield toBytes(head + CRLF);
+
+      const containsDelimiter = createDelimiterCheck(delimiterBytes);
+
+      for await (const chunk of readPart(part.body)) {
+        if (containsDelimiter(chunk)) {
+          throw new TypeError('Multipart part contains the boundary');
+        }
+
+        if (chunk.byteLength !== 0) {
+          yield chunk;
+        }
+      }
+    }
+
+    yield toBytes(`${CRLF}${delimiter}--${CRLF}`);
+  }
+
+  // Non-standard Response streaming multiple parts as a single `multipart/*`
+  // body, e.g for `multipart/byteranges` or batch APIs
+  globalThis.MultipartResponse = class extends Response {
+    readonly boundary: string;
+
+    constructor(parts: MultipartPart[], init?: MultipartResponseInit) {
+      const boundary = LagonSync.multipartBoundary();
+      const chunks = frameParts(parts, boundary);
+
+      const headers = new Headers(init?.headers);
+      headers.set('content-type', `multipart/${init?.subtype ?? 'mixed'}; boundary=${boundary}`);
+
+      super(
+        new ReadableStream<Uint8Array>(
+          {
+            async pull(controller) {
+              try {
+                const { done, value } = await chunks.next();
+
+                if (done) {
+                  controller.close();
+                } else {
+                  controller.enqueue(value);
+                }
+              } catch (error) {
+                controller.error(error);
+              }
+            },
+            async cancel() {
+              await chunks.return(undefined);
+            },
+          },
+          { highWaterMark: 0 },
+        ),
+        { ...init, headers },
+      );
+
+      this.boundary = boundary;
+    }
+  };
+})(globalThis);
@@ -1,6 +1,60 @@
-import { RequestResponseBody } from './body';
+import { bodySizeError, jsonSizeError, RequestResponseBody } from './body';
+
+type Progress = {
+  callback: (progress: RequestProgress) => void;
+  granularity: number;
+  maxSize?: number;
+};
 
 (globalThis => {
+  const DEFAULT_PROGRESS_GRANULARITY = 64 * 1024;
+
+  // Re-emit the chunks of the body in slices of at most `granularity` bytes,
+  // reporting the progress each time another `granularity` bytes are read
+  const trackProgress = (
+    body: ReadableStream<Uint8Array>,
+    total: number | undefined,
+    progress: Progress,
+  ): ReadableStream<Uint8Array> => {
+    const reader = body.getReader();
+    let loaded = 0;
+    let reported = 0;
+
+    return new ReadableStream<Uint8Array>({
+      pull: async controller => {
+        const { done, value } = await reader.read();
+
+        if (done) {
+          if (loaded !== reported) {
+            progress.callback({ loaded, total });
+          }
+
+          controller.close();
+          return;
+        }
+
+        for (let offset = 0; offset < value.byteLength; offset += progress.granularity) {
+          const chunk = value.subarray(offset, offset + progress.granularity);
+          loaded += chunk.byteLength;
+
+          if (progress.maxSize !== undefined && loaded > progress.maxSize) {
+            controller.error(new RangeError('Request body exceeds the size limit'));
+            reader.cancel();
+            return;
+          }
+
+          controller.enqueue(chunk);
+
+          if (loaded - reported >= progress.granularity) {
+            reported = loaded;
+            progress.callback({ loaded, total });
+          }
+        }
+      },
+      cancel: reason => reader.cancel(reason),
+    });
+  };
+
   globalThis.Request = class extends RequestResponseBody {
     readonly method: string;
     readonly cache: RequestCache;
@@ -15,6 +69,8 @@ import { RequestResponseBody } from './body';
 
     private readonly init?: RequestInit;
     private readonly input: RequestInfo | URL;
+    private progress?: Progress;
+    private progressBody?: ReadableStream<Uint8Array> | null;
 
     constructor(input: RequestInfo | URL, init?: RequestInit) {
       if (input instanceof Request && input.bodyUsed) {
@@ -46,6 +102,117 @@ import { RequestResponseBody } from './body';
       return this.init?.signal || new AbortSignal();
     }
 
+    // Non-standard: report the progress of reading the body, e.g for large uploads
+    onProgress(callback: (progress: RequestProgress) => void, options?: RequestProgressOptions) {
+      this.progress = {
+        callback,
+        granularity: options?.granularity ?? DEFAULT_PROGRESS_GRANULARITY,
+        maxSize: options?.maxSize,
+      };
+    }
+
+    // Non-standard: the languages of the Accept-Language header, sorted by quality
+    acceptLanguages(): AcceptLanguage[] {
+      const languages = LagonSync.parseAcceptLanguage(this.headers.get('accept-language') ?? '');
+
+      return languages.map(([locale, quality]) => ({ locale, quality }));
+    }
+
+    // Non-standard: the supported locale best matching the Accept-Language
+    // header, or the first supported locale when none match
+    get locale(): string | undefined {
+      return LagonSync.matchLocale(this.headers.get('accept-language') ?? '');
+    }
+
+    get body(): ReadableStream<Uint8Array> | null {
+      const body = super.body;
+
+      if (!this.progress || !body) {
+        return body;
+      }
+
+      if (this.progressBody === undefined) {
+        const contentLength = this.headers.get('content-length');
+        const total = contentLength !== null ? Number(contentLength) : undefined;
+
+        this.progressBody = trackProgress(body, total, this.progress);
+      }
+
+      return this.progressBody;
+    }
+
+    // Read the body chunk by chunk when tracking the progress,
+    // and concatenate the chunks once at the end
+    protected async readBytes(): Promise<Uint8Array> {
+      if (!this.progress) {
+        return super.readBytes();
+      }
+
+      if (this.bodyUsed) {
+        throw new TypeError('Body is already used');
+      }
+
+      const body = this.body;
+      this.bodyUsed = true;
+
+      if (!body) {
+        return new Uint8Array();
+      }
+
+      const reader = body.getReader();
+      const chunks: Uint8Array[] = [];
+      let length = 0;
+
+      for (;;) {
+        const { done, value } = await reader.read();
+
+        if (done) {
+          break;
+        }
+
+        const error = bodySizeError(length + value.byteLength);
+
+        if (error) {
+          reader.cancel(error);
+          throw error;
+        }
+
+        chunks.push(value);
+        length += value.byteLength;
+      }
+
+      const result = new Uint8Array(length);
+      let offset = 0;
+
+      for (const chunk of chunks) {
+        result.set(chunk, offset);
+        offset += chunk.byteLength;
+      }
+
+      return result;
+    }
+
+    async text(): Promise<string> {
+      if (!this.progress) {
+        return super.text();
+      }
+
+      return globalThis.__lagon__.TEXT_DECODER.decode(await this.readBytes());
+    }
+
+    // Parsed host-side to enforce the size and nesting depth limits. The size is
+    // checked against the Content-Length and while reading the body, before
+    // buffering it entirely
+    async json<T>(): Promise<T> {
+      const error = jsonSizeError(Number(this.headers.get('content-length') ?? 0));
+
+      if (error) {
+        throw error;
+      }
+
+      return this.readText(jsonSizeError).then(text => LagonSync.parseJson(text) as T);
+    }
+
     clone(): Request {
       return new Request(this.url, {
         method: this.method,
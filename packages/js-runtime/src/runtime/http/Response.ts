@@ -36,6 +36,16 @@ import { RequestResponseBody } from './body';
       this.redirected = false;
     }
 
+    // Only the responses of fetch() can have trailers
+    get trailers(): Promise<Headers> {
+      return Promise.resolve(new Headers());
+    }
+
+    // Only set for the responses of fetch()
+    get timing(): FetchTiming | undefined {
+      return undefined;
+    }
+
     clone(): Response {
       return new Response(this.body, {
         status: this.status,
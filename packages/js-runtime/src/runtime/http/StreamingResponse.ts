@@ -0,0 +1,76 @@
+(globalThis => {
+  // Non-standard Response that can be returned right away and written
+  // to afterwards, until `end()` is called
+  globalThis.StreamingResponse = class extends Response {
+    private controller: ReadableStreamDefaultController<Uint8Array>;
+    private ended = false;
+    // Whether the reader of the body is waiting for a chunk, meaning all the
+    // previous ones have been read, and the pending flush() calls
+    private pulling: { waiting: boolean; flushes: (() => void)[] };
+
+    constructor(init?: ResponseInit) {
+      let streamController: ReadableStreamDefaultController<Uint8Array> | undefined;
+      const pulling = { waiting: false, flushes: [] as (() => void)[] };
+
+      super(
+        new ReadableStream<Uint8Array>(
+          {
+            start(controller) {
+              streamController = controller;
+            },
+            // Only called once the queue is empty and a read is pending, since
+            // nothing is buffered in advance with a high water mark of 0
+            pull() {
+              pulling.waiting = true;
+              pulling.flushes.splice(0).forEach(resolve => resolve());
+            },
+          },
+          { highWaterMark: 0 },
+        ),
+        init,
+      );
+
+      this.controller = streamController!;
+      this.pulling = pulling;
+    }
+
+    write(chunk: string | ArrayBuffer | ArrayBufferView) {
+      if (this.ended) {
+        throw new TypeError('Cannot write to a StreamingResponse after end() has been called');
+      }
+
+      this.pulling.waiting = false;
+
+      if (typeof chunk === 'string') {
+        this.controller.enqueue(globalThis.__lagon__.TEXT_ENCODER.encode(chunk));
+      } else if (chunk instanceof ArrayBuffer) {
+        this.controller.enqueue(new Uint8Array(chunk));
+      } else {
+        this.controller.enqueue(new Uint8Array(chunk.buffer, chunk.byteOffset, chunk.byteLength));
+      }
+    }
+
+    // Resolves once the chunks written so far have been sent to the client,
+    // each as its own chunk, before any of the next writes
+    flush(): Promise<void> {
+      if (this.ended || this.pulling.waiting) {
+        return Promise.resolve();
+      }
+
+      return new Promise(resolve => this.pulling.flushes.push(resolve));
+    }
+
+    end(chunk?: string | ArrayBuffer | ArrayBufferView) {
+      if (chunk !== undefined) {
+        this.write(chunk);
+      }
+
+      if (!this.ended) {
+        this.ended = true;
+        this.controller.close();
+        // The body is closed once the remaining chunks have been read
+        this.pulling.flushes.splice(0).forEach(resolve => resolve());
+      }
+    }
+  };
+})(globalThis);
@@ -0,0 +1,117 @@
+(globalThis => {
+  const CONNECTING = 0;
+  const OPEN = 1;
+  const CLOSING = 2;
+  const CLOSED = 3;
+
+  // The server side of an upgraded WebSocket connection, where messages
+  // are sent and received through the host
+  class ServerWebSocket extends EventTarget {
+    readonly CONNECTING = CONNECTING;
+    readonly OPEN = OPEN;
+    readonly CLOSING = CLOSING;
+    readonly CLOSED = CLOSED;
+
+    readyState: number = CONNECTING;
+    binaryType: BinaryType = 'arraybuffer';
+
+    onopen: ((event: Event) => void) | null = null;
+    onmessage: ((event: MessageEvent) => void) | null = null;
+    onclose: ((event: CloseEvent) => void) | null = null;
+    onerror: ((event: Event) => void) | null = null;
+
+    private id: number;
+
+    constructor(id: number) {
+      super();
+
+      this.id = id;
+
+      // Wait for the handler to attach its listeners
+      queueMicrotask(() => {
+        if (this.readyState !== CONNECTING) {
+          return;
+        }
+
+        this.readyState = OPEN;
+        this.emit(new Event('open'));
+        this.receive();
+      });
+    }
+
+    send(data: string | ArrayBufferLike | ArrayBufferView) {
+      if (this.readyState !== OPEN) {
+        throw new TypeError('WebSocket is not open');
+      }
+
+      if (typeof data === 'string') {
+        LagonSync.webSocketSend(this.id, data);
+      } else if (ArrayBuffer.isView(data)) {
+        LagonSync.webSocketSend(this.id, new Uint8Array(data.buffer, data.byteOffset, data.byteLength));
+      } else {
+        LagonSync.webSocketSend(this.id, new Uint8Array(data));
+      }
+    }
+
+    close(code?: number, reason?: string) {
+      if (this.readyState === CLOSING || this.readyState === CLOSED) {
+        return;
+      }
+
+      this.readyState = CLOSING;
+      LagonSync.webSocketClose(this.id);
+      this.closed(code ?? 1000, reason ?? '');
+    }
+
+    private receive() {
+      LagonAsync.webSocketReceive(this.id).then(
+        data => {
+          if (this.readyState !== OPEN) {
+            return;
+          }
+
+          if (data === undefined) {
+            LagonSync.webSocketClose(this.id);
+            this.closed(1000, '');
+            return;
+          }
+
+          this.emit(new MessageEvent('message', { data: typeof data === 'string' ? data : data.buffer }));
+          this.receive();
+        },
+        () => {
+          this.emit(new Event('error'));
+          this.closed(1006, '');
+        },
+      );
+    }
+
+    private closed(code: number, reason: string) {
+      this.readyState = CLOSED;
+      this.emit(new CloseEvent('close', { code, reason, wasClean: code !== 1006 }));
+    }
+
+    private emit(event: Event) {
+      const callback = this[`on${event.type}` as 'onopen'];
+
+      if (typeof callback === 'function') {
+        callback.call(this, event);
+      }
+
+      this.dispatchEvent(event);
+    }
+  }
+
+  globalThis.upgradeWebSocket = (request: Request) => {
+    if (request.headers.get('upgrade')?.toLowerCase() !== 'websocket') {
+      throw new TypeError('Request is not a WebSocket upgrade request');
+    }
+
+    const id = LagonSync.upgradeWebSocket();
+
+    return {
+      socket: new ServerWebSocket(id) as unknown as WebSocket,
+      response: new Response(null, { status: 101 }),
+    };
+  };
+})(globalThis);
@@ -0,0 +1,134 @@
+(globalThis => {
+  const ZIP_CONTENT_TYPE = 'application/zip';
+
+  const toBytes = (chunk: string | ArrayBuffer | ArrayBufferView): Uint8Array => {
+    if (typeof chunk === 'string') {
+      return globalThis.__lagon__.TEXT_ENCODER.encode(chunk);
+    }
+
+    if (chunk instanceof ArrayBuffer) {
+      return new Uint8Array(chunk);
+    }
+
+    return new Uint8Array(chunk.buffer, chunk.byteOffset, chunk.byteLength);
+  };
+
+  async function* readEntry(body: ZipBody): AsyncGenerator<Uint8Array> {
+    if (!(body instanceof ReadableStream)) {
+      yield toBytes(body);
+      return;
+    }
+
+    const reader = body.getReader();
+
+    while (true) {
+      const { done, value } = await reader.read();
+
+      if (done) {
+        return;
+      }
+
+      yield value;
+    }
+  }
+
+  // Write a local header, the data and a data descriptor per entry, followed
+  // by the central directory. Each entry is read only when the previous ones
+  // have been sent, and only the records of the central directory are kept
+  async function* zipEntries(entries: ZipEntry[]): AsyncGenerator<Uint8Array> {
+    const records: ZipEntryRecord[] = [];
+    let offset = 0;
+
+    for (const entry of entries) {
+      const deflate = entry.compress ?? false;
+      const header = LagonSync.zipLocalHeader(entry.name, deflate);
+      const headerOffset = offset;
+
+      offset += header.byteLength;
+      yield header;
+
+      let crc = 0;
+      let compressedSize = 0;
+      let uncompressedSize = 0;
+
+      for await (const chunk of readEntry(entry.body)) {
+        if (chunk.byteLength === 0) {
+          continue;
+        }
+
+        crc = LagonSync.zipCrc32(crc, chunk);
+        uncompressedSize += chunk.byteLength;
+
+        const data = deflate ? LagonSync.zipDeflate(chunk, false) : chunk;
+        compressedSize += data.byteLength;
+        offset += data.byteLength;
+        yield data;
+      }
+
+      // Ends the deflate stream of the entry
+      if (deflate) {
+        const data = LagonSync.zipDeflate(new Uint8Array(0), true);
+        compressedSize += data.byteLength;
+        offset += data.byteLength;
+        yield data;
+      }
+
+      const record: ZipEntryRecord = [entry.name, deflate, crc, compressedSize, uncompressedSize, headerOffset];
+      const descriptor = LagonSync.zipDataDescriptor(...record);
+
+      offset += descriptor.byteLength;
+      yield descriptor;
+
+      records.push(record);
+    }
+
+    let size = 0;
+
+    for (const record of records) {
+      const header = LagonSync.zipCentralDirectoryHeader(...record);
+      size += header.byteLength;
+      yield header;
+    }
+
+    yield LagonSync.zipEndOfCentralDirectory(records.length, size, offset);
+  }
+
+  // Non-standard Response streaming multiple entries as a single ZIP archive,
+  // e.g for "download all" features, without buffering the entries
+  globalThis.ZipResponse = class extends Response {
+    constructor(entries: ZipEntry[], init?: ResponseInit) {
+      const chunks = zipEntries(entries);
+
+      const headers = new Headers(init?.headers);
+
+      if (!headers.has('content-type')) {
+        headers.set('content-type', ZIP_CONTENT_TYPE);
+      }
+
+      super(
+        new ReadableStream<Uint8Array>(
+          {
+            async pull(controller) {
+              try {
+                const { done, value } = await chunks.next();
+
+                if (done) {
+                  controller.close();
+                } else {
+                  controller.enqueue(value);
+                }
+              } catch (error) {
+                controller.error(error);
+              }
+            },
+            async cancel() {
+              await chunks.return(undefined);
+            },
+          },
+          { highWaterMark: 0 },
+        ),
+        { ...init, headers },
+      );
+    }
+  };
+})(globalThis);
@@ -1,3 +1,24 @@
+// Streamed bodies are rejected as soon as they exceed the size limit of the
+// isolate while being read, instead of buffering them entirely
+export const bodySizeError = (length: number): TypeError | undefined => {
+  const maxSize = LagonSync.maxBodySize();
+
+  if (maxSize !== undefined && length > maxSize) {
+    return new TypeError(`Body exceeds the maximum size of ${maxSize} bytes`);
+  }
+};
+
+// The size limit of request.json(), on top of the size limit of the isolate
+export const jsonSizeError = (length: number): TypeError | undefined => {
+  const maxSize = LagonSync.jsonMaxSize();
+
+  if (length > maxSize) {
+    return new TypeError(`JSON body exceeds the maximum size of ${maxSize} bytes`);
+  }
+
+  return bodySizeError(length);
+};
+
 export class RequestResponseBody {
   private theBody: string | ArrayBuffer | FormData | ReadableStream<Uint8Array> | Blob | URLSearchParams | null;
   bodyUsed: boolean;
@@ -14,8 +35,20 @@ export class RequestResponseBody {
     headersInit?: HeadersInit,
   ) {
     const isPrimitive = typeof body === 'number' || typeof body === 'boolean';
-    // @ts-expect-error we ignore ArrayBufferView
-    this.theBody = isPrimitive ? String(body) : body;
+
+    if (isPrimitive) {
+      this.theBody = String(body);
+    } else if (body instanceof ArrayBuffer) {
+      // Binary bodies are viewed as a Uint8Array without copying them,
+      // which the host reads directly
+      this.theBody = new Uint8Array(body);
+    } else if (ArrayBuffer.isView(body) && !(body instanceof Uint8Array)) {
+      this.theBody = new Uint8Array(body.buffer, body.byteOffset, body.byteLength);
+    } else {
+      // @ts-expect-error Uint8Array is an ArrayBufferView
+      this.theBody = body;
+    }
+
     this.headersInit = headersInit;
     this.bodyUsed = false;
     this.isStream = body instanceof ReadableStream;
@@ -86,6 +119,14 @@ export class RequestResponseBody {
   }
 
   async arrayBuffer(): Promise<ArrayBuffer> {
+    const bytes = await this.readBytes();
+
+    // The bytes can be a view of a part of a bigger buffer
+    return bytes.buffer.slice(bytes.byteOffset, bytes.byteOffset + bytes.byteLength);
+  }
+
+  // Most bodies are already stored as a Uint8Array, which is returned without copying it
+  protected async readBytes(): Promise<Uint8Array> {
     if (this.bodyUsed) {
       throw new TypeError('Body is already used');
     }
@@ -102,7 +143,7 @@ export class RequestResponseBody {
 
     if (this.theBody instanceof ArrayBuffer || this.theBody instanceof Uint8Array) {
       this.bodyUsed = true;
-      return this.theBody;
+      return this.theBody instanceof Uint8Array ? this.theBody : new Uint8Array(this.theBody);
     }
 
     if (this.theBody instanceof FormData || this.theBody instanceof URLSearchParams) {
@@ -112,12 +153,20 @@ export class RequestResponseBody {
 
     if (this.theBody instanceof Blob) {
       this.bodyUsed = true;
-      return this.theBody.arrayBuffer();
+      return new Uint8Array(await this.theBody.arrayBuffer());
+    }
+
+    // Read the whole body of fetch() responses host-side at once
+    const fetchBodyId = globalThis.__lagon__.fetchBodies.get(this.theBody as ReadableStream);
+
+    if (fetchBodyId !== undefined && !(this.theBody as ReadableStream).locked) {
+      this.bodyUsed = true;
+      return (await LagonAsync.readFetchBody(fetchBodyId, true)) ?? new Uint8Array();
     }
 
     const reader = (this.theBody as ReadableStream<Uint8Array>).getReader();
 
-    return new Promise(resolve => {
+    return new Promise((resolve, reject) => {
       let result = new Uint8Array();
 
       const pull = () => {
@@ -127,6 +176,14 @@ export class RequestResponseBody {
             return resolve(result);
           }
 
+          const error = bodySizeError(result.length + value.length);
+
+          if (error) {
+            this.bodyUsed = true;
+            reader.cancel(error);
+            return reject(error);
+          }
+
           const newResult = new Uint8Array(result.length + value.length);
           newResult.set(result);
           newResult.set(value, result.length);
@@ -141,10 +198,14 @@ export class RequestResponseBody {
     });
   }
 
+  async bytes(): Promise<Uint8Array> {
+    return this.readBytes();
+  }
+
   async blob(): Promise<Blob> {
     const type = this.headers.get('content-type') || undefined;
 
-    return this.arrayBuffer().then(buffer => new Blob([buffer], { type }));
+    return this.readBytes().then(bytes => new Blob([bytes], { type }));
   }
 
   async formData(): Promise<FormData> {
@@ -162,6 +223,12 @@ export class RequestResponseBody {
   }
 
   async text(): Promise<string> {
+    return this.readText();
+  }
+
+  // Buffered bodies are only checked against a custom size limit, since
+  // they already are within the size limit of the isolate
+  protected async readText(sizeError?: (length: number) => TypeError | undefined): Promise<string> {
     if (this.bodyUsed) {
       throw new TypeError('Body is already used');
     }
@@ -178,6 +245,13 @@ export class RequestResponseBody {
 
     if (this.theBody instanceof ArrayBuffer || this.theBody instanceof Uint8Array) {
       this.bodyUsed = true;
+
+      const error = sizeError?.(this.theBody.byteLength);
+
+      if (error) {
+        throw error;
+      }
+
       return globalThis.__lagon__.TEXT_DECODER.decode(this.theBody);
     }
 
@@ -193,19 +267,45 @@ export class RequestResponseBody {
 
     const reader = (this.theBody as ReadableStream<Uint8Array>).getReader();
 
-    return new Promise(resolve => {
+    return new Promise((resolve, reject) => {
       let result = '';
+      // Bytes are decoded together, since a character
+      // can be split across multiple chunks
+      let bytes = new Uint8Array();
+      let length = 0;
+
+      const decodeBytes = () => {
+        if (bytes.length !== 0) {
+          result += globalThis.__lagon__.TEXT_DECODER.decode(bytes);
+          bytes = new Uint8Array();
+        }
+      };
 
       const pull = () => {
         reader.read().then(({ done, value }) => {
           if (done) {
+            decodeBytes();
             this.bodyUsed = true;
             return resolve(result);
           }
 
+          length += globalThis.__lagon__.isIterable(value) ? value.byteLength : String(value).length;
+          const error = (sizeError ?? bodySizeError)(length);
+
+          if (error) {
+            this.bodyUsed = true;
+            reader.cancel(error);
+            return reject(error);
+          }
+
           if (globalThis.__lagon__.isIterable(value)) {
-            result += globalThis.__lagon__.TEXT_DECODER.decode(value);
+            const newBytes = new Uint8Array(bytes.length + value.byteLength);
+            newBytes.set(bytes);
+            newBytes.set(new Uint8Array(value), bytes.length);
+
+            bytes = newBytes;
           } else {
+            decodeBytes();
             result += value;
           }
 
@@ -1,6 +1,90 @@
 (globalThis => {
   const isHeadersObject = (headers?: HeadersInit): headers is Headers => !!headers && 'entries' in headers;
 
+  // https://fetch.spec.whatwg.org/#null-body-status
+  const NULL_BODY_STATUS = [101, 103, 204, 205, 304];
+
+  let streamsCount = 0;
+
+  // Push the chunks of a streamed body to the host as they are read,
+  // which are sent upstream using chunked transfer encoding. An error
+  // while reading the body aborts the request, rejecting fetch()
+  const pullBody = (id: number, stream: ReadableStream<Uint8Array>) => {
+    const reader = stream.getReader();
+
+    const read = () => {
+      reader
+        .read()
+        .then(({ done, value }) => {
+          if (done) {
+            LagonSync.pullFetchBody(id, done);
+            return;
+          }
+
+          if (value.byteLength !== 0) {
+            LagonSync.pullFetchBody(id, done, value);
+          }
+
+          read();
+        })
+        .catch(error => {
+          LagonSync.pullFetchBody(id, true, undefined, String(error));
+        });
+    };
+
+    read();
+  };
+
+  // Trailers are sent after the body, so the promise resolves once the whole
+  // body has been read. They are only requested from the host when accessed
+  const readTrailers = (response: Response, id: number) => {
+    let trailers: Promise<Headers> | undefined;
+
+    Object.defineProperty(response, 'trailers', {
+      get: () => {
+        if (!trailers) {
+          trailers = LagonAsync.readFetchTrailers(id).then(headers => new Headers(headers));
+        }
+
+        return trailers;
+      },
+    });
+
+    return response;
+  };
+
+  // https://w3c.github.io/webappsec-subresource-integrity/#hash-functions
+  const INTEGRITY_ALGORITHMS = ['sha256', 'sha384', 'sha512'];
+
+  // Only the metadata of the strongest algorithm is checked. Metadata with an
+  // unknown algorithm is ignored, and the check passes when none remains
+  const checkIntegrity = async (integrity: string, body: ArrayBuffer) => {
+    const metadata = integrity
+      .split(/\s+/)
+      .map(hash => {
+        const separator = hash.indexOf('-');
+        // Options after `?` are reserved
+        const [digest] = hash.slice(separator + 1).split('?');
+
+        return { algorithm: separator === -1 ? '' : hash.slice(0, separator), digest };
+      })
+      .filter(({ algorithm, digest }) => INTEGRITY_ALGORITHMS.includes(algorithm) && digest);
+
+    if (metadata.length === 0) {
+      return;
+    }
+
+    const strongest = Math.max(...metadata.map(({ algorithm }) => INTEGRITY_ALGORITHMS.indexOf(algorithm)));
+    const algorithm = INTEGRITY_ALGORITHMS[strongest];
+
+    const digest = new Uint8Array(await crypto.subtle.digest(`SHA-${algorithm.slice(3)}`, body));
+    const base64 = btoa(String.fromCharCode(...digest));
+
+    if (!metadata.some(hash => hash.algorithm === algorithm && hash.digest === base64)) {
+      throw new TypeError(`Failed to match the ${algorithm} integrity of the response`);
+    }
+  };
+
   globalThis.fetch = async (input, init) => {
     let headers: Map<string, string> | undefined = undefined;
 
@@ -15,9 +99,16 @@
     }
 
     let body: ArrayBuffer | undefined;
+    let stream: ReadableStream<Uint8Array> | undefined;
 
     if (init?.body) {
-      if (!globalThis.__lagon__.isIterable(init.body)) {
+      if (init.body instanceof ReadableStream) {
+        stream = init.body;
+      } else if (init.body instanceof Blob) {
+        body = init.body.buffer;
+      } else if (init.body instanceof URLSearchParams || init.body instanceof FormData) {
+        body = globalThis.__lagon__.TEXT_ENCODER.encode(init.body.toString());
+      } else if (!globalThis.__lagon__.isIterable(init.body)) {
         if (typeof init.body !== 'string') {
           // TODO: Support other body types
           throw new Error('Body must be a string or an iterable');
@@ -38,20 +129,69 @@
     try {
       checkAborted();
 
-      const response = await LagonAsync.fetch({
+      const streamId = stream ? streamsCount++ : undefined;
+      const promise = LagonAsync.fetch({
         m: init?.method || 'GET',
         u: input.toString(),
         b: body,
         h: headers,
+        s: streamId,
+        c: init?.cache || (input instanceof Request ? input.cache : undefined),
+        d: init?.decompress,
+        a: init?.signal ? true : undefined,
       });
 
+      if (stream && streamId !== undefined) {
+        pullBody(streamId, stream);
+      }
+
+      const response = await promise;
+
       checkAborted();
 
-      return new Response(response.b, {
+      let responseBody: ArrayBuffer | ReadableStream<Uint8Array> | null = response.b;
+
+      if (NULL_BODY_STATUS.includes(response.s)) {
+        responseBody = null;
+      } else if (response.f !== undefined) {
+        responseBody = globalThis.__lagon__.readFetchBody(response.f);
+      }
+
+      const fetchResponse = new Response(responseBody, {
         // url: response.init.url,
         headers: response.h,
         status: response.s,
       });
+
+      if (response.f !== undefined) {
+        readTrailers(fetchResponse, response.f);
+      }
+
+      const timing = response.t ? Object.freeze(response.t) : undefined;
+      Object.defineProperty(fetchResponse, 'timing', { get: () => timing });
+
+      const integrity = init?.integrity || (input instanceof Request ? input.integrity : '');
+
+      if (!integrity) {
+        return fetchResponse;
+      }
+
+      // The whole body has to be read to check its integrity
+      const integrityBody = await fetchResponse.arrayBuffer();
+      await checkIntegrity(integrity, integrityBody);
+
+      const integrityResponse = new Response(NULL_BODY_STATUS.includes(response.s) ? null : integrityBody, {
+        headers: response.h,
+        status: response.s,
+      });
+
+      if (response.f !== undefined) {
+        Object.defineProperty(integrityResponse, 'trailers', { get: () => fetchResponse.trailers });
+      }
+
+      Object.defineProperty(integrityResponse, 'timing', { get: () => timing });
+
+      return integrityResponse;
     } catch (error) {
       if (typeof error === 'string') {
         throw new Error(error);